@@ -1,7 +1,9 @@
 use std::fmt;
 
 use crate::board::{ Color, Coordinates, Kind, Piece, Square };
+use crate::board::fen::{ piece_to_char };
 use crate::board::parser::{ parse_board };
+use crate::error::{ Error };
 
 #[derive(Debug,Copy,PartialEq,Clone,Hash)]
 pub struct Board {
@@ -13,11 +15,22 @@ pub struct Board {
     black_can_queen_castle: bool,
 }
 
+// Displays the board from Black's side: rank 1 at the top and the h-file on the left.
+#[derive(Debug,Copy,PartialEq,Clone)]
+pub struct FlippedBoard<'a>(pub &'a Board);
+
+#[derive(Debug,Copy,PartialEq,Eq,Clone,Hash)]
 pub enum CastlingSide {
     King,
     Queen
 }
 
+impl Default for Board {
+    fn default() -> Board {
+        Board::new()
+    }
+}
+
 impl Board {
     pub fn new() -> Board {
         Board {
@@ -30,8 +43,8 @@ impl Board {
         }
     }
 
-    pub fn parse_str(input: &str) -> Result<Board, String> {
-        parse_board(input)
+    pub fn parse_str(input: &str) -> Result<Board, Error> {
+        Ok(parse_board(input)?)
     }
 
     pub fn setup(&mut self) {
@@ -209,14 +222,45 @@ impl Board {
         self.en_passant_capturable = coordinates.map(|c| c.index());
     }
 
+    pub fn en_passant_capturable(&self) -> Option<Coordinates> {
+        self.en_passant_capturable.and_then(Coordinates::new_from_index)
+    }
+
     pub fn is_en_passant_capturable(&self, coordinates: Coordinates) -> bool {
         matches!(self.en_passant_capturable, Some(index) if index == coordinates.index())
     }
-}
 
-impl fmt::Display for Board {
-    // This trait requires `fmt` with this exact signature.
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    // The same position with the colors swapped and the board flipped vertically.
+    pub fn mirror(&self) -> Board {
+        let mut mirrored = Board::new();
+        for (index, square) in self.squares.iter().enumerate() {
+            mirrored.squares[index ^ 56] = match *square {
+                Square::Occupied(Piece(color, kind)) => Square::Occupied(Piece(color.opposite(), kind)),
+                Square::Empty => Square::Empty
+            };
+        }
+        Board {
+            en_passant_capturable: self.en_passant_capturable.map(|index| index ^ 56),
+            white_can_king_castle: self.black_can_king_castle,
+            white_can_queen_castle: self.black_can_queen_castle,
+            black_can_king_castle: self.white_can_king_castle,
+            black_can_queen_castle: self.white_can_queen_castle,
+            ..mirrored
+        }
+    }
+
+    pub fn flipped(&self) -> FlippedBoard<'_> {
+        FlippedBoard(self)
+    }
+
+    // The diagram with FEN letters for the pieces and dots for empty squares, which `parse_str` reads as well.
+    pub fn ascii_diagram(&self) -> String {
+        let mut diagram = String::new();
+        self.write_diagram(&mut diagram, false, true).unwrap();
+        diagram
+    }
+
+    fn write_diagram(&self, f: &mut dyn fmt::Write, flipped: bool, ascii: bool) -> fmt::Result {
         fn display_can_castle(value: bool) -> &'static str {
             if value {
                 "v"
@@ -224,21 +268,47 @@ impl fmt::Display for Board {
                 "-"
             }
         }
-        write!(f, "  +-{}-------------{}-+\n", display_can_castle(self.black_can_queen_castle), display_can_castle(self.black_can_king_castle))?;
-        for rank in 0..8 {
-            write!(f, "{} | ", 8 - rank)?;
-            for file in 0..8 {
-                let index: usize = (7 - rank) * 8 + file;
+        // The castling rights of the side at the top, then of the side at the bottom, left to right.
+        let (top, bottom, files) = if flipped {
+            ([self.white_can_king_castle, self.white_can_queen_castle], [self.black_can_king_castle, self.black_can_queen_castle], "h g f e d c b a")
+        } else {
+            ([self.black_can_queen_castle, self.black_can_king_castle], [self.white_can_queen_castle, self.white_can_king_castle], "a b c d e f g h")
+        };
+        write!(f, "  +-{}-------------{}-+\n", display_can_castle(top[0]), display_can_castle(top[1]))?;
+        for row in 0..8 {
+            let rank = if flipped { row } else { 7 - row };
+            write!(f, "{} | ", rank + 1)?;
+            for column in 0..8 {
+                let file = if flipped { 7 - column } else { column };
+                let index: usize = rank * 8 + file;
                 if self.en_passant_capturable.and_then(|i| Some(i == index)).unwrap_or(false) {
                     write!(f, "* ")?;
+                } else if ascii {
+                    match self.squares[index] {
+                        Square::Occupied(piece) => write!(f, "{} ", piece_to_char(piece))?,
+                        Square::Empty => write!(f, ". ")?
+                    }
                 } else {
                     write!(f, "{} ", self.squares[index])?;
                 }
             }
             write!(f, "|\n")?;
         }
-        write!(f, "  +-{}-------------{}-+\n", display_can_castle(self.white_can_queen_castle), display_can_castle(self.white_can_king_castle))?;
-        write!(f, "    a b c d e f g h")?;
+        write!(f, "  +-{}-------------{}-+\n", display_can_castle(bottom[0]), display_can_castle(bottom[1]))?;
+        write!(f, "    {}", files)?;
         write!(f, "\n")
     }
 }
+
+impl fmt::Display for Board {
+    // This trait requires `fmt` with this exact signature.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write_diagram(f, false, false)
+    }
+}
+
+impl fmt::Display for FlippedBoard<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.write_diagram(f, true, false)
+    }
+}
@@ -119,7 +119,6 @@ impl Coordinates {
         self.rank
     }
 
-    #[allow(dead_code)]
     pub fn file(&self) -> File {
         self.file
     }
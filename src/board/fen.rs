@@ -0,0 +1,188 @@
+use std::fmt;
+
+use crate::board::{ Board, CastlingSide, Color, Coordinates, File, Kind, Piece, Rank, Square };
+use crate::error::{ Error, ParseError, ParseErrorKind };
+
+pub const START_POSITION_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+// A board in Forsyth-Edwards Notation, along with the state that the board itself does not track.
+#[derive(Debug,Copy,PartialEq,Clone,Hash)]
+pub struct Fen {
+    pub board: Board,
+    pub color: Color,
+    pub halfmove_clock: u32,
+    pub fullmove_number: u32,
+}
+
+impl Fen {
+    pub fn new(board: Board, color: Color) -> Fen {
+        Fen { board, color, halfmove_clock: 0, fullmove_number: 1 }
+    }
+
+    pub fn parse(input: &str) -> Result<Fen, Error> {
+        Ok(parse_fen(input)?)
+    }
+}
+
+// Parse a FEN string. The move counters are optional, so EPD positions are accepted as well.
+pub fn parse_fen(input: &str) -> Result<Fen, ParseError> {
+    let fields: Vec<&str> = input.split_whitespace().collect();
+    if fields.len() < 4 || fields.len() > 6 {
+        // Point at the first field too many, or at the end when fields are missing.
+        let end = input.trim_end().len();
+        let text = fields.get(6).copied().unwrap_or(&input[end..end]);
+        return Err(ParseError::new(ParseErrorKind::FieldCount, input, text, format!("Expect 4 to 6 fields in FEN, got {}", fields.len())));
+    }
+
+    let mut board = Board::new();
+    parse_placement(&mut board, input, fields[0])?;
+
+    let color = match fields[1] {
+        "w" => Color::White,
+        "b" => Color::Black,
+        other => { return Err(ParseError::new(ParseErrorKind::Unexpected, input, other, format!("Expect w or b for the color to move, got {}", other))); }
+    };
+
+    if fields[2] != "-" {
+        for (index, c) in fields[2].char_indices() {
+            match c {
+                'K' => board.set_castling_allowed(Color::White, CastlingSide::King, true),
+                'Q' => board.set_castling_allowed(Color::White, CastlingSide::Queen, true),
+                'k' => board.set_castling_allowed(Color::Black, CastlingSide::King, true),
+                'q' => board.set_castling_allowed(Color::Black, CastlingSide::Queen, true),
+                other => {
+                    let text = &fields[2][index..index + other.len_utf8()];
+                    return Err(ParseError::new(ParseErrorKind::Unexpected, input, text, format!("Unexpected castling right: {}", other)));
+                }
+            }
+        }
+    }
+
+    if fields[3] != "-" {
+        board.set_en_passant_capturable(Some(parse_square(input, fields[3])?));
+    }
+
+    let halfmove_clock = match fields.get(4) {
+        Some(field) => field.parse().map_err(|_| ParseError::new(ParseErrorKind::InvalidNumber, input, field, format!("Invalid halfmove clock: {}", field)))?,
+        None => 0
+    };
+    let fullmove_number = match fields.get(5) {
+        Some(field) => field.parse().map_err(|_| ParseError::new(ParseErrorKind::InvalidNumber, input, field, format!("Invalid fullmove number: {}", field)))?,
+        None => 1
+    };
+
+    Ok(Fen { board, color, halfmove_clock, fullmove_number })
+}
+
+fn parse_placement(board: &mut Board, input: &str, placement: &str) -> Result<(), ParseError> {
+    let ranks: Vec<&str> = placement.split('/').collect();
+    if ranks.len() != 8 {
+        let text = ranks.get(8).copied().unwrap_or(&placement[placement.len()..]);
+        return Err(ParseError::new(ParseErrorKind::FieldCount, input, text, format!("Expect 8 ranks in FEN, got {}", ranks.len())));
+    }
+
+    for (neg_rank, rank_data) in ranks.iter().enumerate() {
+        let rank = Rank::new_from_index((7 - neg_rank) as u8).unwrap();
+        let mut file_index: u8 = 0;
+        for (index, c) in rank_data.char_indices() {
+            let text = &rank_data[index..index + c.len_utf8()];
+            if let Some(empty_count) = c.to_digit(10) {
+                file_index += empty_count as u8;
+                continue;
+            }
+            let file = File::new_from_index(file_index)
+                .ok_or_else(|| ParseError::new(ParseErrorKind::FieldCount, input, text, format!("Too many squares on rank {}", rank)))?;
+            let piece = piece_from_char(c).ok_or_else(|| ParseError::new(ParseErrorKind::InvalidPiece, input, text, format!("Unexpected piece: {}", c)))?;
+            board.set_square(Coordinates::new(file, rank), Square::Occupied(piece));
+            file_index += 1;
+        }
+        if file_index != 8 {
+            return Err(ParseError::new(ParseErrorKind::FieldCount, input, rank_data, format!("Expect 8 squares on rank {}, got {}", rank, file_index)));
+        }
+    }
+    Ok(())
+}
+
+// Parse coordinates such as "e4".
+pub fn parse_coordinates(input: &str) -> Result<Coordinates, Error> {
+    Ok(parse_square(input, input)?)
+}
+
+// Coordinates from the text, which is a slice of the input.
+fn parse_square(input: &str, text: &str) -> Result<Coordinates, ParseError> {
+    let bytes = text.as_bytes();
+    if bytes.len() != 2 || !(b'a'..=b'h').contains(&bytes[0]) || !(b'1'..=b'8').contains(&bytes[1]) {
+        return Err(ParseError::new(ParseErrorKind::InvalidSquare, input, text, format!("Invalid square: {}", text)));
+    }
+    Ok(Coordinates::new(File::new_from_index(bytes[0] - b'a').unwrap(), Rank::new_from_index(bytes[1] - b'1').unwrap()))
+}
+
+pub fn piece_from_char(c: char) -> Option<Piece> {
+    let color = if c.is_ascii_uppercase() { Color::White } else { Color::Black };
+    let kind = match c.to_ascii_lowercase() {
+        'p' => Kind::Pawn,
+        'n' => Kind::Knight,
+        'b' => Kind::Bishop,
+        'r' => Kind::Rook,
+        'q' => Kind::Queen,
+        'k' => Kind::King,
+        _ => { return None; }
+    };
+    Some(Piece(color, kind))
+}
+
+pub fn piece_to_char(piece: Piece) -> char {
+    let c = match piece.1 {
+        Kind::Pawn => 'p',
+        Kind::Knight => 'n',
+        Kind::Bishop => 'b',
+        Kind::Rook => 'r',
+        Kind::Queen => 'q',
+        Kind::King => 'k'
+    };
+    if piece.0 == Color::White { c.to_ascii_uppercase() } else { c }
+}
+
+impl fmt::Display for Fen {
+    // This trait requires `fmt` with this exact signature.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for rank in (0..8).rev() {
+            let mut empty_count = 0;
+            for file in 0..8 {
+                match self.board.squares()[rank * 8 + file] {
+                    Square::Occupied(piece) => {
+                        if empty_count > 0 {
+                            write!(f, "{}", empty_count)?;
+                            empty_count = 0;
+                        }
+                        write!(f, "{}", piece_to_char(piece))?;
+                    },
+                    Square::Empty => { empty_count += 1; }
+                }
+            }
+            if empty_count > 0 {
+                write!(f, "{}", empty_count)?;
+            }
+            if rank > 0 {
+                write!(f, "/")?;
+            }
+        }
+
+        write!(f, " {} ", if self.color == Color::White { "w" } else { "b" })?;
+
+        let castling: String = [
+            (Color::White, CastlingSide::King, 'K'),
+            (Color::White, CastlingSide::Queen, 'Q'),
+            (Color::Black, CastlingSide::King, 'k'),
+            (Color::Black, CastlingSide::Queen, 'q'),
+        ].iter().filter(|(color, side, _)| self.board.is_castling_allowed(*color, *side)).map(|(_, _, c)| *c).collect();
+        write!(f, "{} ", if castling.is_empty() { "-" } else { &castling })?;
+
+        match self.board.en_passant_capturable() {
+            Some(coordinates) => write!(f, "{}", coordinates)?,
+            None => write!(f, "-")?
+        }
+
+        write!(f, " {} {}", self.halfmove_clock, self.fullmove_number)
+    }
+}
@@ -1,9 +1,21 @@
 #[derive(Debug,Copy,PartialEq,Clone,Hash)]
 pub enum Kind {
-    Rook,
-    Knight,
-    Bishop,
-    Queen,
-    King,
-    Pawn
+    Rook = 0,
+    Knight = 1,
+    Bishop = 2,
+    Queen = 3,
+    King = 4,
+    Pawn = 5
+}
+
+pub const ALL_KINDS: [Kind; 6] = [Kind::Rook, Kind::Knight, Kind::Bishop, Kind::Queen, Kind::King, Kind::Pawn];
+
+impl Kind {
+    pub fn new_from_index(index: usize) -> Option<Kind> {
+        ALL_KINDS.get(index).copied()
+    }
+
+    pub fn index(&self) -> usize {
+        *self as usize
+    }
 }
@@ -1,14 +1,16 @@
 mod board;
 mod color;
 mod coordinates;
+mod fen;
 mod kind;
 mod parser;
 mod piece;
 mod square;
 
-pub use board::{ Board, CastlingSide };
+pub use board::{ Board, CastlingSide, FlippedBoard };
 pub use color::{ Color };
 pub use coordinates::{ Coordinates, File, Rank };
-pub use kind::{ Kind };
+pub use fen::{ parse_coordinates, Fen, START_POSITION_FEN };
+pub use kind::{ Kind, ALL_KINDS };
 pub use piece::{ Piece };
 pub use square::{ Square };
@@ -1,19 +1,32 @@
 use crate::board::{ Board, Color, CastlingSide, Coordinates, File, Kind, Piece, Rank, Square };
-
-pub fn parse_board(input: &str) -> Result<Board, String> {
-    let lines: Vec<&str> = input.trim().split("\n").collect();
+use crate::board::fen::{ piece_from_char };
+use crate::error::{ ParseError, ParseErrorKind };
+
+// Errors point into the input, so every step takes the whole input along with the part it reads.
+pub fn parse_board(input: &str) -> Result<Board, ParseError> {
+    // Blank lines around the diagram do not count.
+    let mut lines: Vec<&str> = input.lines().skip_while(|line| line.trim().is_empty()).collect();
+    while lines.last().is_some_and(|line| line.trim().is_empty()) {
+        lines.pop();
+    }
 
     if lines.len() != 11 {
-        return Err(String::from("Invalid number of lines for board"));
+        // Point at the first line too many, or at the end when lines are missing.
+        let text = match lines.get(11) {
+            Some(line) => *line,
+            None => lines.last().map_or(&input[input.len()..], |line| &line[line.len()..])
+        };
+        return Err(ParseError::new(ParseErrorKind::LineCount, input, text,
+            format!("Expect 11 lines for a board diagram, got {}", lines.len())));
     }
 
     let mut board = Board::new();
 
-    parse_header(&mut board, lines[0])?;
+    parse_header(&mut board, input, lines[0])?;
     for (neg_rank, line) in (&lines[1..=8]).iter().enumerate() {
-        parse_line(&mut board, Rank::new_from_index((7 - neg_rank) as u8).unwrap(), line)?;
+        parse_line(&mut board, input, Rank::new_from_index((7 - neg_rank) as u8).unwrap(), line)?;
     }
-    parse_footer(&mut board, lines[9])?;
+    parse_footer(&mut board, input, lines[9])?;
 
     Ok(board)
 }
@@ -25,44 +38,46 @@ const HEADER_FOOTER_CASTLING_NO: &str = "-";
 const HEADER_FOOTER_CASTLING_MARKERS: [&str; 2] = [HEADER_FOOTER_CASTLING_YES, HEADER_FOOTER_CASTLING_NO];
 const HEADER_FOOTER_SUFFIXES: [&str; 1] = ["-+"];
 
-fn parse_header(board: &mut Board, line: &str) -> Result<(), String> {
-    parse_header_or_footer(board, Color::Black, line)
+fn parse_header(board: &mut Board, input: &str, line: &str) -> Result<(), ParseError> {
+    parse_header_or_footer(board, input, Color::Black, line)
 }
 
-fn parse_footer(board: &mut Board, line: &str) -> Result<(), String> {
-    parse_header_or_footer(board, Color::White, line)
+fn parse_footer(board: &mut Board, input: &str, line: &str) -> Result<(), ParseError> {
+    parse_header_or_footer(board, input, Color::White, line)
 }
 
-fn parse_header_or_footer(board: &mut Board, color: Color, line: &str) -> Result<(), String> {
+fn parse_header_or_footer(board: &mut Board, input: &str, color: Color, line: &str) -> Result<(), ParseError> {
     let remainder = line.trim();
-    let (remainder, _) = expect_prefixes(remainder, &HEADER_FOOTER_PREFIXES)?;
-    let (remainder, prefix) = expect_prefixes(remainder, &HEADER_FOOTER_CASTLING_MARKERS)?;
+    let (remainder, _) = expect_prefixes(input, remainder, &HEADER_FOOTER_PREFIXES)?;
+    let (remainder, prefix) = expect_prefixes(input, remainder, &HEADER_FOOTER_CASTLING_MARKERS)?;
     if prefix == HEADER_FOOTER_CASTLING_YES {
         board.set_castling_allowed(color, CastlingSide::Queen, true)
     }
-    let (remainder, _) = expect_prefixes(remainder, &HEADER_FOOTER_MIDDLE)?;
-    let (remainder, prefix) = expect_prefixes(remainder, &HEADER_FOOTER_CASTLING_MARKERS)?;
+    let (remainder, _) = expect_prefixes(input, remainder, &HEADER_FOOTER_MIDDLE)?;
+    let (remainder, prefix) = expect_prefixes(input, remainder, &HEADER_FOOTER_CASTLING_MARKERS)?;
     if prefix == HEADER_FOOTER_CASTLING_YES {
         board.set_castling_allowed(color, CastlingSide::King, true)
     }
-    let (remainder, _) = expect_prefixes(remainder, &HEADER_FOOTER_SUFFIXES)?;
-    expect_empty(remainder)
+    let (remainder, _) = expect_prefixes(input, remainder, &HEADER_FOOTER_SUFFIXES)?;
+    expect_empty(input, remainder)
 }
 
 const LINE_PREFIXES: [&str; 1] = [" |"];
-const LINE_PIECES: [&str; 14] = ["♜", "♞", "♝", "♛", "♚", "♟︎", "♖", "♘", "♗", "♕", "♔", "♙", " ", "*"];
+// Unicode pieces, or FEN letters with dots for empty squares.
+const LINE_PIECES: [&str; 27] = ["♜", "♞", "♝", "♛", "♚", "♟︎", "♖", "♘", "♗", "♕", "♔", "♙", " ", "*",
+    "r", "n", "b", "q", "k", "p", "R", "N", "B", "Q", "K", "P", "."];
 const LINE_COLSEPS: [&str; 1] = [" "];
 const LINE_SUFFIXES: [&str; 1] = [" |"];
 
-fn parse_line(board: &mut Board, rank: Rank, line: &str) -> Result<(), String> {
+fn parse_line(board: &mut Board, input: &str, rank: Rank, line: &str) -> Result<(), ParseError> {
     let remainder = line.trim();
-    let (remainder, _) = expect_prefixes(remainder, &[&format!("{}", rank)])?;
-    let (mut remainder, _) = expect_prefixes(remainder, &LINE_PREFIXES)?;
+    let (remainder, _) = expect_prefixes(input, remainder, &[&format!("{}", rank)])?;
+    let (mut remainder, _) = expect_prefixes(input, remainder, &LINE_PREFIXES)?;
     for file in 0..8 {
         let coordinates = Coordinates::new(File::new_from_index(file as u8).unwrap(), rank);
 
-        let (loop_remainder, _) = expect_prefixes(remainder, &LINE_COLSEPS)?;
-        let (loop_remainder, piece) = expect_prefixes(loop_remainder, &LINE_PIECES)?;
+        let (square_text, _) = expect_prefixes(input, remainder, &LINE_COLSEPS)?;
+        let (loop_remainder, piece) = expect_prefixes(input, square_text, &LINE_PIECES)?;
         if piece == "*" {
             // En passant.
             board.set_en_passant_capturable(Some(coordinates));
@@ -80,16 +95,19 @@ fn parse_line(board: &mut Board, rank: Rank, line: &str) -> Result<(), String> {
                 "♕" => Square::Occupied(Piece(Color::White, Kind::Queen)),
                 "♔" => Square::Occupied(Piece(Color::White, Kind::King)),
                 "♙" => Square::Occupied(Piece(Color::White, Kind::Pawn)),
-                " " => Square::Empty,
-                _ => { return Err(String::from(format!("Unexpected piece: {}", piece))); }
+                " " | "." => Square::Empty,
+                letter => match letter.chars().next().and_then(piece_from_char) {
+                    Some(piece) => Square::Occupied(piece),
+                    None => { return Err(ParseError::new(ParseErrorKind::InvalidPiece, input, &square_text[..piece.len()], format!("Unexpected piece: {}", piece))); }
+                }
             };
             board.set_square(coordinates, square);
         }
 
         remainder = loop_remainder;
     }
-    let (remainder, _) = expect_prefixes(remainder, &LINE_SUFFIXES)?;
-    expect_empty(remainder)
+    let (remainder, _) = expect_prefixes(input, remainder, &LINE_SUFFIXES)?;
+    expect_empty(input, remainder)
 }
 
 fn try_prefixes<'a, 'b>(input: &'a str, options: &'b [&str]) -> Option<(&'a str, &'b str)> {
@@ -102,18 +120,20 @@ fn try_prefixes<'a, 'b>(input: &'a str, options: &'b [&str]) -> Option<(&'a str,
     None
 }
 
-fn expect_prefixes<'a, 'b>(input: &'a str, options: &'b [&str]) -> Result<(&'a str, &'b str), String> {
-    if let Some(result) = try_prefixes(input, options) {
+fn expect_prefixes<'a, 'b>(input: &str, remainder: &'a str, options: &'b [&str]) -> Result<(&'a str, &'b str), ParseError> {
+    if let Some(result) = try_prefixes(remainder, options) {
         Ok(result)
     } else {
-        Err(format!("Expect one of: {}", options.join(", ")))
+        // The character that does not fit, or nothing at the end of the line.
+        let text = &remainder[..remainder.chars().next().map_or(0, char::len_utf8)];
+        Err(ParseError::new(ParseErrorKind::Unexpected, input, text, format!("Expect one of: {}", options.join(", "))))
     }
 }
 
-fn expect_empty(input: &str) -> Result<(), String> {
-    if input.is_empty() {
+fn expect_empty(input: &str, remainder: &str) -> Result<(), ParseError> {
+    if remainder.is_empty() {
         Ok(())
     } else {
-        Err(format!("Expect end of string, got {}", input))
+        Err(ParseError::new(ParseErrorKind::Unexpected, input, remainder, format!("Expect end of line, got {}", remainder)))
     }
 }
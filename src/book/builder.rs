@@ -0,0 +1,112 @@
+use std::collections::{ HashMap };
+use std::fs;
+use std::path::{ Path };
+
+use crate::board::{ Color };
+use crate::book::pgn::{ PgnGame };
+use crate::book::polyglot::{ encode_move, polyglot_key, BookEntry };
+use crate::error::{ Error };
+
+#[derive(Debug,Clone,PartialEq)]
+pub struct BookBuilderOptions {
+    // Only the first this many plies of each game are recorded.
+    pub max_ply: usize,
+    // Moves played fewer times than this are left out.
+    pub min_count: u32,
+    // Only record the moves of this color.
+    pub color: Option<Color>,
+    // Only record the moves of this player, matched against the White and Black tags.
+    pub player: Option<String>,
+}
+
+impl Default for BookBuilderOptions {
+    fn default() -> BookBuilderOptions {
+        BookBuilderOptions {
+            max_ply: 30,
+            min_count: 1,
+            color: None,
+            player: None,
+        }
+    }
+}
+
+#[derive(Debug,Copy,PartialEq,Eq,Clone,Hash,Default)]
+struct MoveStatistics {
+    count: u32,
+    // Twice the points scored by the side that played the move: 2 per win and 1 per draw.
+    half_points: u32,
+}
+
+// Collects the moves played in games and writes them as a Polyglot book.
+pub struct BookBuilder {
+    options: BookBuilderOptions,
+    moves: HashMap<(u64, u16), MoveStatistics>,
+    pub games: usize,
+    pub skipped_games: usize,
+}
+
+impl BookBuilder {
+    pub fn new(options: BookBuilderOptions) -> BookBuilder {
+        BookBuilder { options, moves: HashMap::new(), games: 0, skipped_games: 0 }
+    }
+
+    // Add the moves of a game. Games with an illegal move are skipped as a whole.
+    pub fn add_game(&mut self, game: &PgnGame) -> Result<(), Error> {
+        let positions = match game.positions() {
+            Ok(positions) => positions,
+            Err(error) => {
+                self.skipped_games += 1;
+                return Err(error);
+            }
+        };
+        self.games += 1;
+
+        let player_color = match &self.options.player {
+            Some(player) if game.tag("White") == Some(player.as_str()) => Some(Color::White),
+            Some(player) if game.tag("Black") == Some(player.as_str()) => Some(Color::Black),
+            Some(_) => { return Ok(()); },
+            None => None
+        };
+        // Games without a result count as draws, so they still add to the frequency.
+        let white_half_points = (game.white_score().unwrap_or(0.5) * 2.0) as u32;
+
+        for (board, color, played) in positions.into_iter().take(self.options.max_ply) {
+            if self.options.color.map(|only| only != color).unwrap_or(false) || player_color.map(|only| only != color).unwrap_or(false) {
+                continue;
+            }
+            let statistics = self.moves.entry((polyglot_key(&board, color), encode_move(&played, &board))).or_default();
+            statistics.count += 1;
+            statistics.half_points += if color == Color::White { white_half_points } else { 2 - white_half_points };
+        }
+        Ok(())
+    }
+
+    // The book entries sorted by key, and by weight from high to low for each key. The weight of a move
+    // is the points scored with it, so it grows with both results and frequency. Moves that never
+    // scored get no weight and are left out.
+    pub fn entries(&self) -> Vec<BookEntry> {
+        let max_weight = self.moves.values().map(|statistics| statistics.half_points).max().unwrap_or(0);
+        let mut entries: Vec<BookEntry> = self.moves.iter()
+            .filter(|(_, statistics)| statistics.count >= self.options.min_count && statistics.half_points > 0)
+            .map(|((key, raw_move), statistics)| {
+                // Scale down when the weights do not fit in 16 bits, keeping every move playable.
+                let weight = if max_weight > u16::MAX as u32 {
+                    ((statistics.half_points as u64 * u16::MAX as u64 / max_weight as u64) as u16).max(1)
+                } else {
+                    statistics.half_points as u16
+                };
+                BookEntry { key: *key, raw_move: *raw_move, weight, learn: 0 }
+            })
+            .collect();
+        entries.sort_by_key(|entry| (entry.key, std::cmp::Reverse(entry.weight), entry.raw_move));
+        entries
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.entries().into_iter().flat_map(|entry| entry.to_bytes().to_vec()).collect()
+    }
+
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        fs::write(path.as_ref(), self.to_bytes()).map_err(|error| Error::Io(format!("Cannot write {}: {}", path.as_ref().display(), error)))
+    }
+}
@@ -0,0 +1,7 @@
+mod builder;
+mod pgn;
+mod polyglot;
+
+pub use builder::{ BookBuilder, BookBuilderOptions };
+pub use pgn::{ load_pgn, parse_pgn, PgnGame };
+pub use polyglot::{ decode_move, encode_move, polyglot_key, BookEntry, BookSelection, OpeningBook };
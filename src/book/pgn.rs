@@ -0,0 +1,204 @@
+use std::fmt;
+use std::fs;
+use std::path::{ Path };
+
+use crate::board::{ Board, Color, Fen };
+use crate::engine::{ Move };
+use crate::error::{ Error, ParseError, ParseErrorKind };
+
+#[derive(Debug,Clone,PartialEq)]
+pub struct PgnGame {
+    pub tags: Vec<(String, String)>,
+    // The moves in standard algebraic notation, without move numbers, comments and variations.
+    pub moves: Vec<String>,
+    // "1-0", "0-1", "1/2-1/2" or "*".
+    pub result: String,
+}
+
+impl PgnGame {
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter().find(|(tag_name, _)| tag_name == name).map(|(_, value)| value.as_str())
+    }
+
+    // The game result for white: 1.0 for a win, 0.5 for a draw and 0.0 for a loss.
+    pub fn white_score(&self) -> Option<f64> {
+        match self.result.as_str() {
+            "1-0" => Some(1.0),
+            "0-1" => Some(0.0),
+            "1/2-1/2" => Some(0.5),
+            _ => None
+        }
+    }
+
+    // The starting position, from the FEN tag if there is one.
+    pub fn start(&self) -> Result<(Board, Color), Error> {
+        match self.tag("FEN") {
+            Some(fen) => {
+                let fen = Fen::parse(fen)?;
+                Ok((fen.board, fen.color))
+            },
+            None => {
+                let mut board = Board::new();
+                board.setup();
+                Ok((board, Color::White))
+            }
+        }
+    }
+
+    // Every position of the game with the color to move and the move played from it.
+    pub fn positions(&self) -> Result<Vec<(Board, Color, Move)>, Error> {
+        let (mut board, mut color) = self.start()?;
+        let mut positions = vec![];
+        for san in self.moves.iter() {
+            let played = Move::from_san(&board, color, san)?;
+            positions.push((board, color, played));
+            board = played.apply(&board, color).unwrap();
+            color = color.opposite();
+        }
+        Ok(positions)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        fs::write(path.as_ref(), self.to_string()).map_err(|error| Error::Io(format!("Cannot write {}: {}", path.as_ref().display(), error)))
+    }
+}
+
+impl fmt::Display for PgnGame {
+    // Tags, then the moves numbered from the starting position and wrapped at 80 columns.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (name, value) in self.tags.iter() {
+            writeln!(f, "[{} \"{}\"]", name, value.replace('\\', "\\\\").replace('"', "\\\""))?;
+        }
+        writeln!(f)?;
+        let (mut number, mut color) = match self.tag("FEN").map(Fen::parse) {
+            Some(Ok(fen)) => (fen.fullmove_number, fen.color),
+            _ => (1, Color::White)
+        };
+        let mut tokens = vec![];
+        for (index, san) in self.moves.iter().enumerate() {
+            if color == Color::White {
+                tokens.push(format!("{}. {}", number, san));
+            } else if index == 0 {
+                tokens.push(format!("{}... {}", number, san));
+            } else {
+                tokens.push(san.clone());
+            }
+            if color == Color::Black {
+                number += 1;
+            }
+            color = color.opposite();
+        }
+        tokens.push(self.result.clone());
+        let mut line = String::new();
+        for token in tokens {
+            if !line.is_empty() && line.len() + 1 + token.len() > 80 {
+                writeln!(f, "{}", line)?;
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&token);
+        }
+        writeln!(f, "{}", line)
+    }
+}
+
+pub fn load_pgn<P: AsRef<Path>>(path: P) -> Result<Vec<PgnGame>, Error> {
+    let input = fs::read_to_string(path.as_ref()).map_err(|error| Error::Io(format!("Cannot read {}: {}", path.as_ref().display(), error)))?;
+    parse_pgn(&input)
+}
+
+pub fn parse_pgn(input: &str) -> Result<Vec<PgnGame>, Error> {
+    let mut games = vec![];
+    let mut game = PgnGame { tags: vec![], moves: vec![], result: String::from("*") };
+    let mut in_game = false;
+    // Nesting depth of variations, and whether we are inside a brace comment.
+    let mut variation_depth: usize = 0;
+    let mut in_comment = false;
+
+    for line in input.lines() {
+        let trimmed = line.trim();
+        if !in_comment && variation_depth == 0 && trimmed.starts_with('[') {
+            if in_game && !game.moves.is_empty() {
+                // A new tag section without a result ends the previous game.
+                games.push(game);
+                game = PgnGame { tags: vec![], moves: vec![], result: String::from("*") };
+            }
+            in_game = true;
+            game.tags.push(parse_tag(input, trimmed)?);
+            continue;
+        }
+        if trimmed.starts_with('%') {
+            continue;
+        }
+
+        let mut token = String::new();
+        // A trailing space ends the last token of the line.
+        for c in line.chars().chain(std::iter::once(' ')) {
+            if in_comment {
+                in_comment = c != '}';
+                continue;
+            }
+            if !c.is_whitespace() && !"{}();".contains(c) {
+                token.push(c);
+                continue;
+            }
+            if !token.is_empty() && variation_depth == 0 {
+                in_game = true;
+                if add_token(&mut game, &token) {
+                    games.push(game);
+                    game = PgnGame { tags: vec![], moves: vec![], result: String::from("*") };
+                    in_game = false;
+                }
+            }
+            token.clear();
+            match c {
+                '{' => { in_comment = true; },
+                '(' => { variation_depth += 1; },
+                ')' => { variation_depth = variation_depth.saturating_sub(1); },
+                // A comment to the end of the line.
+                ';' => { break; },
+                _ => {}
+            }
+        }
+    }
+    if in_game && (!game.moves.is_empty() || !game.tags.is_empty()) {
+        games.push(game);
+    }
+    Ok(games)
+}
+
+// A tag pair on the line, which is a slice of the input.
+fn parse_tag(input: &str, line: &str) -> Result<(String, String), ParseError> {
+    let inner = line.trim_start_matches('[').trim_end_matches(']').trim();
+    let invalid = |text: &str| ParseError::new(ParseErrorKind::InvalidTag, input, text, format!("Invalid tag: {}", line));
+    let (name, value) = inner.split_once(' ').ok_or_else(|| invalid(line))?;
+    let value = value.trim();
+    if !value.starts_with('"') || !value.ends_with('"') || value.len() < 2 {
+        return Err(invalid(value));
+    }
+    Ok((String::from(name), value[1..value.len() - 1].replace("\\\"", "\"")))
+}
+
+// Add a movetext token to the game. Returns whether it was the result that ends the game.
+fn add_token(game: &mut PgnGame, token: &str) -> bool {
+    match token {
+        "1-0" | "0-1" | "1/2-1/2" | "*" => {
+            game.result = String::from(token);
+            return true;
+        },
+        _ if token.starts_with('$') => {},
+        _ => {
+            // Strip move numbers such as "12." and "12...", which may be glued to the move.
+            let san = match token.find('.') {
+                Some(index) if token[..index].chars().all(|c| c.is_ascii_digit()) => token[index..].trim_start_matches('.'),
+                _ => token
+            };
+            if !san.is_empty() && !san.chars().all(|c| c.is_ascii_digit()) {
+                game.moves.push(String::from(san));
+            }
+        }
+    }
+    false
+}
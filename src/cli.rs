@@ -0,0 +1,264 @@
+use std::convert::{ TryInto };
+use std::io::{ BufRead, Write };
+use std::time::{ Duration, Instant };
+
+use chess::board::{ Board, Color };
+use chess::book::{ load_pgn, BookBuilder, BookBuilderOptions };
+use chess::engine::{ evaluation_breakdown, next_boards, perft, perft_divide, search, validate_position, EvaluationParameters, SearchContext, SearchLimits, SearchOptions };
+use chess::notation::{ Fen, Move, START_POSITION_FEN };
+use chess::protocol::{ run_play, PlayOptions };
+use chess::tuning::{ load_labeled_positions, tune, TunerOptions };
+use chess::{ Error };
+
+const USAGE: &str = "Usage: chess <command> [arguments]
+
+Commands:
+  uci                                  Speak UCI on standard input and output
+  xboard                               Speak CECP (XBoard) on standard input and output
+  play [--color white|black] [--depth <plies>] [--time <seconds>] [--fen <fen>] [--book <file>]
+                                       Play the engine in the terminal
+  perft <fen> <depth> [--divide]       Count the move sequences of the given length
+  eval <fen>                           Evaluate the position term by term
+  bestmove <fen> [--depth <plies>] [--time <seconds>]
+                                       Search for the best move
+  convert [<position>|-] [--to fen|unicode|ascii] [--color white|black]
+                                       Convert between FEN and board diagrams
+  validate <fen>                       Report why the position cannot occur in a game
+  tune <positions> [--weights <file>] [--only <prefix>]... [--iterations <n>] [--step <n>] [--threads <n>] [--to weights|rust]
+                                       Fit the evaluation parameters to positions labeled with results
+  book <games.pgn> <book.bin> [--plies <n>] [--min-count <n>] [--color white|black] [--player <name>]
+                                       Build a Polyglot opening book from the games
+  help                                 Show this message
+
+Positions are FEN strings, or startpos. A position of - or none is read from standard input.";
+
+// The depth for bestmove when neither depth nor time is given.
+const DEFAULT_DEPTH: u8 = 6;
+const MAX_DEPTH: u8 = 64;
+
+// Run one command from the command line, without the program name. Output goes to the output,
+// and errors are returned for the caller to report.
+pub fn run_command(args: &[String], input: &mut dyn BufRead, output: &mut dyn Write) -> Result<(), String> {
+    let write_error = |error: std::io::Error| format!("Cannot write output: {}", error);
+    let (command, args) = args.split_first().ok_or_else(|| String::from(USAGE))?;
+    match command.as_str() {
+        "play" => {
+            let options = PlayOptions::from_args(args)?;
+            run_play(input, output, &options)?;
+            Ok(())
+        },
+        "perft" => {
+            let (positional, flags) = split_flags(args, &["--divide"], &[])?;
+            let [fen, depth] = expect_arguments::<2>(&positional, "perft <fen> <depth> [--divide]")?;
+            let fen = parse_position(fen, input)?;
+            let depth = depth.parse::<u8>().map_err(|_| format!("Invalid depth: {}", depth))?;
+            if flags.iter().any(|(flag, _)| *flag == "--divide") {
+                let counts = perft_divide(&fen.board, fen.color, depth);
+                for (played, count) in counts.iter() {
+                    writeln!(output, "{}: {}", played, count).map_err(write_error)?;
+                }
+                writeln!(output, "\nTotal: {}", counts.iter().map(|(_, count)| count).sum::<u64>()).map_err(write_error)
+            } else {
+                writeln!(output, "{}", perft(&fen.board, fen.color, depth)).map_err(write_error)
+            }
+        },
+        "eval" => {
+            let (positional, _) = split_flags(args, &[], &[])?;
+            let [fen] = expect_arguments::<1>(&positional, "eval <fen>")?;
+            let fen = parse_position(fen, input)?;
+            write!(output, "{}", evaluation_breakdown(&fen.board)).map_err(write_error)
+        },
+        "bestmove" => {
+            let (positional, flags) = split_flags(args, &[], &["--depth", "--time"])?;
+            let [fen] = expect_arguments::<1>(&positional, "bestmove <fen> [--depth <plies>] [--time <seconds>]")?;
+            let fen = parse_position(fen, input)?;
+            let mut depth = None;
+            let mut time = None;
+            for (flag, value) in flags {
+                match flag {
+                    "--depth" => { depth = Some(value.parse::<u8>().ok().filter(|depth| *depth > 0).ok_or_else(|| format!("Invalid depth: {}", value))?); },
+                    _ => {
+                        time = Some(value.parse::<f64>().ok().filter(|seconds| *seconds > 0.0 && seconds.is_finite()).map(Duration::from_secs_f64)
+                            .ok_or_else(|| format!("Invalid time: {}", value))?);
+                    }
+                }
+            }
+            // With only a time, the search deepens until the time is up.
+            let depth = depth.unwrap_or(if time.is_some() { MAX_DEPTH } else { DEFAULT_DEPTH });
+            let mut limits = SearchLimits::new();
+            if let Some(time) = time {
+                limits = limits.with_deadline(Instant::now() + time);
+            }
+            let result = search(&fen.board, fen.color, &SearchOptions::default().with_depth(depth), &mut SearchContext::new().with_limits(limits));
+            let next = result.best_board.or_else(|| next_boards(&fen.board, fen.color).into_iter().next()).ok_or_else(|| String::from("No legal moves"))?;
+            let played = Move::between(&fen.board, &next, fen.color).unwrap();
+            writeln!(output, "{}", played).map_err(write_error)
+        },
+        "convert" => {
+            let (positional, flags) = split_flags(args, &[], &["--to", "--color"])?;
+            let text = match positional.as_slice() {
+                [] | ["-"] => read_input(input)?,
+                [text] => text.to_string(),
+                _ => return Err(String::from("Usage: chess convert [<position>|-] [--to fen|unicode|ascii] [--color white|black]"))
+            };
+            let mut color = Color::White;
+            let mut target = None;
+            for (flag, value) in flags {
+                match flag {
+                    "--to" => { target = Some(value); },
+                    _ => { color = parse_color(value)?; }
+                }
+            }
+            // Diagrams do not say whose move it is, so that comes from --color.
+            let (fen, is_diagram) = if text.contains('|') {
+                (Fen::new(Board::parse_str(&text).map_err(|error| report(error, &text))?, color), true)
+            } else {
+                (parse_position(text.trim(), input)?, false)
+            };
+            let converted = match target.unwrap_or(if is_diagram { "fen" } else { "unicode" }) {
+                "fen" => format!("{}\n", fen),
+                "unicode" => fen.board.to_string(),
+                "ascii" => fen.board.ascii_diagram(),
+                other => return Err(format!("Unknown format: {}, expect fen, unicode or ascii", other))
+            };
+            write!(output, "{}", converted).map_err(write_error)
+        },
+        "validate" => {
+            let (positional, _) = split_flags(args, &[], &[])?;
+            let [fen] = expect_arguments::<1>(&positional, "validate <fen>")?;
+            let fen = parse_position(fen, input)?;
+            let problems = validate_position(&fen);
+            if problems.is_empty() {
+                writeln!(output, "valid").map_err(write_error)
+            } else {
+                Err(problems.join("\n"))
+            }
+        },
+        "tune" => {
+            let usage = "tune <positions> [--weights <file>] [--only <prefix>]... [--iterations <n>] [--step <n>] [--threads <n>] [--to weights|rust]";
+            let (positional, flags) = split_flags(args, &[], &["--weights", "--only", "--iterations", "--step", "--threads", "--to"])?;
+            let [path] = expect_arguments::<1>(&positional, usage)?;
+            let positions = load_labeled_positions(path).map_err(|error| error.to_string())?;
+            let mut initial = EvaluationParameters::default();
+            let mut options = TunerOptions::default();
+            let mut target = "weights";
+            for (flag, value) in flags {
+                let number = || value.parse::<usize>().ok().filter(|number| *number > 0).ok_or_else(|| format!("Invalid value for {}: {}", flag, value));
+                match flag {
+                    "--weights" => {
+                        let text = std::fs::read_to_string(value).map_err(|error| format!("Cannot read {}: {}", value, error))?;
+                        initial = EvaluationParameters::parse_weights(&text).map_err(|error| report(error, &text))?;
+                    },
+                    "--only" => { options.only.push(value.to_string()); },
+                    "--iterations" => { options.max_iterations = number()?; },
+                    "--step" => { options.step = number()? as i32; },
+                    "--threads" => { options.threads = number()?; },
+                    _ => { target = value; }
+                }
+            }
+            let result = tune(&positions, &initial, &options);
+            // The summary goes in a comment, so the output can be loaded as it is.
+            let summary = format!("Error {:.6} -> {:.6} after {} iterations over {} positions, scaling constant {:.3}",
+                result.initial_error, result.error, result.iterations, positions.len(), result.scaling_constant);
+            let tuned = match target {
+                "weights" => format!("# {}\n{}", summary, result.parameters.to_weights()),
+                "rust" => format!("// {}\n{}", summary, result.parameters.to_rust_source("TUNED_PARAMETERS")),
+                other => return Err(format!("Unknown format: {}, expect weights or rust", other))
+            };
+            write!(output, "{}", tuned).map_err(write_error)
+        },
+        "book" => {
+            let usage = "book <games.pgn> <book.bin> [--plies <n>] [--min-count <n>] [--color white|black] [--player <name>]";
+            let (positional, flags) = split_flags(args, &[], &["--plies", "--min-count", "--color", "--player"])?;
+            let [games_path, book_path] = expect_arguments::<2>(&positional, usage)?;
+            let mut options = BookBuilderOptions::default();
+            for (flag, value) in flags {
+                let number = || value.parse::<usize>().ok().filter(|number| *number > 0).ok_or_else(|| format!("Invalid value for {}: {}", flag, value));
+                match flag {
+                    "--plies" => { options.max_ply = number()?; },
+                    "--min-count" => { options.min_count = number()?.try_into().map_err(|_| format!("Invalid value for {}: {}", flag, value))?; },
+                    "--color" => { options.color = Some(parse_color(value)?); },
+                    _ => { options.player = Some(value.to_string()); }
+                }
+            }
+            let games = load_pgn(games_path).map_err(|error| error.to_string())?;
+            let mut builder = BookBuilder::new(options);
+            // A game with an illegal move is skipped and counted, so one bad game does not lose the book.
+            for game in games.iter() {
+                let _ = builder.add_game(game);
+            }
+            builder.write(book_path).map_err(|error| error.to_string())?;
+            writeln!(output, "{} entries from {} games, {} skipped", builder.entries().len(), builder.games, builder.skipped_games).map_err(write_error)
+        },
+        "help" | "--help" | "-h" => writeln!(output, "{}", USAGE).map_err(write_error),
+        other => Err(format!("Unknown command: {}\n\n{}", other, USAGE))
+    }
+}
+
+// Flags and their values, empty for flags without one.
+type Flags<'a> = Vec<(&'a str, &'a str)>;
+
+// Split the arguments into positional ones and flags, with or without a value.
+fn split_flags<'a>(args: &'a [String], switches: &[&str], with_values: &[&str]) -> Result<(Vec<&'a str>, Flags<'a>), String> {
+    let mut positional = vec![];
+    let mut flags = vec![];
+    let mut index = 0;
+    while index < args.len() {
+        let arg = args[index].as_str();
+        if switches.contains(&arg) {
+            flags.push((arg, ""));
+        } else if with_values.contains(&arg) {
+            index += 1;
+            let value = args.get(index).ok_or_else(|| format!("Expect a value after {}", arg))?;
+            flags.push((arg, value.as_str()));
+        } else if arg.starts_with("--") {
+            return Err(format!("Unknown option: {}", arg));
+        } else {
+            positional.push(arg);
+        }
+        index += 1;
+    }
+    Ok((positional, flags))
+}
+
+// Exactly the expected number of positional arguments. A position that is the only argument
+// can be left out, to be read from the input.
+fn expect_arguments<'a, const N: usize>(positional: &[&'a str], usage: &str) -> Result<[&'a str; N], String> {
+    let mut arguments = positional.to_vec();
+    if arguments.is_empty() && N == 1 {
+        arguments.push("-");
+    }
+    arguments.try_into().map_err(|_| format!("Usage: chess {}", usage))
+}
+
+// A FEN, startpos, or - to read the FEN from the input.
+fn parse_position(text: &str, input: &mut dyn BufRead) -> Result<Fen, String> {
+    let text = match text {
+        "startpos" => String::from(START_POSITION_FEN),
+        "-" => read_input(input)?.trim().to_string(),
+        fen => fen.to_string()
+    };
+    Fen::parse(&text).map_err(|error| report(error, &text))
+}
+
+// The error for the user, with parse errors shown in the text they were found in.
+fn report(error: Error, text: &str) -> String {
+    match error {
+        Error::Parse(error) => error.snippet(text),
+        error => error.to_string()
+    }
+}
+
+fn parse_color(text: &str) -> Result<Color, String> {
+    match text {
+        "white" | "w" => Ok(Color::White),
+        "black" | "b" => Ok(Color::Black),
+        _ => Err(format!("Invalid color: {}", text))
+    }
+}
+
+fn read_input(input: &mut dyn BufRead) -> Result<String, String> {
+    let mut text = String::new();
+    input.read_to_string(&mut text).map_err(|error| format!("Cannot read input: {}", error))?;
+    Ok(text)
+}
@@ -61,10 +61,61 @@ pub fn is_threatened_by(board: &Board, coordinates: Coordinates, color: Color) -
 }
 
 pub fn is_checked(board: &Board, color: Color) -> bool {
-    if let Some((king_index, _)) = board.squares().iter().enumerate().find(|(_, square)| square.is_occupied_by(Piece(color, Kind::King))) {
-        if let Some(king_coordinates) = Coordinates::new_from_index(king_index) {
-            return is_threatened_by(&board, king_coordinates, color.opposite())
-        }
+    if let Some(king_coordinates) = find_king(board, color) {
+        return is_threatened_by(&board, king_coordinates, color.opposite())
     }
     false
 }
+
+// The squares attacked by the piece on the given coordinates, including those occupied by either color,
+// as a mask with a bit per square index.
+pub fn attacked_squares(board: &Board, coordinates: Coordinates, piece: Piece) -> u64 {
+    fn step(coordinates: Coordinates, offsets: &[(i8, i8)]) -> u64 {
+        offsets.iter()
+            .filter_map(|(file_offset, rank_offset)| coordinates.offset(*file_offset, *rank_offset))
+            .fold(0, |attacked, target| attacked | 1 << target.index())
+    }
+
+    fn slide(board: &Board, coordinates: Coordinates, offsets: &[(i8, i8)]) -> u64 {
+        let mut attacked = 0;
+        for (file_offset, rank_offset) in offsets {
+            for target in coordinates.offsets_repeated(*file_offset, *rank_offset) {
+                attacked |= 1 << target.index();
+                if !board.get_square(target).is_empty() {
+                    // Blocked from here on out.
+                    break
+                }
+            }
+        }
+        attacked
+    }
+
+    match piece.1 {
+        Kind::Pawn => {
+            let rank_offset = if piece.0 == Color::White { 1 } else { -1 };
+            step(coordinates, &[(-1, rank_offset), (1, rank_offset)])
+        },
+        Kind::Knight => step(coordinates, &KNIGHT_OFFSETS),
+        Kind::King => step(coordinates, &KING_OFFSETS),
+        Kind::Bishop => slide(board, coordinates, &BISHOP_OFFSETS),
+        Kind::Rook => slide(board, coordinates, &ROOK_OFFSETS),
+        Kind::Queen => slide(board, coordinates, &BISHOP_OFFSETS) | slide(board, coordinates, &ROOK_OFFSETS)
+    }
+}
+
+// The coordinates of the squares in a mask, by square index.
+pub fn mask_coordinates(mask: u64) -> impl Iterator<Item = Coordinates> {
+    let mut remaining = mask;
+    std::iter::from_fn(move || {
+        if remaining == 0 {
+            return None;
+        }
+        let index = remaining.trailing_zeros() as usize;
+        remaining &= remaining - 1;
+        Coordinates::new_from_index(index)
+    })
+}
+
+pub fn find_king(board: &Board, color: Color) -> Option<Coordinates> {
+    board.squares().iter().position(|square| square.is_occupied_by(Piece(color, Kind::King))).and_then(Coordinates::new_from_index)
+}
@@ -0,0 +1,147 @@
+use crate::board::{ Board, Color, Kind, Piece, Square };
+use crate::engine::parameters::{ EvaluationParameters };
+use crate::engine::pawns::{ PawnHashTable };
+use crate::engine::score::{ score_board_with };
+
+const PAWN_HASH_SIZE: usize = 1 << 14;
+
+// An evaluation function the search can use. Scores are in centipawns, positive being good for white.
+pub trait Evaluator {
+    fn evaluate(&mut self, board: &Board) -> i32;
+
+    // Called when a search starts from a new root board, so incremental state can be rebuilt from scratch.
+    fn reset(&mut self, _root: &Board) {}
+
+    // Called when the search steps from a board to one of its children, and when it steps back.
+    // Evaluators that keep incremental state update it here. Calls are always nested in pairs.
+    fn make(&mut self, _parent: &Board, _child: &Board) {}
+    fn unmake(&mut self, _parent: &Board, _child: &Board) {}
+
+    // A fresh evaluator of the same kind for another search thread, if this one can be copied.
+    fn fork(&self) -> Option<Box<dyn Evaluator + Send>> {
+        None
+    }
+}
+
+impl<E: Evaluator + ?Sized> Evaluator for Box<E> {
+    fn evaluate(&mut self, board: &Board) -> i32 {
+        (**self).evaluate(board)
+    }
+
+    fn reset(&mut self, root: &Board) {
+        (**self).reset(root)
+    }
+
+    fn make(&mut self, parent: &Board, child: &Board) {
+        (**self).make(parent, child)
+    }
+
+    fn unmake(&mut self, parent: &Board, child: &Board) {
+        (**self).unmake(parent, child)
+    }
+
+    fn fork(&self) -> Option<Box<dyn Evaluator + Send>> {
+        (**self).fork()
+    }
+}
+
+// Counts material only: pawn 100, knight and bishop 300, rook 500, queen 900.
+#[derive(Debug,Copy,PartialEq,Eq,Clone,Hash,Default)]
+pub struct MaterialEvaluator;
+
+impl MaterialEvaluator {
+    pub fn kind_value(kind: Kind) -> i32 {
+        match kind {
+            Kind::Pawn => 100,
+            Kind::Knight => 300,
+            Kind::Bishop => 300,
+            Kind::Rook => 500,
+            Kind::Queen => 900,
+            Kind::King => 0
+        }
+    }
+}
+
+impl Evaluator for MaterialEvaluator {
+    fn evaluate(&mut self, board: &Board) -> i32 {
+        board.squares().iter().map(|square| match square {
+            Square::Occupied(Piece(Color::White, kind)) => MaterialEvaluator::kind_value(*kind),
+            Square::Occupied(Piece(Color::Black, kind)) => -MaterialEvaluator::kind_value(*kind),
+            Square::Empty => 0
+        }).sum()
+    }
+
+    fn fork(&self) -> Option<Box<dyn Evaluator + Send>> {
+        Some(Box::new(MaterialEvaluator))
+    }
+}
+
+// The full positional evaluation of `score_board`, with its own pawn hash table and parameters.
+pub struct PositionalEvaluator {
+    pub parameters: EvaluationParameters,
+    pub(crate) pawn_hash_table: PawnHashTable,
+}
+
+impl PositionalEvaluator {
+    pub fn new() -> PositionalEvaluator {
+        PositionalEvaluator::with_parameters(EvaluationParameters::default())
+    }
+
+    pub fn with_parameters(parameters: EvaluationParameters) -> PositionalEvaluator {
+        PositionalEvaluator {
+            parameters,
+            pawn_hash_table: PawnHashTable::new(PAWN_HASH_SIZE),
+        }
+    }
+}
+
+impl Default for PositionalEvaluator {
+    fn default() -> PositionalEvaluator {
+        PositionalEvaluator::new()
+    }
+}
+
+impl Evaluator for PositionalEvaluator {
+    fn evaluate(&mut self, board: &Board) -> i32 {
+        let pawn_structure = self.pawn_hash_table.probe(board);
+        score_board_with(board, &pawn_structure, &self.parameters)
+    }
+
+    fn fork(&self) -> Option<Box<dyn Evaluator + Send>> {
+        Some(Box::new(PositionalEvaluator::with_parameters(self.parameters)))
+    }
+}
+
+// Scores every board as equal. Useful for testing the search in isolation.
+#[derive(Debug,Copy,PartialEq,Eq,Clone,Hash,Default)]
+pub struct ZeroEvaluator;
+
+impl Evaluator for ZeroEvaluator {
+    fn evaluate(&mut self, _board: &Board) -> i32 {
+        0
+    }
+
+    fn fork(&self) -> Option<Box<dyn Evaluator + Send>> {
+        Some(Box::new(ZeroEvaluator))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::board::{ Board, Coordinates, File, Rank };
+    use crate::engine::score::{ score_board };
+    use super::{ Evaluator, PositionalEvaluator };
+
+    #[test]
+    fn test_pawn_hash_table() {
+        // The second lookup of the same pawn structure is served from the evaluator's cache, and
+        // scores the same as without it.
+        let mut start = Board::new();
+        start.setup();
+        let knight_out = start.clone_move_piece(Coordinates::new(File::G, Rank::R1), Coordinates::new(File::F, Rank::R3));
+        let mut evaluator = PositionalEvaluator::new();
+        assert_eq!(score_board(&start), evaluator.evaluate(&start));
+        assert_eq!(score_board(&knight_out), evaluator.evaluate(&knight_out));
+        assert_eq!((1, 1), (evaluator.pawn_hash_table.hits, evaluator.pawn_hash_table.misses));
+    }
+}
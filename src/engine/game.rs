@@ -0,0 +1,47 @@
+use crate::board::{ Board, Color };
+use crate::engine::analysis::{ is_checked };
+use crate::engine::moves::{ next_boards };
+use crate::error::{ Error };
+
+#[derive(Debug,Copy,PartialEq,Clone,Hash)]
+pub enum GameOutcome {
+    Win(Color),
+    Draw
+}
+
+#[derive(Debug,Clone,PartialEq)]
+pub struct GameRecord {
+    // The board after every move.
+    pub boards: Vec<Board>,
+    pub outcome: GameOutcome,
+}
+
+// Play a game between two ways of choosing moves, such as two searches to compare. The game ends
+// in mate, stalemate, a threefold repetition or, as a draw, after the given number of plies.
+pub fn play_game(board: &Board, color: Color, max_plies: usize, white: &mut dyn FnMut(&Board, Color) -> Option<Board>,
+        black: &mut dyn FnMut(&Board, Color) -> Option<Board>) -> Result<GameRecord, Error> {
+    let (mut board, mut color) = (*board, color);
+    let mut seen = vec![(board, color)];
+    let mut boards = vec![];
+    loop {
+        let children = next_boards(&board, color);
+        if children.is_empty() {
+            let outcome = if is_checked(&board, color) { GameOutcome::Win(color.opposite()) } else { GameOutcome::Draw };
+            return Ok(GameRecord { boards, outcome });
+        }
+        if boards.len() >= max_plies {
+            break;
+        }
+        let choice = if color == Color::White { white(&board, color) } else { black(&board, color) };
+        let next = choice.filter(|next| children.contains(next))
+            .ok_or_else(|| Error::IllegalMove(format!("No legal move chosen for {:?} after {} plies", color, boards.len())))?;
+        board = next;
+        color = color.opposite();
+        boards.push(board);
+        seen.push((board, color));
+        if seen.iter().filter(|position| **position == (board, color)).count() >= 3 {
+            break;
+        }
+    }
+    Ok(GameRecord { boards, outcome: GameOutcome::Draw })
+}
@@ -0,0 +1,134 @@
+use crate::board::{ Board, Color, Coordinates, File, Kind, Piece, Square };
+use crate::engine::analysis::{ attacked_squares, find_king };
+use crate::engine::moves::{ KING_OFFSETS };
+use crate::engine::score::{ TaperedScore };
+
+#[derive(Debug,Copy,PartialEq,Eq,Clone,Hash)]
+pub struct KingSafetyWeights {
+    // Per own pawn directly in front of the king, or one square further, on the king's file or an adjacent one.
+    pub shield_close: TaperedScore,
+    pub shield_far: TaperedScore,
+    // Per file next to or at the king without own pawns, and additionally without enemy pawns.
+    pub semi_open_file: TaperedScore,
+    pub open_file: TaperedScore,
+    // Attack units per square in the king zone attacked by each kind of piece.
+    pub knight_attack: i32,
+    pub bishop_attack: i32,
+    pub rook_attack: i32,
+    pub queen_attack: i32,
+    // Percentage of the attack units that counts, by number of attacking pieces. Lone attackers are mostly harmless.
+    pub attacker_scale: [i32; 8],
+}
+
+pub const DEFAULT_KING_SAFETY_WEIGHTS: KingSafetyWeights = KingSafetyWeights {
+    shield_close: TaperedScore::new(12, 0),
+    shield_far: TaperedScore::new(6, 0),
+    semi_open_file: TaperedScore::new(-12, 0),
+    open_file: TaperedScore::new(-18, -4),
+    knight_attack: 8,
+    bishop_attack: 6,
+    rook_attack: 10,
+    queen_attack: 14,
+    attacker_scale: [0, 10, 50, 75, 88, 94, 97, 100],
+};
+
+impl Default for KingSafetyWeights {
+    fn default() -> KingSafetyWeights {
+        DEFAULT_KING_SAFETY_WEIGHTS
+    }
+}
+
+impl KingSafetyWeights {
+    fn attack_units(&self, kind: Kind) -> i32 {
+        match kind {
+            Kind::Knight => self.knight_attack,
+            Kind::Bishop => self.bishop_attack,
+            Kind::Rook => self.rook_attack,
+            Kind::Queen => self.queen_attack,
+            Kind::Pawn | Kind::King => 0
+        }
+    }
+}
+
+// The king safety score, positive being good for white.
+pub fn king_safety_score(board: &Board, weights: &KingSafetyWeights) -> TaperedScore {
+    king_safety(board, Color::White, weights) - king_safety(board, Color::Black, weights)
+}
+
+// The king safety of one color, positive being good for that color.
+pub fn king_safety(board: &Board, color: Color, weights: &KingSafetyWeights) -> TaperedScore {
+    let king = match find_king(board, color) {
+        Some(king) => king,
+        None => { return TaperedScore::default(); }
+    };
+    let forward = if color == Color::White { 1 } else { -1 };
+    let mut score = TaperedScore::default();
+
+    // Pawn shield and open files around the king.
+    for file_offset in -1..=1 {
+        if king.offset(file_offset, 0).is_none() {
+            continue;
+        }
+        if let Some(close) = king.offset(file_offset, forward) {
+            if board.get_square(close).is_occupied_by(Piece(color, Kind::Pawn)) {
+                score += weights.shield_close;
+            } else if let Some(far) = king.offset(file_offset, 2 * forward) {
+                if board.get_square(far).is_occupied_by(Piece(color, Kind::Pawn)) {
+                    score += weights.shield_far;
+                }
+            }
+        }
+
+        let file = File::new_from_index((king.file().index() as i8 + file_offset) as u8).unwrap();
+        let (own_pawns, enemy_pawns) = pawns_on_file(board, file, color);
+        if !own_pawns {
+            score += weights.semi_open_file;
+            if !enemy_pawns {
+                score += weights.open_file;
+            }
+        }
+    }
+
+    // Enemy pieces attacking the squares around the king and in front of it.
+    let mut zone = 1u64 << king.index();
+    for square in king.offsets_filter(&KING_OFFSETS) {
+        zone |= 1 << square.index();
+        if let Some(in_front) = square.offset(0, forward) {
+            zone |= 1 << in_front.index();
+        }
+    }
+
+    let mut attackers = 0;
+    let mut attack_units = 0;
+    for (coordinates, square) in board.squares_coordinates_iter() {
+        if let Square::Occupied(piece) = *square {
+            if piece.0 == color || weights.attack_units(piece.1) == 0 {
+                continue;
+            }
+            let zone_attacks = (attacked_squares(board, coordinates, piece) & zone).count_ones() as i32;
+            if zone_attacks > 0 {
+                attackers += 1;
+                attack_units += zone_attacks * weights.attack_units(piece.1);
+            }
+        }
+    }
+    let scale = weights.attacker_scale[attackers.min(weights.attacker_scale.len() - 1)];
+    let penalty = attack_units * scale / 100;
+    score += TaperedScore::new(-penalty, -penalty / 4);
+
+    score
+}
+
+fn pawns_on_file(board: &Board, file: File, color: Color) -> (bool, bool) {
+    let mut own = false;
+    let mut enemy = false;
+    for rank in 0..8 {
+        let coordinates = Coordinates::new_from_index(rank * 8 + file.index() as usize).unwrap();
+        match board.get_square(coordinates) {
+            Square::Occupied(Piece(piece_color, Kind::Pawn)) if piece_color == color => { own = true; },
+            Square::Occupied(Piece(_, Kind::Pawn)) => { enemy = true; },
+            _ => {}
+        }
+    }
+    (own, enemy)
+}
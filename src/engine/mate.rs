@@ -0,0 +1,123 @@
+use std::collections::{ HashMap };
+
+use crate::board::{ Board, Color };
+use crate::engine::analysis::{ is_checked };
+use crate::engine::moves::{ next_boards };
+use crate::engine::zobrist::{ position_hash };
+
+// A move of the attacker that forces mate, and how the mate follows against every defence.
+#[derive(Debug,Clone,PartialEq)]
+pub struct MateLine {
+    // The board after the attacker's move.
+    pub board: Board,
+    // The attacker's moves to mate against the best defence, this one included.
+    pub moves: u8,
+    // Every reply of the defender, with the attacker's fastest mate after it. Empty when the
+    // move mates.
+    pub replies: Vec<(Board, MateLine)>,
+}
+
+impl MateLine {
+    // The number of positions in the tree, counting both sides' moves.
+    pub fn size(&self) -> usize {
+        1 + self.replies.iter().map(|(_, line)| 1 + line.size()).sum::<usize>()
+    }
+}
+
+// Every move of the color to move that forces mate in at most the given number of moves, with
+// the fastest mates first. Only the attacker's moves are chosen: the defender's replies are all
+// tried, so the result proves the mate rather than estimating it.
+pub fn solve_mate(board: &Board, color: Color, moves: u8) -> Vec<MateLine> {
+    let mut solver = MateSolver { attacker: color, proofs: HashMap::new() };
+    let mut lines: Vec<MateLine> = solver.ordered_children(board, color, moves).into_iter()
+        .filter_map(|child| (1..=moves).find(|moves| solver.forces_mate(&child, *moves)).map(|moves| solver.line(child, moves)))
+        .collect();
+    // A stable sort keeps the move order for mates of equal length.
+    lines.sort_by_key(|line| line.moves);
+    lines
+}
+
+// What is known about the attacker's position: mates found and depths searched in vain.
+#[derive(Debug,Copy,Clone)]
+struct Proof {
+    board: Board,
+    mate_in: Option<u8>,
+    no_mate_in: u8,
+}
+
+struct MateSolver {
+    attacker: Color,
+    // Mating nets and failed attempts, by position hash.
+    proofs: HashMap<u64, Proof>,
+}
+
+impl MateSolver {
+    // Whether the attacker, to move, mates in at most the given number of moves.
+    fn mates(&mut self, board: &Board, moves: u8) -> bool {
+        if moves == 0 {
+            return false;
+        }
+        let hash = position_hash(board, self.attacker);
+        if let Some(proof) = self.proofs.get(&hash).filter(|proof| proof.board == *board) {
+            if proof.mate_in.is_some_and(|mate_in| mate_in <= moves) {
+                return true;
+            }
+            if moves <= proof.no_mate_in {
+                return false;
+            }
+        }
+
+        let mates = self.ordered_children(board, self.attacker, moves).iter().any(|child| self.forces_mate(child, moves));
+        let proof = self.proofs.entry(hash).or_insert(Proof { board: *board, mate_in: None, no_mate_in: 0 });
+        if proof.board != *board {
+            *proof = Proof { board: *board, mate_in: None, no_mate_in: 0 };
+        }
+        if mates {
+            proof.mate_in = Some(proof.mate_in.map_or(moves, |mate_in| mate_in.min(moves)));
+        } else {
+            proof.no_mate_in = proof.no_mate_in.max(moves);
+        }
+        mates
+    }
+
+    // Whether the attacker's move leaves the defender mated, or mated within the moves left
+    // whatever the reply.
+    fn forces_mate(&mut self, child: &Board, moves: u8) -> bool {
+        let defender = self.attacker.opposite();
+        let replies = next_boards(child, defender);
+        if replies.is_empty() {
+            return is_checked(child, defender);
+        }
+        moves > 1 && replies.iter().all(|reply| self.mates(reply, moves - 1))
+    }
+
+    // Checks first, as they are the likeliest to mate. With one move left only checks can.
+    fn ordered_children(&self, board: &Board, color: Color, moves: u8) -> Vec<Board> {
+        let (checks, quiet): (Vec<Board>, Vec<Board>) = next_boards(board, color).into_iter()
+            .partition(|child| is_checked(child, color.opposite()));
+        if moves <= 1 {
+            return checks;
+        }
+        checks.into_iter().chain(quiet).collect()
+    }
+
+    // The tree of a move known to force mate in the given number of moves, with the fastest
+    // mate after every reply. As the move forces mate, every reply is mated in fewer moves, and
+    // the attacker has a move that starts that mate: a reply left out would make the tree lie.
+    fn line(&mut self, child: Board, moves: u8) -> MateLine {
+        debug_assert!(self.forces_mate(&child, moves), "The move does not force mate in {}", moves);
+        let defender = self.attacker.opposite();
+        let mut replies = vec![];
+        let mut longest = 0;
+        for reply in next_boards(&child, defender) {
+            let remaining = (1..moves).find(|remaining| self.mates(&reply, *remaining))
+                .expect("Every reply to a forced mate is mated in fewer moves");
+            let continuation = self.ordered_children(&reply, self.attacker, remaining).into_iter()
+                .find(|next| self.forces_mate(next, remaining))
+                .expect("A position with a mate in some moves has a move that forces it");
+            longest = longest.max(remaining);
+            replies.push((reply, self.line(continuation, remaining)));
+        }
+        MateLine { board: child, moves: longest + 1, replies }
+    }
+}
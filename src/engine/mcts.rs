@@ -0,0 +1,462 @@
+use std::path::{ Path };
+
+use crate::board::{ Board, CastlingSide, Color, Coordinates, Kind, Piece, Square, ALL_KINDS };
+use crate::engine::analysis::{ is_checked };
+use crate::engine::evaluator::{ Evaluator };
+use crate::engine::moves::{ has_legal_move, next_boards };
+use crate::error::{ Error };
+use crate::util::{ Encode, GrowTree };
+
+// Centipawns that make a win about 10 times likelier than a loss, for turning scores into values.
+const VALUE_SCALE: f64 = 400.0;
+// Centipawns per unit of the softmax that turns child scores into priors.
+const PRIOR_TEMPERATURE: f64 = 100.0;
+// When a full tree needs room, this fraction of it is freed beyond what is needed, so it is not
+// searched for nodes to release again on the next expansion.
+const EXTRA_ROOM_FRACTION: usize = 16;
+
+// Saved nodes hold the board in 34 bytes, then the visits, the value sum and the prior.
+const ENCODED_NODE_LENGTH: usize = 54;
+// The bits of the castling rights in saved nodes.
+const CASTLING_RIGHTS: [(Color, CastlingSide); 4] = [(Color::White, CastlingSide::King), (Color::White, CastlingSide::Queen),
+    (Color::Black, CastlingSide::King), (Color::Black, CastlingSide::Queen)];
+
+#[derive(Debug,Copy,PartialEq,Eq,Clone,Hash)]
+pub enum SelectionPolicy {
+    // Upper confidence bounds: every move is tried once before any is tried twice.
+    Uct,
+    // Upper confidence bounds weighted by priors, which come from the evaluator.
+    Puct
+}
+
+#[derive(Debug,Copy,PartialEq,Eq,Clone,Hash)]
+pub enum LeafEvaluation {
+    // Play random moves from the leaf.
+    RandomPlayout,
+    // Play the move the evaluator likes best, with random ties.
+    HeuristicPlayout,
+    // Take the evaluator's score of the leaf.
+    ValueFunction
+}
+
+// Made with `default` and the `with_` methods, so fields can be added.
+#[derive(Debug,Copy,PartialEq,Clone)]
+#[non_exhaustive]
+pub struct MctsOptions {
+    pub iterations: u32,
+    pub selection: SelectionPolicy,
+    // How much unexplored moves are favored over moves that did well.
+    pub exploration: f64,
+    pub leaf_evaluation: LeafEvaluation,
+    // Playouts that reach no mate or stalemate in this many plies are scored by the evaluator.
+    pub playout_plies: u32,
+    pub seed: u64,
+    // The most nodes the tree may hold. When full, the least visited subtrees are released to
+    // make room, their roots keeping their statistics.
+    pub max_nodes: Option<usize>,
+}
+
+impl Default for MctsOptions {
+    fn default() -> MctsOptions {
+        MctsOptions {
+            iterations: 1000,
+            selection: SelectionPolicy::Puct,
+            exploration: 1.5,
+            leaf_evaluation: LeafEvaluation::ValueFunction,
+            playout_plies: 40,
+            seed: 1,
+            max_nodes: None,
+        }
+    }
+}
+
+impl MctsOptions {
+    pub fn with_iterations(mut self, iterations: u32) -> MctsOptions {
+        self.iterations = iterations;
+        self
+    }
+
+    pub fn with_selection(mut self, selection: SelectionPolicy) -> MctsOptions {
+        self.selection = selection;
+        self
+    }
+
+    pub fn with_exploration(mut self, exploration: f64) -> MctsOptions {
+        self.exploration = exploration;
+        self
+    }
+
+    pub fn with_leaf_evaluation(mut self, leaf_evaluation: LeafEvaluation) -> MctsOptions {
+        self.leaf_evaluation = leaf_evaluation;
+        self
+    }
+
+    pub fn with_playout_plies(mut self, playout_plies: u32) -> MctsOptions {
+        self.playout_plies = playout_plies;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> MctsOptions {
+        self.seed = seed;
+        self
+    }
+
+    pub fn with_max_nodes(mut self, max_nodes: usize) -> MctsOptions {
+        self.max_nodes = Some(max_nodes);
+        self
+    }
+}
+
+// A position in the search tree. Values are between 0 for a loss and 1 for a win, for the side
+// that moved into the position.
+#[derive(Debug,Copy,PartialEq,Clone)]
+pub struct MctsNode {
+    pub board: Board,
+    // The side to move.
+    pub color: Color,
+    pub visits: u32,
+    pub value_sum: f64,
+    pub prior: f64,
+    expanded: bool,
+    // The value for the side to move when the game is over: mate or stalemate.
+    terminal: Option<f64>,
+}
+
+impl MctsNode {
+    fn new(board: Board, color: Color, prior: f64) -> MctsNode {
+        let terminal = terminal_value(&board, color);
+        MctsNode { board, color, visits: 0, value_sum: 0.0, prior, expanded: terminal.is_some(), terminal }
+    }
+
+    // Whether the side that moved into the position has mated.
+    fn is_mate(&self) -> bool {
+        self.terminal == Some(0.0)
+    }
+
+    pub fn mean_value(&self) -> f64 {
+        if self.visits == 0 { 0.5 } else { self.value_sum / self.visits as f64 }
+    }
+}
+
+#[derive(Debug)]
+pub struct MctsResult {
+    pub best_board: Option<Board>,
+    // The most visited path from the root.
+    pub principal_variation: Vec<Board>,
+    // The expected result for the side to move at the root, from 0 for a loss to 1 for a win.
+    pub value: f64,
+    pub tree: GrowTree<MctsNode>,
+}
+
+// Monte Carlo tree search: grow a tree by repeatedly selecting a path, expanding its leaf,
+// evaluating it and backing the value up. The most visited move is played.
+pub fn mcts_search(board: &Board, color: Color, options: &MctsOptions, evaluator: &mut dyn Evaluator) -> MctsResult {
+    mcts_search_from(GrowTree::new(MctsNode::new(*board, color, 1.0)), options, evaluator)
+}
+
+// Continue growing a tree from an earlier search, such as one rerooted at the move played or
+// loaded from disk.
+pub fn mcts_search_from(tree: GrowTree<MctsNode>, options: &MctsOptions, evaluator: &mut dyn Evaluator) -> MctsResult {
+    evaluator.reset(&tree.root().board);
+    let tree = match options.max_nodes {
+        Some(max_nodes) => tree.with_max_nodes(max_nodes),
+        None => tree
+    };
+    let mut search = Mcts { options: *options, evaluator, random_state: options.seed | 1, tree };
+    for _ in 0..options.iterations {
+        search.iterate();
+    }
+
+    let mut principal_variation = vec![];
+    let mut index = 0;
+    while let Some(child) = most_visited_child(&search.tree, index) {
+        principal_variation.push(search.tree.item(child).unwrap().board);
+        index = child;
+    }
+    let value = most_visited_child(&search.tree, 0).map(|child| search.tree.item(child).unwrap().mean_value())
+        .or_else(|| search.tree.root().terminal)
+        .unwrap_or(0.5);
+    MctsResult { best_board: principal_variation.first().copied(), principal_variation, value, tree: search.tree }
+}
+
+fn most_visited_child(tree: &GrowTree<MctsNode>, index: usize) -> Option<usize> {
+    if let Some((child, _)) = tree.children(index).find(|(_, node)| node.is_mate()) {
+        return Some(child);
+    }
+    tree.children(index)
+        .filter(|(_, node)| node.visits > 0)
+        .fold(None, |best: Option<(usize, u32)>, (child, node)| match best {
+            Some((_, visits)) if visits >= node.visits => best,
+            _ => Some((child, node.visits))
+        })
+        .map(|(child, _)| child)
+}
+
+struct Mcts<'a> {
+    options: MctsOptions,
+    evaluator: &'a mut dyn Evaluator,
+    random_state: u64,
+    tree: GrowTree<MctsNode>,
+}
+
+impl<'a> Mcts<'a> {
+    fn iterate(&mut self) {
+        let mut path = vec![0];
+        let mut index = 0;
+        while let Some(child) = self.select(index) {
+            path.push(child);
+            index = child;
+        }
+        let node = *self.tree.item(index).unwrap();
+        if !node.expanded {
+            let children = next_boards(&node.board, node.color);
+            if self.make_room(children.len(), &path) {
+                self.expand(index, children);
+                if let Some(child) = self.select(index) {
+                    path.push(child);
+                    index = child;
+                }
+            }
+        }
+
+        // The value for the side to move at the leaf, then for the side that moved into each node.
+        let leaf = *self.tree.item(index).unwrap();
+        let mut value = match leaf.terminal {
+            Some(value) => value,
+            None => self.evaluate_leaf(&leaf)
+        };
+        for index in path.into_iter().rev() {
+            value = 1.0 - value;
+            let node = self.tree.item_mut(index).unwrap();
+            node.visits += 1;
+            node.value_sum += value;
+        }
+    }
+
+    // The child to walk to, if the node has been expanded and has children. Mates are always
+    // walked to, as no other move can be better.
+    fn select(&self, index: usize) -> Option<usize> {
+        let node = self.tree.item(index).unwrap();
+        if !node.expanded {
+            return None;
+        }
+        if let Some((child, _)) = self.tree.children(index).find(|(_, child)| child.is_mate()) {
+            return Some(child);
+        }
+        let exploration = self.options.exploration;
+        let parent_visits = node.visits.max(1) as f64;
+        let score = |child: &MctsNode| match self.options.selection {
+            SelectionPolicy::Uct if child.visits == 0 => f64::INFINITY,
+            SelectionPolicy::Uct => child.mean_value() + exploration * (parent_visits.ln() / child.visits as f64).sqrt(),
+            SelectionPolicy::Puct => child.mean_value() + exploration * child.prior * parent_visits.sqrt() / (1.0 + child.visits as f64)
+        };
+        self.tree.children(index)
+            .fold(None, |best: Option<(usize, f64)>, (child, node)| {
+                let child_score = score(node);
+                match best {
+                    Some((_, best_score)) if best_score >= child_score => best,
+                    _ => Some((child, child_score))
+                }
+            })
+            .map(|(child, _)| child)
+    }
+
+    // Whether the needed children fit, releasing the subtrees of the least visited nodes off the
+    // path if they do not.
+    fn make_room(&mut self, needed: usize, path: &[usize]) -> bool {
+        let max_nodes = match self.tree.max_nodes() {
+            Some(max_nodes) => max_nodes,
+            None => return true
+        };
+        if self.tree.len() + needed <= max_nodes {
+            return true;
+        }
+        // One pass over the tree finds the candidates, least visited first. Descendants have no
+        // more visits than their ancestors, so they mostly go before them.
+        let mut candidates: Vec<(u32, usize)> = self.tree.dfs(0)
+            .filter(|(node_index, _)| !self.tree.is_leaf(*node_index) && !path.contains(node_index))
+            .map(|(node_index, node)| (node.visits, node_index))
+            .collect();
+        candidates.sort_unstable();
+        let target = max_nodes.saturating_sub(needed + max_nodes / EXTRA_ROOM_FRACTION);
+        for (_, candidate) in candidates {
+            if self.tree.len() <= target {
+                break;
+            }
+            // Released already, with an ancestor.
+            if !self.tree.contains(candidate) {
+                continue;
+            }
+            for child in self.tree.child_indices(candidate).to_vec() {
+                self.tree.release_subtree(child).unwrap();
+            }
+            self.tree.item_mut(candidate).unwrap().expanded = false;
+        }
+        self.tree.len() + needed <= max_nodes
+    }
+
+    fn expand(&mut self, index: usize, children: Vec<Board>) {
+        let node = *self.tree.item(index).unwrap();
+        let priors = match self.options.selection {
+            SelectionPolicy::Uct => vec![1.0 / children.len() as f64; children.len()],
+            SelectionPolicy::Puct => {
+                // A softmax of the children's scores for the side to move.
+                let scores: Vec<f64> = children.iter().map(|child| {
+                    self.evaluator.make(&node.board, child);
+                    let score = self.evaluator.evaluate(child);
+                    self.evaluator.unmake(&node.board, child);
+                    relative_score(score, node.color) as f64 / PRIOR_TEMPERATURE
+                }).collect();
+                let highest = scores.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+                let weights: Vec<f64> = scores.iter().map(|score| (score - highest).exp()).collect();
+                let total: f64 = weights.iter().sum();
+                weights.iter().map(|weight| weight / total).collect()
+            }
+        };
+        for (child, prior) in children.into_iter().zip(priors) {
+            self.tree.add_child(MctsNode::new(child, node.color.opposite(), prior), index);
+        }
+        self.tree.item_mut(index).unwrap().expanded = true;
+    }
+
+    // The value of the leaf for its side to move.
+    fn evaluate_leaf(&mut self, leaf: &MctsNode) -> f64 {
+        if self.options.leaf_evaluation == LeafEvaluation::ValueFunction {
+            return self.value(&leaf.board, leaf.color);
+        }
+        let (mut board, mut color) = (leaf.board, leaf.color);
+        for _ in 0..self.options.playout_plies {
+            let children = next_boards(&board, color);
+            if children.is_empty() {
+                let value = if is_checked(&board, color) { 0.0 } else { 0.5 };
+                return if color == leaf.color { value } else { 1.0 - value };
+            }
+            let next = if self.options.leaf_evaluation == LeafEvaluation::RandomPlayout {
+                children[(self.next_random() % children.len() as u64) as usize]
+            } else {
+                let scores: Vec<i32> = children.iter().map(|child| relative_score(self.evaluator.evaluate(child), color)).collect();
+                let best = *scores.iter().max().unwrap();
+                let best_children: Vec<&Board> = children.iter().zip(scores.iter()).filter(|(_, score)| **score == best).map(|(child, _)| child).collect();
+                *best_children[(self.next_random() % best_children.len() as u64) as usize]
+            };
+            board = next;
+            color = color.opposite();
+        }
+        let value = self.value(&board, color);
+        if color == leaf.color { value } else { 1.0 - value }
+    }
+
+    // The evaluator's score as a value for the side to move.
+    fn value(&mut self, board: &Board, color: Color) -> f64 {
+        let score = relative_score(self.evaluator.evaluate(board), color) as f64;
+        1.0 / (1.0 + 10f64.powf(-score / VALUE_SCALE))
+    }
+
+    // Xorshift, which is plenty for playouts.
+    fn next_random(&mut self) -> u64 {
+        self.random_state ^= self.random_state << 13;
+        self.random_state ^= self.random_state >> 7;
+        self.random_state ^= self.random_state << 17;
+        self.random_state
+    }
+}
+
+// The value for the side to move when it has no moves: a loss when mated, a draw when stalemated.
+fn terminal_value(board: &Board, color: Color) -> Option<f64> {
+    if has_legal_move(board, color) {
+        None
+    } else if is_checked(board, color) {
+        Some(0.0)
+    } else {
+        Some(0.5)
+    }
+}
+
+// Search trees are saved to continue the analysis later.
+impl GrowTree<MctsNode> {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.encode_tree()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<GrowTree<MctsNode>, Error> {
+        GrowTree::decode_tree(bytes)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        self.save_tree(path)
+    }
+
+    pub fn load(path: &Path) -> Result<GrowTree<MctsNode>, Error> {
+        GrowTree::load_tree(path)
+    }
+}
+
+// A nibble per square, a byte of flags (castling rights, the side to move and whether the node
+// was expanded) and the en passant square.
+impl Encode for MctsNode {
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        for pair in self.board.squares().chunks(2) {
+            bytes.push(square_nibble(pair[0]) | square_nibble(pair[1]) << 4);
+        }
+        let rights = CASTLING_RIGHTS.iter().enumerate()
+            .filter(|(_, (color, side))| self.board.is_castling_allowed(*color, *side))
+            .fold(0, |rights, (bit, _)| rights | 1 << bit);
+        bytes.push(rights | if self.color == Color::White { 0 } else { 0x10 } | if self.expanded { 0x20 } else { 0 });
+        bytes.push(self.board.en_passant_capturable().map(|coordinates| coordinates.index() as u8).unwrap_or(0xff));
+        bytes.extend_from_slice(&self.visits.to_le_bytes());
+        bytes.extend_from_slice(&self.value_sum.to_le_bytes());
+        bytes.extend_from_slice(&self.prior.to_le_bytes());
+    }
+
+    fn decode(bytes: &[u8]) -> Result<(MctsNode, usize), Error> {
+        if bytes.len() < ENCODED_NODE_LENGTH {
+            return Err(Error::InvalidData(String::from("Truncated node")));
+        }
+        let mut board = Board::new();
+        for (index, byte) in bytes[..32].iter().enumerate() {
+            for (offset, nibble) in [byte & 0x0f, byte >> 4].iter().enumerate() {
+                board.set_square(Coordinates::new_from_index(2 * index + offset).unwrap(), nibble_square(*nibble)?);
+            }
+        }
+        let flags = bytes[32];
+        for (bit, (color, side)) in CASTLING_RIGHTS.iter().enumerate() {
+            board.set_castling_allowed(*color, *side, flags & 1 << bit != 0);
+        }
+        if bytes[33] != 0xff {
+            board.set_en_passant_capturable(Some(Coordinates::new_from_index(bytes[33] as usize).ok_or_else(|| Error::InvalidData(String::from("Invalid en passant square")))?));
+        }
+        let color = if flags & 0x10 == 0 { Color::White } else { Color::Black };
+        let visits = u32::from_le_bytes([bytes[34], bytes[35], bytes[36], bytes[37]]);
+        let read_f64 = |offset: usize| {
+            let mut value = [0; 8];
+            value.copy_from_slice(&bytes[offset..offset + 8]);
+            f64::from_le_bytes(value)
+        };
+        let mut node = MctsNode::new(board, color, read_f64(46));
+        node.visits = visits;
+        node.value_sum = read_f64(38);
+        node.expanded = node.terminal.is_some() || flags & 0x20 != 0;
+        Ok((node, ENCODED_NODE_LENGTH))
+    }
+}
+
+// 0 for an empty square, then the kinds in order for white and, from 8 on, for black.
+fn square_nibble(square: Square) -> u8 {
+    match square {
+        Square::Empty => 0,
+        Square::Occupied(Piece(color, kind)) => 1 + kind.index() as u8 + if color == Color::White { 0 } else { 8 }
+    }
+}
+
+fn nibble_square(nibble: u8) -> Result<Square, Error> {
+    if nibble == 0 {
+        return Ok(Square::Empty);
+    }
+    let color = if nibble < 8 { Color::White } else { Color::Black };
+    let kind: Kind = *ALL_KINDS.get(((nibble & 7) as usize).wrapping_sub(1)).ok_or_else(|| Error::InvalidData(format!("Invalid square: {}", nibble)))?;
+    Ok(Square::Occupied(Piece(color, kind)))
+}
+
+fn relative_score(score: i32, color: Color) -> i32 {
+    if color == Color::White { score } else { -score }
+}
@@ -0,0 +1,76 @@
+use crate::board::{ Board, Color, Kind, Piece, Square };
+use crate::engine::analysis::{ attacked_squares };
+use crate::engine::score::{ TaperedScore };
+
+// Score per safe square a piece attacks, relative to a typical number of squares for that piece.
+#[derive(Debug,Copy,PartialEq,Eq,Clone,Hash)]
+pub struct MobilityWeights {
+    pub knight: TaperedScore,
+    pub bishop: TaperedScore,
+    pub rook: TaperedScore,
+    pub queen: TaperedScore,
+}
+
+pub const DEFAULT_MOBILITY_WEIGHTS: MobilityWeights = MobilityWeights {
+    knight: TaperedScore::new(4, 4),
+    bishop: TaperedScore::new(5, 5),
+    rook: TaperedScore::new(2, 4),
+    queen: TaperedScore::new(1, 2),
+};
+
+impl Default for MobilityWeights {
+    fn default() -> MobilityWeights {
+        DEFAULT_MOBILITY_WEIGHTS
+    }
+}
+
+impl MobilityWeights {
+    fn weight(&self, kind: Kind) -> Option<(TaperedScore, i32)> {
+        // The second value is the number of squares at which a piece scores zero.
+        match kind {
+            Kind::Knight => Some((self.knight, 4)),
+            Kind::Bishop => Some((self.bishop, 6)),
+            Kind::Rook => Some((self.rook, 7)),
+            Kind::Queen => Some((self.queen, 13)),
+            Kind::Pawn | Kind::King => None
+        }
+    }
+}
+
+// The mobility score, positive being good for white.
+pub fn mobility_score(board: &Board, weights: &MobilityWeights) -> TaperedScore {
+    let white_pawn_attacks = pawn_attack_map(board, Color::White);
+    let black_pawn_attacks = pawn_attack_map(board, Color::Black);
+    let white_pieces = piece_map(board, Color::White);
+    let black_pieces = piece_map(board, Color::Black);
+
+    let mut score = TaperedScore::default();
+    for (coordinates, square) in board.squares_coordinates_iter() {
+        if let Square::Occupied(piece) = *square {
+            if let Some((weight, baseline)) = weights.weight(piece.1) {
+                let (enemy_pawn_attacks, own_pieces, sign) = if piece.0 == Color::White {
+                    (black_pawn_attacks, white_pieces, 1)
+                } else {
+                    (white_pawn_attacks, black_pieces, -1)
+                };
+                let safe_squares = (attacked_squares(board, coordinates, piece) & !enemy_pawn_attacks & !own_pieces).count_ones() as i32;
+                score += weight * ((safe_squares - baseline) * sign);
+            }
+        }
+    }
+    score
+}
+
+// The squares attacked by a pawn of the given color, as a mask with a bit per square index.
+pub fn pawn_attack_map(board: &Board, color: Color) -> u64 {
+    board.squares_coordinates_iter()
+        .filter(|(_, square)| square.is_occupied_by(Piece(color, Kind::Pawn)))
+        .fold(0, |attacked, (coordinates, _)| attacked | attacked_squares(board, coordinates, Piece(color, Kind::Pawn)))
+}
+
+// The squares occupied by pieces of the given color.
+fn piece_map(board: &Board, color: Color) -> u64 {
+    board.squares_coordinates_iter()
+        .filter(|(_, square)| matches!(square, Square::Occupied(Piece(piece_color, _)) if *piece_color == color))
+        .fold(0, |occupied, (coordinates, _)| occupied | 1 << coordinates.index())
+}
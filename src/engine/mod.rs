@@ -1,9 +1,41 @@
 mod analysis;
+mod evaluator;
+mod game;
+mod king_safety;
+mod mate;
+mod mcts;
+mod mobility;
 mod moves;
+mod nnue;
+mod notation;
+mod parameters;
+mod pawns;
+mod perft;
+mod player;
 mod score;
-// mod score_tree;
+mod score_tree;
+mod search;
+mod search_trace;
+mod transposition;
+mod validation;
+mod zobrist;
 
-pub use analysis::{ is_checked, is_threatened_by };
-pub use moves::{ next_boards };
-pub use score::{ score_board };
-// pub use score_tree::{ ScoreTarget, ScoreTree };
+pub use analysis::{ attacked_squares, is_checked, is_threatened_by, mask_coordinates };
+pub use evaluator::{ Evaluator, MaterialEvaluator, PositionalEvaluator, ZeroEvaluator };
+pub use game::{ play_game, GameOutcome, GameRecord };
+pub use king_safety::{ king_safety, KingSafetyWeights };
+pub use mate::{ solve_mate, MateLine };
+pub use mcts::{ mcts_search, mcts_search_from, LeafEvaluation, MctsNode, MctsOptions, MctsResult, SelectionPolicy };
+pub use mobility::{ mobility_score, MobilityWeights };
+pub use moves::{ has_legal_move, next_boards };
+pub use nnue::{ Network, NnueEvaluator };
+pub use notation::{ Move };
+pub use parameters::{ EvaluationParameters };
+pub use pawns::{ evaluate_pawn_structure, pawn_breakdown, PawnStructure };
+pub use perft::{ perft, perft_divide };
+pub use player::{ Player };
+pub use score::{ evaluation_breakdown, score_board, score_board_with, EvaluationBreakdown };
+pub use score_tree::{ ScoreTarget, ScoreTree };
+pub use search::{ is_mate_score, search, SearchContext, SearchLimits, SearchLine, SearchOptions, SearchResult, SearchState, MATE_SCORE, TABLEBASE_WIN_SCORE };
+pub use search_trace::{ Cutoff, NodeType, SearchTrace, TraceLimits, TraceNode };
+pub use validation::{ validate_position };
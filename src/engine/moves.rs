@@ -4,33 +4,48 @@ use crate::engine::analysis::{ is_checked, is_threatened_by };
 pub fn next_boards(board: &Board, color: Color) -> Vec<Board> {
     let mut boards: Vec<Board> = vec![];
     for (coordinates, square) in board.squares_coordinates_iter() {
-        match *square {
-            Square::Occupied(Piece(piece_color, Kind::Rook)) if piece_color == color => {
-                add_rook_moves(&board, coordinates, color, &mut boards)
-            },
-            Square::Occupied(Piece(piece_color, Kind::Knight)) if piece_color == color => {
-                add_knight_moves(&board, coordinates, color, &mut boards)
-            },
-            Square::Occupied(Piece(piece_color, Kind::Bishop)) if piece_color == color => {
-                add_bishop_moves(&board, coordinates, color, &mut boards)
-            },
-            Square::Occupied(Piece(piece_color, Kind::Queen)) if piece_color == color => {
-                add_queen_moves(&board, coordinates, color, &mut boards)
-            },
-            Square::Occupied(Piece(piece_color, Kind::King)) if piece_color == color => {
-                add_king_moves(&board, coordinates, color, &mut boards)
-            },
-            Square::Occupied(Piece(piece_color, Kind::Pawn)) if piece_color == color => {
-                add_pawn_moves(&board, coordinates, color, &mut boards)
-            },
-            _ => {}
-        }
+        add_piece_moves(board, coordinates, *square, color, &mut boards);
     }
 
     // Return the boards which are acceptable (not in check).
     boards.into_iter().filter(|board| !is_checked(&board, color)).collect()
 }
 
+// Whether the color can move at all, stopping at the first piece that can. Cheaper than
+// generating every move when only mate or stalemate matters.
+pub fn has_legal_move(board: &Board, color: Color) -> bool {
+    let mut boards: Vec<Board> = vec![];
+    board.squares_coordinates_iter().any(|(coordinates, square)| {
+        boards.clear();
+        add_piece_moves(board, coordinates, *square, color, &mut boards);
+        boards.iter().any(|board| !is_checked(board, color))
+    })
+}
+
+fn add_piece_moves(board: &Board, coordinates: Coordinates, square: Square, color: Color, boards: &mut Vec<Board>) {
+    match square {
+        Square::Occupied(Piece(piece_color, Kind::Rook)) if piece_color == color => {
+            add_rook_moves(board, coordinates, color, boards)
+        },
+        Square::Occupied(Piece(piece_color, Kind::Knight)) if piece_color == color => {
+            add_knight_moves(board, coordinates, color, boards)
+        },
+        Square::Occupied(Piece(piece_color, Kind::Bishop)) if piece_color == color => {
+            add_bishop_moves(board, coordinates, color, boards)
+        },
+        Square::Occupied(Piece(piece_color, Kind::Queen)) if piece_color == color => {
+            add_queen_moves(board, coordinates, color, boards)
+        },
+        Square::Occupied(Piece(piece_color, Kind::King)) if piece_color == color => {
+            add_king_moves(board, coordinates, color, boards)
+        },
+        Square::Occupied(Piece(piece_color, Kind::Pawn)) if piece_color == color => {
+            add_pawn_moves(board, coordinates, color, boards)
+        },
+        _ => {}
+    }
+}
+
 pub const ROOK_OFFSETS: [(i8, i8); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
 
 fn add_rook_moves(board: &Board, from: Coordinates, color: Color, boards: &mut Vec<Board>) {
@@ -122,13 +137,14 @@ fn add_king_moves(board: &Board, from: Coordinates, color: Color, boards: &mut V
 fn add_queens_castle_move(board: &Board, color: Color, boards: &mut Vec<Board>) {
     let rank: Rank = if color == Color::White { Rank::R1 } else { Rank::R8 };
 
+    // The rook may pass an attacked square, but the king may not castle out of or through check.
     let opposite_color = color.opposite();
     if board.get_square(Coordinates::new(File::B, rank)).is_empty()
             && board.get_square(Coordinates::new(File::C, rank)).is_empty()
             && board.get_square(Coordinates::new(File::D, rank)).is_empty()
-            && !is_threatened_by(&board, Coordinates::new(File::B, rank), opposite_color)
-            && !is_threatened_by(&board, Coordinates::new(File::C, rank), opposite_color)
-            && !is_threatened_by(&board, Coordinates::new(File::D, rank), opposite_color) {
+            && !is_threatened_by(&board, Coordinates::new(File::E, rank), opposite_color)
+            && !is_threatened_by(&board, Coordinates::new(File::D, rank), opposite_color)
+            && !is_threatened_by(&board, Coordinates::new(File::C, rank), opposite_color) {
         let mut new_board = board.clone_move_piece(Coordinates::new(File::E, rank), Coordinates::new(File::C, rank));
         new_board.move_piece(Coordinates::new(File::A, rank), Coordinates::new(File::D, rank));
         boards.push(new_board);
@@ -141,6 +157,7 @@ fn add_kings_castle_move(board: &Board, color: Color, boards: &mut Vec<Board>) {
     let opposite_color = color.opposite();
     if board.get_square(Coordinates::new(File::F, rank)).is_empty()
             && board.get_square(Coordinates::new(File::G, rank)).is_empty()
+            && !is_threatened_by(&board, Coordinates::new(File::E, rank), opposite_color)
             && !is_threatened_by(&board, Coordinates::new(File::F, rank), opposite_color)
             && !is_threatened_by(&board, Coordinates::new(File::G, rank), opposite_color) {
         let mut new_board = board.clone_move_piece(Coordinates::new(File::E, rank), Coordinates::new(File::G, rank));
@@ -155,7 +172,7 @@ fn add_pawn_moves(board: &Board, from: Coordinates, color: Color, boards: &mut V
     if let Some(one_forward) = from.offset(0, 1 * move_direction) {
         if let Square::Empty = board.get_square(one_forward) {
             // Forward 1.
-            boards.push(board.clone_move_piece(from, one_forward));
+            add_pawn_move(board, from, one_forward, color, boards);
 
             if from.rank() == start_rank {
                 if let Some(two_forward) = from.offset(0, 2 * move_direction) {
@@ -167,12 +184,12 @@ fn add_pawn_moves(board: &Board, from: Coordinates, color: Color, boards: &mut V
             }
         }
     }
-    let capture_offsets = [(move_direction, -1), (move_direction, 1)];
+    let capture_offsets = [(-1, move_direction), (1, move_direction)];
     for to in from.offsets_filter(&capture_offsets) {
         if let Square::Occupied(Piece(piece_color, _)) = board.get_square(to) {
             if piece_color != color {
                 // Can capture this piece.
-                boards.push(board.clone_move_piece(from, to));
+                add_pawn_move(board, from, to, color, boards);
             }
         }
 
@@ -181,3 +198,21 @@ fn add_pawn_moves(board: &Board, from: Coordinates, color: Color, boards: &mut V
         }
     }
 }
+
+const PROMOTION_KINDS: [Kind; 4] = [Kind::Queen, Kind::Knight, Kind::Rook, Kind::Bishop];
+
+fn add_pawn_move(board: &Board, from: Coordinates, to: Coordinates, color: Color, boards: &mut Vec<Board>) {
+    let new_board = board.clone_move_piece(from, to);
+    let last_rank = if color == Color::White { Rank::R8 } else { Rank::R1 };
+    if to.rank() != last_rank {
+        boards.push(new_board);
+        return;
+    }
+
+    // Promote, to a queen first since that is nearly always best.
+    for kind in PROMOTION_KINDS.iter() {
+        let mut promoted = new_board;
+        promoted.set_square(to, Square::Occupied(Piece(color, *kind)));
+        boards.push(promoted);
+    }
+}
@@ -0,0 +1,411 @@
+// A small efficiently updatable neural network evaluation.
+//
+// The network has 768 inputs per perspective: one per combination of piece owner (own or enemy),
+// kind (in `Kind::index` order) and square (index from a1, mirrored vertically for black). Both
+// perspectives share the input weights and feed an accumulator, which is updated incrementally as
+// pieces move. The clipped accumulators, white's first, feed one hidden layer and then the output.
+//
+// Weights file, all numbers little-endian:
+//
+//   magic            4 bytes  "NNUE"
+//   version          u32      1
+//   accumulator size u32      A
+//   hidden size      u32      H
+//   input weights    i16      768 * A, all A weights of input 0 first
+//   input biases     i16      A
+//   hidden weights   i8       H * 2A, all 2A weights of hidden neuron 0 first
+//   hidden biases    i32      H
+//   output weights   i8       H
+//   output bias      i32
+//
+// Accumulator values are clipped to 0..=127. Hidden sums are shifted right by 6 and clipped to
+// 0..=127. The output sum divided by 16 is the score in centipawns, positive being good for white.
+
+use std::fs;
+use std::path::{ Path };
+
+use crate::board::{ Board, Color, Piece, Square };
+use crate::engine::evaluator::{ Evaluator };
+use crate::error::{ Error };
+
+const MAGIC: &[u8; 4] = b"NNUE";
+const VERSION: u32 = 1;
+const INPUT_SIZE: usize = 768;
+const ACTIVATION_MAX: i32 = 127;
+const HIDDEN_SHIFT: u32 = 6;
+const OUTPUT_DIVISOR: i32 = 16;
+// Castling changes four squares. Beyond that, a full refresh is cheaper.
+const MAX_INCREMENTAL_CHANGES: usize = 4;
+
+#[derive(Debug,PartialEq,Eq,Clone)]
+pub struct Network {
+    pub accumulator_size: usize,
+    pub hidden_size: usize,
+    pub input_weights: Vec<i16>,
+    pub input_biases: Vec<i16>,
+    pub hidden_weights: Vec<i8>,
+    pub hidden_biases: Vec<i32>,
+    pub output_weights: Vec<i8>,
+    pub output_bias: i32,
+}
+
+impl Network {
+    // A network with all weights zero.
+    pub fn new(accumulator_size: usize, hidden_size: usize) -> Network {
+        Network {
+            accumulator_size,
+            hidden_size,
+            input_weights: vec![0; INPUT_SIZE * accumulator_size],
+            input_biases: vec![0; accumulator_size],
+            hidden_weights: vec![0; hidden_size * 2 * accumulator_size],
+            hidden_biases: vec![0; hidden_size],
+            output_weights: vec![0; hidden_size],
+            output_bias: 0,
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Network, Error> {
+        let bytes = fs::read(path.as_ref()).map_err(|error| Error::Io(format!("Cannot read {}: {}", path.as_ref().display(), error)))?;
+        Network::from_bytes(&bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Network, Error> {
+        let mut reader = ByteReader { bytes, position: 0 };
+        if reader.take(4)? != MAGIC {
+            return Err(Error::InvalidData(String::from("Not a network weights file")));
+        }
+        let version = reader.u32()?;
+        if version != VERSION {
+            return Err(Error::InvalidData(format!("Unsupported network version: {}", version)));
+        }
+        let accumulator_size = reader.u32()? as usize;
+        let hidden_size = reader.u32()? as usize;
+        if accumulator_size == 0 || hidden_size == 0 || accumulator_size > 4096 || hidden_size > 4096 {
+            return Err(Error::InvalidData(format!("Invalid network size: {}x{}", accumulator_size, hidden_size)));
+        }
+
+        let mut network = Network::new(accumulator_size, hidden_size);
+        for weight in network.input_weights.iter_mut().chain(network.input_biases.iter_mut()) {
+            *weight = reader.i16()?;
+        }
+        for weight in network.hidden_weights.iter_mut() {
+            *weight = reader.i8()?;
+        }
+        for bias in network.hidden_biases.iter_mut() {
+            *bias = reader.i32()?;
+        }
+        for weight in network.output_weights.iter_mut() {
+            *weight = reader.i8()?;
+        }
+        network.output_bias = reader.i32()?;
+        if reader.position != bytes.len() {
+            return Err(Error::InvalidData(String::from("Unexpected data after the network weights")));
+        }
+        Ok(network)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(self.accumulator_size as u32).to_le_bytes());
+        bytes.extend_from_slice(&(self.hidden_size as u32).to_le_bytes());
+        for weight in self.input_weights.iter().chain(self.input_biases.iter()) {
+            bytes.extend_from_slice(&weight.to_le_bytes());
+        }
+        bytes.extend(self.hidden_weights.iter().map(|weight| *weight as u8));
+        for bias in self.hidden_biases.iter() {
+            bytes.extend_from_slice(&bias.to_le_bytes());
+        }
+        bytes.extend(self.output_weights.iter().map(|weight| *weight as u8));
+        bytes.extend_from_slice(&self.output_bias.to_le_bytes());
+        bytes
+    }
+
+    fn input_weights(&self, feature: usize) -> &[i16] {
+        &self.input_weights[feature * self.accumulator_size..(feature + 1) * self.accumulator_size]
+    }
+}
+
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], Error> {
+        if self.position + count > self.bytes.len() {
+            return Err(Error::InvalidData(String::from("Network weights file is truncated")));
+        }
+        let bytes = &self.bytes[self.position..self.position + count];
+        self.position += count;
+        Ok(bytes)
+    }
+
+    fn i8(&mut self) -> Result<i8, Error> {
+        Ok(self.take(1)?[0] as i8)
+    }
+
+    fn i16(&mut self) -> Result<i16, Error> {
+        let bytes = self.take(2)?;
+        Ok(i16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn i32(&mut self) -> Result<i32, Error> {
+        Ok(self.u32()? as i32)
+    }
+}
+
+fn feature_index(perspective: Color, piece: Piece, square: usize) -> usize {
+    let (own, square) = match perspective {
+        Color::White => (piece.0 == Color::White, square),
+        Color::Black => (piece.0 == Color::Black, square ^ 56)
+    };
+    let owner_offset = if own { 0 } else { INPUT_SIZE / 2 };
+    owner_offset + piece.1.index() * 64 + square
+}
+
+#[derive(Clone)]
+struct Accumulator {
+    board: Board,
+    white: Vec<i16>,
+    black: Vec<i16>,
+}
+
+// Evaluates with a network, keeping a stack of accumulators in step with the search.
+pub struct NnueEvaluator {
+    network: Network,
+    accumulators: Vec<Accumulator>,
+    // Index of the accumulator of the current board.
+    current: usize,
+    simd: bool,
+    input: Vec<u8>,
+    hidden: Vec<u8>,
+}
+
+impl NnueEvaluator {
+    pub fn new(network: Network) -> NnueEvaluator {
+        let accumulator = Accumulator {
+            board: Board::new(),
+            white: network.input_biases.clone(),
+            black: network.input_biases.clone(),
+        };
+        let input = vec![0; 2 * network.accumulator_size];
+        let hidden = vec![0; network.hidden_size];
+        NnueEvaluator { network, accumulators: vec![accumulator], current: 0, simd: simd_available(), input, hidden }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<NnueEvaluator, Error> {
+        Ok(NnueEvaluator::new(Network::load(path)?))
+    }
+
+    // SIMD is used when the CPU supports it. Disabling it falls back to scalar code with identical results.
+    pub fn set_simd(&mut self, enabled: bool) {
+        self.simd = enabled && simd_available();
+    }
+
+    pub fn simd(&self) -> bool {
+        self.simd
+    }
+
+    fn refresh(&mut self, index: usize, board: &Board) {
+        let network = &self.network;
+        let accumulator = &mut self.accumulators[index];
+        accumulator.board = *board;
+        accumulator.white.copy_from_slice(&network.input_biases);
+        accumulator.black.copy_from_slice(&network.input_biases);
+        for (square_index, square) in board.squares().iter().enumerate() {
+            if let Square::Occupied(piece) = *square {
+                add_weights(&mut accumulator.white, network.input_weights(feature_index(Color::White, piece, square_index)), self.simd);
+                add_weights(&mut accumulator.black, network.input_weights(feature_index(Color::Black, piece, square_index)), self.simd);
+            }
+        }
+    }
+}
+
+impl Evaluator for NnueEvaluator {
+    fn evaluate(&mut self, board: &Board) -> i32 {
+        if self.accumulators[self.current].board != *board {
+            self.refresh(self.current, board);
+        }
+
+        let size = self.network.accumulator_size;
+        let accumulator = &self.accumulators[self.current];
+        for (input, value) in self.input.iter_mut().zip(accumulator.white.iter().chain(accumulator.black.iter())) {
+            *input = (*value as i32).clamp(0, ACTIVATION_MAX) as u8;
+        }
+        for (neuron, hidden) in self.hidden.iter_mut().enumerate() {
+            let weights = &self.network.hidden_weights[neuron * 2 * size..(neuron + 1) * 2 * size];
+            // Biases come from the file and may be anything, so the sums are widened before they can overflow.
+            let sum = self.network.hidden_biases[neuron] as i64 + dot(&self.input, weights, self.simd) as i64;
+            *hidden = (sum >> HIDDEN_SHIFT).clamp(0, ACTIVATION_MAX as i64) as u8;
+        }
+        let output = self.network.output_bias as i64 + self.hidden.iter().zip(self.network.output_weights.iter())
+            .map(|(hidden, weight)| *hidden as i64 * *weight as i64)
+            .sum::<i64>();
+        (output / OUTPUT_DIVISOR as i64).clamp(i32::MIN as i64, i32::MAX as i64) as i32
+    }
+
+    fn reset(&mut self, root: &Board) {
+        self.current = 0;
+        self.refresh(0, root);
+    }
+
+    fn make(&mut self, parent: &Board, child: &Board) {
+        let next = self.current + 1;
+        if next == self.accumulators.len() {
+            let accumulator = self.accumulators[self.current].clone();
+            self.accumulators.push(accumulator);
+        }
+        let parent_is_current = self.accumulators[self.current].board == *parent;
+        self.current = next;
+
+        let changes = parent.squares().iter().zip(child.squares().iter()).filter(|(before, after)| before != after).count();
+        if !parent_is_current || changes > MAX_INCREMENTAL_CHANGES {
+            self.refresh(next, child);
+            return;
+        }
+
+        let (previous, rest) = self.accumulators.split_at_mut(next);
+        let previous = &previous[next - 1];
+        let accumulator = &mut rest[0];
+        accumulator.board = *child;
+        accumulator.white.copy_from_slice(&previous.white);
+        accumulator.black.copy_from_slice(&previous.black);
+        for (index, (before, after)) in parent.squares().iter().zip(child.squares().iter()).enumerate() {
+            if before == after {
+                continue;
+            }
+            if let Square::Occupied(piece) = *before {
+                sub_weights(&mut accumulator.white, self.network.input_weights(feature_index(Color::White, piece, index)), self.simd);
+                sub_weights(&mut accumulator.black, self.network.input_weights(feature_index(Color::Black, piece, index)), self.simd);
+            }
+            if let Square::Occupied(piece) = *after {
+                add_weights(&mut accumulator.white, self.network.input_weights(feature_index(Color::White, piece, index)), self.simd);
+                add_weights(&mut accumulator.black, self.network.input_weights(feature_index(Color::Black, piece, index)), self.simd);
+            }
+        }
+    }
+
+    fn unmake(&mut self, _parent: &Board, _child: &Board) {
+        self.current = self.current.saturating_sub(1);
+    }
+
+    fn fork(&self) -> Option<Box<dyn Evaluator + Send>> {
+        let mut evaluator = NnueEvaluator::new(self.network.clone());
+        evaluator.simd = self.simd;
+        Some(Box::new(evaluator))
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+fn simd_available() -> bool {
+    is_x86_feature_detected!("avx2")
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn simd_available() -> bool {
+    false
+}
+
+#[cfg_attr(not(target_arch = "x86_64"), allow(unused_variables))]
+fn add_weights(accumulator: &mut [i16], weights: &[i16], simd: bool) {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if simd {
+            // Only enabled after AVX2 was detected at runtime.
+            unsafe { avx2::add_weights(accumulator, weights) };
+            return;
+        }
+    }
+    for (value, weight) in accumulator.iter_mut().zip(weights.iter()) {
+        *value = value.wrapping_add(*weight);
+    }
+}
+
+#[cfg_attr(not(target_arch = "x86_64"), allow(unused_variables))]
+fn sub_weights(accumulator: &mut [i16], weights: &[i16], simd: bool) {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if simd {
+            unsafe { avx2::sub_weights(accumulator, weights) };
+            return;
+        }
+    }
+    for (value, weight) in accumulator.iter_mut().zip(weights.iter()) {
+        *value = value.wrapping_sub(*weight);
+    }
+}
+
+#[cfg_attr(not(target_arch = "x86_64"), allow(unused_variables))]
+fn dot(input: &[u8], weights: &[i8], simd: bool) -> i32 {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if simd {
+            return unsafe { avx2::dot(input, weights) };
+        }
+    }
+    input.iter().zip(weights.iter()).map(|(input, weight)| *input as i32 * *weight as i32).sum()
+}
+
+#[cfg(target_arch = "x86_64")]
+mod avx2 {
+    use std::arch::x86_64::*;
+
+    const LANES_I16: usize = 16;
+    const LANES_I8: usize = 32;
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn add_weights(accumulator: &mut [i16], weights: &[i16]) {
+        let length = accumulator.len().min(weights.len());
+        let chunks = length / LANES_I16;
+        for chunk in 0..chunks {
+            let value = accumulator.as_mut_ptr().add(chunk * LANES_I16) as *mut __m256i;
+            let weight = weights.as_ptr().add(chunk * LANES_I16) as *const __m256i;
+            _mm256_storeu_si256(value, _mm256_add_epi16(_mm256_loadu_si256(value), _mm256_loadu_si256(weight)));
+        }
+        for index in chunks * LANES_I16..length {
+            accumulator[index] = accumulator[index].wrapping_add(weights[index]);
+        }
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn sub_weights(accumulator: &mut [i16], weights: &[i16]) {
+        let length = accumulator.len().min(weights.len());
+        let chunks = length / LANES_I16;
+        for chunk in 0..chunks {
+            let value = accumulator.as_mut_ptr().add(chunk * LANES_I16) as *mut __m256i;
+            let weight = weights.as_ptr().add(chunk * LANES_I16) as *const __m256i;
+            _mm256_storeu_si256(value, _mm256_sub_epi16(_mm256_loadu_si256(value), _mm256_loadu_si256(weight)));
+        }
+        for index in chunks * LANES_I16..length {
+            accumulator[index] = accumulator[index].wrapping_sub(weights[index]);
+        }
+    }
+
+    // Inputs are at most 127, so the pairwise sums of `maddubs` cannot saturate.
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn dot(input: &[u8], weights: &[i8]) -> i32 {
+        let length = input.len().min(weights.len());
+        let chunks = length / LANES_I8;
+        let ones = _mm256_set1_epi16(1);
+        let mut sum = _mm256_setzero_si256();
+        for chunk in 0..chunks {
+            let input_vector = _mm256_loadu_si256(input.as_ptr().add(chunk * LANES_I8) as *const __m256i);
+            let weight_vector = _mm256_loadu_si256(weights.as_ptr().add(chunk * LANES_I8) as *const __m256i);
+            let products = _mm256_madd_epi16(_mm256_maddubs_epi16(input_vector, weight_vector), ones);
+            sum = _mm256_add_epi32(sum, products);
+        }
+        let mut lanes = [0i32; 8];
+        _mm256_storeu_si256(lanes.as_mut_ptr() as *mut __m256i, sum);
+        let mut total: i32 = lanes.iter().sum();
+        for index in chunks * LANES_I8..length {
+            total += input[index] as i32 * weights[index] as i32;
+        }
+        total
+    }
+}
@@ -0,0 +1,212 @@
+use std::fmt;
+
+use crate::board::{ parse_coordinates, Board, Color, Coordinates, File, Kind, Piece, Rank, Square };
+use crate::engine::analysis::{ is_checked };
+use crate::engine::moves::{ next_boards };
+use crate::error::{ Error, ParseError, ParseErrorKind };
+
+// A move in coordinate notation, such as "e2e4" or "e7e8q". Castling is written as the king's move.
+#[derive(Debug,Copy,PartialEq,Clone,Hash)]
+pub struct Move {
+    pub from: Coordinates,
+    pub to: Coordinates,
+    pub promotion: Option<Kind>,
+}
+
+impl Move {
+    pub fn parse(input: &str) -> Result<Move, Error> {
+        if input.len() < 4 || input.len() > 5 || !input.is_ascii() {
+            return Err(ParseError::new(ParseErrorKind::InvalidMove, input, input, format!("Invalid move: {}", input)).into());
+        }
+        let from = parse_coordinates(&input[0..2]).map_err(|error| error.within(input, &input[0..2]))?;
+        let to = parse_coordinates(&input[2..4]).map_err(|error| error.within(input, &input[2..4]))?;
+        let promotion = match input[4..].chars().next() {
+            None => None,
+            Some('n') => Some(Kind::Knight),
+            Some('b') => Some(Kind::Bishop),
+            Some('r') => Some(Kind::Rook),
+            Some('q') => Some(Kind::Queen),
+            Some(_) => { return Err(ParseError::new(ParseErrorKind::InvalidMove, input, &input[4..], format!("Invalid promotion in move: {}", input)).into()); }
+        };
+        Ok(Move { from, to, promotion })
+    }
+
+    // Parse a move in standard algebraic notation, such as "Nbd2", "exd5", "e8=Q+" or "O-O".
+    pub fn from_san(board: &Board, color: Color, san: &str) -> Result<Move, Error> {
+        let text = san.trim_end_matches(['+', '#', '!', '?']);
+        let rank = if color == Color::White { Rank::R1 } else { Rank::R8 };
+        let castle_to = match text {
+            "O-O" | "0-0" => Some(File::G),
+            "O-O-O" | "0-0-0" => Some(File::C),
+            _ => None
+        };
+        if let Some(file) = castle_to {
+            let castle = Move { from: Coordinates::new(File::E, rank), to: Coordinates::new(file, rank), promotion: None };
+            return match castle.apply(board, color) {
+                Some(_) => Ok(castle),
+                None => Err(Error::IllegalMove(format!("Illegal move: {}", san)))
+            };
+        }
+
+        let (kind, rest) = match text.chars().next().and_then(kind_from_san) {
+            Some(kind) => (kind, &text[1..]),
+            None => (Kind::Pawn, text)
+        };
+        let (rest, promotion) = match rest.char_indices().last() {
+            Some((index, c)) if kind == Kind::Pawn && kind_from_san(c).is_some() => (rest[..index].trim_end_matches('='), kind_from_san(c)),
+            _ => (rest, None)
+        };
+        let rest: String = rest.chars().filter(|c| *c != 'x' && *c != '-').collect();
+        let invalid = || Error::from(ParseError::new(ParseErrorKind::InvalidMove, san, san, format!("Invalid move: {}", san)));
+        if rest.len() < 2 || !rest.is_ascii() {
+            return Err(invalid());
+        }
+        let to = parse_coordinates(&rest[rest.len() - 2..]).map_err(|_| invalid())?;
+        let disambiguation = &rest[..rest.len() - 2];
+
+        let candidates: Vec<Move> = next_boards(board, color).iter()
+            .filter_map(|child| Move::between(board, child, color))
+            .filter(|candidate| candidate.to == to && candidate.promotion == promotion)
+            .filter(|candidate| board.get_square(candidate.from).is_occupied_by(Piece(color, kind)))
+            .filter(|candidate| disambiguation.chars().all(|c| candidate.from.to_string().contains(c)))
+            .collect();
+        match candidates.len() {
+            1 => Ok(candidates[0]),
+            0 => Err(Error::IllegalMove(format!("Illegal move: {}", san))),
+            _ => Err(Error::IllegalMove(format!("Ambiguous move: {}", san)))
+        }
+    }
+
+    // The move the given color made to get from the board to the child.
+    pub fn between(board: &Board, child: &Board, color: Color) -> Option<Move> {
+        let mut vacated = vec![];
+        let mut arrived = vec![];
+        for (index, (before, after)) in board.squares().iter().zip(child.squares().iter()).enumerate() {
+            match (*before, *after) {
+                (before, after) if before == after => {},
+                (Square::Occupied(Piece(piece_color, kind)), Square::Empty) if piece_color == color => vacated.push((index, kind)),
+                (_, Square::Occupied(Piece(piece_color, kind))) if piece_color == color => arrived.push((index, kind)),
+                _ => {}
+            }
+        }
+        // When castling, both the king and the rook move. The king's move is the one that counts.
+        let pick = |squares: &Vec<(usize, Kind)>| if squares.len() == 1 {
+            Some(squares[0])
+        } else {
+            squares.iter().find(|(_, kind)| *kind == Kind::King).copied()
+        };
+        let (from, moved) = pick(&vacated)?;
+        let (to, arrived) = pick(&arrived)?;
+        Some(Move {
+            from: Coordinates::new_from_index(from)?,
+            to: Coordinates::new_from_index(to)?,
+            promotion: if moved == Kind::Pawn && arrived != Kind::Pawn { Some(arrived) } else { None },
+        })
+    }
+
+    // The board after the given color makes this move, if it is legal.
+    pub fn apply(&self, board: &Board, color: Color) -> Option<Board> {
+        next_boards(board, color).into_iter().find(|child| Move::between(board, child, color) == Some(*self))
+    }
+
+    // The move in standard algebraic notation, such as "Nbd2", "exd5", "e8=Q+" or "O-O".
+    pub fn to_san(self, board: &Board, color: Color) -> Result<String, Error> {
+        let next = self.apply(board, color).ok_or_else(|| Error::IllegalMove(format!("Illegal move: {}", self)))?;
+        let kind = match board.get_square(self.from) {
+            Square::Occupied(Piece(_, kind)) => kind,
+            Square::Empty => return Err(Error::IllegalMove(format!("Illegal move: {}", self)))
+        };
+        let from = self.from.to_string();
+        let mut san = String::new();
+        if kind == Kind::King && self.from.file() == File::E && (self.to.file() == File::G || self.to.file() == File::C)
+                && self.from.rank() == self.to.rank() {
+            san.push_str(if self.to.file() == File::G { "O-O" } else { "O-O-O" });
+        } else {
+            // Captures, counting en passant, where a pawn changes file onto an empty square.
+            let captures = !board.get_square(self.to).is_empty() || (kind == Kind::Pawn && self.from.file() != self.to.file());
+            if kind == Kind::Pawn {
+                if captures {
+                    san.push_str(&from[0..1]);
+                }
+            } else {
+                san.push(kind_to_san(kind));
+                // Name the file, the rank or both when another piece of the kind can move to the same square.
+                let others: Vec<Move> = next_boards(board, color).iter()
+                    .filter_map(|child| Move::between(board, child, color))
+                    .filter(|other| other.to == self.to && other.from != self.from && board.get_square(other.from) == board.get_square(self.from))
+                    .collect();
+                if !others.is_empty() {
+                    if others.iter().all(|other| other.from.file() != self.from.file()) {
+                        san.push_str(&from[0..1]);
+                    } else if others.iter().all(|other| other.from.rank() != self.from.rank()) {
+                        san.push_str(&from[1..2]);
+                    } else {
+                        san.push_str(&from);
+                    }
+                }
+            }
+            if captures {
+                san.push('x');
+            }
+            san.push_str(&self.to.to_string());
+            if let Some(promotion) = self.promotion {
+                san.push('=');
+                san.push(kind_to_san(promotion));
+            }
+        }
+        if is_checked(&next, color.opposite()) {
+            san.push(if next_boards(&next, color.opposite()).is_empty() { '#' } else { '+' });
+        }
+        Ok(san)
+    }
+
+    // The moves of a line of boards played from the board, up to the first that is not a move.
+    pub fn line(board: &Board, color: Color, boards: &[Board]) -> Vec<Move> {
+        let (mut board, mut color) = (*board, color);
+        let mut moves = vec![];
+        for child in boards {
+            match Move::between(&board, child, color) {
+                Some(played) => moves.push(played),
+                None => break
+            }
+            board = *child;
+            color = color.opposite();
+        }
+        moves
+    }
+}
+
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.from, self.to)?;
+        match self.promotion {
+            Some(Kind::Knight) => write!(f, "n"),
+            Some(Kind::Bishop) => write!(f, "b"),
+            Some(Kind::Rook) => write!(f, "r"),
+            Some(Kind::Queen) => write!(f, "q"),
+            _ => Ok(())
+        }
+    }
+}
+
+fn kind_from_san(c: char) -> Option<Kind> {
+    match c {
+        'N' => Some(Kind::Knight),
+        'B' => Some(Kind::Bishop),
+        'R' => Some(Kind::Rook),
+        'Q' => Some(Kind::Queen),
+        'K' => Some(Kind::King),
+        _ => None
+    }
+}
+
+fn kind_to_san(kind: Kind) -> char {
+    match kind {
+        Kind::Knight => 'N',
+        Kind::Bishop => 'B',
+        Kind::Rook => 'R',
+        Kind::Queen => 'Q',
+        Kind::King => 'K',
+        Kind::Pawn => 'P'
+    }
+}
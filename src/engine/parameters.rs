@@ -0,0 +1,189 @@
+use std::fmt::{ Write };
+
+use crate::board::{ Kind, ALL_KINDS };
+use crate::engine::king_safety::{ KingSafetyWeights, DEFAULT_KING_SAFETY_WEIGHTS };
+use crate::engine::mobility::{ MobilityWeights, DEFAULT_MOBILITY_WEIGHTS };
+use crate::engine::pawns::{ PawnWeights, DEFAULT_PAWN_WEIGHTS };
+use crate::engine::score::{ default_piece_square_tables, endgame_kind_value, middlegame_kind_value, TaperedScore };
+use crate::error::{ Error, ParseError, ParseErrorKind };
+
+// Every weight of the positional evaluation, so they can be tuned and loaded as a whole.
+#[derive(Debug,Copy,PartialEq,Eq,Clone,Hash)]
+pub struct EvaluationParameters {
+    // Indexed by `Kind::index`.
+    pub piece_values: [TaperedScore; 6],
+    // Indexed by `Kind::index`, then by square as seen from white with a8 first.
+    pub piece_square_tables: [[TaperedScore; 64]; 6],
+    pub pawns: PawnWeights,
+    pub mobility: MobilityWeights,
+    pub king_safety: KingSafetyWeights,
+}
+
+pub const DEFAULT_PARAMETERS: EvaluationParameters = EvaluationParameters {
+    piece_values: default_piece_values(),
+    piece_square_tables: default_piece_square_tables(),
+    pawns: DEFAULT_PAWN_WEIGHTS,
+    mobility: DEFAULT_MOBILITY_WEIGHTS,
+    king_safety: DEFAULT_KING_SAFETY_WEIGHTS,
+};
+
+const fn default_piece_values() -> [TaperedScore; 6] {
+    let mut values = [TaperedScore::new(0, 0); 6];
+    let mut index = 0;
+    while index < 6 {
+        let kind = ALL_KINDS[index];
+        values[index] = TaperedScore::new(middlegame_kind_value(kind), endgame_kind_value(kind));
+        index += 1;
+    }
+    values
+}
+
+impl Default for EvaluationParameters {
+    fn default() -> EvaluationParameters {
+        DEFAULT_PARAMETERS
+    }
+}
+
+fn kind_name(kind: Kind) -> &'static str {
+    match kind {
+        Kind::Rook => "rook",
+        Kind::Knight => "knight",
+        Kind::Bishop => "bishop",
+        Kind::Queen => "queen",
+        Kind::King => "king",
+        Kind::Pawn => "pawn"
+    }
+}
+
+impl EvaluationParameters {
+    // Call the function for every parameter in a fixed order, with a stable name.
+    pub fn visit_mut<F>(&mut self, mut f: F)
+        where F: FnMut(&str, &mut i32) {
+        fn tapered<F>(f: &mut F, name: &str, score: &mut TaperedScore)
+            where F: FnMut(&str, &mut i32) {
+            f(&format!("{}.mg", name), &mut score.middlegame);
+            f(&format!("{}.eg", name), &mut score.endgame);
+        }
+
+        for kind in ALL_KINDS.iter() {
+            tapered(&mut f, &format!("piece_values.{}", kind_name(*kind)), &mut self.piece_values[kind.index()]);
+        }
+        for kind in ALL_KINDS.iter() {
+            for (index, score) in self.piece_square_tables[kind.index()].iter_mut().enumerate() {
+                // Name the squares as they are laid out, from white's side.
+                let square = format!("{}{}", (b'a' + (index % 8) as u8) as char, 8 - index / 8);
+                tapered(&mut f, &format!("piece_square_tables.{}.{}", kind_name(*kind), square), score);
+            }
+        }
+
+        let pawns = &mut self.pawns;
+        tapered(&mut f, "pawns.doubled", &mut pawns.doubled);
+        tapered(&mut f, "pawns.isolated", &mut pawns.isolated);
+        tapered(&mut f, "pawns.backward", &mut pawns.backward);
+        tapered(&mut f, "pawns.island", &mut pawns.island);
+        tapered(&mut f, "pawns.connected_passer", &mut pawns.connected_passer);
+        for (rank, score) in pawns.passed.iter_mut().enumerate() {
+            tapered(&mut f, &format!("pawns.passed.{}", rank + 1), score);
+        }
+        f("pawns.blocked_passed_percentage", &mut pawns.blocked_passed_percentage);
+
+        let mobility = &mut self.mobility;
+        tapered(&mut f, "mobility.knight", &mut mobility.knight);
+        tapered(&mut f, "mobility.bishop", &mut mobility.bishop);
+        tapered(&mut f, "mobility.rook", &mut mobility.rook);
+        tapered(&mut f, "mobility.queen", &mut mobility.queen);
+
+        let king_safety = &mut self.king_safety;
+        tapered(&mut f, "king_safety.shield_close", &mut king_safety.shield_close);
+        tapered(&mut f, "king_safety.shield_far", &mut king_safety.shield_far);
+        tapered(&mut f, "king_safety.semi_open_file", &mut king_safety.semi_open_file);
+        tapered(&mut f, "king_safety.open_file", &mut king_safety.open_file);
+        f("king_safety.knight_attack", &mut king_safety.knight_attack);
+        f("king_safety.bishop_attack", &mut king_safety.bishop_attack);
+        f("king_safety.rook_attack", &mut king_safety.rook_attack);
+        f("king_safety.queen_attack", &mut king_safety.queen_attack);
+        for (attackers, scale) in king_safety.attacker_scale.iter_mut().enumerate() {
+            f(&format!("king_safety.attacker_scale.{}", attackers), scale);
+        }
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        let mut names = vec![];
+        let mut parameters = *self;
+        parameters.visit_mut(|name, _| names.push(String::from(name)));
+        names
+    }
+
+    pub fn to_vector(self) -> Vec<i32> {
+        let mut values = vec![];
+        let mut parameters = self;
+        parameters.visit_mut(|_, value| values.push(*value));
+        values
+    }
+
+    pub fn from_vector(values: &[i32]) -> Result<EvaluationParameters, Error> {
+        let mut parameters = DEFAULT_PARAMETERS;
+        let expected = parameters.to_vector().len();
+        if values.len() != expected {
+            return Err(Error::InvalidArgument(format!("Expect {} parameters, got {}", expected, values.len())));
+        }
+        let mut iter = values.iter();
+        parameters.visit_mut(|_, value| *value = *iter.next().unwrap());
+        Ok(parameters)
+    }
+
+    // A weights file has one `name value` pair per line. Missing names keep their default value.
+    pub fn parse_weights(input: &str) -> Result<EvaluationParameters, Error> {
+        let mut parameters = DEFAULT_PARAMETERS;
+        let names = parameters.names();
+        let mut named: Vec<(String, i32)> = vec![];
+        for line in input.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut fields = line.split_whitespace();
+            match (fields.next(), fields.next(), fields.next()) {
+                (Some(name), Some(value), None) => {
+                    if !names.iter().any(|known| known == name) {
+                        return Err(ParseError::new(ParseErrorKind::UnknownName, input, name, format!("Unknown parameter: {}", name)).into());
+                    }
+                    let value = value.parse()
+                        .map_err(|_| ParseError::new(ParseErrorKind::InvalidNumber, input, value, format!("Invalid value for {}: {}", name, value)))?;
+                    named.push((String::from(name), value));
+                },
+                _ => { return Err(ParseError::new(ParseErrorKind::FieldCount, input, line, String::from("Expect a name and a value")).into()); }
+            }
+        }
+
+        parameters.visit_mut(|name, value| {
+            if let Some((_, new_value)) = named.iter().rev().find(|(named_name, _)| named_name == name) {
+                *value = *new_value;
+            }
+        });
+        Ok(parameters)
+    }
+
+    pub fn to_weights(self) -> String {
+        let mut output = String::new();
+        let mut parameters = self;
+        parameters.visit_mut(|name, value| {
+            writeln!(output, "{} {}", name, value).unwrap();
+        });
+        output
+    }
+
+    // Rust source for a constant vector of the parameters, to be loaded with `from_vector`.
+    pub fn to_rust_source(self, const_name: &str) -> String {
+        let values = self.to_vector();
+        let mut output = String::new();
+        writeln!(output, "#[rustfmt::skip]").unwrap();
+        writeln!(output, "pub const {}: [i32; {}] = [", const_name, values.len()).unwrap();
+        for chunk in values.chunks(16) {
+            let line: Vec<String> = chunk.iter().map(|value| format!("{:>5}", value)).collect();
+            writeln!(output, "   {},", line.join(",")).unwrap();
+        }
+        writeln!(output, "];").unwrap();
+        output
+    }
+}
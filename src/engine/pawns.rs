@@ -0,0 +1,296 @@
+use std::fmt;
+
+use crate::board::{ Board, Color, Kind, Piece, Square };
+use crate::engine::score::{ TaperedScore };
+use crate::engine::zobrist::{ pawn_hash };
+
+#[derive(Debug,Copy,PartialEq,Eq,Clone,Hash)]
+pub struct PawnWeights {
+    pub doubled: TaperedScore,
+    pub isolated: TaperedScore,
+    pub backward: TaperedScore,
+    pub island: TaperedScore,
+    pub connected_passer: TaperedScore,
+    // Bonus for a passed pawn, indexed by the rank as seen from its own side (0 is the first rank).
+    pub passed: [TaperedScore; 8],
+    // A passed pawn with a piece right in front of it only gets this percentage of its bonus.
+    pub blocked_passed_percentage: i32,
+}
+
+pub const DEFAULT_PAWN_WEIGHTS: PawnWeights = PawnWeights {
+    doubled: TaperedScore::new(-10, -25),
+    isolated: TaperedScore::new(-15, -15),
+    backward: TaperedScore::new(-8, -12),
+    island: TaperedScore::new(-5, -10),
+    connected_passer: TaperedScore::new(10, 20),
+    passed: [
+        TaperedScore::new(0, 0),
+        TaperedScore::new(5, 10),
+        TaperedScore::new(10, 15),
+        TaperedScore::new(15, 30),
+        TaperedScore::new(30, 55),
+        TaperedScore::new(50, 95),
+        TaperedScore::new(80, 150),
+        TaperedScore::new(0, 0),
+    ],
+    blocked_passed_percentage: 50,
+};
+
+impl Default for PawnWeights {
+    fn default() -> PawnWeights {
+        DEFAULT_PAWN_WEIGHTS
+    }
+}
+
+// The pawn structure features of one color. Everything in here depends on the pawns only, so it can be cached by pawn hash.
+#[derive(Debug,Copy,PartialEq,Eq,Clone,Hash,Default)]
+pub struct PawnTerms {
+    // Pawns beyond the first on each file.
+    pub doubled: i32,
+    pub isolated: i32,
+    pub backward: i32,
+    // Pawn islands beyond the first.
+    pub islands: i32,
+    pub connected_passers: i32,
+    // The passed pawns as a bitmask over the square indices. They are scored later, as blockers are not pawns.
+    pub passed_pawns: u64,
+}
+
+impl PawnTerms {
+    fn scores(&self, board: &Board, color: Color, weights: &PawnWeights) -> PawnTermScores {
+        PawnTermScores {
+            doubled: weights.doubled * self.doubled,
+            isolated: weights.isolated * self.isolated,
+            backward: weights.backward * self.backward,
+            islands: weights.island * self.islands,
+            passed: passed_score(board, color, self.passed_pawns, weights),
+            connected_passers: weights.connected_passer * self.connected_passers,
+        }
+    }
+}
+
+#[derive(Debug,Copy,PartialEq,Eq,Clone,Hash,Default)]
+pub struct PawnStructure {
+    pub white: PawnTerms,
+    pub black: PawnTerms,
+}
+
+impl PawnStructure {
+    // The pawn structure score, positive being good for white.
+    pub fn score(&self, board: &Board, weights: &PawnWeights) -> TaperedScore {
+        let breakdown = self.breakdown(board, weights);
+        breakdown.white.total() - breakdown.black.total()
+    }
+
+    pub fn breakdown(&self, board: &Board, weights: &PawnWeights) -> PawnBreakdown {
+        PawnBreakdown {
+            white: self.white.scores(board, Color::White, weights),
+            black: self.black.scores(board, Color::Black, weights),
+        }
+    }
+}
+
+#[derive(Debug,Copy,PartialEq,Eq,Clone,Hash,Default)]
+pub struct PawnTermScores {
+    pub doubled: TaperedScore,
+    pub isolated: TaperedScore,
+    pub backward: TaperedScore,
+    pub islands: TaperedScore,
+    pub passed: TaperedScore,
+    pub connected_passers: TaperedScore,
+}
+
+impl PawnTermScores {
+    pub fn total(&self) -> TaperedScore {
+        self.doubled + self.isolated + self.backward + self.islands + self.passed + self.connected_passers
+    }
+}
+
+// Every pawn structure term for both colors, for debugging the evaluation.
+#[derive(Debug,Copy,PartialEq,Eq,Clone,Hash)]
+pub struct PawnBreakdown {
+    pub white: PawnTermScores,
+    pub black: PawnTermScores,
+}
+
+impl fmt::Display for PawnBreakdown {
+    // This trait requires `fmt` with this exact signature.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let white = &self.white;
+        let black = &self.black;
+        writeln!(f, "{:<18} {:>12} {:>12}", "term", "white", "black")?;
+        writeln!(f, "{:<18} {:>12} {:>12}", "doubled", white.doubled, black.doubled)?;
+        writeln!(f, "{:<18} {:>12} {:>12}", "isolated", white.isolated, black.isolated)?;
+        writeln!(f, "{:<18} {:>12} {:>12}", "backward", white.backward, black.backward)?;
+        writeln!(f, "{:<18} {:>12} {:>12}", "islands", white.islands, black.islands)?;
+        writeln!(f, "{:<18} {:>12} {:>12}", "passed", white.passed, black.passed)?;
+        writeln!(f, "{:<18} {:>12} {:>12}", "connected passers", white.connected_passers, black.connected_passers)
+    }
+}
+
+pub fn pawn_breakdown(board: &Board) -> PawnBreakdown {
+    evaluate_pawn_structure(board).breakdown(board, &DEFAULT_PAWN_WEIGHTS)
+}
+
+// Evaluate the pawn structure without consulting a cache.
+pub fn evaluate_pawn_structure(board: &Board) -> PawnStructure {
+    let white_pawns = pawn_mask(board, Color::White);
+    let black_pawns = pawn_mask(board, Color::Black);
+    PawnStructure {
+        white: pawn_terms(Color::White, white_pawns, black_pawns),
+        black: pawn_terms(Color::Black, black_pawns, white_pawns),
+    }
+}
+
+// A fixed size cache of pawn structures, replacing entries whose hash maps to the same slot.
+pub(crate) struct PawnHashTable {
+    entries: Vec<Option<(u64, PawnStructure)>>,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl PawnHashTable {
+    pub fn new(size: usize) -> PawnHashTable {
+        PawnHashTable {
+            entries: vec![None; size.max(1)],
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    pub fn probe(&mut self, board: &Board) -> PawnStructure {
+        let hash = pawn_hash(board);
+        let slot = (hash % self.entries.len() as u64) as usize;
+        match self.entries[slot] {
+            Some((entry_hash, structure)) if entry_hash == hash => {
+                self.hits += 1;
+                structure
+            },
+            _ => {
+                self.misses += 1;
+                let structure = evaluate_pawn_structure(board);
+                self.entries[slot] = Some((hash, structure));
+                structure
+            }
+        }
+    }
+}
+
+const FILE_A_MASK: u64 = 0x0101_0101_0101_0101;
+
+fn file_mask(file: usize) -> u64 {
+    FILE_A_MASK << file
+}
+
+fn adjacent_files_mask(file: usize) -> u64 {
+    let left = if file > 0 { file_mask(file - 1) } else { 0 };
+    let right = if file < 7 { file_mask(file + 1) } else { 0 };
+    left | right
+}
+
+// All squares on the ranks strictly in front of the given rank, as seen by the color.
+fn ranks_in_front_mask(color: Color, rank: usize) -> u64 {
+    match color {
+        Color::White => if rank >= 7 { 0 } else { !0u64 << (8 * (rank + 1)) },
+        Color::Black => if rank == 0 { 0 } else { !0u64 >> (8 * (8 - rank)) }
+    }
+}
+
+fn pawn_mask(board: &Board, color: Color) -> u64 {
+    board.squares().iter().enumerate().fold(0, |mask, (index, square)| {
+        if square.is_occupied_by(Piece(color, Kind::Pawn)) { mask | (1 << index) } else { mask }
+    })
+}
+
+fn pawn_attacks(color: Color, pawns: u64) -> u64 {
+    let not_file_a = !FILE_A_MASK;
+    let not_file_h = !(FILE_A_MASK << 7);
+    match color {
+        Color::White => ((pawns & not_file_a) << 7) | ((pawns & not_file_h) << 9),
+        Color::Black => ((pawns & not_file_h) >> 7) | ((pawns & not_file_a) >> 9)
+    }
+}
+
+fn pawn_terms(color: Color, own: u64, enemy: u64) -> PawnTerms {
+    let mut terms = PawnTerms::default();
+    let enemy_attacks = pawn_attacks(color.opposite(), enemy);
+
+    let mut islands = 0;
+    let mut in_island = false;
+    for file in 0..8 {
+        let on_file = (own & file_mask(file)).count_ones() as i32;
+        if on_file > 1 {
+            terms.doubled += on_file - 1;
+        }
+        if on_file > 0 && !in_island {
+            islands += 1;
+        }
+        in_island = on_file > 0;
+    }
+    if islands > 1 {
+        terms.islands = islands - 1;
+    }
+
+    for index in 0..64 {
+        if own & (1 << index) == 0 {
+            continue;
+        }
+        let file = index % 8;
+        let rank = index / 8;
+        let in_front = ranks_in_front_mask(color, rank);
+
+        if own & adjacent_files_mask(file) == 0 {
+            terms.isolated += 1;
+        } else {
+            // Backward: no friendly pawn beside or behind on an adjacent file that could support the advance,
+            // and the square in front is controlled by an enemy pawn.
+            let supporters = own & adjacent_files_mask(file) & !in_front;
+            let stop_square = match color {
+                Color::White => index + 8,
+                Color::Black => index.wrapping_sub(8)
+            };
+            if supporters == 0 && stop_square < 64 && enemy_attacks & (1 << stop_square) != 0 {
+                terms.backward += 1;
+            }
+        }
+
+        let front_span = in_front & (file_mask(file) | adjacent_files_mask(file));
+        if enemy & front_span == 0 && own & in_front & file_mask(file) == 0 {
+            terms.passed_pawns |= 1 << index;
+        }
+    }
+
+    // Passers on adjacent files within one rank of each other defend each other's advance.
+    for index in 0..64 {
+        if terms.passed_pawns & (1 << index) == 0 {
+            continue;
+        }
+        let rank = index / 8;
+        let near_ranks = (0xFFu64 << (8 * rank)) | (if rank > 0 { 0xFF << (8 * (rank - 1)) } else { 0 }) | (if rank < 7 { 0xFF << (8 * (rank + 1)) } else { 0 });
+        if terms.passed_pawns & adjacent_files_mask(index % 8) & near_ranks != 0 {
+            terms.connected_passers += 1;
+        }
+    }
+
+    terms
+}
+
+fn passed_score(board: &Board, color: Color, passed_pawns: u64, weights: &PawnWeights) -> TaperedScore {
+    let mut score = TaperedScore::default();
+    for index in 0..64 {
+        if passed_pawns & (1 << index) == 0 {
+            continue;
+        }
+        let rank = index / 8;
+        let relative_rank = if color == Color::White { rank } else { 7 - rank };
+        let bonus = weights.passed[relative_rank];
+        let stop_square = if color == Color::White { index + 8 } else { index.wrapping_sub(8) };
+        let blocked = stop_square < 64 && !matches!(board.squares()[stop_square], Square::Empty);
+        score += if blocked {
+            TaperedScore::new(bonus.middlegame * weights.blocked_passed_percentage / 100, bonus.endgame * weights.blocked_passed_percentage / 100)
+        } else {
+            bonus
+        };
+    }
+    score
+}
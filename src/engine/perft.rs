@@ -0,0 +1,24 @@
+use crate::board::{ Board, Color };
+use crate::engine::moves::{ next_boards };
+use crate::engine::notation::{ Move };
+
+// The number of move sequences of the given length, for checking the move generator against known counts.
+pub fn perft(board: &Board, color: Color, depth: u8) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    let children = next_boards(board, color);
+    if depth == 1 {
+        return children.len() as u64;
+    }
+    children.iter().map(|child| perft(child, color.opposite(), depth - 1)).sum()
+}
+
+// Perft split by the first move, in coordinate order, to find where counts differ.
+pub fn perft_divide(board: &Board, color: Color, depth: u8) -> Vec<(Move, u64)> {
+    let mut counts: Vec<(Move, u64)> = next_boards(board, color).iter()
+        .filter_map(|child| Move::between(board, child, color).map(|played| (played, perft(child, color.opposite(), depth.saturating_sub(1)))))
+        .collect();
+    counts.sort_by_key(|(played, _)| played.to_string());
+    counts
+}
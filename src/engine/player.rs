@@ -0,0 +1,85 @@
+use std::time::{ SystemTime, UNIX_EPOCH };
+
+use crate::board::{ Board, Color };
+use crate::book::{ BookSelection, OpeningBook };
+use crate::engine::search::{ search, SearchContext, SearchOptions, SearchState };
+use crate::tablebase::{ Tablebase };
+
+// Picks moves during a game: from the opening book while the game is still in book, then by searching,
+// with the help of endgame tables if there are any.
+pub struct Player {
+    pub depth: u8,
+    pub options: SearchOptions,
+    pub book: Option<OpeningBook>,
+    pub book_selection: BookSelection,
+    pub tablebase: Option<Box<dyn Tablebase + Send>>,
+    in_book: bool,
+    random_state: u64,
+    state: SearchState,
+}
+
+impl Player {
+    pub fn new(depth: u8, options: SearchOptions) -> Player {
+        let seed = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_nanos() as u64).unwrap_or(0);
+        Player {
+            depth,
+            options,
+            book: None,
+            book_selection: BookSelection::WeightedRandom,
+            tablebase: None,
+            in_book: true,
+            random_state: seed | 1,
+            state: SearchState::new(),
+        }
+    }
+
+    pub fn with_book(mut self, book: OpeningBook, selection: BookSelection) -> Player {
+        self.book = Some(book);
+        self.book_selection = selection;
+        self
+    }
+
+    pub fn with_tablebase<T: Tablebase + Send + 'static>(mut self, tablebase: T) -> Player {
+        self.tablebase = Some(Box::new(tablebase));
+        self
+    }
+
+    // Make book move choices repeatable.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.random_state = seed | 1;
+    }
+
+    pub fn new_game(&mut self) {
+        self.in_book = true;
+        self.state.clear();
+    }
+
+    pub fn in_book(&self) -> bool {
+        self.in_book && self.book.is_some()
+    }
+
+    // The board after the move the player chooses, or nothing when there are no legal moves.
+    pub fn play(&mut self, board: &Board, color: Color) -> Option<Board> {
+        if self.in_book {
+            let random = self.next_random();
+            match self.book.as_ref().and_then(|book| book.choose(board, color, self.book_selection, random)) {
+                Some((_, child)) => { return Some(child); },
+                // Once out of book, the game does not get back in.
+                None => { self.in_book = false; }
+            }
+        }
+        let mut context = SearchContext::new().with_state(&mut self.state);
+        if let Some(tablebase) = self.tablebase.as_deref() {
+            context = context.with_tablebase(tablebase as &dyn Tablebase);
+        }
+        search(board, color, &self.options.with_depth(self.depth), &mut context).best_board
+    }
+
+    // Xorshift, which is plenty for picking book moves.
+    fn next_random(&mut self) -> u64 {
+        self.random_state ^= self.random_state << 13;
+        self.random_state ^= self.random_state >> 7;
+        self.random_state ^= self.random_state << 17;
+        self.random_state
+    }
+}
@@ -1,26 +1,381 @@
-use crate::board::{ Board, Color, Kind, Piece, Square };
+use std::fmt;
+use std::ops::{ Add, AddAssign, Mul, Neg, Sub };
 
-// Assign a score to the board, with a positive score being good for white, a negative score being good for black.
+use crate::board::{ Board, Color, Coordinates, Kind, Piece, Square };
+use crate::engine::king_safety::{ king_safety_score };
+use crate::engine::mobility::{ mobility_score };
+use crate::engine::parameters::{ EvaluationParameters, DEFAULT_PARAMETERS };
+use crate::engine::pawns::{ evaluate_pawn_structure, PawnStructure };
+
+// Assign a score to the board in centipawns, with a positive score being good for white, a negative score being good for black.
 pub fn score_board(board: &Board) -> i32 {
-    fn piece_score(piece: Piece) -> i32 {
-        kind_score(piece.1) * (if piece.0 == Color::Black { -1 } else { 1 })
-    }
-    fn kind_score(kind: Kind) -> i32 {
-        match kind {
-            Kind::Pawn => 1,
-            Kind::Knight => 3,
-            Kind::Bishop => 3,
-            Kind::Rook => 5,
-            Kind::Queen => 9,
-            Kind::King => 0
+    score_board_with(board, &evaluate_pawn_structure(board), &DEFAULT_PARAMETERS)
+}
+
+// Score the board with the given pawn structure and parameters, so callers can bring their own cache and tuning.
+pub fn score_board_with(board: &Board, pawn_structure: &PawnStructure, parameters: &EvaluationParameters) -> i32 {
+    let score = piece_square_score(board, parameters)
+        + pawn_structure.score(board, &parameters.pawns)
+        + mobility_score(board, &parameters.mobility)
+        + king_safety_score(board, &parameters.king_safety);
+
+    score.taper(game_phase(board))
+}
+
+// The material and piece-square table score, positive being good for white.
+pub fn piece_square_score(board: &Board, parameters: &EvaluationParameters) -> TaperedScore {
+    let mut score = TaperedScore::default();
+    for (coordinates, square) in board.squares_coordinates_iter() {
+        if let Square::Occupied(piece) = *square {
+            let piece_score = parameters.piece_values[piece.1.index()] + parameters.piece_square_tables[piece.1.index()][table_index(piece.0, coordinates)];
+            score += if piece.0 == Color::White { piece_score } else { -piece_score };
         }
     }
+    score
+}
+
+// The terms of the evaluation, each positive being good for white, as `score_board` adds them up.
+#[derive(Debug,Copy,PartialEq,Eq,Clone,Hash)]
+pub struct EvaluationBreakdown {
+    pub material: TaperedScore,
+    pub piece_squares: TaperedScore,
+    pub pawns: TaperedScore,
+    pub mobility: TaperedScore,
+    pub king_safety: TaperedScore,
+    pub phase: i32,
+}
+
+impl EvaluationBreakdown {
+    pub fn terms(&self) -> [(&'static str, TaperedScore); 5] {
+        [
+            ("Material", self.material),
+            ("Piece squares", self.piece_squares),
+            ("Pawns", self.pawns),
+            ("Mobility", self.mobility),
+            ("King safety", self.king_safety),
+        ]
+    }
+
+    // The same as `score_board`: the terms are added up before tapering.
+    pub fn total(&self) -> i32 {
+        self.terms().iter().fold(TaperedScore::default(), |total, (_, score)| total + *score).taper(self.phase)
+    }
+}
+
+impl fmt::Display for EvaluationBreakdown {
+    // This trait requires `fmt` with this exact signature.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{:<14} {:>10} {:>10} {:>10}", "Term", "Middlegame", "Endgame", "Tapered")?;
+        for (name, score) in self.terms() {
+            writeln!(f, "{:<14} {:>10} {:>10} {:>10}", name, score.middlegame, score.endgame, score.taper(self.phase))?;
+        }
+        writeln!(f, "{:<14} {:>10}", "Phase", format!("{}/{}", self.phase, MAX_PHASE))?;
+        writeln!(f, "{:<14} {:>32}", "Total", self.total())
+    }
+}
+
+// Score the board term by term with the default parameters.
+pub fn evaluation_breakdown(board: &Board) -> EvaluationBreakdown {
+    let parameters = &DEFAULT_PARAMETERS;
+    let material = board.squares().iter().fold(TaperedScore::default(), |material, square| match *square {
+        Square::Occupied(Piece(Color::White, kind)) => material + parameters.piece_values[kind.index()],
+        Square::Occupied(Piece(Color::Black, kind)) => material - parameters.piece_values[kind.index()],
+        Square::Empty => material
+    });
+    EvaluationBreakdown {
+        material,
+        piece_squares: piece_square_score(board, parameters) - material,
+        pawns: evaluate_pawn_structure(board).score(board, &parameters.pawns),
+        mobility: mobility_score(board, &parameters.mobility),
+        king_safety: king_safety_score(board, &parameters.king_safety),
+        phase: game_phase(board),
+    }
+}
+
+// A pair of middlegame and endgame scores, to be interpolated by game phase.
+#[derive(Debug,Copy,PartialEq,Eq,Clone,Hash,Default)]
+pub struct TaperedScore {
+    pub middlegame: i32,
+    pub endgame: i32,
+}
+
+impl TaperedScore {
+    pub const fn new(middlegame: i32, endgame: i32) -> TaperedScore {
+        TaperedScore { middlegame, endgame }
+    }
+
+    pub fn taper(&self, phase: i32) -> i32 {
+        taper(self.middlegame, self.endgame, phase)
+    }
+}
+
+impl Add for TaperedScore {
+    type Output = TaperedScore;
+
+    fn add(self, other: TaperedScore) -> TaperedScore {
+        TaperedScore::new(self.middlegame + other.middlegame, self.endgame + other.endgame)
+    }
+}
+
+impl AddAssign for TaperedScore {
+    fn add_assign(&mut self, other: TaperedScore) {
+        *self = *self + other;
+    }
+}
+
+impl Sub for TaperedScore {
+    type Output = TaperedScore;
+
+    fn sub(self, other: TaperedScore) -> TaperedScore {
+        TaperedScore::new(self.middlegame - other.middlegame, self.endgame - other.endgame)
+    }
+}
+
+impl Neg for TaperedScore {
+    type Output = TaperedScore;
+
+    fn neg(self) -> TaperedScore {
+        TaperedScore::new(-self.middlegame, -self.endgame)
+    }
+}
+
+impl Mul<i32> for TaperedScore {
+    type Output = TaperedScore;
+
+    fn mul(self, factor: i32) -> TaperedScore {
+        TaperedScore::new(self.middlegame * factor, self.endgame * factor)
+    }
+}
 
-    // Just sum the pieces score.
-    let pieces_score = board.squares().iter().map(|square| match square {
-        Square::Occupied(piece) => piece_score(*piece),
+impl fmt::Display for TaperedScore {
+    // This trait requires `fmt` with this exact signature.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(&format!("({}, {})", self.middlegame, self.endgame))
+    }
+}
+
+// The total phase weight of the pieces in the starting position.
+pub const MAX_PHASE: i32 = 24;
+
+// How far the game is from the endgame: `MAX_PHASE` with all pieces on the board, 0 with only kings and pawns.
+pub fn game_phase(board: &Board) -> i32 {
+    let phase: i32 = board.squares().iter().map(|square| match square {
+        Square::Occupied(Piece(_, kind)) => kind_phase(*kind),
         _ => 0
     }).sum();
 
-    pieces_score
+    // Promotions can push the phase beyond the starting position.
+    phase.min(MAX_PHASE)
+}
+
+// Interpolate between the middlegame and the endgame score according to the phase.
+pub fn taper(middlegame: i32, endgame: i32, phase: i32) -> i32 {
+    (middlegame * phase + endgame * (MAX_PHASE - phase)) / MAX_PHASE
+}
+
+fn kind_phase(kind: Kind) -> i32 {
+    match kind {
+        Kind::Knight => 1,
+        Kind::Bishop => 1,
+        Kind::Rook => 2,
+        Kind::Queen => 4,
+        Kind::Pawn | Kind::King => 0
+    }
+}
+
+pub const fn middlegame_kind_value(kind: Kind) -> i32 {
+    match kind {
+        Kind::Pawn => 82,
+        Kind::Knight => 337,
+        Kind::Bishop => 365,
+        Kind::Rook => 477,
+        Kind::Queen => 1025,
+        Kind::King => 0
+    }
+}
+
+pub const fn endgame_kind_value(kind: Kind) -> i32 {
+    match kind {
+        Kind::Pawn => 94,
+        Kind::Knight => 281,
+        Kind::Bishop => 297,
+        Kind::Rook => 512,
+        Kind::Queen => 936,
+        Kind::King => 0
+    }
 }
+
+// The tables below are laid out as seen from white's side of the board, with a8 in the top left corner.
+// Black reads them mirrored vertically, which keeps the evaluation symmetric.
+fn table_index(color: Color, coordinates: Coordinates) -> usize {
+    match color {
+        Color::White => coordinates.index() ^ 56,
+        Color::Black => coordinates.index()
+    }
+}
+
+// The default piece-square tables, indexed by `Kind::index`.
+pub const fn default_piece_square_tables() -> [[TaperedScore; 64]; 6] {
+    const fn combine(middlegame: [i32; 64], endgame: [i32; 64]) -> [TaperedScore; 64] {
+        let mut table = [TaperedScore::new(0, 0); 64];
+        let mut index = 0;
+        while index < 64 {
+            table[index] = TaperedScore::new(middlegame[index], endgame[index]);
+            index += 1;
+        }
+        table
+    }
+
+    [
+        combine(ROOK_MIDDLEGAME, ROOK_ENDGAME),
+        combine(KNIGHT_MIDDLEGAME, KNIGHT_ENDGAME),
+        combine(BISHOP_MIDDLEGAME, BISHOP_ENDGAME),
+        combine(QUEEN_MIDDLEGAME, QUEEN_ENDGAME),
+        combine(KING_MIDDLEGAME, KING_ENDGAME),
+        combine(PAWN_MIDDLEGAME, PAWN_ENDGAME),
+    ]
+}
+
+#[rustfmt::skip]
+const PAWN_MIDDLEGAME: [i32; 64] = [
+      0,   0,   0,   0,   0,   0,   0,   0,
+     98, 134,  61,  95,  68, 126,  34, -11,
+     -6,   7,  26,  31,  65,  56,  25, -20,
+    -14,  13,   6,  21,  23,  12,  17, -23,
+    -27,  -2,  -5,  12,  17,   6,  10, -25,
+    -26,  -4,  -4, -10,   3,   3,  33, -12,
+    -35,  -1, -20, -23, -15,  24,  38, -22,
+      0,   0,   0,   0,   0,   0,   0,   0,
+];
+
+#[rustfmt::skip]
+const PAWN_ENDGAME: [i32; 64] = [
+      0,   0,   0,   0,   0,   0,   0,   0,
+    178, 173, 158, 134, 147, 132, 165, 187,
+     94, 100,  85,  67,  56,  53,  82,  84,
+     32,  24,  13,   5,  -2,   4,  17,  17,
+     13,   9,  -3,  -7,  -7,  -8,   3,  -1,
+      4,   7,  -6,   1,   0,  -5,  -1,  -8,
+     13,   8,   8,  10,  13,   0,   2,  -7,
+      0,   0,   0,   0,   0,   0,   0,   0,
+];
+
+#[rustfmt::skip]
+const KNIGHT_MIDDLEGAME: [i32; 64] = [
+   -167, -89, -34, -49,  61, -97, -15,-107,
+    -73, -41,  72,  36,  23,  62,   7, -17,
+    -47,  60,  37,  65,  84, 129,  73,  44,
+     -9,  17,  19,  53,  37,  69,  18,  22,
+    -13,   4,  16,  13,  28,  19,  21,  -8,
+    -23,  -9,  12,  10,  19,  17,  25, -16,
+    -29, -53, -12,  -3,  -1,  18, -14, -19,
+   -105, -21, -58, -33, -17, -28, -19, -23,
+];
+
+#[rustfmt::skip]
+const KNIGHT_ENDGAME: [i32; 64] = [
+    -58, -38, -13, -28, -31, -27, -63, -99,
+    -25,  -8, -25,  -2,  -9, -25, -24, -52,
+    -24, -20,  10,   9,  -1,  -9, -19, -41,
+    -17,   3,  22,  22,  22,  11,   8, -18,
+    -18,  -6,  16,  25,  16,  17,   4, -18,
+    -23,  -3,  -1,  15,  10,  -3, -20, -22,
+    -42, -20, -10,  -5,  -2, -20, -23, -44,
+    -29, -51, -23, -15, -22, -18, -50, -64,
+];
+
+#[rustfmt::skip]
+const BISHOP_MIDDLEGAME: [i32; 64] = [
+    -29,   4, -82, -37, -25, -42,   7,  -8,
+    -26,  16, -18, -13,  30,  59,  18, -47,
+    -16,  37,  43,  40,  35,  50,  37,  -2,
+     -4,   5,  19,  50,  37,  37,   7,  -2,
+     -6,  13,  13,  26,  34,  12,  10,   4,
+      0,  15,  15,  15,  14,  27,  18,  10,
+      4,  15,  16,   0,   7,  21,  33,   1,
+    -33,  -3, -14, -21, -13, -12, -39, -21,
+];
+
+#[rustfmt::skip]
+const BISHOP_ENDGAME: [i32; 64] = [
+    -14, -21, -11,  -8,  -7,  -9, -17, -24,
+     -8,  -4,   7, -12,  -3, -13,  -4, -14,
+      2,  -8,   0,  -1,  -2,   6,   0,   4,
+     -3,   9,  12,   9,  14,  10,   3,   2,
+     -6,   3,  13,  19,   7,  10,  -3,  -9,
+    -12,  -3,   8,  10,  13,   3,  -7, -15,
+    -14, -18,  -7,  -1,   4,  -9, -15, -27,
+    -23,  -9, -23,  -5,  -9, -16,  -5, -17,
+];
+
+#[rustfmt::skip]
+const ROOK_MIDDLEGAME: [i32; 64] = [
+     32,  42,  32,  51,  63,   9,  31,  43,
+     27,  32,  58,  62,  80,  67,  26,  44,
+     -5,  19,  26,  36,  17,  45,  61,  16,
+    -24, -11,   7,  26,  24,  35,  -8, -20,
+    -36, -26, -12,  -1,   9,  -7,   6, -23,
+    -45, -25, -16, -17,   3,   0,  -5, -33,
+    -44, -16, -20,  -9,  -1,  11,  -6, -71,
+    -19, -13,   1,  17,  16,   7, -37, -26,
+];
+
+#[rustfmt::skip]
+const ROOK_ENDGAME: [i32; 64] = [
+     13,  10,  18,  15,  12,  12,   8,   5,
+     11,  13,  13,  11,  -3,   3,   8,   3,
+      7,   7,   7,   5,   4,  -3,  -5,  -3,
+      4,   3,  13,   1,   2,   1,  -1,   2,
+      3,   5,   8,   4,  -5,  -6,  -8, -11,
+     -4,   0,  -5,  -1,  -7, -12,  -8, -16,
+     -6,  -6,   0,   2,  -9,  -9, -11,  -3,
+     -9,   2,   3,  -1,  -5, -13,   4, -20,
+];
+
+#[rustfmt::skip]
+const QUEEN_MIDDLEGAME: [i32; 64] = [
+    -28,   0,  29,  12,  59,  44,  43,  45,
+    -24, -39,  -5,   1, -16,  57,  28,  54,
+    -13, -17,   7,   8,  29,  56,  47,  57,
+    -27, -27, -16, -16,  -1,  17,  -2,   1,
+     -9, -26,  -9, -10,  -2,  -4,   3,  -3,
+    -14,   2, -11,  -2,  -5,   2,  14,   5,
+    -35,  -8,  11,   2,   8,  15,  -3,   1,
+     -1, -18,  -9,  10, -15, -25, -31, -50,
+];
+
+#[rustfmt::skip]
+const QUEEN_ENDGAME: [i32; 64] = [
+     -9,  22,  22,  27,  27,  19,  10,  20,
+    -17,  20,  32,  41,  58,  25,  30,   0,
+    -20,   6,   9,  49,  47,  35,  19,   9,
+      3,  22,  24,  45,  57,  40,  57,  36,
+    -18,  28,  19,  47,  31,  34,  39,  23,
+    -16, -27,  15,   6,   9,  17,  10,   5,
+    -22, -23, -30, -16, -16, -23, -36, -32,
+    -33, -28, -22, -43,  -5, -32, -20, -41,
+];
+
+#[rustfmt::skip]
+const KING_MIDDLEGAME: [i32; 64] = [
+    -65,  23,  16, -15, -56, -34,   2,  13,
+     29,  -1, -20,  -7,  -8,  -4, -38, -29,
+     -9,  24,   2, -16, -20,   6,  22, -22,
+    -17, -20, -12, -27, -30, -25, -14, -36,
+    -49,  -1, -27, -39, -46, -44, -33, -51,
+    -14, -14, -22, -46, -44, -30, -15, -27,
+      1,   7,  -8, -64, -43, -16,   9,   8,
+    -15,  36,  12, -54,   8, -28,  24,  14,
+];
+
+#[rustfmt::skip]
+const KING_ENDGAME: [i32; 64] = [
+    -74, -35, -18, -18, -11,  15,   4, -17,
+    -12,  17,  14,  17,  17,  38,  23,  11,
+     10,  17,  23,  15,  20,  45,  44,  13,
+     -8,  22,  24,  27,  26,  33,  26,   3,
+    -18,  -4,  21,  24,  27,  23,   9, -11,
+    -19,  -3,  11,  21,  23,  16,   7,  -9,
+    -27, -11,   4,  13,  14,   4,  -5, -17,
+    -53, -34, -21, -11, -28, -14, -24, -43,
+];
@@ -1,7 +1,11 @@
 use std::fmt;
-use std::collections::{ HashMap };
 
-#[derive(Debug)]
+use crate::error::{ Error };
+
+// A minimax tree of scored positions. Every node has the score of its own position and a target
+// for picking among its children. The deep score of a node is the score of the line its children
+// lead to, and is kept up to date as nodes are added.
+#[derive(Debug,Clone)]
 pub struct ScoreTree {
     score_items: Vec<ScoreItem>,
     child_indices: Vec<Vec<usize>>
@@ -24,10 +28,18 @@ impl fmt::Display for ScoreTarget {
 struct ScoreItem {
     local_score: i32,
     score_target: ScoreTarget,
+    // Only for nodes with children.
     deep_score: Option<DeepScore>,
     parent_index: Option<usize>,
 }
 
+impl ScoreItem {
+    // What the node is worth to its parent.
+    fn effective_score(&self) -> DeepScore {
+        self.deep_score.unwrap_or(DeepScore { score: self.local_score, depth: 0 })
+    }
+}
+
 impl fmt::Display for ScoreItem {
     // This trait requires `fmt` with this exact signature.
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -39,6 +51,7 @@ impl fmt::Display for ScoreItem {
     }
 }
 
+// A score found deeper in the tree, with the number of plies below the node it was found at.
 #[derive(Debug,Copy,PartialEq,Eq,Clone,Hash)]
 struct DeepScore {
     score: i32,
@@ -52,6 +65,12 @@ impl fmt::Display for DeepScore {
     }
 }
 
+impl Default for ScoreTree {
+    fn default() -> ScoreTree {
+        ScoreTree::new()
+    }
+}
+
 impl ScoreTree {
     pub fn new() -> ScoreTree {
         ScoreTree {
@@ -60,12 +79,13 @@ impl ScoreTree {
         }
     }
 
-    pub fn add(&mut self, local_score: i32, score_target: ScoreTarget, parent_index: Option<usize>) -> Result<usize, &str> {
+    // Add a node, as a new root or below an existing one. Returns its index.
+    pub fn add(&mut self, local_score: i32, score_target: ScoreTarget, parent_index: Option<usize>) -> Result<usize, Error> {
         let len = self.score_items.len();
 
         if let Some(index) = parent_index {
             if index >= len {
-                return Err("parent_index is too large");
+                return Err(Error::InvalidArgument(format!("Invalid parent index: {}", index)));
             }
         }
 
@@ -80,52 +100,94 @@ impl ScoreTree {
         // Add the child index.
         if let Some(parent_index) = parent_index {
             self.child_indices[parent_index].push(len);
+            self.update_deep_scores(parent_index);
         }
 
-        self.remove_ancestor_deep_scores(len);
-
         Ok(len)
     }
 
-    fn remove_ancestor_deep_scores(&mut self, index: usize) {
-        let score_item = self.score_items[index];
-        if let Some(parent_index) = score_item.parent_index {
-            self.score_items[parent_index].deep_score = None;
-            self.remove_ancestor_deep_scores(parent_index);
-        }
+    pub fn len(&self) -> usize {
+        self.score_items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.score_items.is_empty()
     }
 
+    // The deep score of the node, or its own score when it has no children.
     pub fn score_at(&self, index: usize) -> Option<i32> {
-        return Some(0)
+        self.score_items.get(index).map(|item| item.effective_score().score)
     }
 
-    pub fn subtree(&self, root_index: usize) -> ScoreTree {
-        let mut sub_items = vec![];
-        let mut sub_child_indices = vec![];
+    // The number of plies below the node where its deep score comes from.
+    pub fn depth_at(&self, index: usize) -> Option<i8> {
+        self.score_items.get(index).map(|item| item.effective_score().depth)
+    }
 
-        fn add_sub_items(tree: &ScoreTree, parent_index: usize, &mut sub_items: Vec<ScoreItem>, &mut sub_child_indices: Vec<Vec<usize>>) {
+    pub fn children(&self, index: usize) -> &[usize] {
+        self.child_indices.get(index).map(|children| children.as_slice()).unwrap_or(&[])
+    }
 
-        }
+    pub fn best_child(&self, index: usize) -> Option<usize> {
+        self.choose_child(index).map(|(child_index, _)| child_index)
+    }
 
-        let mut parent_mapping = HashMap::new();
+    // The node and the best child at every level below it, down to a leaf.
+    pub fn best_path(&self, index: usize) -> Vec<usize> {
+        let mut path = vec![];
+        let mut next = Some(index).filter(|index| *index < self.score_items.len());
+        while let Some(index) = next {
+            path.push(index);
+            next = self.best_child(index);
+        }
+        path
+    }
 
-        for (index, item) in self.score_items.iter().enumerate() {
-            if index == root_index {
-                parent_mapping.insert(index, None);
-            } else {
-                if let Some(parent_index) = item.parent_index {
-                    if parent_mapping.contains_key(&parent_index) {
-                        let mut new_item = item.clone();
-                        new_item.parent_index = *parent_mapping.get(&parent_index).unwrap();
-                        parent_mapping.insert(index, Some(sub_items.len()));
-                        sub_items.push(new_item);
-                    }
-                }
+    // A copy of the node and everything below it, the node being the only root.
+    pub fn subtree(&self, root_index: usize) -> ScoreTree {
+        fn add_sub_items(tree: &ScoreTree, index: usize, parent_index: Option<usize>, subtree: &mut ScoreTree) {
+            let sub_index = subtree.score_items.len();
+            subtree.score_items.push(ScoreItem { parent_index, ..tree.score_items[index] });
+            subtree.child_indices.push(vec![]);
+            if let Some(parent_index) = parent_index {
+                subtree.child_indices[parent_index].push(sub_index);
+            }
+            for child_index in tree.child_indices[index].iter() {
+                add_sub_items(tree, *child_index, Some(sub_index), subtree);
             }
         }
 
-        ScoreTree {
-            score_items: sub_items
+        let mut subtree = ScoreTree::new();
+        if root_index < self.score_items.len() {
+            add_sub_items(self, root_index, None, &mut subtree);
+        }
+        subtree
+    }
+
+    fn choose_child(&self, index: usize) -> Option<(usize, DeepScore)> {
+        let item = self.score_items.get(index)?;
+        self.child_indices[index].iter()
+            .map(|child_index| {
+                let score = self.score_items[*child_index].effective_score();
+                (*child_index, DeepScore { score: score.score, depth: score.depth.saturating_add(1) })
+            })
+            .reduce(|best, next| {
+                let chosen = choose_score(item.local_score, item.score_target, best.1, next.1);
+                if chosen == best.1 { best } else { next }
+            })
+    }
+
+    // Recompute the deep scores from the node up to its root.
+    fn update_deep_scores(&mut self, index: usize) {
+        let mut next = Some(index);
+        while let Some(index) = next {
+            let deep_score = self.choose_child(index).map(|(_, score)| score);
+            if deep_score == self.score_items[index].deep_score {
+                // Nothing changes further up.
+                break;
+            }
+            self.score_items[index].deep_score = deep_score;
+            next = self.score_items[index].parent_index;
         }
     }
 }
@@ -159,7 +221,7 @@ fn choose_score(local_score: i32, score_target: ScoreTarget, score1: DeepScore,
                     // The scores are higher than the local score. Choose the one that is farthest (delay losses).
                     if score2.depth > score1.depth { score2 } else { score1 }
                 } else {
-                    // The scores are lower than the local score. Choose the one that is farthest (strike sooner).
+                    // The scores are lower than the local score. Choose the one that is closest (strike sooner).
                     if score1.depth > score2.depth { score2 } else { score1 }
                 }
             }
@@ -170,17 +232,17 @@ fn choose_score(local_score: i32, score_target: ScoreTarget, score1: DeepScore,
 impl fmt::Display for ScoreTree {
     // This trait requires `fmt` with this exact signature.
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        // TODO: make this efficient for large trees. This is O(n^2) but it's just for simple debugging at this stage.
-        fn fmt_with_prefix(f: &mut fmt::Formatter, tree: &ScoreTree, prefix: &str) -> fmt::Result {
-            for (index, item) in tree.score_items.iter().enumerate().filter(|(_, item)| item.parent_index.is_none()) {
-                writeln!(f, "{}- {}", prefix, item)?;
-                fmt_with_prefix(f, &tree.subtree(index), &format!("{}  |", prefix))?;
+        fn fmt_with_prefix(f: &mut fmt::Formatter, tree: &ScoreTree, indices: &[usize], prefix: &str) -> fmt::Result {
+            for index in indices {
+                writeln!(f, "{}- {}", prefix, tree.score_items[*index])?;
+                fmt_with_prefix(f, tree, &tree.child_indices[*index], &format!("{}  |", prefix))?;
             }
             Ok(())
         }
 
+        let roots: Vec<usize> = (0..self.score_items.len()).filter(|index| self.score_items[*index].parent_index.is_none()).collect();
         writeln!(f, "ScoreTree {{")?;
-        fmt_with_prefix(f, self, "")?;
+        fmt_with_prefix(f, self, &roots, "")?;
         writeln!(f, "}}")
     }
 }
@@ -0,0 +1,823 @@
+use std::sync::atomic::{ AtomicBool, Ordering };
+use std::thread;
+use std::time::{ Instant };
+
+use crate::board::{ Board, Color, Kind, Piece, Square };
+use crate::engine::analysis::{ is_checked };
+use crate::engine::evaluator::{ Evaluator, PositionalEvaluator };
+use crate::engine::moves::{ next_boards };
+use crate::engine::score::{ middlegame_kind_value };
+use crate::engine::search_trace::{ Cutoff, SearchTrace, TraceLimits, Tracer };
+use crate::engine::transposition::{ Bound, TableEntry, TranspositionTable };
+use crate::engine::zobrist::{ position_hash };
+use crate::tablebase::{ Tablebase, Wdl };
+
+// Scores at or beyond this magnitude (minus the mate distance) mean a forced mate.
+pub const MATE_SCORE: i32 = 1_000_000;
+
+const MAX_PLY: usize = 128;
+
+// Tablebase wins score below any mate, so a mate found by the search is still preferred.
+pub const TABLEBASE_WIN_SCORE: i32 = 20_000;
+
+// How deep the default options search: a few seconds at most without a clock.
+const DEFAULT_DEPTH: u8 = 4;
+
+// Pruning margins in centipawns, indexed by remaining depth.
+const FUTILITY_MARGINS: [i32; 3] = [0, 200, 500];
+const REVERSE_FUTILITY_MARGIN: i32 = 150;
+const REVERSE_FUTILITY_MAX_DEPTH: u8 = 3;
+
+const NULL_MOVE_MIN_DEPTH: u8 = 3;
+const LMR_MIN_DEPTH: u8 = 3;
+const LMR_FULL_DEPTH_MOVES: usize = 3;
+
+// How often, in nodes, a thread checks the clock and whether it was asked to stop.
+const STOP_CHECK_INTERVAL: u64 = 64;
+
+// How deep to search, and switches for the selective search techniques, so each can be measured
+// separately. Made with `default` or `plain` and the `with_` methods, so fields can be added.
+#[derive(Debug,Copy,PartialEq,Eq,Clone,Hash)]
+#[non_exhaustive]
+pub struct SearchOptions {
+    // Iterative deepening stops here, unless a limit ends the search sooner.
+    pub depth: u8,
+    pub null_move_pruning: bool,
+    pub late_move_reductions: bool,
+    pub futility_pruning: bool,
+    pub reverse_futility_pruning: bool,
+    pub check_extensions: bool,
+    // Number of best root moves to search with their own score and principal variation.
+    pub multi_pv: usize,
+    // Number of threads searching together (Lazy SMP). One thread gives reproducible results.
+    pub threads: usize,
+    pub hash_megabytes: usize,
+    // Record the tree searched by the last finished iteration, for debugging. Slows the search down.
+    pub trace: Option<TraceLimits>,
+}
+
+impl SearchOptions {
+    // All selective techniques disabled: a plain alpha-beta search with quiescence.
+    pub fn plain() -> SearchOptions {
+        SearchOptions {
+            depth: DEFAULT_DEPTH,
+            null_move_pruning: false,
+            late_move_reductions: false,
+            futility_pruning: false,
+            reverse_futility_pruning: false,
+            check_extensions: false,
+            multi_pv: 1,
+            threads: 1,
+            hash_megabytes: 16,
+            trace: None,
+        }
+    }
+
+    pub fn with_depth(mut self, depth: u8) -> SearchOptions {
+        self.depth = depth;
+        self
+    }
+
+    pub fn with_null_move_pruning(mut self, enabled: bool) -> SearchOptions {
+        self.null_move_pruning = enabled;
+        self
+    }
+
+    pub fn with_late_move_reductions(mut self, enabled: bool) -> SearchOptions {
+        self.late_move_reductions = enabled;
+        self
+    }
+
+    pub fn with_futility_pruning(mut self, enabled: bool) -> SearchOptions {
+        self.futility_pruning = enabled;
+        self
+    }
+
+    pub fn with_reverse_futility_pruning(mut self, enabled: bool) -> SearchOptions {
+        self.reverse_futility_pruning = enabled;
+        self
+    }
+
+    pub fn with_check_extensions(mut self, enabled: bool) -> SearchOptions {
+        self.check_extensions = enabled;
+        self
+    }
+
+    pub fn with_multi_pv(mut self, multi_pv: usize) -> SearchOptions {
+        self.multi_pv = multi_pv;
+        self
+    }
+
+    pub fn with_threads(mut self, threads: usize) -> SearchOptions {
+        self.threads = threads;
+        self
+    }
+
+    pub fn with_hash_megabytes(mut self, hash_megabytes: usize) -> SearchOptions {
+        self.hash_megabytes = hash_megabytes;
+        self
+    }
+
+    pub fn with_trace(mut self, trace: TraceLimits) -> SearchOptions {
+        self.trace = Some(trace);
+        self
+    }
+}
+
+impl Default for SearchOptions {
+    fn default() -> SearchOptions {
+        SearchOptions {
+            depth: DEFAULT_DEPTH,
+            null_move_pruning: true,
+            late_move_reductions: true,
+            futility_pruning: true,
+            reverse_futility_pruning: true,
+            check_extensions: true,
+            multi_pv: 1,
+            threads: 1,
+            hash_megabytes: 16,
+            trace: None,
+        }
+    }
+}
+
+// Ways to end a search before it reaches its depth. The search then returns the last iteration
+// it finished. None are set by default.
+#[derive(Debug,Copy,Clone,Default)]
+#[non_exhaustive]
+pub struct SearchLimits<'a> {
+    // Set from another thread to stop the search.
+    pub stop: Option<&'a AtomicBool>,
+    // Nodes per thread.
+    pub max_nodes: Option<u64>,
+    pub deadline: Option<Instant>,
+    // The only root moves to consider, as boards after the move, like UCI's `searchmoves`.
+    pub root_moves: Option<&'a [Board]>,
+}
+
+impl<'a> SearchLimits<'a> {
+    pub fn new() -> SearchLimits<'a> {
+        SearchLimits::default()
+    }
+
+    pub fn with_stop(mut self, stop: &'a AtomicBool) -> SearchLimits<'a> {
+        self.stop = Some(stop);
+        self
+    }
+
+    pub fn with_max_nodes(mut self, max_nodes: u64) -> SearchLimits<'a> {
+        self.max_nodes = Some(max_nodes);
+        self
+    }
+
+    pub fn with_deadline(mut self, deadline: Instant) -> SearchLimits<'a> {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    pub fn with_root_moves(mut self, root_moves: &'a [Board]) -> SearchLimits<'a> {
+        self.root_moves = Some(root_moves);
+        self
+    }
+}
+
+// What a search works with besides the position and the options: the evaluator, the endgame
+// tables, the limits, a callback for every finished iteration and the state kept from earlier
+// searches. A new context searches with the positional evaluator, no tables, no limits and a
+// state of its own.
+#[derive(Default)]
+pub struct SearchContext<'a> {
+    evaluator: Option<&'a mut dyn Evaluator>,
+    tablebase: Option<&'a dyn Tablebase>,
+    limits: SearchLimits<'a>,
+    report: Option<&'a mut dyn FnMut(&SearchResult)>,
+    state: Option<&'a mut SearchState>,
+}
+
+impl<'a> SearchContext<'a> {
+    pub fn new() -> SearchContext<'a> {
+        SearchContext::default()
+    }
+
+    pub fn with_evaluator(mut self, evaluator: &'a mut dyn Evaluator) -> SearchContext<'a> {
+        self.evaluator = Some(evaluator);
+        self
+    }
+
+    // Root moves that spoil the table result are not searched, and positions in the tables are
+    // scored by them instead of being searched.
+    pub fn with_tablebase(mut self, tablebase: &'a dyn Tablebase) -> SearchContext<'a> {
+        self.tablebase = Some(tablebase);
+        self
+    }
+
+    pub fn with_limits(mut self, limits: SearchLimits<'a>) -> SearchContext<'a> {
+        self.limits = limits;
+        self
+    }
+
+    pub fn with_reporter(mut self, report: &'a mut dyn FnMut(&SearchResult)) -> SearchContext<'a> {
+        self.report = Some(report);
+        self
+    }
+
+    // For callers that search again and again, as in a game.
+    pub fn with_state(mut self, state: &'a mut SearchState) -> SearchContext<'a> {
+        self.state = Some(state);
+        self
+    }
+}
+
+// What a series of searches keeps from one search to the next, so they do not set it up again:
+// the transposition table, with what it learnt, and the principal variation tables, one per thread.
+pub struct SearchState {
+    // Made by the first search, and again when the size in the options changes.
+    table: Option<TranspositionTable>,
+    pv_tables: Vec<PvTable>,
+}
+
+impl SearchState {
+    pub fn new() -> SearchState {
+        SearchState { table: None, pv_tables: vec![] }
+    }
+
+    // Forget what earlier searches learnt, as for a new game.
+    pub fn clear(&mut self) {
+        if let Some(table) = &self.table {
+            table.clear();
+        }
+    }
+}
+
+impl Default for SearchState {
+    fn default() -> SearchState {
+        SearchState::new()
+    }
+}
+
+// The best line found from each ply, built up from the end as the search returns.
+struct PvTable {
+    lines: Vec<Vec<Board>>,
+    lengths: Vec<usize>,
+}
+
+impl PvTable {
+    fn new() -> PvTable {
+        PvTable { lines: vec![vec![Board::new(); MAX_PLY + 1]; MAX_PLY + 1], lengths: vec![0; MAX_PLY + 1] }
+    }
+}
+
+// One root move with its score and the line the search expects to follow it.
+#[derive(Debug,Clone,PartialEq,Default)]
+#[non_exhaustive]
+pub struct SearchLine {
+    // Score from the point of view of the color to move.
+    pub score: i32,
+    pub principal_variation: Vec<Board>,
+}
+
+#[derive(Debug,Clone,PartialEq,Default)]
+#[non_exhaustive]
+pub struct SearchResult {
+    // Score from the point of view of the color to move.
+    pub score: i32,
+    pub best_board: Option<Board>,
+    pub principal_variation: Vec<Board>,
+    pub depth: u8,
+    pub nodes: u64,
+    pub tablebase_hits: u64,
+    // The best lines, best first. Holds up to `multi_pv` lines, the first being the one above.
+    pub lines: Vec<SearchLine>,
+    // The searched tree, when the options ask for it.
+    pub trace: Option<SearchTrace>,
+}
+
+// Search the board with iterative deepening, for the given color to move, to the depth in the
+// options or until a limit in the context ends the search.
+pub fn search(board: &Board, color: Color, options: &SearchOptions, context: &mut SearchContext) -> SearchResult {
+    let mut positional;
+    let evaluator: &mut dyn Evaluator = match context.evaluator.as_deref_mut() {
+        Some(evaluator) => evaluator,
+        None => {
+            positional = PositionalEvaluator::new();
+            &mut positional
+        }
+    };
+    let mut fresh_state;
+    let state = match context.state.as_deref_mut() {
+        Some(state) => state,
+        None => {
+            fresh_state = SearchState::new();
+            &mut fresh_state
+        }
+    };
+    let report: &mut dyn FnMut(&SearchResult) = match context.report.as_deref_mut() {
+        Some(report) => report,
+        None => &mut |_| {},
+    };
+    let (depth, tablebase, limits) = (options.depth, context.tablebase, context.limits);
+    let SearchState { table, pv_tables } = state;
+    if table.as_ref().map(|table| table.megabytes()) != Some(options.hash_megabytes) {
+        *table = Some(TranspositionTable::new(options.hash_megabytes));
+    }
+    let table = table.as_ref().unwrap();
+    // Helper threads need evaluators of their own. Evaluators that cannot be forked search alone.
+    let helpers: Vec<Box<dyn Evaluator + Send>> = (1..options.threads.max(1)).map_while(|_| evaluator.fork()).collect();
+    if pv_tables.len() <= helpers.len() {
+        pv_tables.resize_with(helpers.len() + 1, PvTable::new);
+    }
+    let (pv, helper_pvs) = pv_tables.split_first_mut().unwrap();
+    if helpers.is_empty() {
+        return iterative_deepening(board, color, depth, options, evaluator, table, pv, tablebase, limits, None, 0, report);
+    }
+
+    // Lazy SMP: all threads search the same root, sharing what they learn through the table.
+    // The first thread to finish the full depth stops the others.
+    let stop = AtomicBool::new(false);
+    let mut results = thread::scope(|scope| {
+        let handles: Vec<_> = helpers.into_iter().zip(helper_pvs.iter_mut()).enumerate().map(|(index, (mut helper, pv))| {
+            let stop = &stop;
+            scope.spawn(move || iterative_deepening(board, color, depth, options, &mut *helper, table, pv, tablebase, limits, Some(stop), index + 1, &mut |_| {}))
+        }).collect();
+        let mut results = vec![iterative_deepening(board, color, depth, options, evaluator, table, pv, tablebase, limits, Some(&stop), 0, report)];
+        results.extend(handles.into_iter().map(|handle| handle.join().unwrap()));
+        results
+    });
+
+    // Take the deepest finished iteration, preferring the main thread when depths are equal.
+    let nodes = results.iter().map(|result| result.nodes).sum();
+    let tablebase_hits = results.iter().map(|result| result.tablebase_hits).sum();
+    // Only the main thread records a trace.
+    let trace = results[0].trace.take();
+    let best = (1..results.len()).fold(0, |best, index| if results[index].depth > results[best].depth { index } else { best });
+    let mut result = results.swap_remove(best);
+    result.nodes = nodes;
+    result.tablebase_hits = tablebase_hits;
+    result.trace = trace;
+    result
+}
+
+#[allow(clippy::too_many_arguments)]
+fn iterative_deepening(board: &Board, color: Color, depth: u8, options: &SearchOptions, evaluator: &mut dyn Evaluator,
+        table: &TranspositionTable, pv: &mut PvTable, tablebase: Option<&dyn Tablebase>, limits: SearchLimits, stop: Option<&AtomicBool>,
+        thread_index: usize, report: &mut dyn FnMut(&SearchResult)) -> SearchResult {
+    evaluator.reset(board);
+    let mut searcher = Searcher::new(*options, evaluator, table, pv, stop);
+    searcher.limits = limits;
+    searcher.tablebase = tablebase;
+    searcher.thread_index = thread_index;
+    searcher.trace = options.trace.filter(|_| thread_index == 0).map(Tracer::new);
+    // In the tables, only the moves that keep the best result are searched.
+    searcher.root_children = tablebase.filter(|tablebase| tablebase.can_probe(board))
+        .and_then(|tablebase| tablebase.filter_root_moves(board, color).ok())
+        .filter(|children| !children.is_empty());
+    // Root moves given by the caller narrow that down, unless none of them keeps the table result.
+    if let Some(allowed) = limits.root_moves.filter(|allowed| !allowed.is_empty()) {
+        let kept: Vec<Board> = searcher.root_children.iter().flatten().filter(|child| allowed.contains(child)).copied().collect();
+        searcher.root_children = Some(if kept.is_empty() { allowed.to_vec() } else { kept });
+    }
+    let mut result = SearchResult::default();
+    let root_moves = searcher.root_children.as_ref().map(|children| children.len()).unwrap_or_else(|| next_boards(board, color).len());
+    for iteration_depth in 1..=depth.max(1) {
+        if iteration_depth < depth && skips_iteration(thread_index, iteration_depth) {
+            continue;
+        }
+        searcher.root_depth = iteration_depth;
+        let previous_lines = result.lines.clone();
+        let mut lines: Vec<SearchLine> = vec![];
+        if let Some(trace) = searcher.trace.as_mut() {
+            trace.restart();
+        }
+        // Search the root once per line, each time excluding the moves of the lines found before,
+        // with a full window so every line gets an exact score.
+        for index in 0..options.multi_pv.max(1).min(root_moves.max(1)) {
+            searcher.previous_pv = previous_lines.get(index).map(|line| line.principal_variation.clone()).unwrap_or_default();
+            searcher.excluded_root_children = lines.iter().filter_map(|line| line.principal_variation.first().copied()).collect();
+            let score = searcher.alpha_beta(board, color, iteration_depth, 0, -MATE_SCORE, MATE_SCORE, true);
+            if searcher.stopped {
+                break;
+            }
+            let principal_variation = searcher.pv.lines[0][..searcher.pv.lengths[0]].to_vec();
+            lines.push(SearchLine { score, principal_variation });
+        }
+        if searcher.stopped {
+            // An unfinished iteration is worthless: keep the last finished one.
+            break;
+        }
+        if let Some(trace) = searcher.trace.as_mut() {
+            trace.finish();
+        }
+        // A stable sort keeps the search order for equal scores.
+        lines.sort_by_key(|line| -line.score);
+
+        let best = lines[0].clone();
+        result = SearchResult {
+            score: best.score,
+            best_board: best.principal_variation.first().copied(),
+            principal_variation: best.principal_variation,
+            depth: iteration_depth,
+            nodes: searcher.nodes,
+            tablebase_hits: searcher.tablebase_hits,
+            lines,
+            trace: None,
+        };
+        report(&result);
+    }
+    if let Some(stop) = stop {
+        stop.store(true, Ordering::Relaxed);
+    }
+    result.nodes = searcher.nodes;
+    result.tablebase_hits = searcher.tablebase_hits;
+    result.trace = searcher.trace.take().and_then(Tracer::into_trace);
+    result
+}
+
+pub fn is_mate_score(score: i32) -> bool {
+    score.abs() >= MATE_SCORE - MAX_PLY as i32
+}
+
+struct Searcher<'a> {
+    options: SearchOptions,
+    evaluator: &'a mut dyn Evaluator,
+    table: &'a TranspositionTable,
+    tablebase: Option<&'a dyn Tablebase>,
+    // Set by the other threads of a parallel search when one of them finishes.
+    stop: Option<&'a AtomicBool>,
+    limits: SearchLimits<'a>,
+    stopped: bool,
+    root_depth: u8,
+    // 0 for the main thread, which searches the moves in the usual order.
+    thread_index: usize,
+    nodes: u64,
+    tablebase_hits: u64,
+    pv: &'a mut PvTable,
+    // The line from the previous iteration, searched first.
+    previous_pv: Vec<Board>,
+    // Root moves already reported in other lines of a multi-PV search.
+    excluded_root_children: Vec<Board>,
+    // The only root moves to search, when the tables decide.
+    root_children: Option<Vec<Board>>,
+    trace: Option<Tracer>,
+}
+
+impl<'a> Searcher<'a> {
+    fn new(options: SearchOptions, evaluator: &'a mut dyn Evaluator, table: &'a TranspositionTable, pv: &'a mut PvTable,
+            stop: Option<&'a AtomicBool>) -> Searcher<'a> {
+        Searcher {
+            options,
+            evaluator,
+            table,
+            tablebase: None,
+            stop,
+            limits: SearchLimits::default(),
+            stopped: false,
+            root_depth: 0,
+            thread_index: 0,
+            nodes: 0,
+            tablebase_hits: 0,
+            pv,
+            previous_pv: vec![],
+            excluded_root_children: vec![],
+            root_children: None,
+            trace: None,
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn alpha_beta(&mut self, board: &Board, color: Color, depth: u8, ply: usize, alpha: i32, beta: i32, allow_null: bool) -> i32 {
+        self.pv.lengths[ply] = ply;
+        if ply >= MAX_PLY {
+            return self.evaluate(board, color);
+        }
+
+        let in_check = is_checked(board, color);
+        if depth == 0 && !in_check {
+            return self.quiescence(board, color, ply, alpha, beta);
+        }
+        // Never drop into quiescence while in check: all evasions need to be considered.
+        let depth = depth.max(1);
+        if let Some(trace) = self.trace.as_mut() {
+            trace.enter(board, color, depth, ply, alpha, beta, false);
+        }
+        let (score, cutoff) = self.search_node(board, color, depth, ply, alpha, beta, allow_null, in_check);
+        if let Some(trace) = self.trace.as_mut() {
+            trace.exit(score, cutoff);
+        }
+        score
+    }
+
+    // The search of a node below the quiescence horizon, with the reason it ended early, if it did.
+    #[allow(clippy::too_many_arguments)]
+    fn search_node(&mut self, board: &Board, color: Color, depth: u8, ply: usize, mut alpha: i32, beta: i32, allow_null: bool,
+            in_check: bool) -> (i32, Option<Cutoff>) {
+        self.nodes += 1;
+        if self.should_stop() {
+            return (0, Some(Cutoff::Stopped));
+        }
+
+        let is_pv = beta - alpha > 1;
+        let original_alpha = alpha;
+        let hash = position_hash(board, color);
+        let entry = self.table.probe(hash);
+        if let Some(entry) = entry {
+            let score = score_from_table(entry.score, ply);
+            if ply > 0 && !is_pv && entry.depth >= depth && match entry.bound {
+                Bound::Exact => true,
+                Bound::Lower => score >= beta,
+                Bound::Upper => score <= alpha
+            } {
+                return (score, Some(Cutoff::TranspositionTable));
+            }
+        }
+        if let Some(score) = self.probe_tablebase(board, color, ply) {
+            return (score, Some(Cutoff::Tablebase));
+        }
+        let static_eval = self.evaluate(board, color);
+
+        // Reverse futility pruning: if we are far enough ahead that even a margin per ply
+        // cannot bring the opponent back under beta, trust the static evaluation.
+        if self.options.reverse_futility_pruning && !is_pv && !in_check && depth <= REVERSE_FUTILITY_MAX_DEPTH
+                && !is_mate_score(beta) && static_eval - REVERSE_FUTILITY_MARGIN * depth as i32 >= beta {
+            return (static_eval - REVERSE_FUTILITY_MARGIN * depth as i32, Some(Cutoff::ReverseFutility));
+        }
+
+        // Null move pruning: give the opponent a free move. If we still beat beta, this node is
+        // good enough to cut. Not in pawn endings, where zugzwang makes passing a real advantage.
+        if self.options.null_move_pruning && allow_null && !is_pv && !in_check && depth >= NULL_MOVE_MIN_DEPTH
+                && static_eval >= beta && has_non_pawn_material(board, color) {
+            let reduction = if depth > 6 { 3 } else { 2 };
+            let mut null_board = *board;
+            null_board.set_en_passant_capturable(None);
+            self.evaluator.make(board, &null_board);
+            let score = -self.alpha_beta(&null_board, color.opposite(), depth.saturating_sub(1 + reduction), ply + 1, -beta, -beta + 1, false);
+            self.evaluator.unmake(board, &null_board);
+            if score >= beta && !is_mate_score(score) {
+                return (beta, Some(Cutoff::NullMove));
+            }
+        }
+
+        // Children keep the index they were generated at, which is what the table stores.
+        let mut children: Vec<(usize, Board)> = next_boards(board, color).into_iter().enumerate().collect();
+        if children.is_empty() {
+            // Checkmate or stalemate. Prefer faster mates by taking the ply into account.
+            return if in_check { (-MATE_SCORE + ply as i32, Some(Cutoff::Mate)) } else { (0, Some(Cutoff::Stalemate)) };
+        }
+        if ply == 0 {
+            children.retain(|(_, child)| !self.excluded_root_children.contains(child));
+            if let Some(root_children) = &self.root_children {
+                children.retain(|(_, child)| root_children.contains(child));
+            }
+        }
+        let table_child = entry.and_then(|entry| entry.best_child).map(|index| index as usize);
+        self.order_children(board, color, ply, table_child, &mut children);
+
+        let futility_allowed = self.options.futility_pruning && !is_pv && !in_check
+            && (depth as usize) < FUTILITY_MARGINS.len() && !is_mate_score(alpha)
+            && static_eval + FUTILITY_MARGINS[depth as usize] <= alpha;
+
+        let mut best_score = -MATE_SCORE;
+        let mut best_child = None;
+        let mut searched = 0;
+        let mut cutoff = None;
+        for (index, child) in children.iter() {
+            let gives_check = is_checked(child, color.opposite());
+            let capture = is_capture(board, child);
+            let quiet = !capture && !gives_check;
+
+            // Futility pruning: quiet moves near the leaves cannot raise a hopeless score above alpha.
+            if futility_allowed && quiet && searched > 0 {
+                continue;
+            }
+
+            let extension = if self.options.check_extensions && gives_check && ply < 2 * self.root_depth as usize { 1 } else { 0 };
+            let new_depth = depth - 1 + extension;
+
+            self.evaluator.make(board, child);
+            let score = if searched == 0 {
+                -self.alpha_beta(child, color.opposite(), new_depth, ply + 1, -beta, -alpha, true)
+            } else {
+                // Late move reductions: moves ordered late are unlikely to be best, so search them
+                // shallower with a null window, and only re-search at full depth if they surprise us.
+                let reduction = if self.options.late_move_reductions && depth >= LMR_MIN_DEPTH && searched >= LMR_FULL_DEPTH_MOVES
+                        && quiet && !in_check {
+                    if searched >= 2 * LMR_FULL_DEPTH_MOVES { 2 } else { 1 }
+                } else {
+                    0
+                };
+                let mut score = -self.alpha_beta(child, color.opposite(), new_depth.saturating_sub(reduction), ply + 1, -alpha - 1, -alpha, true);
+                if score > alpha && reduction > 0 {
+                    score = -self.alpha_beta(child, color.opposite(), new_depth, ply + 1, -alpha - 1, -alpha, true);
+                }
+                if score > alpha && score < beta {
+                    score = -self.alpha_beta(child, color.opposite(), new_depth, ply + 1, -beta, -alpha, true);
+                }
+                score
+            };
+            self.evaluator.unmake(board, child);
+            searched += 1;
+
+            if self.stopped {
+                return (0, Some(Cutoff::Stopped));
+            }
+
+            if score > best_score {
+                best_score = score;
+                best_child = Some(*index as u8);
+            }
+            if score > alpha {
+                alpha = score;
+                self.update_pv(ply, *child);
+            }
+            if alpha >= beta {
+                cutoff = Some(Cutoff::Beta);
+                break;
+            }
+        }
+
+        if searched == 0 {
+            // Everything was pruned as futile.
+            return (static_eval, Some(Cutoff::Futility));
+        }
+
+        // With root moves excluded, the score is not the score of the position.
+        if ply > 0 || (self.excluded_root_children.is_empty() && self.root_children.is_none()) {
+            let bound = if best_score >= beta {
+                Bound::Lower
+            } else if best_score > original_alpha {
+                Bound::Exact
+            } else {
+                Bound::Upper
+            };
+            let best_child = if bound == Bound::Upper { None } else { best_child };
+            self.table.store(hash, TableEntry { score: score_to_table(best_score, ply), depth, bound, best_child });
+        }
+        (best_score, cutoff)
+    }
+
+    fn quiescence(&mut self, board: &Board, color: Color, ply: usize, alpha: i32, beta: i32) -> i32 {
+        if let Some(trace) = self.trace.as_mut() {
+            trace.enter(board, color, 0, ply, alpha, beta, true);
+        }
+        let (score, cutoff) = self.quiescence_node(board, color, ply, alpha, beta);
+        if let Some(trace) = self.trace.as_mut() {
+            trace.exit(score, cutoff);
+        }
+        score
+    }
+
+    fn quiescence_node(&mut self, board: &Board, color: Color, ply: usize, mut alpha: i32, beta: i32) -> (i32, Option<Cutoff>) {
+        self.nodes += 1;
+        self.pv.lengths[ply] = ply;
+        if self.should_stop() {
+            return (0, Some(Cutoff::Stopped));
+        }
+
+        let stand_pat = self.evaluate(board, color);
+        if stand_pat >= beta || ply >= MAX_PLY {
+            return (stand_pat, Some(Cutoff::StandPat));
+        }
+        if stand_pat > alpha {
+            alpha = stand_pat;
+        }
+
+        let mut captures: Vec<Board> = next_boards(board, color).into_iter().filter(|child| is_capture(board, child)).collect();
+        captures.sort_by_key(|child| -captured_value(board, child, color));
+        for child in captures.iter() {
+            self.evaluator.make(board, child);
+            let score = -self.quiescence(child, color.opposite(), ply + 1, -beta, -alpha);
+            self.evaluator.unmake(board, child);
+            if score >= beta {
+                return (score, Some(Cutoff::Beta));
+            }
+            if score > alpha {
+                alpha = score;
+                self.update_pv(ply, *child);
+            }
+        }
+        (alpha, None)
+    }
+
+    // The score the tables give, below the root. Draws include cursed wins and blessed losses.
+    fn probe_tablebase(&mut self, board: &Board, color: Color, ply: usize) -> Option<i32> {
+        let tablebase = self.tablebase?;
+        if ply == 0 || !tablebase.can_probe(board) {
+            return None;
+        }
+        let wdl = tablebase.probe_wdl(board, color).ok()?;
+        self.tablebase_hits += 1;
+        // With the distance to mate known, the score is an exact mate score, as long as the
+        // mate is near enough to be told apart from other scores.
+        if let Ok(Some(plies)) = tablebase.probe_dtm(board, color) {
+            let distance = (ply + plies as usize).min(MAX_PLY) as i32;
+            match wdl {
+                Wdl::Win if distance < MAX_PLY as i32 => { return Some(MATE_SCORE - distance); },
+                Wdl::Loss if distance < MAX_PLY as i32 => { return Some(-MATE_SCORE + distance); },
+                _ => {}
+            }
+        }
+        Some(match wdl {
+            Wdl::Win => TABLEBASE_WIN_SCORE - ply as i32,
+            Wdl::Loss => -TABLEBASE_WIN_SCORE + ply as i32,
+            _ => 0
+        })
+    }
+
+    fn should_stop(&mut self) -> bool {
+        if !self.stopped && self.limits.max_nodes.is_some_and(|max_nodes| self.nodes > max_nodes) {
+            self.stopped = true;
+        }
+        if !self.stopped && self.nodes % STOP_CHECK_INTERVAL == 0 {
+            let is_set = |stop: Option<&AtomicBool>| stop.is_some_and(|stop| stop.load(Ordering::Relaxed));
+            self.stopped = is_set(self.stop) || is_set(self.limits.stop)
+                || self.limits.deadline.is_some_and(|deadline| Instant::now() >= deadline);
+        }
+        self.stopped
+    }
+
+    // The score of the board from the point of view of the given color.
+    fn evaluate(&mut self, board: &Board, color: Color) -> i32 {
+        let score = self.evaluator.evaluate(board);
+        if color == Color::White { score } else { -score }
+    }
+
+    fn update_pv(&mut self, ply: usize, child: Board) {
+        self.pv.lines[ply][ply] = child;
+        for next_ply in (ply + 1)..self.pv.lengths[ply + 1] {
+            self.pv.lines[ply][next_ply] = self.pv.lines[ply + 1][next_ply];
+        }
+        self.pv.lengths[ply] = self.pv.lengths[ply + 1].max(ply + 1);
+    }
+
+    fn order_children(&self, board: &Board, color: Color, ply: usize, table_child: Option<usize>, children: &mut Vec<(usize, Board)>) {
+        // Most valuable captures first, then the best move the table remembers, then in front of
+        // everything the principal variation from the previous iteration. Helper threads start the
+        // quiet moves at another place, so they do not all search the same tree.
+        if self.thread_index > 0 && !children.is_empty() {
+            let shift = (self.thread_index + ply) % children.len();
+            children.rotate_left(shift);
+        }
+        children.sort_by_key(|(_, child)| -captured_value(board, child, color));
+        if let Some(position) = children.iter().position(|(index, _)| Some(*index) == table_child) {
+            let child = children.remove(position);
+            children.insert(0, child);
+        }
+        let pv_child = self.previous_pv.get(ply).copied();
+        if let Some(position) = children.iter().position(|(_, child)| Some(*child) == pv_child) {
+            let child = children.remove(position);
+            children.insert(0, child);
+        }
+    }
+}
+
+// Helper threads skip some iterations, each its own, so the threads are spread over different depths.
+// The pattern repeats every 20 threads.
+const SKIP_SIZE: [u8; 20] = [1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 3, 3, 4, 4, 4, 4, 4, 4, 4, 4];
+const SKIP_PHASE: [u8; 20] = [0, 1, 0, 1, 2, 3, 0, 1, 2, 3, 4, 5, 0, 1, 2, 3, 4, 5, 6, 7];
+
+fn skips_iteration(thread_index: usize, depth: u8) -> bool {
+    if thread_index == 0 {
+        return false;
+    }
+    let index = (thread_index - 1) % SKIP_SIZE.len();
+    (depth + SKIP_PHASE[index]) / SKIP_SIZE[index] % 2 == 1
+}
+
+// Mate scores are stored relative to the node, so they stay correct when found again at another ply.
+fn score_to_table(score: i32, ply: usize) -> i32 {
+    if is_mate_score(score) {
+        score + score.signum() * ply as i32
+    } else {
+        score
+    }
+}
+
+fn score_from_table(score: i32, ply: usize) -> i32 {
+    if is_mate_score(score) {
+        score - score.signum() * ply as i32
+    } else {
+        score
+    }
+}
+
+// The value of the opponent's pieces that disappeared from the board in the child.
+fn captured_value(board: &Board, child: &Board, color: Color) -> i32 {
+    board.squares().iter().zip(child.squares().iter()).map(|(before, after)| match (*before, *after) {
+        (Square::Occupied(captured), after) if captured.0 != color && after != Square::Occupied(captured) => middlegame_kind_value(captured.1),
+        _ => 0
+    }).sum()
+}
+
+fn count_pieces(board: &Board) -> usize {
+    board.squares().iter().filter(|square| !square.is_empty()).count()
+}
+
+fn is_capture(board: &Board, child: &Board) -> bool {
+    count_pieces(child) < count_pieces(board)
+}
+
+fn has_non_pawn_material(board: &Board, color: Color) -> bool {
+    board.squares().iter().any(|square| match square {
+        Square::Occupied(Piece(piece_color, kind)) => *piece_color == color && *kind != Kind::Pawn && *kind != Kind::King,
+        _ => false
+    })
+}
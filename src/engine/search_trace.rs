@@ -0,0 +1,208 @@
+use std::fmt::Write;
+
+use crate::board::{ Board, Color, Fen };
+use crate::engine::notation::{ Move };
+use crate::util::{ GrowTree };
+
+// How much of the search to record. Nodes beyond either limit are searched but not recorded.
+#[derive(Debug,Copy,PartialEq,Eq,Clone,Hash)]
+pub struct TraceLimits {
+    pub max_nodes: usize,
+    pub max_ply: usize,
+}
+
+// What the search found the node to be: inside the window, failing high or failing low.
+#[derive(Debug,Copy,PartialEq,Eq,Clone,Hash)]
+pub enum NodeType {
+    Pv,
+    Cut,
+    All
+}
+
+// Why a node was not searched through all its moves.
+#[derive(Debug,Copy,PartialEq,Eq,Clone,Hash)]
+pub enum Cutoff {
+    // A move reached beta.
+    Beta,
+    TranspositionTable,
+    Tablebase,
+    ReverseFutility,
+    NullMove,
+    // Every move was pruned as futile.
+    Futility,
+    // Quiescence: the static evaluation reached beta.
+    StandPat,
+    Mate,
+    Stalemate,
+    Stopped
+}
+
+#[derive(Debug,Copy,PartialEq,Clone)]
+pub struct TraceNode {
+    pub board: Board,
+    // The side to move.
+    pub color: Color,
+    // The move into the node. None at the root and after a null move.
+    pub played: Option<Move>,
+    pub ply: usize,
+    // The remaining depth, 0 in quiescence.
+    pub depth: u8,
+    pub quiescence: bool,
+    // The window and the score, from the point of view of the side to move.
+    pub alpha: i32,
+    pub beta: i32,
+    pub score: i32,
+    pub cutoff: Option<Cutoff>,
+    pub node_type: NodeType,
+}
+
+// The tree the search explored in its last finished iteration, in search order.
+#[derive(Debug,Clone,PartialEq)]
+pub struct SearchTrace {
+    pub tree: GrowTree<TraceNode>,
+}
+
+impl SearchTrace {
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    // A trace always has its root.
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    // Only the nodes to keep, their ancestors and the root.
+    pub fn pruned(&self, keep: &dyn Fn(&TraceNode) -> bool) -> SearchTrace {
+        // In reverse depth first order, every subtree is decided before its root.
+        let order: Vec<usize> = self.tree.dfs(0).map(|(index, _)| index).collect();
+        let mut kept = vec![false; self.tree.len()];
+        for index in order.into_iter().rev() {
+            kept[index] = keep(self.node(index)) || self.tree.child_indices(index).iter().any(|child| kept[*child]);
+        }
+        let mut tree = self.tree.clone();
+        tree.retain(|index, _| kept[index]);
+        SearchTrace { tree }
+    }
+
+    // Graphviz DOT, with moves on the edges and principal variation nodes highlighted.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph search {\n    node [shape=box, fontname=\"monospace\"];\n");
+        for (index, node) in self.tree.dfs(0) {
+            let color = match node.node_type {
+                NodeType::Pv => "green",
+                NodeType::Cut => "red",
+                NodeType::All => "gray"
+            };
+            let cutoff = node.cutoff.map(|cutoff| format!("\\n{:?}", cutoff)).unwrap_or_default();
+            let kind = if node.quiescence { "q" } else { "d" };
+            writeln!(dot, "    n{} [label=\"{}{} [{}, {}]\\n{} {:?}{}\", color={}];", index, kind, node.depth, node.alpha, node.beta,
+                node.score, node.node_type, cutoff, color).unwrap();
+            if let Some((parent, _)) = self.tree.parent(index) {
+                let played = node.played.map(|played| played.to_string()).unwrap_or_else(|| String::from("null"));
+                writeln!(dot, "    n{} -> n{} [label=\"{}\"];", parent, index, played).unwrap();
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    // Nested JSON objects, one per node, with the children in search order.
+    pub fn to_json(&self) -> String {
+        fn write_node(json: &mut String, tree: &GrowTree<TraceNode>, index: usize) {
+            let node = tree.item(index).unwrap();
+            let played = node.played.map(|played| format!("\"{}\"", played)).unwrap_or_else(|| String::from("null"));
+            let cutoff = node.cutoff.map(|cutoff| format!("\"{:?}\"", cutoff)).unwrap_or_else(|| String::from("null"));
+            write!(json, "{{\"move\":{},\"fen\":\"{}\",\"ply\":{},\"depth\":{},\"quiescence\":{},\"alpha\":{},\"beta\":{},\"score\":{},\"type\":\"{:?}\",\"cutoff\":{},\"children\":[",
+                played, Fen::new(node.board, node.color), node.ply, node.depth, node.quiescence, node.alpha, node.beta, node.score,
+                node.node_type, cutoff).unwrap();
+            for (position, child) in tree.child_indices(index).iter().enumerate() {
+                if position > 0 {
+                    json.push(',');
+                }
+                write_node(json, tree, *child);
+            }
+            json.push_str("]}");
+        }
+
+        let mut json = String::new();
+        write_node(&mut json, &self.tree, 0);
+        json
+    }
+
+    fn node(&self, index: usize) -> &TraceNode {
+        self.tree.item(index).unwrap()
+    }
+}
+
+// Records the nodes of a search as it enters and leaves them.
+pub struct Tracer {
+    limits: TraceLimits,
+    tree: Option<GrowTree<TraceNode>>,
+    nodes: usize,
+    // The recorded nodes on the current path, None for nodes that are not recorded.
+    path: Vec<Option<usize>>,
+    // The tree of the last finished iteration.
+    finished: Option<GrowTree<TraceNode>>,
+}
+
+impl Tracer {
+    pub fn new(limits: TraceLimits) -> Tracer {
+        Tracer { limits, tree: None, nodes: 0, path: vec![], finished: None }
+    }
+
+    // Start recording a new iteration.
+    pub fn restart(&mut self) {
+        self.tree = None;
+        self.nodes = 0;
+        self.path.clear();
+    }
+
+    // Keep the tree of the iteration that just finished.
+    pub fn finish(&mut self) {
+        self.finished = self.tree.take();
+    }
+
+    pub fn into_trace(self) -> Option<SearchTrace> {
+        self.finished.map(|tree| SearchTrace { tree })
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn enter(&mut self, board: &Board, color: Color, depth: u8, ply: usize, alpha: i32, beta: i32, quiescence: bool) {
+        let mut node = TraceNode { board: *board, color, played: None, ply, depth, quiescence, alpha, beta, score: 0, cutoff: None, node_type: NodeType::Pv };
+        let index = match (self.path.last().copied(), self.tree.as_mut()) {
+            // Only the first root search of an iteration is recorded, not the other lines of a multi-PV search.
+            (None, None) => {
+                self.tree = Some(GrowTree::new(node));
+                self.nodes = 1;
+                Some(0)
+            },
+            (Some(Some(parent)), Some(tree)) if self.nodes < self.limits.max_nodes && ply <= self.limits.max_ply => {
+                let parent_node = tree.item(parent).unwrap();
+                node.played = Move::between(&parent_node.board, board, parent_node.color);
+                self.nodes += 1;
+                Some(tree.add_child(node, parent))
+            },
+            _ => None
+        };
+        self.path.push(index);
+    }
+
+    pub fn exit(&mut self, score: i32, cutoff: Option<Cutoff>) {
+        let index = match self.path.pop() {
+            Some(Some(index)) => index,
+            _ => return
+        };
+        if let Some(node) = self.tree.as_mut().and_then(|tree| tree.item_mut(index)) {
+            node.score = score;
+            node.cutoff = cutoff;
+            node.node_type = if score >= node.beta {
+                NodeType::Cut
+            } else if score <= node.alpha {
+                NodeType::All
+            } else {
+                NodeType::Pv
+            };
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use std::sync::atomic::{ AtomicU64, Ordering };
+
+#[derive(Debug,Copy,PartialEq,Eq,Clone,Hash)]
+pub(crate) enum Bound {
+    Exact,
+    // The score is at least this much: the search failed high.
+    Lower,
+    // The score is at most this much: no move raised alpha.
+    Upper
+}
+
+#[derive(Debug,Copy,PartialEq,Eq,Clone,Hash)]
+pub(crate) struct TableEntry {
+    pub score: i32,
+    pub depth: u8,
+    pub bound: Bound,
+    // Index of the best child in the order `next_boards` generates them.
+    pub best_child: Option<u8>,
+}
+
+impl TableEntry {
+    fn pack(&self) -> u64 {
+        let bound = match self.bound {
+            Bound::Exact => 1,
+            Bound::Lower => 2,
+            Bound::Upper => 3
+        };
+        let best_child = self.best_child.map(|index| index as u64 + 1).unwrap_or(0);
+        (self.score as u32 as u64) | (self.depth as u64) << 32 | bound << 40 | best_child << 42
+    }
+
+    fn unpack(data: u64) -> Option<TableEntry> {
+        let bound = match (data >> 40) & 0b11 {
+            1 => Bound::Exact,
+            2 => Bound::Lower,
+            3 => Bound::Upper,
+            _ => { return None; }
+        };
+        let best_child = (data >> 42) & 0x1FF;
+        Some(TableEntry {
+            score: data as u32 as i32,
+            depth: (data >> 32) as u8,
+            bound,
+            best_child: if best_child == 0 { None } else { Some((best_child - 1) as u8) },
+        })
+    }
+}
+
+// A transposition table that search threads share without locks. Each slot stores the hash xor'ed
+// with the data, so a slot torn by two threads writing at once fails the check instead of lying.
+pub(crate) struct TranspositionTable {
+    megabytes: usize,
+    slots: Vec<AtomicU64>,
+}
+
+impl TranspositionTable {
+    pub fn new(megabytes: usize) -> TranspositionTable {
+        let entries = (megabytes * 1024 * 1024 / 16).max(1);
+        TranspositionTable {
+            megabytes,
+            slots: (0..2 * entries).map(|_| AtomicU64::new(0)).collect(),
+        }
+    }
+
+    pub fn megabytes(&self) -> usize {
+        self.megabytes
+    }
+
+    // Empty slots fail the check of every hash but 0, which no position has in practice.
+    pub fn clear(&self) {
+        for slot in &self.slots {
+            slot.store(0, Ordering::Relaxed);
+        }
+    }
+
+    fn slot(&self, hash: u64) -> usize {
+        2 * (hash % (self.slots.len() / 2) as u64) as usize
+    }
+
+    pub fn probe(&self, hash: u64) -> Option<TableEntry> {
+        let slot = self.slot(hash);
+        let check = self.slots[slot].load(Ordering::Relaxed);
+        let data = self.slots[slot + 1].load(Ordering::Relaxed);
+        if check ^ data != hash {
+            return None;
+        }
+        TableEntry::unpack(data)
+    }
+
+    // Always replaces: recent results are the most likely to be needed again.
+    pub fn store(&self, hash: u64, entry: TableEntry) {
+        let slot = self.slot(hash);
+        let data = entry.pack();
+        self.slots[slot].store(hash ^ data, Ordering::Relaxed);
+        self.slots[slot + 1].store(data, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ Bound, TableEntry, TranspositionTable };
+
+    #[test]
+    fn test_transposition_table() {
+        let table = TranspositionTable::new(1);
+        assert_eq!(None, table.probe(12345));
+        for entry in &[
+            TableEntry { score: -1_000_000 + 7, depth: 3, bound: Bound::Exact, best_child: Some(0) },
+            TableEntry { score: 42, depth: 255, bound: Bound::Lower, best_child: Some(217) },
+            TableEntry { score: -42, depth: 1, bound: Bound::Upper, best_child: None },
+        ] {
+            table.store(12345, *entry);
+            assert_eq!(Some(*entry), table.probe(12345));
+            assert_eq!(None, table.probe(12345 ^ (1 << 40)));
+        }
+        table.clear();
+        assert_eq!(None, table.probe(12345));
+        assert_eq!(1, table.megabytes());
+    }
+}
@@ -0,0 +1,74 @@
+use crate::board::{ CastlingSide, Color, Coordinates, Fen, File, Kind, Piece, Rank, Square, ALL_KINDS };
+use crate::engine::analysis::{ attacked_squares, find_king, is_checked };
+
+// The reasons the position cannot come up in a legal game, or none when it can as far as these
+// checks go: kings, pawns on the back ranks, piece counts, checks, castling rights and en passant.
+pub fn validate_position(fen: &Fen) -> Vec<String> {
+    let board = &fen.board;
+    let mut problems = vec![];
+    for color in [Color::White, Color::Black] {
+        let count = |kind: Kind| board.squares().iter().filter(|square| square.is_occupied_by(Piece(color, kind))).count();
+        let kings = count(Kind::King);
+        if kings != 1 {
+            problems.push(format!("{:?} has {} kings instead of one", color, kings));
+        }
+        let pawns = count(Kind::Pawn);
+        if pawns > 8 {
+            problems.push(format!("{:?} has {} pawns", color, pawns));
+        }
+        // Every piece beyond the starting set must be a promoted pawn.
+        let promoted: usize = ALL_KINDS.iter().map(|kind| count(*kind).saturating_sub(match kind {
+            Kind::Rook | Kind::Knight | Kind::Bishop => 2,
+            Kind::Queen | Kind::King => 1,
+            Kind::Pawn => 8
+        })).sum();
+        if promoted + pawns > 8 {
+            problems.push(format!("{:?} has more promoted pieces than missing pawns", color));
+        }
+        for (coordinates, square) in board.squares_coordinates_iter() {
+            if square.is_occupied_by(Piece(color, Kind::Pawn)) && (coordinates.rank() == Rank::R1 || coordinates.rank() == Rank::R8) {
+                problems.push(format!("{:?} has a pawn on {}", color, coordinates));
+            }
+        }
+        let home = if color == Color::White { Rank::R1 } else { Rank::R8 };
+        for (side, file) in [(CastlingSide::King, File::H), (CastlingSide::Queen, File::A)] {
+            if board.is_castling_allowed(color, side) && (!board.get_square(Coordinates::new(File::E, home)).is_occupied_by(Piece(color, Kind::King))
+                    || !board.get_square(Coordinates::new(file, home)).is_occupied_by(Piece(color, Kind::Rook))) {
+                problems.push(format!("{:?} may castle {:?} side without the king and rook on their squares", color, side));
+            }
+        }
+    }
+
+    if is_checked(board, fen.color.opposite()) {
+        problems.push(format!("{:?} is in check but not to move", fen.color.opposite()));
+    }
+    // Discovered checks give at most two checkers.
+    if let Some(king) = find_king(board, fen.color) {
+        let checkers = board.squares_coordinates_iter().filter(|(coordinates, square)| match **square {
+            Square::Occupied(piece) if piece.0 != fen.color => attacked_squares(board, *coordinates, piece) & 1 << king.index() != 0,
+            _ => false
+        }).count();
+        if checkers > 2 {
+            problems.push(format!("{:?} is in check by {} pieces", fen.color, checkers));
+        }
+    }
+
+    if let Some(target) = board.en_passant_capturable() {
+        // The pawn that just moved two squares passed the target and stands in front of it.
+        let (target_rank, pawn_rank, mover) = match fen.color {
+            Color::White => (Rank::R6, Rank::R5, Color::Black),
+            Color::Black => (Rank::R3, Rank::R4, Color::White)
+        };
+        let start_rank = if mover == Color::White { Rank::R2 } else { Rank::R7 };
+        if target.rank() != target_rank
+                || !board.get_square(Coordinates::new(target.file(), pawn_rank)).is_occupied_by(Piece(mover, Kind::Pawn))
+                || !board.get_square(target).is_empty()
+                || !board.get_square(Coordinates::new(target.file(), start_rank)).is_empty() {
+            problems.push(format!("En passant on {} does not follow a two-square pawn move", target));
+        }
+    }
+    if fen.fullmove_number == 0 {
+        problems.push(String::from("The fullmove number starts at 1"));
+    }
+    problems
+}
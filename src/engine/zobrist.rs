@@ -0,0 +1,108 @@
+use crate::board::{ Board, CastlingSide, Color, Kind, Piece, Square };
+
+// Random keys for Zobrist hashing, generated at compile time with splitmix64 so they are stable between builds.
+const fn splitmix64(state: u64) -> (u64, u64) {
+    let state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    (state, z ^ (z >> 31))
+}
+
+const fn generate_piece_keys() -> [[u64; 64]; 12] {
+    let mut keys = [[0; 64]; 12];
+    let mut state = 0x0C0F_FEE0_C4E5_5000;
+    let mut piece = 0;
+    while piece < 12 {
+        let mut index = 0;
+        while index < 64 {
+            let (next_state, key) = splitmix64(state);
+            state = next_state;
+            keys[piece][index] = key;
+            index += 1;
+        }
+        piece += 1;
+    }
+    keys
+}
+
+const PIECE_KEYS: [[u64; 64]; 12] = generate_piece_keys();
+
+// Keys for black to move, the four castling rights, and the file of an en passant capture.
+const fn generate_state_keys() -> [u64; 13] {
+    let mut keys = [0; 13];
+    let mut state = 0x5EED_5747_E000_0001;
+    let mut index = 0;
+    while index < 13 {
+        let (next_state, key) = splitmix64(state);
+        state = next_state;
+        keys[index] = key;
+        index += 1;
+    }
+    keys
+}
+
+const STATE_KEYS: [u64; 13] = generate_state_keys();
+const BLACK_TO_MOVE_KEY: usize = 0;
+const CASTLING_KEYS: usize = 1;
+const EN_PASSANT_KEYS: usize = 5;
+
+fn piece_key(piece: Piece, index: usize) -> u64 {
+    let color_offset = if piece.0 == Color::White { 0 } else { 6 };
+    let kind_offset = match piece.1 {
+        Kind::Pawn => 0,
+        Kind::Knight => 1,
+        Kind::Bishop => 2,
+        Kind::Rook => 3,
+        Kind::Queen => 4,
+        Kind::King => 5
+    };
+    PIECE_KEYS[color_offset + kind_offset][index]
+}
+
+// A hash of only the pawns on the board, to cache pawn structure evaluation.
+pub(crate) fn pawn_hash(board: &Board) -> u64 {
+    board.squares().iter().enumerate().fold(0, |hash, (index, square)| match *square {
+        Square::Occupied(piece) if piece.1 == Kind::Pawn => hash ^ piece_key(piece, index),
+        _ => hash
+    })
+}
+
+// A hash of the whole position with the color to move, for the transposition table.
+pub(crate) fn position_hash(board: &Board, color: Color) -> u64 {
+    let mut hash = board.squares().iter().enumerate().fold(0, |hash, (index, square)| match *square {
+        Square::Occupied(piece) => hash ^ piece_key(piece, index),
+        Square::Empty => hash
+    });
+    if color == Color::Black {
+        hash ^= STATE_KEYS[BLACK_TO_MOVE_KEY];
+    }
+    let castling = [(Color::White, CastlingSide::King), (Color::White, CastlingSide::Queen), (Color::Black, CastlingSide::King), (Color::Black, CastlingSide::Queen)];
+    for (index, (castling_color, side)) in castling.iter().enumerate() {
+        if board.is_castling_allowed(*castling_color, *side) {
+            hash ^= STATE_KEYS[CASTLING_KEYS + index];
+        }
+    }
+    if let Some(coordinates) = board.en_passant_capturable() {
+        hash ^= STATE_KEYS[EN_PASSANT_KEYS + coordinates.file().index() as usize];
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::board::{ Board, CastlingSide, Color, Coordinates, File, Rank };
+    use super::{ position_hash };
+
+    #[test]
+    fn test_position_hash() {
+        let mut board = Board::new();
+        board.setup();
+        let after_e4 = board.clone_move_piece(Coordinates::new(File::E, Rank::R2), Coordinates::new(File::E, Rank::R4));
+        assert!(position_hash(&board, Color::White) != position_hash(&board, Color::Black));
+        assert!(position_hash(&board, Color::Black) != position_hash(&after_e4, Color::Black));
+        let mut no_castling = board;
+        no_castling.set_castling_allowed(Color::White, CastlingSide::King, false);
+        assert!(position_hash(&board, Color::White) != position_hash(&no_castling, Color::White));
+    }
+}
@@ -0,0 +1,159 @@
+use std::error;
+use std::fmt;
+
+// Everything that can go wrong in the library, by kind, each with a message for people.
+#[derive(Debug,Clone,PartialEq,Eq,Hash)]
+#[non_exhaustive]
+pub enum Error {
+    // Text that is not valid in its notation: FEN, a board diagram, a move, PGN, weights.
+    Parse(ParseError),
+    // A move that is well formed but not legal in the position, or ambiguous.
+    IllegalMove(String),
+    // A file or directory that cannot be read or written.
+    Io(String),
+    // Binary data that is truncated or malformed: books, networks, trees, tablebases.
+    InvalidData(String),
+    // An argument out of range for the call, such as the root of a tree where it cannot go.
+    InvalidArgument(String),
+    // Something to look up that is not there, such as a tablebase for the material.
+    NotFound(String),
+}
+
+impl Error {
+    pub fn message(&self) -> &str {
+        match self {
+            Error::Parse(error) => &error.message,
+            Error::IllegalMove(message) | Error::Io(message) | Error::InvalidData(message)
+                | Error::InvalidArgument(message) | Error::NotFound(message) => message
+        }
+    }
+
+    // For a parse error from parsing the part, which is a slice of the input: the same error with
+    // its line and column in the input. Other errors stay as they are.
+    pub fn within(self, input: &str, part: &str) -> Error {
+        match self {
+            Error::Parse(error) => Error::Parse(error.within(input, part)),
+            error => error
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    // This trait requires `fmt` with this exact signature.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Parse(error) => write!(f, "{}", error),
+            error => f.write_str(error.message())
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<ParseError> for Error {
+    fn from(error: ParseError) -> Error {
+        Error::Parse(error)
+    }
+}
+
+// Front ends that answer in text, such as the protocols, keep only the message.
+impl From<Error> for String {
+    fn from(error: Error) -> String {
+        error.message().to_string()
+    }
+}
+
+// What is wrong with the text.
+#[derive(Debug,Copy,Clone,PartialEq,Eq,Hash)]
+#[non_exhaustive]
+pub enum ParseErrorKind {
+    // A board diagram without its eleven lines.
+    LineCount,
+    // Too few or too many fields, ranks, squares or pieces, such as a FEN without its color to move.
+    FieldCount,
+    // Text that the format does not allow here, such as a broken diagram border.
+    Unexpected,
+    InvalidPiece,
+    InvalidSquare,
+    InvalidNumber,
+    InvalidMove,
+    InvalidTag,
+    // A game result that is missing, or not one of 1-0, 0-1, 1/2-1/2 or a score from 0 to 1.
+    InvalidResult,
+    // A name that the format does not know, such as an evaluation weight.
+    UnknownName,
+    // Something the format requires that is not there, such as a king in a material signature.
+    Missing,
+    // Well formed text asking for more than is supported, such as a table with too many pieces.
+    Unsupported,
+}
+
+// Where the text is wrong and how. Lines and columns count from 1, and columns count characters.
+#[derive(Debug,Clone,PartialEq,Eq,Hash)]
+#[non_exhaustive]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub line: usize,
+    pub column: usize,
+    // The offending text, or empty when something is missing at the end.
+    pub text: String,
+    pub message: String,
+}
+
+impl ParseError {
+    // An error about the text, which is a slice of the input. Its line and column follow from where
+    // the slice starts; for text that is not part of the input, that is the start of the input.
+    pub fn new(kind: ParseErrorKind, input: &str, text: &str, message: String) -> ParseError {
+        let (line, column) = position(input, text);
+        ParseError { kind, line, column, text: text.to_string(), message }
+    }
+
+    // The error from parsing the part, which is a slice of the input, placed in the input instead.
+    pub fn within(self, input: &str, part: &str) -> ParseError {
+        let (line, column) = position(input, part);
+        let column = if self.line == 1 { column + self.column - 1 } else { self.column };
+        ParseError { line: line + self.line - 1, column, ..self }
+    }
+
+    // The message with the line of the input the error is on, and a marker under the offending
+    // text, such as:
+    //
+    //     Unexpected piece: x
+    //      --> line 1, column 14
+    //       |
+    //     1 | rnbqkbnr/ppppxppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1
+    //       |              ^
+    pub fn snippet(&self, input: &str) -> String {
+        let source = input.lines().nth(self.line - 1).unwrap_or("");
+        let gutter = " ".repeat(self.line.to_string().len());
+        // Tabs stay tabs and variation selectors take no room, so the marker lines up.
+        let indent: String = source.chars().take(self.column - 1).filter_map(|c| match c {
+            '\t' => Some('\t'),
+            '\u{fe00}'..='\u{fe0f}' => None,
+            _ => Some(' ')
+        }).collect();
+        let width = self.text.chars().filter(|c| !('\u{fe00}'..='\u{fe0f}').contains(c)).count().max(1);
+        format!("{}\n{}--> line {}, column {}\n{} |\n{} | {}\n{} | {}{}", self.message, gutter, self.line, self.column,
+            gutter, self.line, source, gutter, indent, "^".repeat(width))
+    }
+}
+
+impl fmt::Display for ParseError {
+    // This trait requires `fmt` with this exact signature.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at line {}, column {}", self.message, self.line, self.column)
+    }
+}
+
+impl error::Error for ParseError {}
+
+// The line and column where the text starts in the input, or the start for text from elsewhere.
+fn position(input: &str, text: &str) -> (usize, usize) {
+    let offset = (text.as_ptr() as usize).wrapping_sub(input.as_ptr() as usize);
+    if input.get(offset..offset.wrapping_add(text.len())).is_none() {
+        return (1, 1);
+    }
+    let before = &input[..offset];
+    let line_start = before.rfind('\n').map(|index| index + 1).unwrap_or(0);
+    (before.matches('\n').count() + 1, before[line_start..].chars().count() + 1)
+}
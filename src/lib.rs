@@ -0,0 +1,15 @@
+// A chess engine as a library: boards and positions, move generation and search, notation for
+// reading and writing them, opening books, tablebases, tuning and the UCI and CECP front ends.
+// Everything public is re-exported from these modules; the files behind them are private, so
+// they can move without breaking callers.
+pub mod board;
+pub mod book;
+pub mod engine;
+mod error;
+pub mod notation;
+pub mod protocol;
+pub mod tablebase;
+pub mod tuning;
+pub mod util;
+
+pub use error::{ Error, ParseError, ParseErrorKind };
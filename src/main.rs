@@ -1,10 +1,21 @@
-mod board;
-mod engine;
-mod tests;
-mod util;
+mod cli;
 
-use tests::{ test_all };
+use std::io::{ self };
+use std::process::{ exit };
+
+use chess::protocol::{ run_uci, run_xboard };
+use cli::{ run_command };
 
 fn main() {
-    test_all();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("uci") => { run_uci(io::stdin().lock(), io::stdout()); },
+        Some("xboard") => { run_xboard(io::stdin().lock(), io::stdout()); },
+        _ => {
+            if let Err(error) = run_command(&args, &mut io::stdin().lock(), &mut io::stdout().lock()) {
+                eprintln!("{}", error);
+                exit(1);
+            }
+        }
+    }
 }
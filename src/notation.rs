@@ -0,0 +1,5 @@
+// Reading and writing chess notation in one place: squares, FEN, moves in coordinate and standard
+// algebraic notation, and PGN games.
+pub use crate::board::{ parse_coordinates, Fen, START_POSITION_FEN };
+pub use crate::book::{ load_pgn, parse_pgn, PgnGame };
+pub use crate::engine::{ Move };
@@ -0,0 +1,9 @@
+mod play;
+mod session;
+mod uci;
+mod xboard;
+
+pub use play::{ run_play, PlayOptions };
+pub use session::{ run_session, Session };
+pub use uci::{ run_uci, UciEngine };
+pub use xboard::{ run_xboard, XboardEngine };
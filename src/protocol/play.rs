@@ -0,0 +1,302 @@
+use std::io::{ self, BufRead, Write };
+use std::time::{ Duration, Instant };
+
+use crate::board::{ Board, Color, Fen, Kind, Piece, Square, START_POSITION_FEN };
+use crate::book::{ OpeningBook, PgnGame };
+use crate::engine::{ is_checked, next_boards, search, Move, SearchContext, SearchLimits, SearchOptions, SearchState };
+use crate::error::{ Error };
+use crate::protocol::session::{ probe_book };
+
+const HELP: &str = "Moves: SAN (Nf3, exd5, O-O, e8=Q) or coordinates (g1f3, e7e8q).
+Commands: undo, hint, flip, fen, resign, depth <plies>, time <seconds>, help, quit.";
+
+// Who plays which side, how strongly and how fast the engine plays, where the game starts and
+// the book the engine plays the opening from.
+#[derive(Debug,PartialEq,Clone)]
+pub struct PlayOptions {
+    pub human: Color,
+    // The engine's search depth, which sets its strength.
+    pub depth: u8,
+    // The most time the engine spends on a move.
+    pub move_time: Duration,
+    pub start: Fen,
+    // A Polyglot book file.
+    pub book: Option<String>,
+}
+
+impl Default for PlayOptions {
+    fn default() -> PlayOptions {
+        PlayOptions { human: Color::White, depth: 6, move_time: Duration::from_secs(5), start: Fen::parse(START_POSITION_FEN).unwrap(), book: None }
+    }
+}
+
+impl PlayOptions {
+    // Options from arguments such as "--color black --depth 4 --time 2.5 --fen <fen> --book <file>".
+    pub fn from_args(args: &[String]) -> Result<PlayOptions, Error> {
+        let mut options = PlayOptions::default();
+        let mut index = 0;
+        while index < args.len() {
+            let value = args.get(index + 1).ok_or_else(|| Error::InvalidArgument(format!("Expect a value after {}", args[index])))?;
+            match args[index].as_str() {
+                "--color" => {
+                    options.human = match value.as_str() {
+                        "white" => Color::White,
+                        "black" => Color::Black,
+                        _ => return Err(Error::InvalidArgument(format!("Invalid color: {}", value)))
+                    };
+                },
+                "--depth" => { options.depth = parse_depth(value)?; },
+                "--time" => { options.move_time = parse_seconds(value)?; },
+                "--fen" => { options.start = Fen::parse(value)?; },
+                "--book" => { options.book = Some(value.clone()); },
+                argument => return Err(Error::InvalidArgument(format!("Unknown argument: {}", argument)))
+            }
+            index += 2;
+        }
+        Ok(options)
+    }
+}
+
+// Play a game against the engine, reading moves and commands from the input and showing the board
+// and the engine's moves on the output. When the game is over it can be saved as PGN.
+// Returns the game as played.
+pub fn run_play<R: BufRead, W: Write + ?Sized>(input: R, output: &mut W, options: &PlayOptions) -> Result<PgnGame, Error> {
+    let book = options.book.as_ref().map(OpeningBook::load).transpose()?;
+    play(input, output, options.clone(), book.as_ref()).map_err(|error| Error::Io(format!("Cannot write to the terminal: {}", error)))
+}
+
+fn play<R: BufRead, W: Write + ?Sized>(input: R, output: &mut W, mut options: PlayOptions, book: Option<&OpeningBook>) -> io::Result<PgnGame> {
+    let mut lines = input.lines();
+    let mut game = Game { positions: vec![options.start], moves: vec![] };
+    let mut flipped = options.human == Color::Black;
+    let mut show_board = true;
+    let mut result = "*";
+    let mut state = SearchState::new();
+    writeln!(output, "You play {:?}. {}", options.human, HELP)?;
+    loop {
+        let position = game.current();
+        let end = game.end();
+        if end.is_none() && position.color != options.human {
+            if let Some(played) = think(&position, &options, &mut state, book) {
+                writeln!(output, "Engine plays {}.", game.play(played))?;
+                show_board = true;
+                continue;
+            }
+        }
+        if show_board {
+            if flipped {
+                write!(output, "\n{}\n", position.board.flipped())?;
+            } else {
+                write!(output, "\n{}\n", position.board)?;
+            }
+            show_board = false;
+        }
+        if let Some((announcement, game_result)) = end {
+            writeln!(output, "{}", announcement)?;
+            result = game_result;
+            break;
+        }
+        if is_checked(&position.board, position.color) {
+            writeln!(output, "Check.")?;
+        }
+
+        write!(output, "{:?} to move> ", position.color)?;
+        output.flush()?;
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            _ => {
+                writeln!(output)?;
+                break;
+            }
+        };
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens.as_slice() {
+            [] => {},
+            ["help"] => writeln!(output, "{}", HELP)?,
+            ["quit"] => break,
+            ["flip"] => {
+                flipped = !flipped;
+                show_board = true;
+            },
+            ["fen"] => writeln!(output, "{}", position)?,
+            ["hint"] => match think(&position, &options, &mut state, book) {
+                Some(played) => writeln!(output, "Hint: {}", played.to_san(&position.board, position.color).unwrap())?,
+                None => writeln!(output, "No moves.")?
+            },
+            ["resign"] => {
+                writeln!(output, "You resign. {:?} wins.", options.human.opposite())?;
+                result = if options.human == Color::White { "0-1" } else { "1-0" };
+                break;
+            },
+            ["undo"] => {
+                if game.undo(options.human) {
+                    show_board = true;
+                } else {
+                    writeln!(output, "Nothing to undo.")?;
+                }
+            },
+            ["depth", depth] => match parse_depth(depth) {
+                Ok(depth) => options.depth = depth,
+                Err(error) => writeln!(output, "{}", error)?
+            },
+            ["time", seconds] => match parse_seconds(seconds) {
+                Ok(time) => options.move_time = time,
+                Err(error) => writeln!(output, "{}", error)?
+            },
+            [text] => match parse_move(&position, text) {
+                Ok(played) => {
+                    game.play(played);
+                    show_board = true;
+                },
+                // The move is all there is on the line, so where the error is adds nothing.
+                Err(error) => writeln!(output, "{}", error.message())?
+            },
+            _ => writeln!(output, "Unknown command: {}", line.trim())?
+        }
+    }
+
+    let pgn = game.to_pgn(&options, result);
+    if !pgn.moves.is_empty() {
+        write!(output, "Save the game as PGN (file name, or nothing to skip)> ")?;
+        output.flush()?;
+        if let Some(Ok(path)) = lines.next() {
+            let path = path.trim();
+            if !path.is_empty() {
+                match pgn.save(path) {
+                    Ok(()) => writeln!(output, "Saved to {}.", path)?,
+                    Err(error) => writeln!(output, "{}", error)?
+                }
+            }
+        } else {
+            writeln!(output)?;
+        }
+    }
+    Ok(pgn)
+}
+
+struct Game {
+    // Every position of the game, the current one last, with its move clocks.
+    positions: Vec<Fen>,
+    // The moves played, in standard algebraic notation.
+    moves: Vec<String>,
+}
+
+impl Game {
+    fn current(&self) -> Fen {
+        *self.positions.last().unwrap()
+    }
+
+    // Play a legal move and return it in standard algebraic notation.
+    fn play(&mut self, played: Move) -> String {
+        let position = self.current();
+        let san = played.to_san(&position.board, position.color).unwrap();
+        // The fifty-move rule counts from the last capture or pawn move.
+        let resets_clock = !position.board.get_square(played.to).is_empty()
+            || position.board.get_square(played.from).is_occupied_by(Piece(position.color, Kind::Pawn));
+        self.positions.push(Fen {
+            board: played.apply(&position.board, position.color).unwrap(),
+            color: position.color.opposite(),
+            halfmove_clock: if resets_clock { 0 } else { position.halfmove_clock + 1 },
+            fullmove_number: if position.color == Color::Black { position.fullmove_number + 1 } else { position.fullmove_number },
+        });
+        self.moves.push(san.clone());
+        san
+    }
+
+    // Take back moves up to and including the last move of the given color. False if it made none.
+    fn undo(&mut self, color: Color) -> bool {
+        let moved = self.positions[..self.positions.len() - 1].iter().any(|position| position.color == color);
+        if moved {
+            while self.positions.len() > 1 {
+                self.positions.pop();
+                self.moves.pop();
+                if self.current().color == color {
+                    break;
+                }
+            }
+        }
+        moved
+    }
+
+    // What to announce when the game is over, and the result.
+    fn end(&self) -> Option<(String, &'static str)> {
+        let position = self.current();
+        if next_boards(&position.board, position.color).is_empty() {
+            return Some(if is_checked(&position.board, position.color) {
+                let winner = position.color.opposite();
+                (format!("Checkmate. {:?} wins.", winner), if winner == Color::White { "1-0" } else { "0-1" })
+            } else {
+                (String::from("Draw by stalemate."), "1/2-1/2")
+            });
+        }
+        let repetitions = self.positions.iter().filter(|other| other.board == position.board && other.color == position.color).count();
+        let reason = if repetitions >= 3 {
+            "threefold repetition"
+        } else if position.halfmove_clock >= 100 {
+            "the fifty-move rule"
+        } else if is_insufficient_material(&position.board) {
+            "insufficient material"
+        } else {
+            return None;
+        };
+        Some((format!("Draw by {}.", reason), "1/2-1/2"))
+    }
+
+    fn to_pgn(&self, options: &PlayOptions, result: &str) -> PgnGame {
+        let engine = format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+        let (white, black) = if options.human == Color::White { (String::from("Human"), engine) } else { (engine, String::from("Human")) };
+        let mut tags = vec![
+            (String::from("Event"), String::from("Casual game")),
+            (String::from("Site"), String::from("Terminal")),
+            (String::from("Date"), String::from("????.??.??")),
+            (String::from("Round"), String::from("-")),
+            (String::from("White"), white),
+            (String::from("Black"), black),
+            (String::from("Result"), String::from(result)),
+        ];
+        let start = self.positions[0];
+        if start.to_string() != START_POSITION_FEN {
+            tags.push((String::from("SetUp"), String::from("1")));
+            tags.push((String::from("FEN"), start.to_string()));
+        }
+        PgnGame { tags, moves: self.moves.clone(), result: String::from(result) }
+    }
+}
+
+// The engine's move, from the book while the position is in it, or None when there are no legal moves.
+fn think(position: &Fen, options: &PlayOptions, state: &mut SearchState, book: Option<&OpeningBook>) -> Option<Move> {
+    if let Some(played) = book.and_then(|book| probe_book(book, &position.board, position.color)) {
+        return Some(played);
+    }
+    let limits = SearchLimits { stop: None, max_nodes: None, deadline: Some(Instant::now() + options.move_time), root_moves: None };
+    let mut context = SearchContext::new().with_limits(limits).with_state(state);
+    let result = search(&position.board, position.color, &SearchOptions::default().with_depth(options.depth), &mut context);
+    let next = result.best_board.or_else(|| next_boards(&position.board, position.color).into_iter().next())?;
+    Move::between(&position.board, &next, position.color)
+}
+
+// A move in coordinate notation or, failing that, in standard algebraic notation.
+fn parse_move(position: &Fen, text: &str) -> Result<Move, Error> {
+    match Move::parse(text) {
+        Ok(played) if played.apply(&position.board, position.color).is_some() => Ok(played),
+        _ => Move::from_san(&position.board, position.color, text)
+    }
+}
+
+// Kings alone, or with a single knight or bishop, cannot mate.
+fn is_insufficient_material(board: &Board) -> bool {
+    let pieces: Vec<Kind> = board.squares().iter().filter_map(|square| match square {
+        Square::Occupied(Piece(_, kind)) if *kind != Kind::King => Some(*kind),
+        _ => None
+    }).collect();
+    matches!(pieces.as_slice(), [] | [Kind::Knight] | [Kind::Bishop])
+}
+
+fn parse_depth(text: &str) -> Result<u8, Error> {
+    text.parse::<u8>().ok().filter(|depth| *depth > 0).ok_or_else(|| Error::InvalidArgument(format!("Invalid depth: {}", text)))
+}
+
+fn parse_seconds(text: &str) -> Result<Duration, Error> {
+    text.parse::<f64>().ok().filter(|seconds| *seconds > 0.0 && seconds.is_finite()).map(Duration::from_secs_f64)
+        .ok_or_else(|| Error::InvalidArgument(format!("Invalid time: {}", text)))
+}
@@ -0,0 +1,122 @@
+use std::io::{ BufRead, Write };
+use std::sync::mpsc::{ channel, Sender };
+use std::thread;
+use std::time::{ Duration, SystemTime, UNIX_EPOCH };
+
+use crate::board::{ Board, Color, Fen, Kind, Piece };
+use crate::book::{ BookSelection, OpeningBook };
+use crate::engine::{ Move };
+use crate::error::{ Error, ParseError, ParseErrorKind };
+
+// The deepest a search goes when only time, nodes or a stop end it.
+pub const MAX_DEPTH: u8 = 64;
+// Time kept back for sending the move.
+pub const MOVE_OVERHEAD: Duration = Duration::from_millis(20);
+// The number of moves the remaining time is spread over when the GUI does not say.
+const DEFAULT_MOVES_TO_GO: u32 = 30;
+
+// An engine speaking a protocol to a GUI, one line at a time. Lines to send go to the output
+// channel, without a newline, so searches running in the background can report too.
+pub trait Session {
+    fn new(output: Sender<String>) -> Self;
+
+    // Handle one line of input. Returns false when the session is over.
+    fn handle(&mut self, line: &str) -> bool;
+
+    // Wait for the running search to end, stopping it only if it would never end by itself.
+    fn finish_search(&mut self);
+
+    // The opening book to play from, when the GUI asked for one.
+    fn book(&self) -> Option<&OpeningBook> {
+        None
+    }
+
+    // The book move to play instead of searching, if the position is in the book.
+    fn book_move(&self, board: &Board, color: Color) -> Option<Move> {
+        probe_book(self.book()?, board, color)
+    }
+}
+
+// Run a session over the input and output until it ends or the input does. At the end of the
+// input a search that is still running is allowed to finish, so scripts can wait for it.
+// Returns the output.
+pub fn run_session<S: Session, R: BufRead, W: Write + Send + 'static>(input: R, mut output: W) -> W {
+    let (sender, receiver) = channel::<String>();
+    // Search threads report while the input is read, so one thread does all the writing.
+    let printer = thread::spawn(move || {
+        for line in receiver {
+            // Nothing can be done about a GUI that stopped listening.
+            let _ = writeln!(output, "{}", line).and_then(|_| output.flush());
+        }
+        output
+    });
+    let mut session = S::new(sender);
+    for line in input.lines().map_while(Result::ok) {
+        if !session.handle(&line) {
+            break;
+        }
+    }
+    session.finish_search();
+    drop(session);
+    printer.join().unwrap()
+}
+
+// The time to spend on a move: a share of the time left plus most of the increment, never more
+// than is left on the clock.
+pub fn time_for_move(time: Duration, increment: Duration, moves_to_go: Option<u32>) -> Duration {
+    let moves = moves_to_go.unwrap_or(DEFAULT_MOVES_TO_GO).max(1);
+    (time / moves + increment * 3 / 4).min(time.saturating_sub(MOVE_OVERHEAD))
+}
+
+// A FEN for a position to play from. Without one king on each side there is no game to play, so
+// such positions are turned away here rather than ending in a search without moves.
+pub fn parse_playable_fen(text: &str) -> Result<Fen, Error> {
+    let fen = Fen::parse(text)?;
+    let placement = text.split_whitespace().next().unwrap_or(text);
+    for color in [Color::White, Color::Black] {
+        let kings = fen.board.squares().iter().filter(|square| square.is_occupied_by(Piece(color, Kind::King))).count();
+        if kings != 1 {
+            let kind = if kings == 0 { ParseErrorKind::Missing } else { ParseErrorKind::InvalidPiece };
+            return Err(ParseError::new(kind, text, placement, format!("{:?} has {} kings instead of one", color, kings)).into());
+        }
+    }
+    Ok(fen)
+}
+
+// A book move for the position, picked at random by weight so games vary, or None when the
+// position is not in the book.
+pub fn probe_book(book: &OpeningBook, board: &Board, color: Color) -> Option<Move> {
+    let random = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_nanos() as u64).unwrap_or(0);
+    book.choose(board, color, BookSelection::WeightedRandom, random).map(|(played, _)| played)
+}
+
+// A book file named in an option. The empty name, or <empty> as GUIs show it, means no book.
+pub fn load_book(path: &str) -> Result<Option<OpeningBook>, Error> {
+    match path {
+        "" | "<empty>" => Ok(None),
+        path => OpeningBook::load(path).map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::{ Error, ParseErrorKind };
+    use super::{ parse_playable_fen };
+
+    #[test]
+    fn test_parse_playable_fen() {
+        assert!(parse_playable_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1").is_ok());
+        // The king count is checked after the FEN itself, and points at the piece placement.
+        let errors = [("8/8/8/8/8/8/8/4K3 w - - 0 1", ParseErrorKind::Missing), ("4k3/8/8/8/8/8/8/3KK3 w - - 0 1", ParseErrorKind::InvalidPiece),
+            ("4k3/8/8/8/8/8/8/4K3 x - - 0 1", ParseErrorKind::Unexpected)];
+        for (fen, kind) in errors {
+            match parse_playable_fen(fen) {
+                Err(Error::Parse(error)) => {
+                    assert_eq!(error.kind, kind);
+                    assert_eq!(error.column, if kind == ParseErrorKind::Unexpected { 21 } else { 1 });
+                },
+                other => panic!("Expect a parse error for {}, got {:?}", fen, other)
+            }
+        }
+    }
+}
@@ -0,0 +1,391 @@
+use std::io::{ BufRead, Write };
+use std::sync::{ Arc, Mutex };
+use std::sync::atomic::{ AtomicBool, Ordering };
+use std::sync::mpsc::{ Sender };
+use std::thread::{ self, JoinHandle };
+use std::time::{ Duration, Instant };
+
+use crate::board::{ Board, Color, Fen, START_POSITION_FEN };
+use crate::book::{ OpeningBook };
+use crate::engine::{ is_mate_score, next_boards, search, Move, SearchContext, SearchLimits, SearchOptions, SearchResult, SearchState, MATE_SCORE };
+use crate::protocol::session::{ load_book, parse_playable_fen, run_session, time_for_move, Session, MAX_DEPTH, MOVE_OVERHEAD };
+use crate::error::{ Error, ParseError, ParseErrorKind };
+use crate::tablebase::{ SyzygyTablebase, Tablebase };
+
+const MAX_HASH_MEGABYTES: usize = 4096;
+const MAX_THREADS: usize = 64;
+const MAX_MULTI_PV: usize = 64;
+
+// Speak UCI over the input and output until `quit` or the end of the input. Returns the output.
+pub fn run_uci<R: BufRead, W: Write + Send + 'static>(input: R, output: W) -> W {
+    run_session::<UciEngine, R, W>(input, output)
+}
+
+// The state of a UCI session: the position, the options and the search running in the background.
+pub struct UciEngine {
+    output: Sender<String>,
+    board: Board,
+    color: Color,
+    options: SearchOptions,
+    tablebase: Option<Arc<dyn Tablebase + Send>>,
+    book: Option<OpeningBook>,
+    // Whether to play from the book, as set by OwnBook.
+    own_book: bool,
+    // Kept from one search to the next. Only the running search holds the lock.
+    state: Arc<Mutex<SearchState>>,
+    search: Option<RunningSearch>,
+}
+
+struct RunningSearch {
+    stop: Arc<AtomicBool>,
+    // Set while the best move has to wait for `stop` or `ponderhit`, as infinite and ponder
+    // searches never end by themselves.
+    hold: Arc<AtomicBool>,
+    // The time to allow once a ponder search becomes a real one.
+    ponder_time: Option<Duration>,
+    handle: JoinHandle<()>,
+}
+
+impl Session for UciEngine {
+    fn new(output: Sender<String>) -> UciEngine {
+        let start = Fen::parse(START_POSITION_FEN).unwrap();
+        UciEngine { output, board: start.board, color: start.color, options: SearchOptions::default(), tablebase: None,
+            book: None, own_book: false, state: Arc::new(Mutex::new(SearchState::new())), search: None }
+    }
+
+    fn handle(&mut self, line: &str) -> bool {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let result = match tokens.first().copied() {
+            Some("uci") => {
+                self.identify();
+                Ok(())
+            },
+            Some("isready") => {
+                self.send(String::from("readyok"));
+                Ok(())
+            },
+            Some("ucinewgame") => {
+                self.stop_search();
+                self.state.lock().unwrap().clear();
+                Ok(())
+            },
+            Some("stop") => {
+                self.stop_search();
+                Ok(())
+            },
+            Some("ponderhit") => {
+                self.ponder_hit();
+                Ok(())
+            },
+            Some("position") => self.set_position(line).map_err(String::from),
+            Some("go") => self.go(line).map_err(String::from),
+            Some("setoption") => self.set_option(&tokens[1..]),
+            Some("quit") => {
+                self.stop_search();
+                return false;
+            },
+            Some("debug") | Some("register") | None => Ok(()),
+            Some(command) => Err(format!("Unknown command: {}", command))
+        };
+        if let Err(error) = result {
+            self.send(format!("info string {}", error));
+        }
+        true
+    }
+
+    fn finish_search(&mut self) {
+        if let Some(search) = self.search.take() {
+            if search.hold.load(Ordering::Relaxed) {
+                search.stop.store(true, Ordering::Relaxed);
+            }
+            search.handle.join().unwrap();
+        }
+    }
+
+    fn book(&self) -> Option<&OpeningBook> {
+        self.book.as_ref().filter(|_| self.own_book)
+    }
+}
+
+impl UciEngine {
+    fn stop_search(&mut self) {
+        if let Some(search) = self.search.take() {
+            search.stop.store(true, Ordering::Relaxed);
+            search.hold.store(false, Ordering::Relaxed);
+            search.handle.join().unwrap();
+        }
+    }
+
+    // The opponent played the expected move: the ponder search goes on as a search on the clock.
+    fn ponder_hit(&mut self) {
+        if let Some(search) = self.search.as_mut() {
+            if let Some(time) = search.ponder_time.take() {
+                let stop = search.stop.clone();
+                thread::spawn(move || {
+                    thread::sleep(time);
+                    stop.store(true, Ordering::Relaxed);
+                });
+            }
+            search.hold.store(false, Ordering::Relaxed);
+        }
+    }
+
+    fn identify(&self) {
+        let defaults = SearchOptions::default();
+        self.send(format!("id name {} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")));
+        self.send(format!("id author {}", env!("CARGO_PKG_AUTHORS")));
+        self.send(format!("option name Hash type spin default {} min 1 max {}", defaults.hash_megabytes, MAX_HASH_MEGABYTES));
+        self.send(format!("option name Threads type spin default {} min 1 max {}", defaults.threads, MAX_THREADS));
+        self.send(format!("option name MultiPV type spin default {} min 1 max {}", defaults.multi_pv, MAX_MULTI_PV));
+        self.send(String::from("option name Ponder type check default false"));
+        self.send(String::from("option name SyzygyPath type string default <empty>"));
+        self.send(String::from("option name OwnBook type check default false"));
+        self.send(String::from("option name BookFile type string default <empty>"));
+        self.send(String::from("uciok"));
+    }
+
+    // position startpos|fen <fen> [moves <move>...]
+    fn set_position(&mut self, line: &str) -> Result<(), Error> {
+        let tokens: Vec<&str> = line.split_whitespace().skip(1).collect();
+        let moves_index = tokens.iter().position(|token| *token == "moves").unwrap_or(tokens.len());
+        let fen = match tokens.first() {
+            Some(&"startpos") => Fen::parse(START_POSITION_FEN)?,
+            Some(&"fen") => parse_playable_fen(&tokens[1..moves_index].join(" "))?,
+            other => {
+                let text = other.copied().unwrap_or(&line[line.len()..]);
+                return Err(ParseError::new(ParseErrorKind::Unexpected, line, text, String::from("Expect startpos or fen")).into());
+            }
+        };
+        let (mut board, mut color) = (fen.board, fen.color);
+        for text in tokens.iter().skip(moves_index + 1) {
+            board = Move::parse(text).map_err(|error| error.within(line, text))?.apply(&board, color)
+                .ok_or_else(|| Error::IllegalMove(format!("Illegal move: {}", text)))?;
+            color = color.opposite();
+        }
+        self.board = board;
+        self.color = color;
+        Ok(())
+    }
+
+    // setoption name <name> [value <value>]. Names are not case sensitive.
+    fn set_option(&mut self, tokens: &[&str]) -> Result<(), String> {
+        if tokens.first() != Some(&"name") {
+            return Err(String::from("Expect setoption name <name> [value <value>]"));
+        }
+        let value_index = tokens.iter().position(|token| *token == "value").unwrap_or(tokens.len());
+        let name = tokens[1..value_index].join(" ");
+        let value = tokens.get(value_index + 1..).map(|value| value.join(" ")).unwrap_or_default();
+        let number = |max: usize| value.parse::<usize>().map(|number| number.clamp(1, max))
+            .map_err(|_| format!("Invalid value for {}: {}", name, value));
+        match name.to_lowercase().as_str() {
+            "hash" => { self.options.hash_megabytes = number(MAX_HASH_MEGABYTES)?; },
+            "threads" => { self.options.threads = number(MAX_THREADS)?; },
+            "multipv" => { self.options.multi_pv = number(MAX_MULTI_PV)?; },
+            // The GUI only says whether it will ask for pondering.
+            "ponder" => {},
+            "syzygypath" => {
+                self.tablebase = match value.as_str() {
+                    "" | "<empty>" => None,
+                    directory => Some(Arc::new(SyzygyTablebase::open(directory)?))
+                };
+            },
+            "ownbook" => {
+                self.own_book = value.parse::<bool>().map_err(|_| format!("Invalid value for {}: {}", name, value))?;
+            },
+            "bookfile" => { self.book = load_book(&value)?; },
+            _ => { return Err(format!("Unknown option: {}", name)); }
+        }
+        Ok(())
+    }
+
+    fn go(&mut self, line: &str) -> Result<(), Error> {
+        let go = GoParameters::parse(line)?;
+        // The moves to choose from, as the boards they lead to.
+        let root_moves = go.search_moves.iter()
+            .map(|played| played.apply(&self.board, self.color).ok_or_else(|| Error::IllegalMove(format!("Illegal move: {}", played))))
+            .collect::<Result<Vec<Board>, Error>>()?;
+        self.finish_search();
+
+        // A book move is played at once, unless the GUI waits for `stop` or wants other moves searched.
+        if !go.infinite && !go.ponder {
+            if let Some(played) = self.book_move(&self.board, self.color).filter(|played| go.search_moves.is_empty() || go.search_moves.contains(played)) {
+                self.send(format!("bestmove {}", played));
+                return Ok(());
+            }
+        }
+
+        let start = Instant::now();
+        let time = go.allotted_time(self.color);
+        let deadline = if go.ponder { None } else { time.map(|time| start + time) };
+        let stop = Arc::new(AtomicBool::new(false));
+        let hold = Arc::new(AtomicBool::new(go.infinite || go.ponder));
+        let (board, color, options) = (self.board, self.color, self.options);
+        let (output, tablebase, state) = (self.output.clone(), self.tablebase.clone(), self.state.clone());
+        let (search_stop, search_hold) = (stop.clone(), hold.clone());
+        let (depth, nodes, ponder) = (go.depth, go.nodes, go.ponder);
+        let handle = thread::spawn(move || {
+            let root_moves = Some(root_moves.as_slice()).filter(|moves| !moves.is_empty());
+            let limits = SearchLimits { stop: Some(&search_stop), max_nodes: nodes, deadline, root_moves };
+            let mut report = |result: &SearchResult| {
+                for index in 0..result.lines.len() {
+                    let _ = output.send(info_line(&board, color, result, index, options.multi_pv > 1, start.elapsed()));
+                }
+            };
+            let mut state = state.lock().unwrap();
+            let mut context = SearchContext::new().with_limits(limits).with_state(&mut state).with_reporter(&mut report);
+            if let Some(tablebase) = tablebase.as_deref() {
+                context = context.with_tablebase(tablebase as &dyn Tablebase);
+            }
+            let result = search(&board, color, &options.with_depth(depth.unwrap_or(MAX_DEPTH)), &mut context);
+            while search_hold.load(Ordering::Relaxed) && !search_stop.load(Ordering::Relaxed) {
+                thread::sleep(Duration::from_millis(1));
+            }
+            let _ = output.send(best_move_line(&board, color, &result));
+        });
+        self.search = Some(RunningSearch { stop, hold, ponder_time: if ponder { time } else { None }, handle });
+        Ok(())
+    }
+
+    fn send(&self, line: String) {
+        // The receiving end only goes away when the session is over.
+        let _ = self.output.send(line);
+    }
+}
+
+// The parameters of `go` that take a number.
+const GO_NUMBERS: [&str; 9] = ["depth", "nodes", "mate", "movetime", "wtime", "btime", "winc", "binc", "movestogo"];
+// The parameters of `go` that stand alone.
+const GO_FLAGS: [&str; 3] = ["infinite", "ponder", "searchmoves"];
+
+#[derive(Debug,Clone,Default)]
+struct GoParameters {
+    search_moves: Vec<Move>,
+    depth: Option<u8>,
+    nodes: Option<u64>,
+    move_time: Option<Duration>,
+    white_time: Option<Duration>,
+    black_time: Option<Duration>,
+    white_increment: Option<Duration>,
+    black_increment: Option<Duration>,
+    moves_to_go: Option<u32>,
+    infinite: bool,
+    ponder: bool,
+}
+
+impl GoParameters {
+    // The parameters of a `go` line. Unknown tokens are skipped.
+    fn parse(line: &str) -> Result<GoParameters, Error> {
+        let tokens: Vec<&str> = line.split_whitespace().skip(1).collect();
+        let mut go = GoParameters::default();
+        let mut index = 0;
+        while index < tokens.len() {
+            let name = tokens[index];
+            index += 1;
+            // Clocks can go below zero when a GUI is late, which counts as no time left.
+            let number = || {
+                let text = tokens.get(index).copied().unwrap_or(&line[line.len()..]);
+                text.parse::<i64>().map(|number| number.max(0) as u64)
+                    .map_err(|_| ParseError::new(ParseErrorKind::InvalidNumber, line, text, format!("Expect a number after {}", name)))
+            };
+            let millis = || number().map(|number| Some(Duration::from_millis(number)));
+            match name {
+                "infinite" => { go.infinite = true; },
+                "ponder" => { go.ponder = true; },
+                // The moves run up to the next parameter.
+                "searchmoves" => {
+                    while let Some(text) = tokens.get(index).filter(|token| !GO_NUMBERS.contains(token) && !GO_FLAGS.contains(token)) {
+                        go.search_moves.push(Move::parse(text).map_err(|error| error.within(line, text))?);
+                        index += 1;
+                    }
+                },
+                "depth" => { go.depth = Some(number()?.clamp(1, MAX_DEPTH as u64) as u8); },
+                // A mate in n moves takes 2n - 1 plies to find.
+                "mate" => { go.depth = Some((number()? * 2).saturating_sub(1).clamp(1, MAX_DEPTH as u64) as u8); },
+                "nodes" => { go.nodes = Some(number()?); },
+                "movetime" => { go.move_time = millis()?; },
+                "wtime" => { go.white_time = millis()?; },
+                "btime" => { go.black_time = millis()?; },
+                "winc" => { go.white_increment = millis()?; },
+                "binc" => { go.black_increment = millis()?; },
+                "movestogo" => { go.moves_to_go = Some(number()? as u32); },
+                _ => {}
+            }
+            if GO_NUMBERS.contains(&name) {
+                index += 1;
+            }
+        }
+        Ok(go)
+    }
+
+    // The time to search for the color to move.
+    fn allotted_time(&self, color: Color) -> Option<Duration> {
+        if let Some(move_time) = self.move_time {
+            return Some(move_time.saturating_sub(MOVE_OVERHEAD));
+        }
+        let (time, increment) = if color == Color::White {
+            (self.white_time?, self.white_increment.unwrap_or_default())
+        } else {
+            (self.black_time?, self.black_increment.unwrap_or_default())
+        };
+        Some(time_for_move(time, increment, self.moves_to_go))
+    }
+}
+
+fn info_line(board: &Board, color: Color, result: &SearchResult, index: usize, multi_pv: bool, elapsed: Duration) -> String {
+    let line = &result.lines[index];
+    let millis = elapsed.as_millis() as u64;
+    let mut info = format!("info depth {}", result.depth);
+    if multi_pv {
+        info.push_str(&format!(" multipv {}", index + 1));
+    }
+    info.push_str(&format!(" score {} nodes {} nps {} time {} tbhits {} pv", score_text(line.score), result.nodes,
+        result.nodes * 1000 / millis.max(1), millis, result.tablebase_hits));
+    for played in Move::line(board, color, &line.principal_variation) {
+        info.push_str(&format!(" {}", played));
+    }
+    info
+}
+
+// Centipawns, or the number of moves to mate: negative when getting mated.
+fn score_text(score: i32) -> String {
+    if is_mate_score(score) {
+        let moves = (MATE_SCORE - score.abs() + 1) / 2;
+        format!("mate {}", if score > 0 { moves } else { -moves })
+    } else {
+        format!("cp {}", score)
+    }
+}
+
+// The best move and the expected reply. A search stopped before it finished any iteration still
+// plays a legal move, and without legal moves the null move is sent.
+fn best_move_line(board: &Board, color: Color, result: &SearchResult) -> String {
+    let mut moves = Move::line(board, color, &result.principal_variation);
+    if moves.is_empty() {
+        moves.extend(next_boards(board, color).first().and_then(|child| Move::between(board, child, color)));
+    }
+    match moves.as_slice() {
+        [] => String::from("bestmove 0000"),
+        [best] => format!("bestmove {}", best),
+        [best, reply, ..] => format!("bestmove {} ponder {}", best, reply)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::{ Error, ParseErrorKind };
+    use super::{ GoParameters };
+
+    #[test]
+    fn test_go_parameters() {
+        let go = GoParameters::parse("go wtime 1000 btime -5 depth 3").unwrap();
+        assert_eq!((go.depth, go.black_time.map(|time| time.as_millis())), (Some(3), Some(0)));
+        // Errors point at the token in the line, or at its end when the number is missing.
+        let kind_and_column = |line: &str| match GoParameters::parse(line) {
+            Err(Error::Parse(error)) => (error.kind, error.column),
+            other => panic!("Expect a parse error for {}, got {:?}", line, other)
+        };
+        assert_eq!(kind_and_column("go depth x"), (ParseErrorKind::InvalidNumber, 10));
+        assert_eq!(kind_and_column("go movetime"), (ParseErrorKind::InvalidNumber, 12));
+        assert_eq!(kind_and_column("go searchmoves e2e4 e2z9"), (ParseErrorKind::InvalidSquare, 23));
+    }
+}
@@ -0,0 +1,333 @@
+use std::io::{ BufRead, Write };
+use std::sync::{ Arc, Mutex };
+use std::sync::atomic::{ AtomicBool, Ordering };
+use std::sync::mpsc::{ Sender };
+use std::thread::{ self, JoinHandle };
+use std::time::{ Duration, Instant };
+
+use crate::board::{ Board, Color, Fen, START_POSITION_FEN };
+use crate::book::{ OpeningBook };
+use crate::engine::{ is_checked, is_mate_score, next_boards, search, Move, SearchContext, SearchLimits, SearchOptions, SearchResult, SearchState, MATE_SCORE };
+use crate::error::{ Error, ParseError, ParseErrorKind };
+use crate::protocol::session::{ load_book, parse_playable_fen, run_session, time_for_move, Session, MAX_DEPTH, MOVE_OVERHEAD };
+
+// Mate scores in thinking output are this plus the number of moves to mate.
+const XBOARD_MATE_SCORE: i32 = 100_000;
+
+// Speak CECP (the XBoard protocol) over the input and output until `quit` or the end of the
+// input. Returns the output.
+pub fn run_xboard<R: BufRead, W: Write + Send + 'static>(input: R, output: W) -> W {
+    run_session::<XboardEngine, R, W>(input, output)
+}
+
+// The state of a CECP game: the moves so far, which side the engine plays and the time control.
+pub struct XboardEngine {
+    output: Sender<String>,
+    // Every position of the game, the current one last, so moves can be taken back.
+    history: Vec<(Board, Color)>,
+    // None in force mode, when the engine only keeps track of the moves.
+    engine_color: Option<Color>,
+    post: bool,
+    max_depth: Option<u8>,
+    // Set by `st`: the time for every move.
+    move_time: Option<Duration>,
+    // Set by `level`: the moves in each time control, 0 for the whole game, and the increment.
+    moves_per_session: u32,
+    increment: Duration,
+    // The engine's clock, as set by `level` and `time`.
+    clock: Duration,
+    book: Option<OpeningBook>,
+    // Whether to play from the book, as set by the OwnBook option.
+    own_book: bool,
+    // Kept from one search to the next. Only the running search holds the lock.
+    state: Arc<Mutex<SearchState>>,
+    search: Option<RunningSearch>,
+}
+
+struct RunningSearch {
+    stop: Arc<AtomicBool>,
+    // Set when the move is no longer wanted, as after `force` or `new`.
+    cancel: Arc<AtomicBool>,
+    // The board after the move the engine sent, if it sent one.
+    handle: JoinHandle<Option<Board>>,
+}
+
+impl Session for XboardEngine {
+    fn new(output: Sender<String>) -> XboardEngine {
+        let start = Fen::parse(START_POSITION_FEN).unwrap();
+        XboardEngine {
+            output,
+            history: vec![(start.board, start.color)],
+            engine_color: Some(Color::Black),
+            post: false,
+            max_depth: None,
+            move_time: None,
+            // XBoard's own default: 40 moves in 5 minutes.
+            moves_per_session: 40,
+            increment: Duration::ZERO,
+            clock: Duration::from_secs(300),
+            book: None,
+            own_book: false,
+            state: Arc::new(Mutex::new(SearchState::new())),
+            search: None,
+        }
+    }
+
+    fn handle(&mut self, line: &str) -> bool {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let arguments = line.trim().split_once(' ').map(|(_, arguments)| arguments.trim()).unwrap_or("");
+        let result = match tokens.first().copied() {
+            Some("protover") => {
+                self.send(format!("feature myname=\"{} {}\" ping=1 setboard=1 usermove=1 playother=0 san=0 time=1 draw=0 sigint=0 sigterm=0 reuse=1 analyze=0 colors=0 option=\"OwnBook -check 0\" option=\"BookFile -file \" done=1",
+                    env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")));
+                Ok(())
+            },
+            Some("ping") => {
+                self.send(format!("pong {}", arguments));
+                Ok(())
+            },
+            Some("new") => {
+                self.cancel_search();
+                self.state.lock().unwrap().clear();
+                let start = Fen::parse(START_POSITION_FEN).unwrap();
+                self.history = vec![(start.board, start.color)];
+                self.engine_color = Some(Color::Black);
+                self.max_depth = None;
+                Ok(())
+            },
+            Some("setboard") => self.set_board(arguments),
+            Some("usermove") => self.user_move(arguments),
+            Some("go") => {
+                self.finish_search();
+                self.engine_color = Some(self.position().1);
+                self.think();
+                Ok(())
+            },
+            Some("force") | Some("result") => {
+                self.cancel_search();
+                self.engine_color = None;
+                Ok(())
+            },
+            Some("?") => {
+                if let Some(search) = self.search.as_ref() {
+                    search.stop.store(true, Ordering::Relaxed);
+                }
+                Ok(())
+            },
+            Some("undo") => self.take_back(1, line),
+            Some("remove") => self.take_back(2, line),
+            Some("level") => self.set_level(&tokens[1..]),
+            Some("option") => self.set_option(arguments, line),
+            Some("st") => seconds(arguments).map(|time| { self.move_time = Some(time); })
+                .map_err(|error| format!("Error ({}): {}", String::from(error), line)),
+            Some("sd") => arguments.parse::<u8>().map(|depth| { self.max_depth = Some(depth.clamp(1, MAX_DEPTH)); })
+                .map_err(|_| format!("Error (invalid depth): {}", line)),
+            Some("time") => arguments.parse::<u64>().map(|centiseconds| { self.clock = Duration::from_millis(centiseconds * 10); })
+                .map_err(|_| format!("Error (invalid time): {}", line)),
+            Some("post") => {
+                self.post = true;
+                Ok(())
+            },
+            Some("nopost") => {
+                self.post = false;
+                Ok(())
+            },
+            Some("quit") => {
+                self.cancel_search();
+                return false;
+            },
+            Some("xboard") | Some("accepted") | Some("rejected") | Some("random") | Some("hard") | Some("easy")
+                | Some("computer") | Some("otim") | Some("name") | Some("rating") | None => Ok(()),
+            // Older interfaces send moves without `usermove`.
+            Some(command) if Move::parse(command).is_ok() => self.user_move(command),
+            Some(_) => Err(format!("Error (unknown command): {}", line))
+        };
+        if let Err(error) = result {
+            self.send(error);
+        }
+        true
+    }
+
+    fn finish_search(&mut self) {
+        if let Some(search) = self.search.take() {
+            self.collect(search);
+        }
+    }
+
+    fn book(&self) -> Option<&OpeningBook> {
+        self.book.as_ref().filter(|_| self.own_book)
+    }
+}
+
+impl XboardEngine {
+    fn position(&self) -> (Board, Color) {
+        *self.history.last().unwrap()
+    }
+
+    // Stop the search without playing its move, unless the move was sent already.
+    fn cancel_search(&mut self) {
+        if let Some(search) = self.search.take() {
+            search.cancel.store(true, Ordering::Relaxed);
+            search.stop.store(true, Ordering::Relaxed);
+            self.collect(search);
+        }
+    }
+
+    fn collect(&mut self, search: RunningSearch) {
+        if let Some(board) = search.handle.join().unwrap() {
+            let color = self.position().1.opposite();
+            self.history.push((board, color));
+        }
+    }
+
+    fn set_board(&mut self, fen: &str) -> Result<(), String> {
+        self.cancel_search();
+        let fen = parse_playable_fen(fen).map_err(|_| String::from("tellusererror Illegal position"))?;
+        self.history = vec![(fen.board, fen.color)];
+        Ok(())
+    }
+
+    fn user_move(&mut self, text: &str) -> Result<(), String> {
+        self.finish_search();
+        let (board, color) = self.position();
+        let next = Move::parse(text).ok().and_then(|played| played.apply(&board, color))
+            .ok_or_else(|| format!("Illegal move: {}", text))?;
+        self.history.push((next, color.opposite()));
+        match game_result(&next, color.opposite()) {
+            Some(result) => self.send(result),
+            None if self.engine_color == Some(color.opposite()) => self.think(),
+            None => {}
+        }
+        Ok(())
+    }
+
+    fn take_back(&mut self, plies: usize, command: &str) -> Result<(), String> {
+        self.cancel_search();
+        if self.history.len() <= plies {
+            return Err(format!("Error (no moves to take back): {}", command));
+        }
+        self.history.truncate(self.history.len() - plies);
+        Ok(())
+    }
+
+    // level <moves> <minutes or minutes:seconds> <increment seconds>
+    fn set_level(&mut self, tokens: &[&str]) -> Result<(), String> {
+        let invalid = || format!("Error (invalid time control): level {}", tokens.join(" "));
+        if tokens.len() != 3 {
+            return Err(invalid());
+        }
+        let moves = tokens[0].parse::<u32>().map_err(|_| invalid())?;
+        let base = match tokens[1].split_once(':') {
+            Some((minutes, extra)) => minutes.parse::<u64>().ok().zip(extra.parse::<u64>().ok())
+                .map(|(minutes, extra)| Duration::from_secs(minutes * 60 + extra)),
+            None => tokens[1].parse::<f64>().ok().filter(|minutes| *minutes >= 0.0).map(|minutes| Duration::from_secs_f64(minutes * 60.0))
+        }.ok_or_else(invalid)?;
+        self.increment = seconds(tokens[2]).map_err(|_| invalid())?;
+        self.moves_per_session = moves;
+        self.clock = base;
+        self.move_time = None;
+        Ok(())
+    }
+
+    // option <name>=<value>, for the options announced in the features.
+    fn set_option(&mut self, arguments: &str, line: &str) -> Result<(), String> {
+        match arguments.split_once('=') {
+            Some(("OwnBook", value)) => {
+                self.own_book = match value {
+                    "1" => true,
+                    "0" => false,
+                    _ => return Err(format!("Error (invalid value): {}", line))
+                };
+            },
+            Some(("BookFile", path)) => {
+                self.book = load_book(path).map_err(|error| format!("tellusererror {}", String::from(error)))?;
+            },
+            _ => { return Err(format!("Error (unknown option): {}", line)); }
+        }
+        Ok(())
+    }
+
+    // Play a book move, or search the current position in the background. The search sends its
+    // move when done.
+    fn think(&mut self) {
+        let (board, color) = self.position();
+        if let Some(played) = self.book_move(&board, color) {
+            let next = played.apply(&board, color).unwrap();
+            self.send(format!("move {}", played));
+            self.history.push((next, color.opposite()));
+            if let Some(result) = game_result(&next, color.opposite()) {
+                self.send(result);
+            }
+            return;
+        }
+        let start = Instant::now();
+        let time = match self.move_time {
+            Some(move_time) => move_time.saturating_sub(MOVE_OVERHEAD),
+            None => {
+                let moves_played = (self.history.len() as u32 - 1) / 2;
+                let moves_to_go = Some(self.moves_per_session).filter(|moves| *moves > 0).map(|moves| moves - moves_played % moves);
+                time_for_move(self.clock, self.increment, moves_to_go)
+            }
+        };
+        let (depth, post, output) = (self.max_depth.unwrap_or(MAX_DEPTH), self.post, self.output.clone());
+        let (stop, cancel) = (Arc::new(AtomicBool::new(false)), Arc::new(AtomicBool::new(false)));
+        let (search_stop, search_cancel, state) = (stop.clone(), cancel.clone(), self.state.clone());
+        let handle = thread::spawn(move || {
+            let limits = SearchLimits { stop: Some(&search_stop), max_nodes: None, deadline: Some(start + time), root_moves: None };
+            let mut report = |result: &SearchResult| {
+                if post {
+                    let _ = output.send(thinking_line(&board, color, result, start.elapsed()));
+                }
+            };
+            let mut state = state.lock().unwrap();
+            let mut context = SearchContext::new().with_limits(limits).with_state(&mut state).with_reporter(&mut report);
+            let result = search(&board, color, &SearchOptions::default().with_depth(depth), &mut context);
+            // A search stopped before it finished any iteration still plays a legal move.
+            let next = result.best_board.or_else(|| next_boards(&board, color).into_iter().next())?;
+            if search_cancel.load(Ordering::Relaxed) {
+                return None;
+            }
+            let _ = output.send(format!("move {}", Move::between(&board, &next, color)?));
+            if let Some(result) = game_result(&next, color.opposite()) {
+                let _ = output.send(result);
+            }
+            Some(next)
+        });
+        self.search = Some(RunningSearch { stop, cancel, handle });
+    }
+
+    fn send(&self, line: String) {
+        // The receiving end only goes away when the session is over.
+        let _ = self.output.send(line);
+    }
+}
+
+// Seconds, which may have a fraction. The message is the error type CECP puts in brackets.
+fn seconds(text: &str) -> Result<Duration, Error> {
+    text.parse::<f64>().ok().filter(|seconds| *seconds >= 0.0 && seconds.is_finite()).map(Duration::from_secs_f64)
+        .ok_or_else(|| ParseError::new(ParseErrorKind::InvalidNumber, text, text, String::from("invalid time")).into())
+}
+
+// Depth, score in centipawns, time in centiseconds, nodes and the principal variation.
+fn thinking_line(board: &Board, color: Color, result: &SearchResult, elapsed: Duration) -> String {
+    let score = if is_mate_score(result.score) {
+        let moves = XBOARD_MATE_SCORE + (MATE_SCORE - result.score.abs() + 1) / 2;
+        if result.score > 0 { moves } else { -moves }
+    } else {
+        result.score
+    };
+    let moves: Vec<String> = Move::line(board, color, &result.principal_variation).iter().map(|played| played.to_string()).collect();
+    format!("{} {} {} {} {}", result.depth, score, elapsed.as_millis() / 10, result.nodes, moves.join(" "))
+}
+
+// The result to announce when the color to move has no moves left.
+fn game_result(board: &Board, color: Color) -> Option<String> {
+    if !next_boards(board, color).is_empty() {
+        return None;
+    }
+    Some(String::from(match (is_checked(board, color), color) {
+        (true, Color::White) => "0-1 {Black mates}",
+        (true, Color::Black) => "1-0 {White mates}",
+        (false, _) => "1/2-1/2 {Stalemate}"
+    }))
+}
@@ -0,0 +1,128 @@
+use std::sync::{ OnceLock };
+
+// Up to 7 pieces, so a group has at most 6 and there are at most 5 leading pawns.
+pub const MAX_GROUP: usize = 7;
+
+// Lookup tables to turn a placement of pieces into a table index, following the Syzygy encoding.
+// Squares are indexed from a1, like the board does.
+pub struct Encoding {
+    // binomial[k][n] ways to choose k of n squares.
+    pub binomial: [[u64; 64]; MAX_GROUP],
+    // Squares a2-h7 numbered so the leading pawn, nearest the edge and lowest, has the highest number.
+    pub map_pawns: [usize; 64],
+    // Index of the leading pawn square, per number of leading pawns.
+    pub lead_pawn_index: [[u64; 64]; MAX_GROUP],
+    // Number of placements per file of the leading pawn, per number of leading pawns.
+    pub lead_pawns_size: [[u64; 4]; MAX_GROUP],
+    // Squares below the a1-h8 diagonal numbered 0..28.
+    pub map_b1h1h7: [u64; 64],
+    // The a1-d1-d4 triangle numbered 0..10, the diagonal last.
+    pub map_a1d1d4: [usize; 64],
+    // The 462 placements of two kings, the first in the a1-d1-d4 triangle.
+    pub map_kk: [[u64; 64]; 10],
+}
+
+pub fn encoding() -> &'static Encoding {
+    static ENCODING: OnceLock<Encoding> = OnceLock::new();
+    ENCODING.get_or_init(Encoding::new)
+}
+
+// Rank minus file: zero on the a1-h8 diagonal, negative below it.
+pub fn off_diagonal(square: usize) -> i32 {
+    (square / 8) as i32 - (square % 8) as i32
+}
+
+impl Encoding {
+    fn new() -> Encoding {
+        let mut encoding = Encoding {
+            binomial: [[0; 64]; MAX_GROUP],
+            map_pawns: [0; 64],
+            lead_pawn_index: [[0; 64]; MAX_GROUP],
+            lead_pawns_size: [[0; 4]; MAX_GROUP],
+            map_b1h1h7: [0; 64],
+            map_a1d1d4: [0; 64],
+            map_kk: [[0; 64]; 10],
+        };
+
+        let mut code = 0;
+        for square in 0..64 {
+            if off_diagonal(square) < 0 {
+                encoding.map_b1h1h7[square] = code;
+                code += 1;
+            }
+        }
+
+        let mut diagonal = vec![];
+        let mut code = 0;
+        for square in (0..=27).filter(|square| square % 8 <= 3) {
+            if off_diagonal(square) < 0 {
+                encoding.map_a1d1d4[square] = code;
+                code += 1;
+            } else if off_diagonal(square) == 0 {
+                diagonal.push(square);
+            }
+        }
+        for square in diagonal {
+            encoding.map_a1d1d4[square] = code;
+            code += 1;
+        }
+
+        // With the first king on the diagonal, the second is never above it. Placements with
+        // both kings on the diagonal come last.
+        let mut both_on_diagonal = vec![];
+        let mut code = 0;
+        for index in 0..10 {
+            for first in (0..=27).filter(|square| square % 8 <= 3) {
+                if encoding.map_a1d1d4[first] != index || (index == 0 && first != 1) {
+                    continue;
+                }
+                for second in 0..64 {
+                    let adjacent = (first / 8).abs_diff(second / 8) <= 1 && (first % 8).abs_diff(second % 8) <= 1;
+                    if adjacent || (off_diagonal(first) == 0 && off_diagonal(second) > 0) {
+                        continue;
+                    }
+                    if off_diagonal(first) == 0 && off_diagonal(second) == 0 {
+                        both_on_diagonal.push((index, second));
+                    } else {
+                        encoding.map_kk[index][second] = code;
+                        code += 1;
+                    }
+                }
+            }
+        }
+        for (index, second) in both_on_diagonal {
+            encoding.map_kk[index][second] = code;
+            code += 1;
+        }
+
+        encoding.binomial[0][0] = 1;
+        for n in 1..64 {
+            for k in 0..MAX_GROUP.min(n + 1) {
+                let with_last = if k > 0 { encoding.binomial[k - 1][n - 1] } else { 0 };
+                let without_last = if k < n { encoding.binomial[k][n - 1] } else { 0 };
+                encoding.binomial[k][n] = with_last + without_last;
+            }
+        }
+
+        // 47 squares are left for other pawns when the leading pawn is on a2, two fewer for every
+        // rank further, since the squares below and their mirror images are excluded.
+        let mut available = 48;
+        for lead_pawns in 1..MAX_GROUP {
+            for file in 0..4 {
+                let mut index = 0;
+                for rank in 1..7 {
+                    let square = rank * 8 + file;
+                    if lead_pawns == 1 {
+                        encoding.map_pawns[square] = available - 1;
+                        encoding.map_pawns[square ^ 7] = available - 2;
+                        available -= 2;
+                    }
+                    encoding.lead_pawn_index[lead_pawns][square] = index;
+                    index += encoding.binomial[lead_pawns - 1][encoding.map_pawns[square]];
+                }
+                encoding.lead_pawns_size[lead_pawns][file] = index;
+            }
+        }
+        encoding
+    }
+}
@@ -0,0 +1,616 @@
+// Distance-to-mate tables generated by retrograde analysis, for up to 4 pieces.
+//
+// A table covers one material signature, such as "KQK" or "KBNK": white's pieces, then black's.
+// Tables are stored with white as the stronger side; positions with the colors the other way round
+// are probed mirrored. Every position has one value: 0 for a draw or an impossible placement, or
+// the number of plies to mate plus one. Odd plies are a win for the color to move, even plies a
+// loss. En passant captures are ignored, and positions with castling rights are not covered.
+//
+// A position's index is side to move (white first), white king, black king, then the other pieces
+// in signature order, 64 squares each. The white king is first moved to the a1-d1-d4 triangle by
+// mirroring the board, or only to the a-d files with pawns. Identical pieces are in square order.
+//
+// Table file, named after the signature with a ".dtm" extension, numbers little-endian:
+//
+//   magic       4 bytes  "DTMT"
+//   version     u32      1
+//   name length u8       N
+//   name        N bytes  the signature
+//   positions   u32      P
+//   values      runs of (count u8, value u8) until all P values are read
+
+use std::collections::{ HashMap };
+use std::fs;
+use std::path::{ Path };
+
+use crate::board::{ Board, CastlingSide, Color, Coordinates, Kind, Piece, Square };
+use crate::engine::{ attacked_squares, is_checked, mask_coordinates, next_boards };
+use crate::error::{ Error, ParseError, ParseErrorKind };
+use crate::tablebase::tablebase::{ Tablebase, Wdl };
+
+const MAGIC: &[u8; 4] = b"DTMT";
+const VERSION: u32 = 1;
+const MAX_PIECES: usize = 4;
+// The order of the pieces in a signature.
+const SIGNATURE_KINDS: [Kind; 5] = [Kind::Queen, Kind::Rook, Kind::Bishop, Kind::Knight, Kind::Pawn];
+// The a1-d1-d4 triangle.
+const KING_TRIANGLE: [usize; 10] = [0, 1, 2, 3, 9, 10, 11, 18, 19, 27];
+
+#[derive(Debug,Clone,PartialEq)]
+struct Material {
+    // The pieces besides the king, in signature order.
+    white: Vec<Kind>,
+    black: Vec<Kind>,
+    // All pieces in index order: the kings, then the others in signature order.
+    slots: Vec<Piece>,
+}
+
+impl Material {
+    fn parse(signature: &str) -> Result<Material, ParseError> {
+        let invalid = |text: &str| ParseError::new(ParseErrorKind::InvalidPiece, signature, text, format!("Invalid material: {}", signature));
+        let missing_king = |text: &str| ParseError::new(ParseErrorKind::Missing, signature, text, format!("Missing king in material: {}", signature));
+        if !signature.starts_with('K') {
+            return Err(missing_king(&signature[..0]));
+        }
+        let black_king = signature[1..].find('K').ok_or_else(|| missing_king(&signature[signature.len()..]))? + 1;
+        let kinds = |letters: &str| letters.char_indices().map(|(index, c)| match c {
+            'Q' => Ok(Kind::Queen),
+            'R' => Ok(Kind::Rook),
+            'B' => Ok(Kind::Bishop),
+            'N' => Ok(Kind::Knight),
+            'P' => Ok(Kind::Pawn),
+            _ => Err(invalid(&letters[index..index + c.len_utf8()]))
+        }).collect::<Result<Vec<Kind>, ParseError>>();
+        let material = Material::new(kinds(&signature[1..black_king])?, kinds(&signature[black_king + 1..])?);
+        if material.piece_count() > MAX_PIECES {
+            return Err(ParseError::new(ParseErrorKind::Unsupported, signature, signature, format!("Too many pieces: {}", signature)));
+        }
+        Ok(material)
+    }
+
+    fn new(mut white: Vec<Kind>, mut black: Vec<Kind>) -> Material {
+        let order = |kind: &Kind| SIGNATURE_KINDS.iter().position(|other| other == kind);
+        white.sort_by_key(order);
+        black.sort_by_key(order);
+        let mut slots = vec![Piece(Color::White, Kind::King), Piece(Color::Black, Kind::King)];
+        slots.extend(white.iter().map(|kind| Piece(Color::White, *kind)));
+        slots.extend(black.iter().map(|kind| Piece(Color::Black, *kind)));
+        Material { white, black, slots }
+    }
+
+    // The material on the board, if there is one king per side.
+    fn of(board: &Board) -> Result<Material, Error> {
+        let (mut white, mut black, mut kings) = (vec![], vec![], 0);
+        for square in board.squares() {
+            match *square {
+                Square::Occupied(Piece(_, Kind::King)) => { kings += 1; },
+                Square::Occupied(Piece(Color::White, kind)) => white.push(kind),
+                Square::Occupied(Piece(Color::Black, kind)) => black.push(kind),
+                Square::Empty => {}
+            }
+        }
+        if kings != 2 {
+            return Err(Error::InvalidArgument(String::from("Positions need one king per side")));
+        }
+        Ok(Material::new(white, black))
+    }
+
+    fn name(&self) -> String {
+        let letters = |kinds: &Vec<Kind>| kinds.iter().map(|kind| match kind {
+            Kind::Queen => 'Q',
+            Kind::Rook => 'R',
+            Kind::Bishop => 'B',
+            Kind::Knight => 'N',
+            _ => 'P'
+        }).collect::<String>();
+        format!("K{}K{}", letters(&self.white), letters(&self.black))
+    }
+
+    fn piece_count(&self) -> usize {
+        2 + self.white.len() + self.black.len()
+    }
+
+    fn has_pawns(&self) -> bool {
+        self.white.contains(&Kind::Pawn) || self.black.contains(&Kind::Pawn)
+    }
+
+    fn is_bare_kings(&self) -> bool {
+        self.piece_count() == 2
+    }
+
+    // White is the stronger side in stored tables. With equal material, either way round is stored.
+    fn is_canonical(&self) -> bool {
+        let strength = |kinds: &Vec<Kind>| (kinds.iter().map(|kind| match kind {
+            Kind::Queen => 9,
+            Kind::Rook => 5,
+            Kind::Bishop | Kind::Knight => 3,
+            _ => 1
+        }).sum::<u32>(), kinds.len());
+        (strength(&self.white), self.name()) >= (strength(&self.black), self.mirror().name())
+    }
+
+    fn mirror(&self) -> Material {
+        Material::new(self.black.clone(), self.white.clone())
+    }
+
+    fn canonical(&self) -> Material {
+        if self.is_canonical() { self.clone() } else { self.mirror() }
+    }
+
+    // The materials a capture or a promotion leads to.
+    fn successors(&self) -> Vec<Material> {
+        let mut successors = vec![];
+        for color in [Color::White, Color::Black] {
+            let (own, other) = if color == Color::White { (&self.white, &self.black) } else { (&self.black, &self.white) };
+            let sides = |own: Vec<Kind>, other: Vec<Kind>| if color == Color::White { Material::new(own, other) } else { Material::new(other, own) };
+            let captures = |own: &Vec<Kind>| (0..other.len()).map(|index| {
+                let mut captured = other.clone();
+                captured.remove(index);
+                sides(own.clone(), captured)
+            }).collect::<Vec<Material>>();
+            successors.extend(captures(own));
+            for pawn in (0..own.len()).filter(|index| own[*index] == Kind::Pawn) {
+                for promotion in &SIGNATURE_KINDS[..4] {
+                    let mut promoted = own.clone();
+                    promoted[pawn] = *promotion;
+                    successors.push(sides(promoted.clone(), other.clone()));
+                    successors.extend(captures(&promoted));
+                }
+            }
+        }
+        successors.into_iter().map(|material| material.canonical()).collect()
+    }
+
+    fn king_squares(&self) -> usize {
+        if self.has_pawns() { 32 } else { 10 }
+    }
+
+    fn positions(&self) -> usize {
+        2 * self.king_squares() * 64usize.pow(self.piece_count() as u32 - 1)
+    }
+
+    // The index of a position with this material, white being the side it is stored for.
+    fn index(&self, board: &Board, color: Color) -> usize {
+        self.placement_index(&placement(board), color)
+    }
+
+    fn placement_index(&self, pieces: &[(Piece, usize)], color: Color) -> usize {
+        let pawns = self.has_pawns();
+        let mut king = pieces.iter().find(|(piece, _)| *piece == self.slots[0]).map(|(_, square)| *square).unwrap_or(0);
+        let flip_file = king % 8 >= 4;
+        if flip_file {
+            king ^= 7;
+        }
+        let flip_rank = !pawns && king / 8 >= 4;
+        if flip_rank {
+            king ^= 56;
+        }
+        let place = |transpose: bool| {
+            let orient = |mut square: usize| {
+                if flip_file {
+                    square ^= 7;
+                }
+                if flip_rank {
+                    square ^= 56;
+                }
+                if transpose { (square % 8) * 8 + square / 8 } else { square }
+            };
+            let mut squares: Vec<usize> = self.slots.iter()
+                .map(|slot| pieces.iter().filter(|(piece, _)| piece == slot).map(|(_, square)| orient(*square)).min().unwrap_or(0))
+                .collect();
+            // Identical pieces follow each other, in square order.
+            for slot in 1..squares.len() {
+                if self.slots[slot] == self.slots[slot - 1] {
+                    squares[slot] = pieces.iter().filter(|(piece, _)| *piece == self.slots[slot]).map(|(_, square)| orient(*square))
+                        .filter(|square| *square > squares[slot - 1]).min().unwrap_or(0);
+                }
+            }
+            squares
+        };
+        // With the king on the diagonal, the position and its reflection along the diagonal are the
+        // same: the one with the lower squares counts.
+        let squares = if pawns || king / 8 < king % 8 {
+            place(false)
+        } else if king / 8 > king % 8 {
+            place(true)
+        } else {
+            place(false).min(place(true))
+        };
+
+        let king_slot = if pawns {
+            (squares[0] / 8) * 4 + squares[0] % 8
+        } else {
+            KING_TRIANGLE.iter().position(|square| *square == squares[0]).unwrap_or(0)
+        };
+        let side = if color == Color::White { 0 } else { 1 };
+        squares[1..].iter().fold(side * self.king_squares() + king_slot, |index, square| index * 64 + square)
+    }
+
+    // The position at the index, or nothing if the placement is impossible.
+    fn decode(&self, original: usize) -> Option<(Board, Color)> {
+        let mut index = original;
+        let slots = &self.slots;
+        let mut squares = vec![0; slots.len()];
+        for slot in (1..slots.len()).rev() {
+            squares[slot] = index % 64;
+            index /= 64;
+        }
+        let king_slot = index % self.king_squares();
+        squares[0] = if self.has_pawns() { (king_slot / 4) * 8 + king_slot % 4 } else { KING_TRIANGLE[king_slot] };
+        let color = if index / self.king_squares() == 0 { Color::White } else { Color::Black };
+
+        for slot in 1..slots.len() {
+            if squares[..slot].contains(&squares[slot]) {
+                return None;
+            }
+            if slots[slot].1 == Kind::Pawn && (squares[slot] / 8 == 0 || squares[slot] / 8 == 7) {
+                return None;
+            }
+        }
+        if (squares[0] / 8).abs_diff(squares[1] / 8) <= 1 && (squares[0] % 8).abs_diff(squares[1] % 8) <= 1 {
+            return None;
+        }
+        let mut board = Board::new();
+        for (piece, square) in slots.iter().zip(squares.iter()) {
+            board.set_square(Coordinates::new_from_index(*square)?, Square::Occupied(*piece));
+        }
+        // Placements that index elsewhere are reflections of other ones.
+        let pieces: Vec<(Piece, usize)> = slots.iter().copied().zip(squares.iter().copied()).collect();
+        if self.placement_index(&pieces, color) != original || is_checked(&board, color.opposite()) {
+            return None;
+        }
+        Some((board, color))
+    }
+
+    // The indexes of the positions with this material that lead to the position in one move.
+    // Moves are taken back without uncapturing or unpromoting, which would change the material.
+    fn predecessors(&self, board: &Board, color: Color) -> Vec<usize> {
+        let mover = color.opposite();
+        let pieces = placement(board);
+        let mut indexes = vec![];
+        for (moved, (piece, square)) in pieces.iter().copied().enumerate().filter(|(_, (piece, _))| piece.0 == mover) {
+            let coordinates = match Coordinates::new_from_index(square) {
+                Some(coordinates) => coordinates,
+                None => continue
+            };
+            let origins: Vec<Coordinates> = if piece.1 == Kind::Pawn {
+                let (direction, start_rank) = if mover == Color::White { (-1, 1) } else { (1, 6) };
+                let empty = |origin: &Coordinates| board.get_square(*origin).is_empty();
+                let mut origins = vec![];
+                if let Some(one) = coordinates.offset(0, direction).filter(empty).filter(|one| one.rank().index() % 7 != 0) {
+                    origins.push(one);
+                    if let Some(two) = one.offset(0, direction).filter(empty).filter(|two| two.rank().index() == start_rank) {
+                        origins.push(two);
+                    }
+                }
+                origins
+            } else {
+                mask_coordinates(attacked_squares(board, coordinates, piece)).filter(|origin| board.get_square(*origin).is_empty()).collect()
+            };
+            for origin in origins {
+                let mut previous = *board;
+                previous.set_square(coordinates, Square::Empty);
+                previous.set_square(origin, Square::Occupied(piece));
+                if !is_checked(&previous, color) {
+                    let mut previous_pieces = pieces.clone();
+                    previous_pieces[moved].1 = origin.index();
+                    indexes.push(self.placement_index(&previous_pieces, mover));
+                }
+            }
+        }
+        indexes.sort_unstable();
+        indexes.dedup();
+        indexes
+    }
+}
+
+struct DtmTable {
+    material: Material,
+    values: Vec<u8>,
+}
+
+impl DtmTable {
+    fn from_bytes(bytes: &[u8]) -> Result<DtmTable, Error> {
+        let truncated = || Error::InvalidData(String::from("Table file is truncated"));
+        if bytes.get(..4) != Some(&MAGIC[..]) {
+            return Err(Error::InvalidData(String::from("Not a distance to mate table")));
+        }
+        let version = read_u32(bytes, 4).ok_or_else(truncated)?;
+        if version != VERSION {
+            return Err(Error::InvalidData(format!("Unsupported table version: {}", version)));
+        }
+        let name_length = *bytes.get(8).ok_or_else(truncated)? as usize;
+        let name = std::str::from_utf8(bytes.get(9..9 + name_length).ok_or_else(truncated)?).map_err(|_| Error::InvalidData(String::from("Invalid table name")))?;
+        let material = Material::parse(name).map_err(|_| Error::InvalidData(format!("Invalid table material: {}", name)))?;
+        if !material.is_canonical() || material.is_bare_kings() {
+            return Err(Error::InvalidData(format!("Invalid table material: {}", name)));
+        }
+        let mut position = 9 + name_length;
+        let positions = read_u32(bytes, position).ok_or_else(truncated)? as usize;
+        if positions != material.positions() {
+            return Err(Error::InvalidData(format!("Wrong table size for {}: {}", name, positions)));
+        }
+        position += 4;
+
+        let mut values = Vec::with_capacity(positions);
+        while values.len() < positions {
+            let run = bytes.get(position..position + 2).ok_or_else(truncated)?;
+            values.extend(std::iter::repeat_n(run[1], run[0] as usize));
+            position += 2;
+        }
+        if values.len() != positions || position != bytes.len() {
+            return Err(Error::InvalidData(String::from("Unexpected data after the table")));
+        }
+        Ok(DtmTable { material, values })
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let name = self.material.name();
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.push(name.len() as u8);
+        bytes.extend_from_slice(name.as_bytes());
+        bytes.extend_from_slice(&(self.values.len() as u32).to_le_bytes());
+        let mut start = 0;
+        while start < self.values.len() {
+            let value = self.values[start];
+            let run = self.values[start..].iter().take(u8::MAX as usize).take_while(|other| **other == value).count();
+            bytes.extend_from_slice(&[run as u8, value]);
+            start += run;
+        }
+        bytes
+    }
+}
+
+// The pieces on the board with their squares.
+fn placement(board: &Board) -> Vec<(Piece, usize)> {
+    board.squares().iter().enumerate().filter_map(|(index, square)| match *square {
+        Square::Occupied(piece) => Some((piece, index)),
+        Square::Empty => None
+    }).collect()
+}
+
+// Whether a move keeps the material: it captures nothing and promotes nothing.
+fn moves_within(board: &Board, child: &Board) -> bool {
+    let mut difference = (0, 0);
+    for (before, after) in board.squares().iter().zip(child.squares().iter()) {
+        if before == after {
+            continue;
+        }
+        for (square, sign) in [(before, 1), (after, -1)] {
+            if let Square::Occupied(Piece(_, kind)) = *square {
+                difference.0 += sign;
+                difference.1 += if kind == Kind::Pawn { sign } else { 0 };
+            }
+        }
+    }
+    difference == (0, 0)
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    let slice = bytes.get(offset..offset + 4)?;
+    Some(u32::from_le_bytes([slice[0], slice[1], slice[2], slice[3]]))
+}
+
+// What happens to a position when the search for mates reaches a number of plies.
+#[derive(Debug,Copy,Clone)]
+enum Event {
+    // The position is decided in that many plies, unless it was decided sooner.
+    Settle(usize),
+    // One more move loses: the position is lost once none are left.
+    Decrement(usize),
+}
+
+// Endgame tables with the distance to mate of every position, generated without external files.
+pub struct DtmTablebase {
+    tables: HashMap<String, DtmTable>,
+}
+
+impl Default for DtmTablebase {
+    fn default() -> DtmTablebase {
+        DtmTablebase::new()
+    }
+}
+
+impl DtmTablebase {
+    pub fn new() -> DtmTablebase {
+        DtmTablebase { tables: HashMap::new() }
+    }
+
+    // Load all ".dtm" tables in a directory.
+    pub fn load<P: AsRef<Path>>(directory: P) -> Result<DtmTablebase, Error> {
+        let directory = directory.as_ref();
+        let entries = fs::read_dir(directory).map_err(|error| Error::Io(format!("Cannot read {}: {}", directory.display(), error)))?;
+        let mut tablebase = DtmTablebase::new();
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().and_then(|extension| extension.to_str()) != Some("dtm") {
+                continue;
+            }
+            let bytes = fs::read(&path).map_err(|error| Error::Io(format!("Cannot read {}: {}", path.display(), error)))?;
+            let table = DtmTable::from_bytes(&bytes).map_err(|error| Error::InvalidData(format!("{}: {}", path.display(), error)))?;
+            tablebase.tables.insert(table.material.name(), table);
+        }
+        if tablebase.tables.is_empty() {
+            return Err(Error::NotFound(format!("No tables in {}", directory.display())));
+        }
+        Ok(tablebase)
+    }
+
+    // Write every table to the directory, one file per material.
+    pub fn save<P: AsRef<Path>>(&self, directory: P) -> Result<(), Error> {
+        let directory = directory.as_ref();
+        fs::create_dir_all(directory).map_err(|error| Error::Io(format!("Cannot create {}: {}", directory.display(), error)))?;
+        for (name, table) in self.tables.iter() {
+            let path = directory.join(format!("{}.dtm", name));
+            fs::write(&path, table.to_bytes()).map_err(|error| Error::Io(format!("Cannot write {}: {}", path.display(), error)))?;
+        }
+        Ok(())
+    }
+
+    // Generate the table for a material signature such as "KQK", and the tables its captures and
+    // promotions lead to.
+    pub fn generate(&mut self, signature: &str) -> Result<(), Error> {
+        let material = Material::parse(signature)?.canonical();
+        self.generate_material(&material)
+    }
+
+    // The signatures of the tables, sorted.
+    pub fn materials(&self) -> Vec<String> {
+        let mut materials: Vec<String> = self.tables.keys().cloned().collect();
+        materials.sort();
+        materials
+    }
+
+    // The plies to mate in the longest win of the table, for white to move or black to move.
+    pub fn longest_mate(&self, signature: &str) -> Option<u32> {
+        let table = self.tables.get(&Material::parse(signature).ok()?.canonical().name())?;
+        table.values.iter().filter(|value| **value % 2 == 0 && **value > 0).map(|value| *value as u32 - 1).max()
+    }
+
+    fn generate_material(&mut self, material: &Material) -> Result<(), Error> {
+        if material.is_bare_kings() || self.tables.contains_key(&material.name()) {
+            return Ok(());
+        }
+        for successor in material.successors() {
+            self.generate_material(&successor)?;
+        }
+        let values = self.retrograde(material)?;
+        self.tables.insert(material.name(), DtmTable { material: material.clone(), values });
+        Ok(())
+    }
+
+    // Work backwards from the mates: a position where some move leads to a loss in n plies is a win
+    // in n + 1, and one where all moves lead to wins is a loss in one more than the longest.
+    fn retrograde(&self, material: &Material) -> Result<Vec<u8>, Error> {
+        let size = material.positions();
+        let mut values = vec![0u8; size];
+        // The moves of each position that have not been found to lose.
+        let mut remaining = vec![0u8; size];
+        let mut events: Vec<Vec<Event>> = vec![];
+        fn schedule(events: &mut Vec<Vec<Event>>, plies: usize, event: Event) {
+            if events.len() <= plies {
+                events.resize(plies + 1, vec![]);
+            }
+            events[plies].push(event);
+        }
+
+        for (index, moves) in remaining.iter_mut().enumerate() {
+            let (board, color) = match material.decode(index) {
+                Some(position) => position,
+                None => continue
+            };
+            let children = next_boards(&board, color);
+            if children.is_empty() && is_checked(&board, color) {
+                schedule(&mut events, 0, Event::Settle(index));
+            }
+            // Moves within the table are counted once per resulting index, as symmetrical moves
+            // lead to the same one. Captures and promotions are decided by the smaller tables.
+            let mut inside = vec![];
+            for child in children.iter() {
+                if moves_within(&board, child) {
+                    inside.push(material.index(child, color.opposite()));
+                    continue;
+                }
+                *moves += 1;
+                match self.probe_value(child, color.opposite())? {
+                    0 => {},
+                    value if value % 2 == 1 => schedule(&mut events, value as usize, Event::Settle(index)),
+                    value => schedule(&mut events, value as usize - 1, Event::Decrement(index))
+                }
+            }
+            inside.sort_unstable();
+            inside.dedup();
+            *moves += inside.len() as u8;
+        }
+
+        let mut plies = 0;
+        while plies < events.len() {
+            for event in std::mem::take(&mut events[plies]) {
+                let index = match event {
+                    Event::Settle(index) => index,
+                    Event::Decrement(index) => {
+                        remaining[index] -= 1;
+                        if remaining[index] == 0 && values[index] == 0 {
+                            schedule(&mut events, plies + 1, Event::Settle(index));
+                        }
+                        continue;
+                    }
+                };
+                if values[index] != 0 {
+                    continue;
+                }
+                // With 4 pieces, mates are far shorter than the 254 plies a value can hold.
+                values[index] = (plies + 1) as u8;
+                let (board, color) = material.decode(index).ok_or_else(|| Error::InvalidData(String::from("Decided an impossible position")))?;
+                for previous in material.predecessors(&board, color) {
+                    if values[previous] != 0 {
+                        continue;
+                    }
+                    if plies % 2 == 0 {
+                        schedule(&mut events, plies + 1, Event::Settle(previous));
+                    } else {
+                        remaining[previous] -= 1;
+                        if remaining[previous] == 0 {
+                            schedule(&mut events, plies + 1, Event::Settle(previous));
+                        }
+                    }
+                }
+            }
+            plies += 1;
+        }
+        Ok(values)
+    }
+
+    // The stored value of the position: 0 for a draw, otherwise the plies to mate plus one.
+    fn probe_value(&self, board: &Board, color: Color) -> Result<u8, Error> {
+        if [Color::White, Color::Black].iter().any(|color|
+                board.is_castling_allowed(*color, CastlingSide::King) || board.is_castling_allowed(*color, CastlingSide::Queen)) {
+            return Err(Error::InvalidArgument(String::from("Positions with castling rights are not in the tables")));
+        }
+        let material = Material::of(board)?;
+        if material.is_bare_kings() {
+            return Ok(0);
+        }
+        let (board, color, material) = if material.is_canonical() {
+            (*board, color, material)
+        } else {
+            (board.mirror(), color.opposite(), material.mirror())
+        };
+        let table = self.tables.get(&material.name()).ok_or_else(|| Error::NotFound(format!("Missing table: {}", material.name())))?;
+        Ok(table.values[material.index(&board, color)])
+    }
+}
+
+impl Tablebase for DtmTablebase {
+    fn can_probe(&self, board: &Board) -> bool {
+        self.probe_value(board, Color::White).is_ok()
+    }
+
+    fn probe_wdl(&self, board: &Board, color: Color) -> Result<Wdl, Error> {
+        Ok(match self.probe_value(board, color)? {
+            0 => Wdl::Draw,
+            value if value % 2 == 0 => Wdl::Win,
+            _ => Wdl::Loss
+        })
+    }
+
+    fn probe_dtm(&self, board: &Board, color: Color) -> Result<Option<u32>, Error> {
+        Ok(self.probe_value(board, color)?.checked_sub(1).map(u32::from))
+    }
+
+    // The moves that mate fastest, or when losing, that hold out longest.
+    fn filter_root_moves(&self, board: &Board, color: Color) -> Result<Vec<Board>, Error> {
+        let mut ranked = vec![];
+        for child in next_boards(board, color) {
+            let rank = match self.probe_value(&child, color.opposite())? as i32 {
+                0 => 0,
+                value if value % 2 == 1 => 1000 - value,
+                value => -1000 + value
+            };
+            ranked.push((child, rank));
+        }
+        let best = ranked.iter().map(|(_, rank)| *rank).max();
+        Ok(ranked.into_iter().filter(|(_, rank)| Some(*rank) == best).map(|(child, _)| child).collect())
+    }
+}
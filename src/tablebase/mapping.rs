@@ -0,0 +1,30 @@
+use std::fs::{ File };
+use std::ops::{ Deref };
+use std::path::{ Path };
+
+use memmap2::{ Mmap };
+
+use crate::error::{ Error };
+
+// A read-only file mapped into memory, so only the parts that are probed get loaded.
+pub struct MappedFile {
+    map: Mmap,
+}
+
+impl MappedFile {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<MappedFile, Error> {
+        let path = path.as_ref();
+        let file = File::open(path).map_err(|error| Error::Io(format!("Cannot open {}: {}", path.display(), error)))?;
+        // Safe as long as no one changes the file while it is open, which table files never are.
+        let map = unsafe { Mmap::map(&file) }.map_err(|error| Error::Io(format!("Cannot read {}: {}", path.display(), error)))?;
+        Ok(MappedFile { map })
+    }
+}
+
+impl Deref for MappedFile {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.map
+    }
+}
@@ -0,0 +1,10 @@
+mod encoding;
+mod generator;
+mod mapping;
+mod pairs;
+mod syzygy;
+mod tablebase;
+
+pub use generator::{ DtmTablebase };
+pub use syzygy::{ SyzygyTablebase };
+pub use tablebase::{ Tablebase, Wdl };
@@ -0,0 +1,285 @@
+use crate::tablebase::encoding::{ encoding };
+
+// Flags of a compressed table.
+pub const FLAG_SIDE_TO_MOVE: u8 = 1;
+pub const FLAG_MAPPED: u8 = 2;
+pub const FLAG_WIN_PLIES: u8 = 4;
+pub const FLAG_LOSS_PLIES: u8 = 8;
+pub const FLAG_WIDE: u8 = 16;
+pub const FLAG_SINGLE_VALUE: u8 = 128;
+
+// A symbol whose right half is this is a leaf: its left half is the stored value.
+const LEAF: u16 = 0xFFF;
+const SPARSE_ENTRY_SIZE: usize = 6;
+
+// One compressed table of values: for one side to move and, with pawns, one file of the leading pawn.
+//
+// Values are compressed with recursive pairing: every symbol stands for a value or for a pair of
+// symbols. The symbols are stored in blocks of canonical Huffman codes, and a sparse index points
+// into the list of block lengths so a value can be found without decoding earlier blocks.
+// Positions are encoded as indexes group by group, with the pieces of each group in `pieces` order.
+#[derive(Debug,Clone,Default)]
+pub struct PairsData {
+    pub flags: u8,
+    pub pieces: Vec<u8>,
+    pub group_lengths: Vec<usize>,
+    // The multiplier of each group's index. The last one is the size of the table.
+    pub group_factors: Vec<u64>,
+    // For DTZ tables, where the value map of each result starts in the file.
+    pub map_offsets: [usize; 4],
+    block_size: usize,
+    span: u64,
+    sparse_index_size: usize,
+    block_count: usize,
+    block_lengths_size: usize,
+    // Also the value of a single value table.
+    min_symbol_length: u8,
+    lowest_symbols: usize,
+    bases: Vec<u64>,
+    symbol_lengths: Vec<u8>,
+    tree: usize,
+    sparse_index: usize,
+    block_lengths: usize,
+    data: usize,
+}
+
+impl PairsData {
+    pub fn new(pieces: Vec<u8>) -> PairsData {
+        PairsData { pieces, ..PairsData::default() }
+    }
+
+    // Split the pieces into groups and compute the index factor of each group. The leading group is
+    // the pawns of one side, or the kings together with a unique piece if there is one.
+    pub fn set_groups(&mut self, order: [usize; 2], file: usize, has_pawns: bool, has_unique_pieces: bool, pawns_on_both_sides: bool) {
+        let mut first_length: i32 = if has_pawns { 0 } else if has_unique_pieces { 3 } else { 2 };
+        self.group_lengths = vec![1];
+        for index in 1..self.pieces.len() {
+            first_length -= 1;
+            if first_length > 0 || self.pieces[index] == self.pieces[index - 1] {
+                *self.group_lengths.last_mut().unwrap() += 1;
+            } else {
+                self.group_lengths.push(1);
+            }
+        }
+
+        let encoding = encoding();
+        let groups = self.group_lengths.len();
+        self.group_factors = vec![0; groups + 1];
+        let mut next = if pawns_on_both_sides { 2 } else { 1 };
+        let mut free_squares = 64 - self.group_lengths[0] - if pawns_on_both_sides { self.group_lengths[1] } else { 0 };
+        let mut factor: u64 = 1;
+        // The groups are not encoded in piece order: `order` tells when the leading group and the
+        // other side's pawns come.
+        let mut k = 0;
+        while next < groups || k == order[0] || k == order[1] {
+            if k == order[0] {
+                self.group_factors[0] = factor;
+                factor *= if has_pawns {
+                    encoding.lead_pawns_size[self.group_lengths[0]][file]
+                } else if has_unique_pieces {
+                    31332
+                } else {
+                    462
+                };
+            } else if k == order[1] {
+                self.group_factors[1] = factor;
+                factor *= encoding.binomial[self.group_lengths[1]][48 - self.group_lengths[0]];
+            } else if next < groups {
+                self.group_factors[next] = factor;
+                factor *= encoding.binomial[self.group_lengths[next]][free_squares];
+                free_squares -= self.group_lengths[next];
+                next += 1;
+            }
+            k += 1;
+        }
+        self.group_factors[groups] = factor;
+    }
+
+    pub fn size(&self) -> u64 {
+        *self.group_factors.last().unwrap_or(&0)
+    }
+
+    pub fn is_single_value(&self) -> bool {
+        self.flags & FLAG_SINGLE_VALUE != 0
+    }
+
+    // Read the compression parameters at the offset. Returns the offset after them.
+    pub fn read_sizes(&mut self, bytes: &[u8], offset: usize) -> Option<usize> {
+        self.flags = *bytes.get(offset)?;
+        if self.is_single_value() {
+            self.min_symbol_length = *bytes.get(offset + 1)?;
+            return Some(offset + 2);
+        }
+        let header = bytes.get(offset..offset + 10)?;
+        self.block_size = 1 << header[1];
+        self.span = 1 << header[2];
+        self.sparse_index_size = self.size().div_ceil(self.span) as usize;
+        let padding = header[3] as usize;
+        self.block_count = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
+        // Padded so the sparse index never points beyond the block lengths.
+        self.block_lengths_size = self.block_count + padding;
+        let max_symbol_length = header[8];
+        self.min_symbol_length = header[9];
+        if max_symbol_length < self.min_symbol_length || self.min_symbol_length == 0 {
+            return None;
+        }
+        self.lowest_symbols = offset + 10;
+
+        // The canonical code gives longer codes lower values. For every code length, the base is the
+        // lowest code of that length, left-aligned in 64 bits, so the bases decrease with length.
+        let lengths = (max_symbol_length - self.min_symbol_length) as usize + 1;
+        self.bases = vec![0; lengths];
+        for index in (0..lengths - 1).rev() {
+            let lowest = read_u16(bytes, self.lowest_symbols + 2 * index)? as u64;
+            let next_lowest = read_u16(bytes, self.lowest_symbols + 2 * (index + 1))? as u64;
+            self.bases[index] = self.bases[index + 1].wrapping_add(lowest).wrapping_sub(next_lowest) / 2;
+        }
+        for (index, base) in self.bases.iter_mut().enumerate() {
+            *base = base.checked_shl(64 - index as u32 - self.min_symbol_length as u32).unwrap_or(0);
+        }
+
+        let symbols_offset = self.lowest_symbols + 2 * lengths;
+        let symbols = read_u16(bytes, symbols_offset)? as usize;
+        self.tree = symbols_offset + 2;
+        bytes.get(self.tree..self.tree + 3 * symbols)?;
+        self.symbol_lengths = vec![0; symbols];
+        let mut visited = vec![false; symbols];
+        for symbol in 0..symbols {
+            if !visited[symbol] {
+                self.symbol_lengths[symbol] = self.set_symbol_length(bytes, symbol, &mut visited)?;
+            }
+        }
+        Some(self.tree + 3 * symbols + (symbols & 1))
+    }
+
+    // The number of values a symbol expands to, minus one.
+    fn set_symbol_length(&mut self, bytes: &[u8], symbol: usize, visited: &mut Vec<bool>) -> Option<u8> {
+        visited[symbol] = true;
+        let (left, right) = self.children(bytes, symbol);
+        if right == LEAF {
+            return Some(0);
+        }
+        for child in [left as usize, right as usize] {
+            if child >= visited.len() {
+                return None;
+            }
+            if !visited[child] {
+                self.symbol_lengths[child] = self.set_symbol_length(bytes, child, visited)?;
+            }
+        }
+        Some(self.symbol_lengths[left as usize].wrapping_add(self.symbol_lengths[right as usize]).wrapping_add(1))
+    }
+
+    // The two halves of a symbol: 12 bits each in three bytes.
+    fn children(&self, bytes: &[u8], symbol: usize) -> (u16, u16) {
+        let entry = &bytes[self.tree + 3 * symbol..self.tree + 3 * symbol + 3];
+        let left = ((entry[1] as u16 & 0xF) << 8) | entry[0] as u16;
+        let right = ((entry[2] as u16) << 4) | (entry[1] as u16 >> 4);
+        (left, right)
+    }
+
+    // Where the sparse index, block lengths and blocks start.
+    pub fn set_sparse_index(&mut self, offset: usize) -> usize {
+        self.sparse_index = offset;
+        offset + self.sparse_index_size * SPARSE_ENTRY_SIZE
+    }
+
+    pub fn set_block_lengths(&mut self, offset: usize) -> usize {
+        self.block_lengths = offset;
+        offset + self.block_lengths_size * 2
+    }
+
+    pub fn set_data(&mut self, offset: usize) -> usize {
+        self.data = offset;
+        offset + self.block_count * self.block_size
+    }
+
+    // The value stored at the index, or nothing if the table is corrupt.
+    pub fn decompress(&self, bytes: &[u8], index: u64) -> Option<u16> {
+        if self.is_single_value() {
+            return Some(self.min_symbol_length as u16);
+        }
+
+        // Sparse index entry k holds the block and the offset in it of value k * span + span / 2.
+        let k = (index / self.span) as usize;
+        let entry = self.sparse_index + SPARSE_ENTRY_SIZE * k;
+        let mut block = read_u32(bytes, entry)? as usize;
+        let mut offset = read_u16(bytes, entry + 4)? as i64;
+        offset += (index % self.span) as i64 - (self.span / 2) as i64;
+        // Every block holds its length plus one values. Walk to the block that has the index.
+        while offset < 0 {
+            block = block.checked_sub(1)?;
+            offset += read_u16(bytes, self.block_lengths + 2 * block)? as i64 + 1;
+        }
+        loop {
+            let length = read_u16(bytes, self.block_lengths + 2 * block)? as i64;
+            if offset <= length {
+                break;
+            }
+            offset -= length + 1;
+            block += 1;
+        }
+
+        // Decode symbols from the start of the block until the one covering the offset.
+        let mut position = self.data + block * self.block_size;
+        let mut buffer = read_u64_be(bytes, position)?;
+        position += 8;
+        let mut buffer_bits = 64;
+        let mut symbol;
+        loop {
+            let mut length = 0;
+            while buffer < self.bases[length] {
+                length += 1;
+            }
+            let shift = 64 - length as u32 - self.min_symbol_length as u32;
+            symbol = ((buffer - self.bases[length]) >> shift) as usize + read_u16(bytes, self.lowest_symbols + 2 * length)? as usize;
+            let values = *self.symbol_lengths.get(symbol)? as i64 + 1;
+            if offset < values {
+                break;
+            }
+            offset -= values;
+            let code_length = length as u32 + self.min_symbol_length as u32;
+            buffer <<= code_length;
+            buffer_bits -= code_length;
+            if buffer_bits <= 32 {
+                buffer_bits += 32;
+                buffer |= (read_u32_be(bytes, position).unwrap_or(0) as u64) << (64 - buffer_bits);
+                position += 4;
+            }
+        }
+
+        // Expand the pairs down to the value at the offset.
+        while self.symbol_lengths[symbol] > 0 {
+            let (left, right) = self.children(bytes, symbol);
+            let left_values = *self.symbol_lengths.get(left as usize)? as i64 + 1;
+            if offset < left_values {
+                symbol = left as usize;
+            } else {
+                offset -= left_values;
+                symbol = right as usize;
+            }
+            self.symbol_lengths.get(symbol)?;
+        }
+        Some(self.children(bytes, symbol).0)
+    }
+}
+
+pub fn read_u16(bytes: &[u8], offset: usize) -> Option<u16> {
+    let slice = bytes.get(offset..offset + 2)?;
+    Some(u16::from_le_bytes([slice[0], slice[1]]))
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    let slice = bytes.get(offset..offset + 4)?;
+    Some(u32::from_le_bytes([slice[0], slice[1], slice[2], slice[3]]))
+}
+
+fn read_u32_be(bytes: &[u8], offset: usize) -> Option<u32> {
+    let slice = bytes.get(offset..offset + 4)?;
+    Some(u32::from_be_bytes([slice[0], slice[1], slice[2], slice[3]]))
+}
+
+fn read_u64_be(bytes: &[u8], offset: usize) -> Option<u64> {
+    let slice = bytes.get(offset..offset + 8)?;
+    Some(u64::from_be_bytes([slice[0], slice[1], slice[2], slice[3], slice[4], slice[5], slice[6], slice[7]]))
+}
@@ -0,0 +1,582 @@
+use std::collections::{ HashMap };
+use std::fs;
+use std::path::{ Path };
+
+use crate::board::{ Board, CastlingSide, Color, Kind, Piece, Square };
+use crate::engine::{ is_checked, next_boards };
+use crate::error::{ Error };
+use crate::tablebase::encoding::{ encoding, off_diagonal };
+use crate::tablebase::mapping::{ MappedFile };
+use crate::tablebase::pairs::{ read_u16, PairsData, FLAG_LOSS_PLIES, FLAG_MAPPED, FLAG_SIDE_TO_MOVE, FLAG_WIDE, FLAG_WIN_PLIES };
+use crate::tablebase::tablebase::{ Tablebase, Wdl };
+
+const WDL_MAGIC: [u8; 4] = [0x71, 0xE8, 0x23, 0x5D];
+const DTZ_MAGIC: [u8; 4] = [0xD7, 0x66, 0x0C, 0xA5];
+// Tables from 3 to 5 pieces, kings included.
+const MAX_PIECES: usize = 5;
+// Piece letters in table names, strongest first. Tables number the kinds in the opposite order, from 1.
+const PIECE_LETTERS: [char; 6] = ['K', 'Q', 'R', 'B', 'N', 'P'];
+
+// Syzygy endgame tables: win/draw/loss tables (.rtbw) and distance to zeroing tables (.rtbz), which
+// count the plies to the next capture or pawn move on the way to the result. Positions with castling
+// rights are not in the tables.
+pub struct SyzygyTablebase {
+    wdl: HashMap<String, Table>,
+    dtz: HashMap<String, Table>,
+    max_pieces: usize,
+}
+
+impl SyzygyTablebase {
+    pub fn open<P: AsRef<Path>>(directory: P) -> Result<SyzygyTablebase, Error> {
+        let mut tablebase = SyzygyTablebase { wdl: HashMap::new(), dtz: HashMap::new(), max_pieces: 0 };
+        tablebase.add_directory(directory)?;
+        Ok(tablebase)
+    }
+
+    // Map every table in the directory. Files that are not named like tables are skipped.
+    pub fn add_directory<P: AsRef<Path>>(&mut self, directory: P) -> Result<(), Error> {
+        let directory = directory.as_ref();
+        let entries = fs::read_dir(directory).map_err(|error| Error::Io(format!("Cannot read {}: {}", directory.display(), error)))?;
+        let mut found = false;
+        for entry in entries {
+            let path = entry.map_err(|error| Error::Io(format!("Cannot read {}: {}", directory.display(), error)))?.path();
+            let is_dtz = match path.extension().and_then(|extension| extension.to_str()) {
+                Some("rtbw") => false,
+                Some("rtbz") => true,
+                _ => { continue; }
+            };
+            let name = match path.file_stem().and_then(|stem| stem.to_str()) {
+                Some(name) => name,
+                None => { continue; }
+            };
+            let material = match Material::parse(name) {
+                Some(material) => material,
+                None => { continue; }
+            };
+            let table = Table::parse(MappedFile::open(&path)?, &material, is_dtz)
+                .map_err(|error| Error::InvalidData(format!("{}: {}", path.display(), error)))?;
+            if is_dtz {
+                self.dtz.insert(material.name(), table);
+            } else {
+                self.max_pieces = self.max_pieces.max(material.piece_count());
+                self.wdl.insert(material.name(), table);
+            }
+            found = true;
+        }
+        if !found {
+            return Err(Error::NotFound(format!("No tablebase files in {}", directory.display())));
+        }
+        Ok(())
+    }
+
+    // The largest number of pieces, kings included, that positions can have to be probed.
+    pub fn max_pieces(&self) -> usize {
+        self.max_pieces
+    }
+
+    // The distance in plies to the next capture or pawn move that keeps the result, positive when
+    // winning and negative when losing. Zero for draws.
+    pub fn probe_dtz(&self, board: &Board, color: Color) -> Result<i32, Error> {
+        self.check_probe(board)?;
+        self.dtz(board, color)
+    }
+
+    fn check_probe(&self, board: &Board) -> Result<(), Error> {
+        if self.can_probe(board) { Ok(()) } else { Err(Error::NotFound(String::from("Position not in the tablebase"))) }
+    }
+
+    // The tables store "don't care" values where a capture is the best move, and know nothing of
+    // en passant, so captures are searched first. Also returns whether the best move is zeroing.
+    fn search_wdl(&self, board: &Board, color: Color, with_pawn_moves: bool) -> Result<(Wdl, bool), Error> {
+        let children = next_boards(board, color);
+        let mut best = Wdl::Loss;
+        let mut searched = 0;
+        for child in children.iter() {
+            let zeroing = is_capture(board, child) || (with_pawn_moves && is_pawn_move(board, child, color));
+            if !zeroing {
+                continue;
+            }
+            searched += 1;
+            let value = -self.search_wdl(child, color.opposite(), false)?.0;
+            if value > best {
+                best = value;
+                if value == Wdl::Win {
+                    return Ok((value, true));
+                }
+            }
+        }
+
+        // When every move was searched, the table value may be wrong: it does not know en passant.
+        let no_more_moves = searched > 0 && searched == children.len();
+        let value = if no_more_moves {
+            best
+        } else {
+            Wdl::from_value(self.probe_table(board, color, None)?.unwrap_or(0))
+        };
+        if best >= value {
+            return Ok((best, best > Wdl::Draw || no_more_moves));
+        }
+        Ok((value, false))
+    }
+
+    fn dtz(&self, board: &Board, color: Color) -> Result<i32, Error> {
+        let (wdl, zeroing) = self.search_wdl(board, color, true)?;
+        if wdl == Wdl::Draw {
+            return Ok(0);
+        }
+        if zeroing {
+            return Ok(dtz_before_zeroing(wdl));
+        }
+        if let Some(dtz) = self.probe_table(board, color, Some(wdl))? {
+            let cursed = if wdl == Wdl::CursedWin || wdl == Wdl::BlessedLoss { 100 } else { 0 };
+            return Ok((dtz + cursed) * wdl.value().signum());
+        }
+
+        // The table only has the other side to move: take the best move by a one ply search.
+        let mut best: Option<i32> = None;
+        for child in next_boards(board, color) {
+            let zeroing = is_zeroing(board, &child, color);
+            let mut dtz = if zeroing {
+                -dtz_before_zeroing(self.search_wdl(&child, color.opposite(), false)?.0)
+            } else {
+                -self.dtz(&child, color.opposite())?
+            };
+            if dtz == 1 && is_checked(&child, color.opposite()) && next_boards(&child, color.opposite()).is_empty() {
+                best = Some(1);
+            }
+            if !zeroing {
+                dtz += dtz.signum();
+            }
+            if dtz.signum() == wdl.value().signum() && best.map(|best| dtz < best).unwrap_or(true) {
+                best = Some(dtz);
+            }
+        }
+        // Without legal moves, the position is mate.
+        Ok(best.unwrap_or(-1))
+    }
+
+    // The raw value in the WDL table, or with a result given, in the DTZ table. Nothing when the
+    // DTZ table only stores the other side to move.
+    fn probe_table(&self, board: &Board, color: Color, dtz_result: Option<Wdl>) -> Result<Option<i32>, Error> {
+        let mut placement: Vec<(usize, u8)> = board.squares().iter().enumerate().filter_map(|(square, content)| match content {
+            Square::Occupied(piece) => Some((square, piece_code(*piece))),
+            Square::Empty => None
+        }).collect();
+        if placement.len() == 2 {
+            // Bare kings.
+            return Ok(Some(0));
+        }
+
+        // Tables are made with the stronger side as white. Otherwise the colors are swapped and the
+        // board flipped, as also happens for black to move in tables with the same pieces on both sides.
+        let tables = if dtz_result.is_some() { &self.dtz } else { &self.wdl };
+        let name = Material::of(board, Color::White).name();
+        let (table, black_stronger) = match tables.get(&name) {
+            Some(table) => (table, false),
+            None => match tables.get(&Material::of(board, Color::Black).name()) {
+                Some(table) => (table, true),
+                None => { return Err(Error::NotFound(format!("Missing table {}.{}", name, if dtz_result.is_some() { "rtbz" } else { "rtbw" }))); }
+            }
+        };
+        let flip = black_stronger || (table.symmetric && color == Color::Black);
+        let side = (flip != (color == Color::Black)) as usize;
+        if flip {
+            for (square, code) in placement.iter_mut() {
+                *square ^= 56;
+                *code ^= 8;
+            }
+        }
+
+        // With pawns, there is a table per file of the leading pawn: the pawn of the leading color
+        // nearest to the edge and then lowest.
+        let encoding = encoding();
+        let mut lead_pawns = 0;
+        let mut file = 0;
+        if table.has_pawns {
+            let lead = table.pairs[0][0].pieces[0];
+            placement.sort_by_key(|(_, code)| *code != lead);
+            lead_pawns = placement.iter().filter(|(_, code)| *code == lead).count();
+            let leading = (0..lead_pawns).max_by_key(|index| encoding.map_pawns[placement[*index].0]).unwrap_or(0);
+            placement.swap(0, leading);
+            file = placement[0].0 % 8;
+            if file > 3 {
+                file = 7 - file;
+            }
+        }
+
+        if dtz_result.is_some() {
+            // Symmetric tables without pawns serve both sides to move.
+            let stored_side = (table.pairs[0][file].flags & FLAG_SIDE_TO_MOVE) as usize;
+            if stored_side != side && (table.has_pawns || !table.symmetric) {
+                return Ok(None);
+            }
+        }
+        let data = &table.pairs[side % table.pairs.len()][file];
+
+        // Put the pieces in the order the table encodes them.
+        for index in lead_pawns..placement.len() - 1 {
+            if let Some(other) = (index + 1..placement.len()).find(|other| placement[*other].1 == data.pieces[index]) {
+                placement.swap(index, other);
+            }
+        }
+        let mut squares: Vec<usize> = placement.iter().map(|(square, _)| *square).collect();
+
+        // Mirror so the leading piece is on files a-d.
+        if squares[0] % 8 > 3 {
+            for square in squares.iter_mut() {
+                *square ^= 7;
+            }
+        }
+
+        let mut index = if table.has_pawns {
+            let mut index = encoding.lead_pawn_index[lead_pawns][squares[0]];
+            squares[1..lead_pawns].sort_by_key(|square| encoding.map_pawns[*square]);
+            for (count, square) in squares[1..lead_pawns].iter().enumerate() {
+                index += encoding.binomial[count + 1][encoding.map_pawns[*square]];
+            }
+            index
+        } else {
+            // Without pawns, also mirror so the leading piece is on ranks 1-4, and then so the first
+            // leading piece off the a1-h8 diagonal is below it.
+            if squares[0] / 8 > 3 {
+                for square in squares.iter_mut() {
+                    *square ^= 56;
+                }
+            }
+            for index in 0..data.group_lengths[0] {
+                let off = off_diagonal(squares[index]);
+                if off == 0 {
+                    continue;
+                }
+                if off > 0 {
+                    for square in squares[index..].iter_mut() {
+                        *square = ((*square >> 3) | (*square << 3)) & 63;
+                    }
+                }
+                break;
+            }
+            if table.has_unique_pieces {
+                unique_pieces_index(&squares)
+            } else {
+                encoding.map_kk[encoding.map_a1d1d4[squares[0]]][squares[1]]
+            }
+        };
+
+        // The other groups: each as a combination of the squares the earlier groups left free.
+        index *= data.group_factors[0];
+        let mut start = data.group_lengths[0];
+        let mut remaining_pawns = table.pawns_on_both_sides;
+        for group in 1..data.group_lengths.len() {
+            let length = data.group_lengths[group];
+            squares[start..start + length].sort_unstable();
+            let mut group_index = 0;
+            for offset in 0..length {
+                let square = squares[start + offset];
+                let taken = squares[..start].iter().filter(|other| square > **other).count();
+                // Pawns are never on the first rank.
+                let pawn_shift = if remaining_pawns { 8 } else { 0 };
+                group_index += encoding.binomial[offset + 1][square - taken - pawn_shift];
+            }
+            remaining_pawns = false;
+            index += group_index * data.group_factors[group];
+            start += length;
+        }
+
+        let value = data.decompress(&table.file, index).ok_or_else(|| Error::InvalidData(format!("Corrupt table {}", table.name)))? as i32;
+        match dtz_result {
+            None => Ok(Some(value - 2)),
+            Some(wdl) => table.map_dtz(file, value, wdl).map(Some).ok_or_else(|| Error::InvalidData(format!("Corrupt table {}", table.name)))
+        }
+    }
+}
+
+impl Tablebase for SyzygyTablebase {
+    fn can_probe(&self, board: &Board) -> bool {
+        count_pieces(board) <= self.max_pieces && ![Color::White, Color::Black].iter().any(|color|
+            board.is_castling_allowed(*color, CastlingSide::King) || board.is_castling_allowed(*color, CastlingSide::Queen))
+    }
+
+    fn probe_wdl(&self, board: &Board, color: Color) -> Result<Wdl, Error> {
+        self.check_probe(board)?;
+        Ok(self.search_wdl(board, color, false)?.0)
+    }
+
+    // The moves that keep the best result, reaching it in the fewest moves: for a win, the moves
+    // with the shortest distance to zeroing, which guarantees progress. For a loss, the longest.
+    fn filter_root_moves(&self, board: &Board, color: Color) -> Result<Vec<Board>, Error> {
+        self.check_probe(board)?;
+        let mut ranked = vec![];
+        for child in next_boards(board, color) {
+            let mut dtz = if is_zeroing(board, &child, color) {
+                dtz_before_zeroing(-self.search_wdl(&child, color.opposite(), false)?.0)
+            } else {
+                let dtz = -self.dtz(&child, color.opposite())?;
+                dtz + dtz.signum()
+            };
+            // A mate is as short as it gets.
+            if dtz == 2 && is_checked(&child, color.opposite()) && next_boards(&child, color.opposite()).is_empty() {
+                dtz = 1;
+            }
+            let rank = match dtz {
+                dtz if dtz > 0 => 100_000 - dtz,
+                dtz if dtz < 0 => -100_000 - dtz,
+                _ => 0
+            };
+            ranked.push((child, rank));
+        }
+        let best = ranked.iter().map(|(_, rank)| *rank).max();
+        Ok(ranked.into_iter().filter(|(_, rank)| Some(*rank) == best).map(|(child, _)| child).collect())
+    }
+}
+
+// The index of the kings and one unique piece together: the first piece in the a1-d1-d4 triangle,
+// the others on the squares left.
+fn unique_pieces_index(squares: &[usize]) -> u64 {
+    let encoding = encoding();
+    let (first, second, third) = (squares[0], squares[1], squares[2]);
+    let adjust_second = (second > first) as u64;
+    let adjust_third = (third > first) as u64 + (third > second) as u64;
+    let rank = |square: usize| (square / 8) as u64;
+    if off_diagonal(first) != 0 {
+        (encoding.map_a1d1d4[first] as u64 * 63 + (second as u64 - adjust_second)) * 62 + third as u64 - adjust_third
+    } else if off_diagonal(second) != 0 {
+        (6 * 63 + rank(first) * 28 + encoding.map_b1h1h7[second]) * 62 + third as u64 - adjust_third
+    } else if off_diagonal(third) != 0 {
+        6 * 63 * 62 + 4 * 28 * 62 + rank(first) * 7 * 28 + (rank(second) - adjust_second) * 28 + encoding.map_b1h1h7[third]
+    } else {
+        6 * 63 * 62 + 4 * 28 * 62 + 4 * 7 * 28 + rank(first) * 7 * 6 + (rank(second) - adjust_second) * 6 + (rank(third) - adjust_third)
+    }
+}
+
+// The pieces of a table: the counts per color, indexed by piece code minus one.
+struct Material {
+    counts: [[usize; 6]; 2],
+}
+
+impl Material {
+    // A table name such as "KRPvKR". Every side needs a king.
+    fn parse(name: &str) -> Option<Material> {
+        let (white, black) = name.split_once('v')?;
+        let mut counts = [[0; 6]; 2];
+        for (side, letters) in [white, black].iter().enumerate() {
+            for letter in letters.chars() {
+                let position = PIECE_LETTERS.iter().position(|other| *other == letter)?;
+                counts[side][5 - position] += 1;
+            }
+        }
+        let material = Material { counts };
+        if counts[0][5] != 1 || counts[1][5] != 1 || material.piece_count() > MAX_PIECES || material.name() != name {
+            return None;
+        }
+        Some(material)
+    }
+
+    // The material of the board with the given color as the first side.
+    fn of(board: &Board, first: Color) -> Material {
+        let mut counts = [[0; 6]; 2];
+        for square in board.squares() {
+            if let Square::Occupied(piece) = square {
+                let side = if piece.0 == first { 0 } else { 1 };
+                counts[side][(piece_code(*piece) & 7) as usize - 1] += 1;
+            }
+        }
+        Material { counts }
+    }
+
+    fn name(&self) -> String {
+        let side_name = |counts: &[usize; 6]| PIECE_LETTERS.iter().enumerate()
+            .map(|(position, letter)| letter.to_string().repeat(counts[5 - position])).collect::<String>();
+        format!("{}v{}", side_name(&self.counts[0]), side_name(&self.counts[1]))
+    }
+
+    fn piece_count(&self) -> usize {
+        self.counts.iter().flatten().sum()
+    }
+
+    fn has_pawns(&self) -> bool {
+        self.counts[0][0] + self.counts[1][0] > 0
+    }
+
+    // Whether table piece codes are exactly these pieces.
+    fn matches(&self, pieces: &[u8]) -> bool {
+        let mut counts = [[0; 6]; 2];
+        for code in pieces {
+            let kind = (code & 7) as usize;
+            if !(1..=6).contains(&kind) {
+                return false;
+            }
+            counts[(code >> 3) as usize & 1][kind - 1] += 1;
+        }
+        counts == self.counts
+    }
+}
+
+struct Table {
+    name: String,
+    file: MappedFile,
+    symmetric: bool,
+    has_pawns: bool,
+    has_unique_pieces: bool,
+    pawns_on_both_sides: bool,
+    // Per side to move, then per file of the leading pawn.
+    pairs: Vec<Vec<PairsData>>,
+}
+
+impl Table {
+    fn parse(file: MappedFile, material: &Material, is_dtz: bool) -> Result<Table, Error> {
+        let corrupt = || Error::InvalidData(String::from("Corrupt table"));
+        let bytes: &[u8] = &file;
+        let magic = if is_dtz { DTZ_MAGIC } else { WDL_MAGIC };
+        if bytes.len() < 5 || bytes[0..4] != magic {
+            return Err(Error::InvalidData(String::from("Not a Syzygy table")));
+        }
+        let symmetric = material.counts[0] == material.counts[1];
+        let has_pawns = material.has_pawns();
+        let has_unique_pieces = material.counts.iter().any(|counts| counts[..5].contains(&1));
+        let pawns_on_both_sides = material.counts[0][0] > 0 && material.counts[1][0] > 0;
+        let split = bytes[4] & 1 != 0;
+        if (bytes[4] & 2 != 0) != has_pawns || split == symmetric {
+            return Err(Error::InvalidData(String::from("Table does not match its name")));
+        }
+
+        let sides = if is_dtz || symmetric { 1 } else { 2 };
+        let files = if has_pawns { 4 } else { 1 };
+        let piece_count = material.piece_count();
+        let mut pairs = vec![vec![]; sides];
+        let mut offset = 5;
+        for file in 0..files {
+            let first = *bytes.get(offset).ok_or_else(corrupt)? as usize;
+            let second = if pawns_on_both_sides { *bytes.get(offset + 1).ok_or_else(corrupt)? as usize } else { 0xFF };
+            let orders = [[first & 0xF, second & 0xF], [first >> 4, second >> 4]];
+            offset += if pawns_on_both_sides { 2 } else { 1 };
+            let piece_bytes = bytes.get(offset..offset + piece_count).ok_or_else(corrupt)?;
+            offset += piece_count;
+            for (side, side_pairs) in pairs.iter_mut().enumerate() {
+                let pieces: Vec<u8> = piece_bytes.iter().map(|byte| if side == 1 { byte >> 4 } else { byte & 0xF }).collect();
+                if !material.matches(&pieces) || (has_pawns && pieces[0] & 7 != 1) {
+                    return Err(corrupt());
+                }
+                let mut data = PairsData::new(pieces);
+                data.set_groups(orders[side], file, has_pawns, has_unique_pieces, pawns_on_both_sides);
+                side_pairs.push(data);
+            }
+        }
+        offset += offset & 1;
+
+        for file in 0..files {
+            for side_pairs in pairs.iter_mut() {
+                offset = side_pairs[file].read_sizes(bytes, offset).ok_or_else(corrupt)?;
+            }
+        }
+
+        // DTZ tables may map stored values to distances, with a map per result.
+        if is_dtz {
+            for data in pairs[0].iter_mut() {
+                if data.flags & FLAG_MAPPED == 0 {
+                    continue;
+                }
+                let wide = data.flags & FLAG_WIDE != 0;
+                if wide {
+                    offset += offset & 1;
+                }
+                for map_offset in data.map_offsets.iter_mut() {
+                    if wide {
+                        *map_offset = offset + 2;
+                        offset += 2 * read_u16(bytes, offset).ok_or_else(corrupt)? as usize + 2;
+                    } else {
+                        *map_offset = offset + 1;
+                        offset += *bytes.get(offset).ok_or_else(corrupt)? as usize + 1;
+                    }
+                }
+            }
+            offset += offset & 1;
+        }
+
+        for file in 0..files {
+            for side_pairs in pairs.iter_mut() {
+                offset = side_pairs[file].set_sparse_index(offset);
+            }
+        }
+        for file in 0..files {
+            for side_pairs in pairs.iter_mut() {
+                offset = side_pairs[file].set_block_lengths(offset);
+            }
+        }
+        for file in 0..files {
+            for side_pairs in pairs.iter_mut() {
+                // Blocks are aligned to 64 bytes.
+                offset = (offset + 63) & !63;
+                offset = side_pairs[file].set_data(offset);
+                if !side_pairs[file].is_single_value() && offset > bytes.len() {
+                    return Err(corrupt());
+                }
+            }
+        }
+
+        Ok(Table { name: material.name(), file, symmetric, has_pawns, has_unique_pieces, pawns_on_both_sides, pairs })
+    }
+
+    // Turn a stored DTZ value into plies, for a position with the given result.
+    fn map_dtz(&self, file: usize, value: i32, wdl: Wdl) -> Option<i32> {
+        let data = &self.pairs[0][file];
+        let mut value = value;
+        if data.flags & FLAG_MAPPED != 0 {
+            let map = data.map_offsets[[1, 3, 0, 2, 0][(wdl.value() + 2) as usize]];
+            value = if data.flags & FLAG_WIDE != 0 {
+                read_u16(&self.file, map + 2 * value as usize)? as i32
+            } else {
+                *self.file.get(map + value as usize)? as i32
+            };
+        }
+        let in_moves = match wdl {
+            Wdl::Win => data.flags & FLAG_WIN_PLIES == 0,
+            Wdl::Loss => data.flags & FLAG_LOSS_PLIES == 0,
+            _ => true
+        };
+        if in_moves {
+            value *= 2;
+        }
+        Some(value + 1)
+    }
+}
+
+// Table piece codes: pawn 1, knight 2, bishop 3, rook 4, queen 5 and king 6, plus 8 for black.
+fn piece_code(piece: Piece) -> u8 {
+    let code = match piece.1 {
+        Kind::Pawn => 1,
+        Kind::Knight => 2,
+        Kind::Bishop => 3,
+        Kind::Rook => 4,
+        Kind::Queen => 5,
+        Kind::King => 6
+    };
+    if piece.0 == Color::Black { code + 8 } else { code }
+}
+
+fn dtz_before_zeroing(wdl: Wdl) -> i32 {
+    match wdl {
+        Wdl::Win => 1,
+        Wdl::CursedWin => 101,
+        Wdl::BlessedLoss => -101,
+        Wdl::Loss => -1,
+        Wdl::Draw => 0
+    }
+}
+
+fn count_pieces(board: &Board) -> usize {
+    board.squares().iter().filter(|square| !square.is_empty()).count()
+}
+
+fn is_capture(board: &Board, child: &Board) -> bool {
+    count_pieces(child) < count_pieces(board)
+}
+
+fn is_pawn_move(board: &Board, child: &Board, color: Color) -> bool {
+    board.squares().iter().zip(child.squares().iter())
+        .any(|(before, after)| *before == Square::Occupied(Piece(color, Kind::Pawn)) && *after != *before)
+}
+
+// Captures and pawn moves reset the fifty-move counter.
+fn is_zeroing(board: &Board, child: &Board, color: Color) -> bool {
+    is_capture(board, child) || is_pawn_move(board, child, color)
+}
@@ -0,0 +1,57 @@
+use std::ops::{ Neg };
+
+use crate::board::{ Board, Color };
+use crate::error::{ Error };
+
+// The result of a position with the color to move, assuming best play. Cursed wins and blessed
+// losses are wins and losses that take too long: the fifty-move rule makes them draws.
+#[derive(Debug,Copy,PartialEq,Eq,Clone,Hash,PartialOrd,Ord)]
+pub enum Wdl {
+    Loss,
+    BlessedLoss,
+    Draw,
+    CursedWin,
+    Win
+}
+
+impl Wdl {
+    pub fn from_value(value: i32) -> Wdl {
+        match value {
+            -2 => Wdl::Loss,
+            -1 => Wdl::BlessedLoss,
+            1 => Wdl::CursedWin,
+            2 => Wdl::Win,
+            _ => Wdl::Draw
+        }
+    }
+
+    // From -2 for a loss to 2 for a win.
+    pub fn value(self) -> i32 {
+        self as i32 - 2
+    }
+}
+
+impl Neg for Wdl {
+    type Output = Wdl;
+
+    fn neg(self) -> Wdl {
+        Wdl::from_value(-self.value())
+    }
+}
+
+// Endgame tables the search can consult.
+pub trait Tablebase: Sync {
+    // Whether positions with this material are in the tables.
+    fn can_probe(&self, board: &Board) -> bool;
+
+    fn probe_wdl(&self, board: &Board, color: Color) -> Result<Wdl, Error>;
+
+    // The plies until mate with best play in a won or lost position, zero when already mated.
+    // Nothing for draws, or when the tables do not know distances to mate.
+    fn probe_dtm(&self, _board: &Board, _color: Color) -> Result<Option<u32>, Error> {
+        Ok(None)
+    }
+
+    // The moves that keep the best result, and make progress towards it.
+    fn filter_root_moves(&self, board: &Board, color: Color) -> Result<Vec<Board>, Error>;
+}
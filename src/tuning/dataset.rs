@@ -0,0 +1,68 @@
+use std::fs;
+use std::path::{ Path };
+
+use crate::board::{ Board, Color, Fen };
+use crate::error::{ Error, ParseError, ParseErrorKind };
+
+// A position with the result of the game it was taken from.
+#[derive(Debug,Copy,PartialEq,Clone)]
+pub struct LabeledPosition {
+    pub board: Board,
+    pub color: Color,
+    // 1.0 for a white win, 0.5 for a draw and 0.0 for a black win.
+    pub result: f64,
+}
+
+pub fn load_labeled_positions<P: AsRef<Path>>(path: P) -> Result<Vec<LabeledPosition>, Error> {
+    let input = fs::read_to_string(path.as_ref()).map_err(|error| Error::Io(format!("Cannot read {}: {}", path.as_ref().display(), error)))?;
+    parse_labeled_positions(&input)
+}
+
+// Parse one labeled position per line. Accepted labels are an EPD `c9 "1-0";` opcode, a bracketed
+// result such as `[1.0]`, `[0.5]` or `[1/2-1/2]`, or a bare `1-0`, `0-1` or `1/2-1/2` after the position.
+pub fn parse_labeled_positions(input: &str) -> Result<Vec<LabeledPosition>, Error> {
+    let mut positions = vec![];
+    for line in input.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let position = parse_labeled_position(input, line)?;
+        positions.push(position);
+    }
+    Ok(positions)
+}
+
+// The position on the line, which is a slice of the input.
+fn parse_labeled_position(input: &str, line: &str) -> Result<LabeledPosition, Error> {
+    let (position, label) = if let Some(index) = line.find("c9 \"") {
+        let label = line[index + 4..].split('"').next().unwrap_or("");
+        (&line[..index], label)
+    } else if let (Some(start), Some(end)) = (line.rfind('['), line.rfind(']')) {
+        (&line[..start], &line[start + 1..end])
+    } else {
+        // Only a game result may stand bare, so the move counters of an unlabeled FEN are never taken for one.
+        match line.rsplit_once(' ') {
+            Some((position, label)) if ["1-0", "0-1", "1/2-1/2"].contains(&label) => (position, label),
+            _ => { return Err(ParseError::new(ParseErrorKind::InvalidResult, input, &line[line.len()..], String::from("Missing result label")).into()); }
+        }
+    };
+
+    let result = parse_result(input, label.trim())?;
+    // EPD operations are separated by semicolons; only the position fields matter here.
+    let position = position.split(';').next().unwrap_or("").trim();
+    let fen = Fen::parse(position).map_err(|error| error.within(input, position))?;
+    Ok(LabeledPosition { board: fen.board, color: fen.color, result })
+}
+
+fn parse_result(input: &str, label: &str) -> Result<f64, ParseError> {
+    match label {
+        "1-0" => Ok(1.0),
+        "0-1" => Ok(0.0),
+        "1/2-1/2" => Ok(0.5),
+        _ => match label.parse::<f64>() {
+            Ok(result) if (0.0..=1.0).contains(&result) => Ok(result),
+            _ => Err(ParseError::new(ParseErrorKind::InvalidResult, input, label, format!("Invalid result: {}", label)))
+        }
+    }
+}
@@ -0,0 +1,5 @@
+mod dataset;
+mod texel;
+
+pub use dataset::{ load_labeled_positions, parse_labeled_positions, LabeledPosition };
+pub use texel::{ evaluation_error, find_scaling_constant, tune, TuneResult, TunerOptions };
@@ -0,0 +1,192 @@
+use std::sync::mpsc::{ self, Receiver, Sender };
+use std::thread;
+
+use crate::board::{ Board };
+use crate::engine::{ evaluate_pawn_structure, score_board_with, EvaluationParameters, PawnStructure };
+use crate::tuning::dataset::{ LabeledPosition };
+
+#[derive(Debug,Clone,PartialEq)]
+pub struct TunerOptions {
+    pub threads: usize,
+    // Passes over all parameters. Tuning stops early when a pass brings no improvement.
+    pub max_iterations: usize,
+    pub step: i32,
+    // Only parameters whose name starts with one of these prefixes are tuned. Everything is tuned when empty.
+    pub only: Vec<String>,
+    // The scaling constant of the sigmoid. It is fitted to the initial parameters when not given.
+    pub scaling_constant: Option<f64>,
+}
+
+impl Default for TunerOptions {
+    fn default() -> TunerOptions {
+        TunerOptions {
+            threads: thread::available_parallelism().map(|threads| threads.get()).unwrap_or(1),
+            max_iterations: 100,
+            step: 1,
+            only: vec![],
+            scaling_constant: None,
+        }
+    }
+}
+
+#[derive(Debug,Clone,PartialEq)]
+pub struct TuneResult {
+    pub parameters: EvaluationParameters,
+    pub initial_error: f64,
+    pub error: f64,
+    pub scaling_constant: f64,
+    pub iterations: usize,
+}
+
+// The pawn structure features do not depend on the parameters, so they are computed once per position.
+struct PreparedPosition {
+    board: Board,
+    pawn_structure: PawnStructure,
+    result: f64,
+}
+
+fn prepare(positions: &[LabeledPosition]) -> Vec<PreparedPosition> {
+    positions.iter().map(|position| PreparedPosition {
+        board: position.board,
+        pawn_structure: evaluate_pawn_structure(&position.board),
+        result: position.result,
+    }).collect()
+}
+
+// The expected result for white of a position with the given score.
+fn sigmoid(score: i32, scaling_constant: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-scaling_constant * score as f64 / 400.0))
+}
+
+// Worker threads that each keep one chunk of the positions for as long as the pool lives, and
+// compute the error over their chunk for every set of parameters they are sent.
+struct ErrorPool {
+    requests: Vec<Sender<(EvaluationParameters, f64)>>,
+    sums: Receiver<(usize, f64)>,
+    positions: usize,
+}
+
+impl ErrorPool {
+    // The mean squared difference between game results and the results predicted by the static evaluation.
+    fn error(&self, parameters: &EvaluationParameters, scaling_constant: f64) -> f64 {
+        if self.positions == 0 {
+            return 0.0;
+        }
+        for request in self.requests.iter() {
+            request.send((*parameters, scaling_constant)).unwrap();
+        }
+        // Add the chunks up in order, so the error does not depend on which thread finishes first.
+        let mut sums = vec![0.0; self.requests.len()];
+        for _ in 0..self.requests.len() {
+            let (index, sum) = self.sums.recv().unwrap();
+            sums[index] = sum;
+        }
+        sums.iter().sum::<f64>() / self.positions as f64
+    }
+}
+
+// Run the function with a pool over the positions. The workers stop when it returns.
+fn with_error_pool<T>(positions: &[PreparedPosition], threads: usize, f: impl FnOnce(&ErrorPool) -> T) -> T {
+    let chunk_size = positions.len().div_ceil(threads.max(1)).max(1);
+    thread::scope(|scope| {
+        let (sender, sums) = mpsc::channel();
+        let requests = positions.chunks(chunk_size).enumerate().map(|(index, chunk)| {
+            let (request, received) = mpsc::channel::<(EvaluationParameters, f64)>();
+            let sender = sender.clone();
+            scope.spawn(move || {
+                for (parameters, scaling_constant) in received {
+                    sender.send((index, chunk_error(chunk, &parameters, scaling_constant))).unwrap();
+                }
+            });
+            request
+        }).collect();
+        f(&ErrorPool { requests, sums, positions: positions.len() })
+    })
+}
+
+fn chunk_error(chunk: &[PreparedPosition], parameters: &EvaluationParameters, scaling_constant: f64) -> f64 {
+    chunk.iter().map(|position| {
+        let score = score_board_with(&position.board, &position.pawn_structure, parameters);
+        (position.result - sigmoid(score, scaling_constant)).powi(2)
+    }).sum()
+}
+
+pub fn evaluation_error(positions: &[LabeledPosition], parameters: &EvaluationParameters, scaling_constant: f64, threads: usize) -> f64 {
+    with_error_pool(&prepare(positions), threads, |pool| pool.error(parameters, scaling_constant))
+}
+
+// Find the scaling constant that best fits the evaluation to the results, before any parameter changes.
+pub fn find_scaling_constant(positions: &[LabeledPosition], parameters: &EvaluationParameters, threads: usize) -> f64 {
+    with_error_pool(&prepare(positions), threads, |pool| pool_scaling_constant(pool, parameters))
+}
+
+fn pool_scaling_constant(pool: &ErrorPool, parameters: &EvaluationParameters) -> f64 {
+    let mut best = 1.0;
+    let mut best_error = pool.error(parameters, best);
+    // Refine around the best value found so far, with ever smaller steps.
+    for step in [0.1, 0.01, 0.001].iter() {
+        let start = (best - 10.0 * step).max(0.0);
+        for i in 0..=20 {
+            let candidate = start + *step * i as f64;
+            let error = pool.error(parameters, candidate);
+            if error < best_error {
+                best = candidate;
+                best_error = error;
+            }
+        }
+    }
+    best
+}
+
+// Parameters that are never tuned. Both sides always have their king, so its value cancels out and
+// any change to it only drifts.
+const FIXED_PARAMETERS: [&str; 1] = ["piece_values.king."];
+
+// Tune the parameters by coordinate descent: nudge each parameter up and down by the step, and keep any change that lowers the error.
+pub fn tune(positions: &[LabeledPosition], initial: &EvaluationParameters, options: &TunerOptions) -> TuneResult {
+    with_error_pool(&prepare(positions), options.threads, |pool| tune_with_pool(pool, initial, options))
+}
+
+fn tune_with_pool(pool: &ErrorPool, initial: &EvaluationParameters, options: &TunerOptions) -> TuneResult {
+    let scaling_constant = options.scaling_constant.unwrap_or_else(|| pool_scaling_constant(pool, initial));
+
+    let names = initial.names();
+    let active: Vec<usize> = (0..names.len())
+        .filter(|index| !FIXED_PARAMETERS.iter().any(|prefix| names[*index].starts_with(prefix)))
+        .filter(|index| options.only.is_empty() || options.only.iter().any(|prefix| names[*index].starts_with(prefix.as_str())))
+        .collect();
+
+    let mut values = initial.to_vector();
+    let error_for = |values: &[i32]| pool.error(&EvaluationParameters::from_vector(values).unwrap(), scaling_constant);
+    let initial_error = error_for(&values);
+    let mut best_error = initial_error;
+
+    let mut iterations = 0;
+    while iterations < options.max_iterations {
+        iterations += 1;
+        let mut improved = false;
+        for index in active.iter() {
+            for delta in [options.step, -options.step].iter() {
+                values[*index] += delta;
+                let error = error_for(&values);
+                if error < best_error {
+                    best_error = error;
+                    improved = true;
+                    break;
+                }
+                values[*index] -= delta;
+            }
+        }
+        if !improved {
+            break;
+        }
+    }
+
+    TuneResult {
+        parameters: EvaluationParameters::from_vector(&values).unwrap(),
+        initial_error,
+        error: best_error,
+        scaling_constant,
+        iterations,
+    }
+}
@@ -1,109 +1,294 @@
+use std::collections::{ VecDeque };
 use std::fmt;
+use std::path::{ Path };
 
-#[derive(Debug)]
+use crate::error::{ Error };
+
+// Saved trees start with a magic and a format version (u32, little endian), followed by the
+// number of nodes (varint) and every node depth first: the distance back to its parent in that
+// order (varint, 0 for the root) and its encoded payload.
+const MAGIC: &[u8; 4] = b"GTRE";
+const VERSION: u32 = 1;
+
+#[derive(Debug,Clone,PartialEq)]
 pub struct GrowTree<T> {
-    all_items: Vec<T>,
+    // None for released nodes, whose slots are reused before the arena grows.
+    all_items: Vec<Option<T>>,
     parent_indices: Vec<Option<usize>>,
-    child_indices: Vec<Vec<usize>>
+    child_indices: Vec<Vec<usize>>,
+    free_indices: Vec<usize>,
+    // The most nodes the tree should hold. Adding is never refused: callers check `is_full` and
+    // release nodes to make room.
+    max_nodes: Option<usize>,
+}
+
+// Payloads that can be saved with their tree.
+pub(crate) trait Encode: Sized {
+    fn encode(&self, bytes: &mut Vec<u8>);
+    // The payload and the number of bytes it was read from.
+    fn decode(bytes: &[u8]) -> Result<(Self, usize), Error>;
 }
 
 impl<T> GrowTree<T> {
     pub fn new(root: T) -> GrowTree<T> {
         GrowTree {
-            all_items: vec![root],
+            all_items: vec![Some(root)],
             child_indices: vec![vec![]],
             parent_indices: vec![None],
+            free_indices: vec![],
+            max_nodes: None,
         }
     }
 
-    fn add_item(&mut self, item: T) -> usize {
-        let index = self.all_items.len();
-        self.all_items.push(item);
-        self.child_indices.push(vec![]);
-        index
+    pub fn with_max_nodes(mut self, max_nodes: usize) -> GrowTree<T> {
+        self.max_nodes = Some(max_nodes);
+        self
+    }
+
+    pub fn max_nodes(&self) -> Option<usize> {
+        self.max_nodes
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.max_nodes.is_some_and(|max_nodes| self.len() >= max_nodes)
+    }
+
+    fn add_item(&mut self, item: T, parent_index: usize) -> usize {
+        match self.free_indices.pop() {
+            Some(index) => {
+                self.all_items[index] = Some(item);
+                self.parent_indices[index] = Some(parent_index);
+                index
+            },
+            None => {
+                self.all_items.push(Some(item));
+                self.child_indices.push(vec![]);
+                self.parent_indices.push(Some(parent_index));
+                self.all_items.len() - 1
+            }
+        }
     }
 
     pub fn add_child(&mut self, item: T, parent_index: usize) -> usize {
-        if parent_index >= self.all_items.len() {
+        if !self.contains(parent_index) {
             panic!("Invalid parent_index: {}", parent_index);
         }
-        let index = self.add_item(item);
+        let index = self.add_item(item, parent_index);
         self.child_indices[parent_index].push(index);
-        self.parent_indices.push(Some(parent_index));
         index
     }
 
+    // Whether the index is a node of the tree, rather than out of range or released.
+    pub fn contains(&self, index: usize) -> bool {
+        self.all_items.get(index).is_some_and(|item| item.is_some())
+    }
+
     pub fn item(&self, index: usize) -> Option<&T> {
-        self.all_items.get(index)
+        self.all_items.get(index).and_then(|item| item.as_ref())
     }
 
     pub fn item_mut(&mut self, index: usize) -> Option<&mut T> {
-        self.all_items.get_mut(index)
+        self.all_items.get_mut(index).and_then(|item| item.as_mut())
     }
 
     pub fn root(&self) -> &T {
-        &self.all_items[0]
+        self.node(0)
+    }
+
+    // The item of an index known to be in the tree.
+    fn node(&self, index: usize) -> &T {
+        self.all_items[index].as_ref().unwrap()
     }
 
-    pub fn children(&self, parent_index: usize) -> Vec<(usize, &T)> {
-        self.child_indices.get(parent_index).unwrap_or(&vec![]).iter().map(|index| (*index, &self.all_items[*index])).collect()
+    // The number of nodes, the root included.
+    pub fn len(&self) -> usize {
+        self.all_items.len() - self.free_indices.len()
+    }
+
+    // A tree always has its root.
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    pub fn children(&self, parent_index: usize) -> impl DoubleEndedIterator<Item = (usize, &T)> + ExactSizeIterator + '_ {
+        self.child_indices(parent_index).iter().map(move |index| (*index, self.node(*index)))
+    }
+
+    pub fn child_indices(&self, parent_index: usize) -> &[usize] {
+        self.child_indices.get(parent_index).map(|indices| indices.as_slice()).unwrap_or(&[])
+    }
+
+    pub fn is_leaf(&self, index: usize) -> bool {
+        self.child_indices(index).is_empty()
     }
 
     pub fn parent(&self, index: usize) -> Option<(usize, &T)> {
-        self.parent_indices.get(index).unwrap_or(&None).map(|index| (index, &self.all_items[index]))
+        self.parent_indices.get(index).copied().flatten().map(|index| (index, self.node(index)))
+    }
+
+    // The parent, its parent and so on up to the root.
+    pub fn ancestors(&self, index: usize) -> impl Iterator<Item = (usize, &T)> + '_ {
+        Ancestors { tree: self, next: self.parent(index).map(|(parent, _)| parent) }
+    }
+
+    // The node itself, then its ancestors.
+    pub fn path_to_root(&self, index: usize) -> impl Iterator<Item = (usize, &T)> + '_ {
+        Ancestors { tree: self, next: Some(index).filter(|index| self.contains(*index)) }
+    }
+
+    // The number of nodes between the node and the root: 0 for the root.
+    pub fn depth(&self, index: usize) -> Option<usize> {
+        if self.contains(index) { Some(self.ancestors(index).count()) } else { None }
+    }
+
+    // The node and everything below it, depth first: every node before its children, and
+    // children in the order they were added.
+    pub fn dfs(&self, index: usize) -> impl Iterator<Item = (usize, &T)> + '_ {
+        Dfs { tree: self, stack: if self.contains(index) { vec![index] } else { vec![] } }
+    }
+
+    // The node and everything below it, breadth first.
+    pub fn bfs(&self, index: usize) -> impl Iterator<Item = (usize, &T)> + '_ {
+        Bfs { tree: self, queue: if self.contains(index) { VecDeque::from(vec![index]) } else { VecDeque::new() } }
+    }
+
+    // The nodes without children below the node, depth first.
+    pub fn leaves(&self, index: usize) -> impl Iterator<Item = (usize, &T)> + '_ {
+        self.dfs(index).filter(move |(index, _)| self.is_leaf(*index))
+    }
+
+    // Remove the node and everything below it. The remaining nodes move down to fill the gaps, so
+    // indices change: the result maps every old index to its new one, or None when removed.
+    pub fn remove_subtree(&mut self, index: usize) -> Result<Vec<Option<usize>>, Error> {
+        if index == 0 || !self.contains(index) {
+            return Err(Error::InvalidArgument(format!("Cannot remove subtree at index: {}", index)));
+        }
+        Ok(self.retain(|node_index, _| node_index != index))
+    }
+
+    // Keep only the nodes the function keeps, removing the others with everything below them.
+    // Nodes below a removed node are removed without being asked about, and the root is always
+    // kept. Returns the new index of every old index, like `remove_subtree`.
+    pub fn retain<F>(&mut self, mut keep: F) -> Vec<Option<usize>>
+        where F: FnMut(usize, &T) -> bool {
+        // Depth first, every node is decided after its parent.
+        let mut kept = vec![false; self.all_items.len()];
+        kept[0] = true;
+        for (index, item) in self.dfs(0).skip(1) {
+            kept[index] = kept[self.parent_indices[index].unwrap()] && keep(index, item);
+        }
+        self.compact(&kept, 0)
+    }
+
+    // Keep only the node and everything below it, the node becoming the root. Indices change as
+    // with `remove_subtree`.
+    pub fn reroot(&mut self, index: usize) -> Result<Vec<Option<usize>>, Error> {
+        if !self.contains(index) {
+            return Err(Error::InvalidArgument(format!("Cannot reroot at index: {}", index)));
+        }
+        let mut kept = vec![false; self.all_items.len()];
+        for (node_index, _) in self.dfs(index) {
+            kept[node_index] = true;
+        }
+        Ok(self.compact(&kept, index))
+    }
+
+    // Remove the node and everything below it without moving other nodes: their indices stay
+    // valid, and the slots are reused by the next nodes added. Returns the number of nodes removed.
+    pub fn release_subtree(&mut self, index: usize) -> Result<usize, Error> {
+        if index == 0 || !self.contains(index) {
+            return Err(Error::InvalidArgument(format!("Cannot release subtree at index: {}", index)));
+        }
+        let released: Vec<usize> = self.dfs(index).map(|(node_index, _)| node_index).collect();
+        if let Some(parent_index) = self.parent_indices[index] {
+            self.child_indices[parent_index].retain(|child_index| *child_index != index);
+        }
+        for node_index in released.iter() {
+            self.all_items[*node_index] = None;
+            self.parent_indices[*node_index] = None;
+            self.child_indices[*node_index].clear();
+        }
+        self.free_indices.extend(released.iter().rev());
+        Ok(released.len())
+    }
+
+    // Move the kept nodes together, the new root first and the others in index order.
+    fn compact(&mut self, kept: &[bool], root_index: usize) -> Vec<Option<usize>> {
+        let mut new_indices = vec![None; kept.len()];
+        new_indices[root_index] = Some(0);
+        let mut next_index = 1;
+        for (index, is_kept) in kept.iter().enumerate() {
+            if *is_kept && index != root_index {
+                new_indices[index] = Some(next_index);
+                next_index += 1;
+            }
+        }
+
+        let mut all_items: Vec<Option<T>> = (0..next_index).map(|_| None).collect();
+        let mut parent_indices = vec![None; next_index];
+        let mut child_indices = vec![vec![]; next_index];
+        for (index, item) in std::mem::take(&mut self.all_items).into_iter().enumerate() {
+            if let Some(new_index) = new_indices[index] {
+                all_items[new_index] = item;
+                if index != root_index {
+                    parent_indices[new_index] = self.parent_indices[index].and_then(|parent| new_indices[parent]);
+                }
+                child_indices[new_index] = self.child_indices[index].iter().filter_map(|child| new_indices[*child]).collect();
+            }
+        }
+        self.all_items = all_items;
+        self.parent_indices = parent_indices;
+        self.child_indices = child_indices;
+        self.free_indices.clear();
+        new_indices
     }
 
     pub fn subtree<'a>(&'a self, index: usize) -> Option<GrowTree<&'a T>> {
         self.as_refs().into_subtree(index)
     }
 
-    pub fn map<F, O>(&self, f: F) -> GrowTree<O>
+    pub fn map<F, O>(&self, mut f: F) -> GrowTree<O>
         where F: FnMut(&T) -> O {
         GrowTree {
-            all_items: self.all_items.iter().map(f).collect(),
+            all_items: self.all_items.iter().map(|item| item.as_ref().map(&mut f)).collect(),
             child_indices: self.child_indices.clone(),
             parent_indices: self.parent_indices.clone(),
+            free_indices: self.free_indices.clone(),
+            max_nodes: self.max_nodes,
         }
     }
 
     pub fn as_refs(&self) -> GrowTree<&T> {
         GrowTree {
-            all_items: self.all_items.iter().collect(),
+            all_items: self.all_items.iter().map(|item| item.as_ref()).collect(),
             child_indices: self.child_indices.clone(),
             parent_indices: self.parent_indices.clone(),
+            free_indices: self.free_indices.clone(),
+            max_nodes: self.max_nodes,
         }
     }
 
-    pub fn map_into<F, O>(self, f: F) -> GrowTree<O>
+    pub fn map_into<F, O>(self, mut f: F) -> GrowTree<O>
         where F: FnMut(T) -> O {
             GrowTree {
-                all_items: self.all_items.into_iter().map(f).collect(),
-                child_indices: self.child_indices.clone(),
-                parent_indices: self.parent_indices.clone(),
+                all_items: self.all_items.into_iter().map(|item| item.map(&mut f)).collect(),
+                child_indices: self.child_indices,
+                parent_indices: self.parent_indices,
+                free_indices: self.free_indices,
+                max_nodes: self.max_nodes,
             }
     }
 
-    pub fn into_subtree(self, index: usize) -> Option<GrowTree<T>> {
-        if self.all_items.len() <= index {
-            return None;
-        }
-
-        let mut source_tree = self.map_into(|item| Some(item));
-
-        let mut subtree = GrowTree::new(std::mem::replace(&mut source_tree.all_items[index], None).unwrap());
-        for child_index in source_tree.child_indices[index].clone() {
-            subtree.import_tree(&mut source_tree, child_index, 0);
-        }
-        Some(subtree)
+    pub fn into_subtree(mut self, index: usize) -> Option<GrowTree<T>> {
+        self.reroot(index).ok()?;
+        Some(self)
     }
 
-    pub fn add_tree_into(&mut self, parent_index: usize, tree: GrowTree<T>) {
-        let mut source_tree = tree.map_into(|item| Some(item));
-
-        self.import_tree(&mut source_tree, 0, parent_index);
+    pub fn add_tree_into(&mut self, parent_index: usize, mut tree: GrowTree<T>) {
+        self.import_tree(&mut tree, 0, parent_index);
     }
 
-    fn import_tree(&mut self, source: &mut GrowTree<Option<T>>, source_index: usize, target_parent_index: usize) {
+    fn import_tree(&mut self, source: &mut GrowTree<T>, source_index: usize, target_parent_index: usize) {
         let item = std::mem::replace(&mut source.all_items[source_index], None).unwrap();
         let target_index = self.add_child(item, target_parent_index);
         let source_child_indices = source.child_indices[source_index].clone();
@@ -113,23 +298,147 @@ impl<T> GrowTree<T> {
     }
 }
 
+// Trees of payloads the crate knows how to encode. The public ways to save a tree are on the
+// trees of those payloads.
+impl<T> GrowTree<T> {
+    pub(crate) fn encode_tree(&self) -> Vec<u8> where T: Encode {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        write_varint(&mut bytes, self.len() as u64);
+        // Where every node went in the depth first order.
+        let mut positions = vec![0; self.all_items.len()];
+        for (position, (index, item)) in self.dfs(0).enumerate() {
+            positions[index] = position;
+            let distance = self.parent_indices[index].map(|parent| position - positions[parent]).unwrap_or(0);
+            write_varint(&mut bytes, distance as u64);
+            item.encode(&mut bytes);
+        }
+        bytes
+    }
+
+    pub(crate) fn decode_tree(bytes: &[u8]) -> Result<GrowTree<T>, Error> where T: Encode {
+        if bytes.len() < 8 || &bytes[..4] != MAGIC {
+            return Err(Error::InvalidData(String::from("Not a tree file")));
+        }
+        let version = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+        if version != VERSION {
+            return Err(Error::InvalidData(format!("Unsupported tree version: {}", version)));
+        }
+        let mut offset = 8;
+        let count = read_varint(bytes, &mut offset)? as usize;
+        let mut tree: Option<GrowTree<T>> = None;
+        for position in 0..count {
+            let distance = read_varint(bytes, &mut offset)? as usize;
+            let (item, length) = T::decode(&bytes[offset..])?;
+            offset += length;
+            // Nodes are added in the saved order, so a node's position is its index.
+            tree = match tree {
+                None if distance == 0 => Some(GrowTree::new(item)),
+                Some(mut tree) if distance > 0 && distance <= position => {
+                    tree.add_child(item, position - distance);
+                    Some(tree)
+                },
+                _ => return Err(Error::InvalidData(format!("Invalid parent distance {} at node {}", distance, position)))
+            };
+        }
+        if offset != bytes.len() {
+            return Err(Error::InvalidData(String::from("Trailing bytes after tree")));
+        }
+        tree.ok_or_else(|| Error::InvalidData(String::from("Tree without nodes")))
+    }
+
+    pub(crate) fn save_tree(&self, path: &Path) -> Result<(), Error> where T: Encode {
+        std::fs::write(path, self.encode_tree()).map_err(|error| Error::Io(format!("Cannot write {}: {}", path.display(), error)))
+    }
+
+    pub(crate) fn load_tree(path: &Path) -> Result<GrowTree<T>, Error> where T: Encode {
+        let bytes = std::fs::read(path).map_err(|error| Error::Io(format!("Cannot read {}: {}", path.display(), error)))?;
+        GrowTree::decode_tree(&bytes)
+    }
+}
+
+// Seven bits per byte, low bits first, with the high bit set on all bytes but the last.
+fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push((value & 0x7f) as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+fn read_varint(bytes: &[u8], offset: &mut usize) -> Result<u64, Error> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let byte = *bytes.get(*offset).ok_or_else(|| Error::InvalidData(String::from("Truncated tree")))?;
+        *offset += 1;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(Error::InvalidData(String::from("Invalid varint")))
+}
+
 impl<T> fmt::Display for GrowTree<T>
   where T: fmt::Display {
     // This trait requires `fmt` with this exact signature.
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        fn write_items<T>(f: &mut fmt::Formatter, tree: &GrowTree<T>, items: Vec<(usize, &T)>, prefix: &str) -> fmt::Result
+        fn write_items<T>(f: &mut fmt::Formatter, tree: &GrowTree<T>, indices: &[usize], prefix: &str) -> fmt::Result
             where T: fmt::Display {
-            let len = items.len();
-            for (index, item) in items {
-                writeln!(f, "{}-- {}", prefix, item)?;
-                let children = tree.children(index);
-                write_items(f, tree, children, &format!("{} |", prefix))?
+            for index in indices {
+                writeln!(f, "{}-- {}", prefix, tree.node(*index))?;
+                write_items(f, tree, tree.child_indices(*index), &format!("{} |", prefix))?
             }
             Ok(())
         }
 
         writeln!(f, "GrowTree {{")?;
-        write_items(f, self, vec![(0, &self.all_items[0])], "  ")?;
+        write_items(f, self, &[0], "  ")?;
         writeln!(f, "}}")
     }
 }
+
+pub(crate) struct Ancestors<'a, T> {
+    tree: &'a GrowTree<T>,
+    next: Option<usize>,
+}
+
+impl<'a, T> Iterator for Ancestors<'a, T> {
+    type Item = (usize, &'a T);
+
+    fn next(&mut self) -> Option<(usize, &'a T)> {
+        let index = self.next?;
+        self.next = self.tree.parent_indices[index];
+        Some((index, self.tree.node(index)))
+    }
+}
+
+pub(crate) struct Dfs<'a, T> {
+    tree: &'a GrowTree<T>,
+    stack: Vec<usize>,
+}
+
+impl<'a, T> Iterator for Dfs<'a, T> {
+    type Item = (usize, &'a T);
+
+    fn next(&mut self) -> Option<(usize, &'a T)> {
+        let index = self.stack.pop()?;
+        self.stack.extend(self.tree.child_indices[index].iter().rev());
+        Some((index, self.tree.node(index)))
+    }
+}
+
+pub(crate) struct Bfs<'a, T> {
+    tree: &'a GrowTree<T>,
+    queue: VecDeque<usize>,
+}
+
+impl<'a, T> Iterator for Bfs<'a, T> {
+    type Item = (usize, &'a T);
+
+    fn next(&mut self) -> Option<(usize, &'a T)> {
+        let index = self.queue.pop_front()?;
+        self.queue.extend(self.tree.child_indices[index].iter());
+        Some((index, self.tree.node(index)))
+    }
+}
@@ -1,3 +1,4 @@
 mod grow_tree;
 
 pub use self::grow_tree::{ GrowTree };
+pub(crate) use self::grow_tree::{ Encode };
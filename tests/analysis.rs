@@ -0,0 +1,46 @@
+use chess::board::{ Kind };
+use chess::engine::{ evaluation_breakdown, perft, perft_divide, score_board, validate_position, EvaluationBreakdown, EvaluationParameters };
+use chess::notation::{ Fen, START_POSITION_FEN };
+
+#[test]
+fn test_perft() {
+    // Known perft counts: the start position, "Kiwipete" and a position full of en passant and checks.
+    let start = Fen::parse(START_POSITION_FEN).unwrap();
+    assert_eq!(perft(&start.board, start.color, 3), 8902);
+    let kiwipete = Fen::parse("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").unwrap();
+    assert_eq!(perft(&kiwipete.board, kiwipete.color, 2), 2039);
+    let endgame = Fen::parse("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1").unwrap();
+    assert_eq!(perft(&endgame.board, endgame.color, 3), 2812);
+    let divided = perft_divide(&kiwipete.board, kiwipete.color, 2);
+    assert_eq!(divided.len(), 48);
+    assert_eq!(divided.iter().map(|(_, count)| count).sum::<u64>(), 2039);
+}
+
+#[test]
+fn test_evaluation_breakdown() {
+    // The terms add up to the evaluation.
+    for fen in [START_POSITION_FEN, "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1"] {
+        let fen = Fen::parse(fen).unwrap();
+        let breakdown: EvaluationBreakdown = evaluation_breakdown(&fen.board);
+        assert_eq!(breakdown.total(), score_board(&fen.board));
+    }
+    let breakdown = evaluation_breakdown(&Fen::parse("4k3/8/8/8/8/8/8/3QK3 w - - 0 1").unwrap().board);
+    assert_eq!(breakdown.material.middlegame, EvaluationParameters::default().piece_values[Kind::Queen.index()].middlegame);
+}
+
+#[test]
+fn test_validate_position() {
+    // Positions that cannot come up in a game, and ones that can.
+    let problems = |fen: &str| validate_position(&Fen::parse(fen).unwrap());
+    assert_eq!(problems(START_POSITION_FEN), Vec::<String>::new());
+    assert_eq!(problems("rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2"), Vec::<String>::new());
+    assert_eq!(problems("8/8/8/8/8/8/8/K7 w - - 0 1"), vec!["Black has 0 kings instead of one"]);
+    assert_eq!(problems("k7/8/8/8/8/8/8/K6P w - - 0 1"), vec!["White has a pawn on h1"]);
+    assert_eq!(problems("k7/8/8/8/8/8/8/KQQQQQQ1 w - - 0 1").len(), 0);
+    assert_eq!(problems("k7/8/8/8/8/8/PPPPPPPP/KQQ5 w - - 0 1"), vec!["White has more promoted pieces than missing pawns"]);
+    assert_eq!(problems("k7/8/8/8/8/8/8/K6Q w - - 0 1"), vec!["Black is in check but not to move"]);
+    assert_eq!(problems("k7/8/1N6/8/8/8/8/K5RQ b - - 0 1").len(), 0);
+    assert_eq!(problems("k1R5/8/1N6/8/8/8/8/K6Q b - - 0 1"), vec!["Black is in check by 3 pieces"]);
+    assert_eq!(problems("4k3/8/8/8/8/8/8/4K3 w K - 0 1"), vec!["White may castle King side without the king and rook on their squares"]);
+    assert_eq!(problems("4k3/8/8/8/8/8/8/4K3 w - e6 0 1"), vec!["En passant on e6 does not follow a two-square pawn move"]);
+}
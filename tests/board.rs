@@ -1,48 +1,33 @@
-use crate::board::{ Board, CastlingSide, Color, Coordinates, File, Kind, Piece, Rank, Square };
-use crate::engine::{ is_threatened_by, next_boards, score_board };
-use crate::util::{ GrowTree };
-
-pub fn test_all() {
-    test_board_parser();
-    test_opening_moves();
-    test_en_passant();
-    test_threats();
-    test_castle();
-    test_check();
-    test_score();
-    test_display_board();
-    test_score_tree();
-    test_score_deep();
-    test_grow_tree();
-    println!("OK");
-}
-
-fn test_display_board() {
-    let mut board = Board::new();
-    board.setup();
+use chess::board::{ Board, CastlingSide, Color, Coordinates, Fen, File, Kind, Piece, Rank, ALL_KINDS, START_POSITION_FEN };
+use chess::engine::{ attacked_squares, has_legal_move, is_threatened_by, mask_coordinates, next_boards, perft, Move };
 
+#[test]
+fn test_board_parser() {
     let board_data = "
   +-v-------------v-+
 8 | ♜ ♞ ♝ ♛ ♚ ♝ ♞ ♜ |
-7 | ♟︎ ♟︎ ♟︎ ♟︎ ♟︎ ♟︎ ♟︎ ♟︎ |
+7 | ♟︎ ♟︎ ♟︎ ♟︎ ♟︎   ♟︎ ♟︎ |
 6 |                 |
-5 |                 |
-4 |                 |
-3 |                 |
-2 | ♙ ♙ ♙ ♙ ♙ ♙ ♙ ♙ |
+5 |     ♕           |
+4 |             ♙   |
+3 |             *   |
+2 | ♙ ♙ ♙ ♙ ♙ ♙   ♙ |
 1 | ♖ ♘ ♗ ♕ ♔ ♗ ♘ ♖ |
-  +-v-------------v-+
+  +-v---------------+
     a b c d e f g h";
 
-    assert_eq!(String::from(format!("{}", board).trim()), String::from(board_data.trim()));
+    let board = Board::parse_str(board_data);
+    assert_eq!(board.map(|board| String::from(format!("{}", board).trim())), Ok(String::from(board_data.trim())));
 }
 
+#[test]
 fn test_opening_moves() {
     let mut board = Board::new();
     board.setup();
     assert_eq!(20, next_boards(&board, Color::White).len());
 }
 
+#[test]
 fn test_en_passant() {
     // TODO: replace all this code with a simple board diagram, like above.
     // Just print it out and copy it.
@@ -64,6 +49,7 @@ fn test_en_passant() {
         .any(|_| true));
 }
 
+#[test]
 fn test_threats() {
     // Check pawns.
     let board = Board::parse_str("
@@ -126,6 +112,7 @@ fn test_threats() {
     assert_eq!(false, is_threatened_by(&board, Coordinates::new(File::E, Rank::R5), Color::White));
 }
 
+#[test]
 fn test_castle() {
     // White king's castle.
     let board = Board::parse_str("
@@ -167,6 +154,19 @@ fn test_castle() {
         .filter(|board| !board.is_castling_allowed(Color::White, CastlingSide::Queen))
         .any(|_| true));
 
+    // Not out of check, but past an attacked square next to the rook is fine.
+    for (fen, castles) in &[("4k3/8/8/8/8/8/8/R3K1r1 w Q - 0 1", false), ("4k3/8/8/8/8/8/8/R3K2r w Q - 0 1", false),
+            ("1r2k3/8/8/8/8/8/8/R3K3 w Q - 0 1", true), ("2r1k3/8/8/8/8/8/8/R3K3 w Q - 0 1", false)] {
+        let board = Fen::parse(fen).unwrap().board;
+        assert_eq!(*castles, Move::parse("e1c1").unwrap().apply(&board, Color::White).is_some());
+    }
+    // The king's side too.
+    for (fen, castles) in &[("4k3/8/8/8/8/8/8/4K2R w K - 0 1", true), ("4k3/8/8/8/8/8/8/r3K2R w K - 0 1", false),
+            ("4k3/8/8/8/8/8/8/4K1rR w K - 0 1", false), ("4k3/8/8/8/8/8/7r/4K2R w K - 0 1", true)] {
+        let board = Fen::parse(fen).unwrap().board;
+        assert_eq!(*castles, Move::parse("e1g1").unwrap().apply(&board, Color::White).is_some());
+    }
+
     // Black queen's castle.
     let board = Board::parse_str("
       +-v---------------+
@@ -188,6 +188,7 @@ fn test_castle() {
         .any(|_| true));
 }
 
+#[test]
 fn test_check() {
     // Test that we cannot move the king into check.
     let board = Board::parse_str("
@@ -220,103 +221,107 @@ fn test_check() {
     assert_eq!(2, next_boards(&board, Color::Black).len());
 }
 
-fn test_score() {
-    let mut board = Board::new();
-    assert_eq!(0, score_board(&board));
+#[test]
+fn test_pawn_captures() {
+    // Pawns capture diagonally forward, never sideways or backwards.
+    let board = Fen::parse("4k3/8/8/3p1p2/4P3/3p1p2/8/4K3 w - - 0 1").unwrap().board;
+    let captures: Vec<Move> = next_boards(&board, Color::White).iter()
+        .filter_map(|child| Move::between(&board, child, Color::White))
+        .filter(|child_move| child_move.from == Coordinates::new(File::E, Rank::R4) && child_move.to.file() != File::E)
+        .collect();
+    assert_eq!(2, captures.len());
+    assert!(captures.iter().all(|capture| capture.to.rank() == Rank::R5));
+    // The same for black, moving down the board.
+    let board = Fen::parse("4k3/8/8/5P2/4p3/3P1P2/8/4K3 b - - 0 1").unwrap().board;
+    let captures: Vec<Move> = next_boards(&board, Color::Black).iter()
+        .filter_map(|child| Move::between(&board, child, Color::Black))
+        .filter(|child_move| child_move.from == Coordinates::new(File::E, Rank::R4) && child_move.to.file() != File::E)
+        .collect();
+    assert_eq!(2, captures.len());
+    assert!(captures.iter().all(|capture| capture.to.rank() == Rank::R3));
+
+    // Pawns reaching the last rank promote, to any piece but a king.
+    let board = Fen::parse("1n2k3/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap().board;
+    let promotions: Vec<Move> = next_boards(&board, Color::White).iter()
+        .filter_map(|child| Move::between(&board, child, Color::White))
+        .filter(|child_move| child_move.promotion.is_some())
+        .collect();
+    assert_eq!(8, promotions.len());
+    assert_eq!(2, promotions.iter().filter(|promotion| promotion.promotion == Some(Kind::Queen)).count());
+}
 
-    board.setup();
-    assert_eq!(0, score_board(&board));
+#[test]
+fn test_move_counts() {
+    // Known counts from positions full of castling, promotions and checks.
+    for (fen, depth, expected) in &[
+        ("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", 2, 2039),
+        ("r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1", 3, 9467),
+        ("rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8", 2, 1486),
+        ("n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1", 3, 9483)] {
+        let fen = Fen::parse(fen).unwrap();
+        assert_eq!(*expected, perft(&fen.board, fen.color, *depth), "{}", fen);
+    }
+}
 
-    board.set_square(Coordinates::new(File::A, Rank::R1), Square::Empty);
-    assert_eq!(true, score_board(&board) < 0);
+#[test]
+fn test_attacked_squares() {
+    // The rook sees up to and including the first piece in each direction, of either color.
+    let board = Fen::parse("8/8/8/8/1P1R2p1/8/8/k2K4 w - - 0 1").unwrap().board;
+    let d4 = Coordinates::new(File::D, Rank::R4);
+    let attacked: Vec<String> = mask_coordinates(attacked_squares(&board, d4, Piece(Color::White, Kind::Rook)))
+        .map(|coordinates| coordinates.to_string()).collect();
+    assert_eq!(vec!["d1", "d2", "d3", "b4", "c4", "e4", "f4", "g4", "d5", "d6", "d7", "d8"], attacked);
+    // Pawns attack diagonally forward only, and not off the board.
+    let a2 = Coordinates::new(File::A, Rank::R2);
+    assert_eq!(1 << Coordinates::new(File::B, Rank::R3).index(), attacked_squares(&board, a2, Piece(Color::White, Kind::Pawn)));
+    assert_eq!(0, mask_coordinates(0).count());
+}
 
-    board.set_square(Coordinates::new(File::A, Rank::R8), Square::Empty);
-    assert_eq!(0, score_board(&board));
+#[test]
+fn test_has_legal_move() {
+    // Mate, stalemate, a king that can only take, and a king that cannot move but a rook far away can.
+    for (fen, expected) in &[
+        ("k7/1Q6/1K6/8/8/8/8/8 b - - 0 1", false),
+        ("k7/8/1QK5/8/8/8/8/8 b - - 0 1", false),
+        ("k7/1Q6/8/8/8/8/8/7K b - - 0 1", true),
+        ("7R/8/8/4k3/8/8/2q5/K7 w - - 0 1", true),
+        ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", true)] {
+        let fen = Fen::parse(fen).unwrap();
+        assert_eq!(*expected, has_legal_move(&fen.board, fen.color), "{}", fen);
+        assert_eq!(*expected, !next_boards(&fen.board, fen.color).is_empty(), "{}", fen);
+    }
+}
 
-    board.set_square(Coordinates::new(File::A, Rank::R7), Square::Empty);
-    assert_eq!(true, score_board(&board) > 0);
+#[test]
+fn test_start_position_fen() {
+    let fen = Fen::parse(START_POSITION_FEN).unwrap();
+    let mut board = Board::new();
+    board.setup();
+    assert_eq!(board, fen.board);
+    assert_eq!(Color::White, fen.color);
+    assert_eq!(START_POSITION_FEN, format!("{}", Fen::new(board, Color::White)));
+    for kind in ALL_KINDS.iter() {
+        assert_eq!(Some(*kind), Kind::new_from_index(kind.index()));
+    }
 }
 
-fn test_board_parser() {
+#[test]
+fn test_display_board() {
+    let mut board = Board::new();
+    board.setup();
+
     let board_data = "
   +-v-------------v-+
 8 | ♜ ♞ ♝ ♛ ♚ ♝ ♞ ♜ |
-7 | ♟︎ ♟︎ ♟︎ ♟︎ ♟︎   ♟︎ ♟︎ |
+7 | ♟︎ ♟︎ ♟︎ ♟︎ ♟︎ ♟︎ ♟︎ ♟︎ |
 6 |                 |
-5 |     ♕           |
-4 |             ♙   |
-3 |             *   |
-2 | ♙ ♙ ♙ ♙ ♙ ♙   ♙ |
+5 |                 |
+4 |                 |
+3 |                 |
+2 | ♙ ♙ ♙ ♙ ♙ ♙ ♙ ♙ |
 1 | ♖ ♘ ♗ ♕ ♔ ♗ ♘ ♖ |
-  +-v---------------+
+  +-v-------------v-+
     a b c d e f g h";
 
-    let board = Board::parse_str(board_data);
-    assert_eq!(board.map(|board| String::from(format!("{}", board).trim())), Ok(String::from(board_data.trim())));
-}
-
-fn test_grow_tree() {
-  let mut tree: GrowTree<&str> = GrowTree::new("root");
-  let aap_index = tree.add_child("aap", 0);
-  let schaap_index = tree.add_child("schaap", 0);
-  let aap1 = tree.add_child("aap 1", aap_index);
-
-  let mut tree2: GrowTree<&str> = GrowTree::new("root");
-  let aap_index = tree2.add_child("aap", 0);
-  let schaap_index = tree2.add_child("schaap", 0);
-  let aap1 = tree2.add_child("aap 1", aap_index);
-
-  tree.add_tree_into(schaap_index, tree2);
-  println!("{}", tree);
-
-  if let Some(subtree) = tree.subtree(aap_index) {
-    println!("{}", subtree);
-  }
-
-  // TODO: add some tests
-  if let Some(subtree) = tree.into_subtree(aap_index) {
-    println!("{}", subtree);
-  }
-}
-
-fn test_score_tree() {
-  /*
-  let mut tree = ScoreTree::new();
-  let index = tree.add(10, ScoreTarget::Lowest, None).unwrap();
-  let index_2 = tree.add(20, ScoreTarget::Lowest, Some(index)).unwrap();
-  let index_3 = tree.add(30, ScoreTarget::Highest, Some(index_2)).unwrap();
-  let index_3a = tree.add(31, ScoreTarget::Highest, Some(index_2)).unwrap();
-  let index_4 = tree.add(30, ScoreTarget::Lowest, Some(index)).unwrap();
-  let index_5 = tree.add(50, ScoreTarget::Highest, None).unwrap();
-  println!("{}", tree);
-  */
-}
-
-fn test_score_deep() {
-  // TODO: Take a look at this. White thinks it can checkmate, which it cannot!
-  let board = Board::parse_str("
-    +-----------------+
-  8 |             ♖   |
-  7 |                 |
-  6 |                 |
-  5 |                 |
-  4 |                 |
-  3 |                 |
-  2 |               ♚ |
-  1 |             ♖   |
-    +-----------------+
-      a b c d e f g h").unwrap();
-
-  /*
-  let mut steps_remaining = 10000;
-
-  let scored_boards = scored_next_boards(&board, Color::White, &mut || {
-    steps_remaining = steps_remaining - 1;
-    steps_remaining >= 0
-  });
-
-  for (score, board) in scored_boards {
-    println!("SCORE: {}\n{}\n\n", score, board);
-  }
-  */
+    assert_eq!(String::from(format!("{}", board).trim()), String::from(board_data.trim()));
 }
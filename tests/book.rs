@@ -0,0 +1,230 @@
+use chess::board::{ Board, Color, Coordinates, Fen, File, Kind, Piece, Rank, START_POSITION_FEN };
+use chess::book::{ decode_move, encode_move, load_pgn, parse_pgn, polyglot_key, BookBuilder, BookBuilderOptions, BookEntry, BookSelection, OpeningBook, PgnGame };
+use chess::engine::{ Move, Player, SearchOptions };
+
+#[test]
+fn test_polyglot_key() {
+    // Reference keys from the Polyglot specification.
+    for (fen, key) in &[
+        ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", 0x463b_9618_1691_fc9c),
+        ("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1", 0x823c_9b50_fd11_4196),
+        ("rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 2", 0x0756_b944_61c5_0fb0),
+        ("rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR b KQkq - 0 2", 0x662f_afb9_65db_29d4),
+        ("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3", 0x22a4_8b5a_8e47_ff78),
+        ("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPPKPPP/RNBQ1BNR b kq - 0 3", 0x652a_607c_a3f2_42c1),
+        ("rnbq1bnr/ppp1pkpp/8/3pPp2/8/8/PPPPKPPP/RNBQ1BNR w - - 0 4", 0x00fd_d303_c946_bdd9),
+        ("rnbqkbnr/p1pppppp/8/8/PpP4P/8/1P1PPPP1/RNBQKBNR b KQkq c3 0 3", 0x3c81_23ea_7b06_7637),
+        ("rnbqkbnr/p1pppppp/8/8/P6P/R1p5/1P1PPPP1/1NBQKBNR b Kkq - 0 4", 0x5c3f_9b82_9b27_9560),
+    ] {
+        let fen = Fen::parse(fen).unwrap();
+        assert_eq!(*key, polyglot_key(&fen.board, fen.color));
+    }
+}
+
+#[test]
+fn test_move_encoding() {
+    assert_eq!("e7e8q", format!("{}", Move::parse("e7e8q").unwrap()));
+    assert!(Move::parse("e7e9").is_err());
+    assert!(Move::parse("e7e8k").is_err());
+
+    // Castling is stored as the king taking its own rook.
+    let castling = castling_position();
+    let castle = Move::parse("e1g1").unwrap();
+    assert_eq!(Move::parse("e1h1").unwrap(), decode_move(encode_move(&castle, &castling), &Board::new()));
+    assert_eq!(castle, decode_move(encode_move(&castle, &castling), &castling));
+}
+
+// The position after 1. e4 e5 2. Nf3 Nc6 3. Bc4 Bc5, with White to castle.
+fn castling_position() -> Board {
+    Fen::parse("r1bqk1nr/pppp1ppp/2n5/2b1p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4").unwrap().board
+}
+
+// A small book: 1. e4 and 1. d4 from the start, and castling in the castling position.
+fn small_book() -> Vec<u8> {
+    let start = Fen::parse(START_POSITION_FEN).unwrap().board;
+    let castling = castling_position();
+    let start_key = polyglot_key(&start, Color::White);
+    let castling_key = polyglot_key(&castling, Color::White);
+    let e4 = Move::parse("e2e4").unwrap();
+    let d4 = Move::parse("d2d4").unwrap();
+    let castle = Move::parse("e1g1").unwrap();
+    let mut entries = [
+        BookEntry { key: start_key, raw_move: encode_move(&e4, &start), weight: 30, learn: 0 },
+        BookEntry { key: start_key, raw_move: encode_move(&d4, &start), weight: 10, learn: 0 },
+        // Not legal here, so never played.
+        BookEntry { key: start_key, raw_move: encode_move(&Move::parse("e2e5").unwrap(), &start), weight: 100, learn: 0 },
+        BookEntry { key: castling_key, raw_move: encode_move(&castle, &castling), weight: 1, learn: 0 },
+    ];
+    entries.sort_by_key(|entry| entry.key);
+    entries.iter().flat_map(|entry| entry.to_bytes().to_vec()).collect()
+}
+
+#[test]
+fn test_opening_book() {
+    let start = Fen::parse(START_POSITION_FEN).unwrap().board;
+    let castling = castling_position();
+    let start_key = polyglot_key(&start, Color::White);
+    let e4 = Move::parse("e2e4").unwrap();
+    let d4 = Move::parse("d2d4").unwrap();
+    let castle = Move::parse("e1g1").unwrap();
+    let bytes = small_book();
+    let reversed: Vec<u8> = bytes.chunks(16).rev().flatten().copied().collect();
+    assert!(OpeningBook::from_bytes(&reversed).is_err());
+    assert!(OpeningBook::from_bytes(&bytes[1..]).is_err());
+    let book = OpeningBook::from_bytes(&bytes).unwrap();
+    assert!(!book.is_empty());
+    assert_eq!(4, book.len());
+    assert_eq!(3, book.entries(start_key).len());
+    assert!(book.entries(start_key ^ 1).is_empty());
+
+    let moves: Vec<(Move, u16)> = book.moves(&start, Color::White).iter().map(|(book_move, _, weight)| (*book_move, *weight)).collect();
+    assert_eq!(vec![(e4, 30), (d4, 10)], moves);
+    assert_eq!(Some(e4), book.choose(&start, Color::White, BookSelection::BestWeight, 0).map(|(book_move, _)| book_move));
+    let random_choices: Vec<Move> = (0..40).filter_map(|random| book.choose(&start, Color::White, BookSelection::WeightedRandom, random))
+        .map(|(book_move, _)| book_move).collect();
+    assert_eq!(30, random_choices.iter().filter(|book_move| **book_move == e4).count());
+    assert_eq!(10, random_choices.iter().filter(|book_move| **book_move == d4).count());
+
+    let (_, castled) = book.choose(&castling, Color::White, BookSelection::BestWeight, 0).unwrap();
+    assert!(castled.get_square(Coordinates::new(File::G, Rank::R1)).is_occupied_by(Piece(Color::White, Kind::King)));
+    assert!(castled.get_square(Coordinates::new(File::F, Rank::R1)).is_occupied_by(Piece(Color::White, Kind::Rook)));
+    assert_eq!(Some(castle), Move::between(&castling, &castled, Color::White));
+    assert_eq!(Some(castled), castle.apply(&castling, Color::White));
+}
+
+#[test]
+fn test_book_player() {
+    // The player follows the book while it can, then searches.
+    let start = Fen::parse(START_POSITION_FEN).unwrap().board;
+    let castling = castling_position();
+    let e4 = Move::parse("e2e4").unwrap();
+    let bytes = small_book();
+    let path = std::env::temp_dir().join(format!("crate-book-{}.bin", std::process::id()));
+    std::fs::write(&path, &bytes).unwrap();
+    let loaded = OpeningBook::load(&path);
+    std::fs::remove_file(&path).unwrap();
+    let mut player = Player::new(1, SearchOptions::default()).with_book(loaded.unwrap(), BookSelection::BestWeight);
+    player.set_seed(7);
+    assert!(player.in_book());
+    assert_eq!(e4.apply(&start, Color::White), player.play(&start, Color::White));
+    let after_e4 = e4.apply(&start, Color::White).unwrap();
+    assert!(player.play(&after_e4, Color::Black).is_some());
+    assert!(!player.in_book());
+    // Back in a book position, but the game already left the book.
+    assert!(player.play(&castling, Color::White).is_some());
+    player.new_game();
+    assert_eq!(Move::parse("e1g1").unwrap().apply(&castling, Color::White), player.play(&castling, Color::White));
+}
+
+#[test]
+fn test_from_san() {
+    // Standard algebraic notation.
+    let board = Fen::parse("4k3/8/8/8/8/5N2/8/1N2K3 w - - 0 1").unwrap().board;
+    assert!(Move::from_san(&board, Color::White, "Nd2").is_err());
+    assert_eq!(Move::parse("b1d2").unwrap(), Move::from_san(&board, Color::White, "Nbd2").unwrap());
+    assert_eq!(Move::parse("f3d2").unwrap(), Move::from_san(&board, Color::White, "Nfd2+").unwrap());
+    let board = Fen::parse("4k3/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap().board;
+    assert_eq!(Move::parse("a7a8q").unwrap(), Move::from_san(&board, Color::White, "a8=Q+").unwrap());
+    assert_eq!(Move::parse("a7a8n").unwrap(), Move::from_san(&board, Color::White, "a8N").unwrap());
+    let board = Fen::parse("r3k3/8/8/8/8/8/8/4K3 b q - 0 1").unwrap().board;
+    assert_eq!(Move::parse("e8c8").unwrap(), Move::from_san(&board, Color::Black, "O-O-O").unwrap());
+    assert!(Move::from_san(&board, Color::Black, "O-O").is_err());
+}
+
+// A won game with comments and variations, a drawn one, a lost one and an unfinished one with an illegal move.
+fn sample_games() -> Vec<PgnGame> {
+    parse_pgn(r#"
+[Event "Test"]
+[White "Alice"]
+[Black "Bob"]
+[Result "1-0"]
+
+1. e4 e5 2. Nf3 {A comment (with parentheses)} Nc6 3. Bb5 (3. Bc4 Bc5 (3... Nf6)) a6 $1
+4. Ba4 Nf6 5. O-O 1-0
+
+[White "Bob"]
+[Black "Alice"]
+[Result "1/2-1/2"]
+
+1.e4 c5 ; The Sicilian
+2.Nf3 d6 1/2-1/2
+
+[White "Carol"]
+[Black "Dave"]
+[Result "0-1"]
+
+1. d4 d5 2. Qd3 0-1
+
+[White "Eve"]
+[Black "Mallory"]
+
+1. e4 e5 2. Ke3 *
+"#).unwrap()
+}
+
+fn book_moves(book: &OpeningBook, board: &Board, color: Color) -> Vec<(String, u16)> {
+    book.moves(board, color).iter().map(|(book_move, _, weight)| (book_move.to_string(), *weight)).collect()
+}
+
+#[test]
+fn test_parse_pgn() {
+    let games = sample_games();
+    assert_eq!(4, games.len());
+    assert_eq!(Some("Alice"), games[0].tag("White"));
+    assert_eq!("e4 e5 Nf3 Nc6 Bb5 a6 Ba4 Nf6 O-O", games[0].moves.join(" "));
+    assert_eq!(Some(0.5), games[1].white_score());
+    assert_eq!(None, games[3].white_score());
+}
+
+#[test]
+fn test_book_builder() {
+    let games = sample_games();
+    let build = |options: BookBuilderOptions| {
+        let mut builder = BookBuilder::new(options);
+        let added = games.iter().filter(|game| builder.add_game(game).is_ok()).count();
+        assert_eq!(3, added);
+        assert_eq!((3, 1), (builder.games, builder.skipped_games));
+        OpeningBook::from_bytes(&builder.to_bytes()).unwrap()
+    };
+    let start = Fen::parse(START_POSITION_FEN).unwrap().board;
+    let after_e4 = Move::parse("e2e4").unwrap().apply(&start, Color::White).unwrap();
+
+    // A win counts two, a draw one. The loss with 1. d4 leaves it out.
+    let book = build(BookBuilderOptions::default());
+    assert_eq!(vec![(String::from("e2e4"), 3)], book_moves(&book, &start, Color::White));
+    assert_eq!(vec![(String::from("c7c5"), 1)], book_moves(&book, &after_e4, Color::Black));
+    let (before_castling, color, _) = games[0].positions().unwrap()[8];
+    assert_eq!(vec![(String::from("e1g1"), 2)], book_moves(&book, &before_castling, color));
+
+    let book = build(BookBuilderOptions { max_ply: 8, ..BookBuilderOptions::default() });
+    assert!(book_moves(&book, &before_castling, color).is_empty());
+    let book = build(BookBuilderOptions { min_count: 2, ..BookBuilderOptions::default() });
+    assert!(book_moves(&book, &after_e4, Color::Black).is_empty());
+    assert_eq!(1, book.len());
+    let book = build(BookBuilderOptions { color: Some(Color::Black), ..BookBuilderOptions::default() });
+    assert!(book_moves(&book, &start, Color::White).is_empty());
+    assert_eq!(vec![(String::from("c7c5"), 1)], book_moves(&book, &after_e4, Color::Black));
+    let book = build(BookBuilderOptions { player: Some(String::from("Alice")), ..BookBuilderOptions::default() });
+    assert_eq!(vec![(String::from("e2e4"), 2)], book_moves(&book, &start, Color::White));
+    assert_eq!(vec![(String::from("c7c5"), 1)], book_moves(&book, &after_e4, Color::Black));
+}
+
+#[test]
+fn test_book_file() {
+    // Books are written sorted, and read back from disk.
+    let mut builder = BookBuilder::new(BookBuilderOptions::default());
+    let pgn_path = std::env::temp_dir().join(format!("crate-games-{}.pgn", std::process::id()));
+    let book_path = std::env::temp_dir().join(format!("crate-games-{}.bin", std::process::id()));
+    std::fs::write(&pgn_path, "1. e4 e5 2. Nf3 Nc6 1-0\n\n1. d4 d5 1/2-1/2\n").unwrap();
+    for game in load_pgn(&pgn_path).unwrap().iter() {
+        builder.add_game(game).unwrap();
+    }
+    let entries = builder.entries();
+    assert!(entries.windows(2).all(|pair| pair[0].key <= pair[1].key));
+    builder.write(&book_path).unwrap();
+    let book = OpeningBook::load(&book_path).unwrap();
+    std::fs::remove_file(&pgn_path).unwrap();
+    std::fs::remove_file(&book_path).unwrap();
+    let start = Fen::parse(START_POSITION_FEN).unwrap().board;
+    assert_eq!(vec![(String::from("e2e4"), 2), (String::from("d2d4"), 1)], book_moves(&book, &start, Color::White));
+}
@@ -0,0 +1,142 @@
+use std::io::{ Write };
+use std::process::{ Command, Stdio };
+
+use chess::board::{ Board, Color };
+use chess::book::{ BookSelection, OpeningBook };
+use chess::engine::{ EvaluationParameters };
+use chess::notation::{ Fen, Move, START_POSITION_FEN };
+
+// Run the binary with the arguments and standard input: standard output when it succeeds,
+// standard error when it fails.
+fn run(args: &[&str], input: &str) -> Result<String, String> {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_chess")).args(args)
+        .stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn().unwrap();
+    child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+    if output.status.success() {
+        Ok(String::from_utf8(output.stdout).unwrap())
+    } else {
+        assert_eq!(output.status.code(), Some(1));
+        Err(String::from_utf8(output.stderr).unwrap().trim_end().to_string())
+    }
+}
+
+#[test]
+fn test_perft_command() {
+    assert_eq!(run(&["perft", "startpos", "2"], ""), Ok(String::from("400\n")));
+    let output = run(&["perft", START_POSITION_FEN, "1", "--divide"], "").unwrap();
+    assert!(output.starts_with("a2a3: 1\na2a4: 1\n"));
+    assert!(output.ends_with("\nTotal: 20\n"));
+}
+
+#[test]
+fn test_eval_command() {
+    let output = run(&["eval", "startpos"], "").unwrap();
+    assert!(output.contains("\nKing safety "));
+    assert!(output.ends_with(" 0\n"));
+}
+
+#[test]
+fn test_bestmove_command() {
+    let output = run(&["bestmove", "k7/2K5/8/1Q6/8/8/8/8 w - - 0 1", "--depth", "3"], "").unwrap();
+    assert!(output == "b5b7\n" || output == "b5a6\n" || output == "b5b8\n");
+    assert!(run(&["bestmove", "startpos", "--time", "0.2"], "").is_ok());
+    assert_eq!(run(&["bestmove", "k7/1Q6/1K6/8/8/8/8/8 b - - 0 1"], ""), Err(String::from("No legal moves")));
+}
+
+#[test]
+fn test_convert_command() {
+    // Conversions between FEN, the Unicode diagram and the ASCII diagram, in both directions.
+    let start = Fen::parse(START_POSITION_FEN).unwrap();
+    let ascii = run(&["convert", "startpos", "--to", "ascii"], "").unwrap();
+    assert!(ascii.contains("\n8 | r n b q k b n r |\n7 | p p p p p p p p |\n6 | . . . . . . . . |\n"));
+    assert_eq!(Board::parse_str(&ascii), Ok(start.board));
+    assert_eq!(run(&["convert", "--color", "black"], &ascii), Ok(String::from("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1\n")));
+    let unicode = run(&["convert", "-", "--to", "unicode"], &ascii).unwrap();
+    assert_eq!(unicode, start.board.to_string());
+    assert_eq!(run(&["convert", "--to", "ascii"], &unicode), Ok(ascii));
+    assert!(run(&["convert", "startpos", "--to", "svg"], "").is_err());
+}
+
+#[test]
+fn test_validate_command() {
+    assert_eq!(run(&["validate", "startpos"], ""), Ok(String::from("valid\n")));
+    assert_eq!(run(&["validate", "-"], "k7/8/8/8/8/8/8/K6Q w - - 0 1"), Err(String::from("Black is in check but not to move")));
+}
+
+#[test]
+fn test_play_command() {
+    assert!(run(&["play", "--depth", "1"], "resign\n\n").unwrap().contains("You resign. Black wins."));
+}
+
+#[test]
+fn test_tune_command() {
+    let path = std::env::temp_dir().join(format!("crate-tune-{}.epd", std::process::id()));
+    std::fs::write(&path, "4k3/8/8/8/8/8/8/3QK3 w - - 0 1 1-0\n4k3/8/8/8/8/8/8/3QK3 b - - 0 1 1/2-1/2\n").unwrap();
+    let path = path.to_str().unwrap();
+    let weights = run(&["tune", path, "--only", "piece_values.queen", "--iterations", "2", "--step", "50"], "");
+    let rust = run(&["tune", path, "--iterations", "1", "--threads", "2", "--to", "rust"], "");
+    let unknown = run(&["tune", path, "--to", "json"], "");
+    std::fs::remove_file(path).unwrap();
+    // The output loads as a weights file, with the summary in a comment.
+    let weights = weights.unwrap();
+    assert!(weights.starts_with("# Error "));
+    assert!(EvaluationParameters::parse_weights(&weights).is_ok());
+    assert!(rust.unwrap().contains("\npub const TUNED_PARAMETERS: [i32; "));
+    assert_eq!(unknown, Err(String::from("Unknown format: json, expect weights or rust")));
+    assert!(run(&["tune", path], "").unwrap_err().starts_with(&format!("Cannot read {}: ", path)));
+}
+
+#[test]
+fn test_book_command() {
+    let directory = std::env::temp_dir();
+    let games_path = directory.join(format!("crate-book-{}.pgn", std::process::id()));
+    let book_path = directory.join(format!("crate-book-{}.bin", std::process::id()));
+    std::fs::write(&games_path, "[White \"Alice\"]\n[Black \"Bob\"]\n[Result \"1-0\"]\n\n1. e4 e5 2. Nf3 1-0\n\n\
+        [White \"Bob\"]\n[Black \"Alice\"]\n[Result \"0-1\"]\n\n1. d4 d5 2. c4 0-1\n\n\
+        [White \"Carol\"]\n[Black \"Dave\"]\n[Result \"1-0\"]\n\n1. e4 e5 2. Ke3 1-0\n").unwrap();
+    let (games, book) = (games_path.to_str().unwrap(), book_path.to_str().unwrap());
+    let summary = run(&["book", games, book, "--plies", "2", "--player", "Alice"], "");
+    let loaded = OpeningBook::load(book);
+    let invalid = run(&["book", games, book, "--color", "red"], "");
+    std::fs::remove_file(&games_path).unwrap();
+    std::fs::remove_file(&book_path).unwrap();
+
+    // Alice won with 1. e4 and with 1... d5, so the first two plies give one move each. The game with Ke3 is skipped.
+    assert_eq!(summary, Ok(String::from("2 entries from 2 games, 1 skipped\n")));
+    let book = loaded.unwrap();
+    let start = Fen::parse(START_POSITION_FEN).unwrap().board;
+    let (played, after_e4) = book.choose(&start, Color::White, BookSelection::BestWeight, 0).unwrap();
+    assert_eq!("e2e4", played.to_string());
+    assert!(book.choose(&after_e4, Color::Black, BookSelection::BestWeight, 0).is_none());
+    let after_d4 = Move::parse("d2d4").unwrap().apply(&start, Color::White).unwrap();
+    assert_eq!(Some(String::from("d7d5")), book.choose(&after_d4, Color::Black, BookSelection::BestWeight, 0).map(|(played, _)| played.to_string()));
+    assert_eq!(invalid, Err(String::from("Invalid color: red")));
+    assert_eq!(run(&["book", games], ""), Err(String::from("Usage: chess book <games.pgn> <book.bin> [--plies <n>] [--min-count <n>] [--color white|black] [--player <name>]")));
+}
+
+#[test]
+fn test_uci_go() {
+    // Only the moves after `searchmoves` are searched, and other parameters can follow them.
+    let output = run(&["uci"], "position startpos\ngo searchmoves h2h3 a2a3 depth 2\nquit\n").unwrap();
+    assert!(output.contains("\nbestmove h2h3 ") || output.contains("\nbestmove a2a3 "));
+    assert!(run(&["uci"], "position startpos\ngo searchmoves e2e4 depth 2\nquit\n").unwrap().contains("\nbestmove e2e4 "));
+    // Parameters that are not known, or not first, do not stop the search.
+    assert!(run(&["uci"], "position startpos\ngo mate 3\nquit\n").unwrap().contains("bestmove "));
+    assert!(run(&["uci"], "position startpos\ngo frobnicate depth 1\nquit\n").unwrap().contains("bestmove "));
+    // A position without both kings is turned away, and the last one stays.
+    let output = run(&["uci"], "position fen 8/8/8/8/8/8/8/8 w - - 0 1\ngo depth 1\nquit\n").unwrap();
+    assert!(output.starts_with("info string White has 0 kings instead of one\n"));
+    assert!(output.contains("\nbestmove ") && !output.contains("bestmove 0000"));
+}
+
+#[test]
+fn test_errors() {
+    // Errors go to standard error, with a failing exit code.
+    assert_eq!(run(&["validate", "8/8 w"], ""), Err(String::from("Expect 4 to 6 fields in FEN, got 2\n --> line 1, column 6\n  |\n1 | 8/8 w\n  |      ^")));
+    assert_eq!(run(&["perft", "startpos"], ""), Err(String::from("Usage: chess perft <fen> <depth> [--divide]")));
+    assert_eq!(run(&["perft", "startpos", "2", "--deep"], ""), Err(String::from("Unknown option: --deep")));
+    assert!(run(&["frobnicate"], "").unwrap_err().starts_with("Unknown command: frobnicate\n"));
+    assert!(run(&[], "").unwrap_err().starts_with("Usage: "));
+    assert!(run(&["help"], "").unwrap().contains("\n  validate <fen> "));
+}
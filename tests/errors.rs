@@ -0,0 +1,87 @@
+use chess::board::{ Board };
+use chess::book::{ OpeningBook };
+use chess::engine::{ EvaluationParameters, Network };
+use chess::notation::{ parse_pgn, Fen, Move, START_POSITION_FEN };
+use chess::tablebase::{ DtmTablebase, Tablebase };
+use chess::tuning::{ parse_labeled_positions };
+use chess::util::{ GrowTree };
+use chess::{ Error, ParseError, ParseErrorKind };
+
+#[test]
+fn test_error_kinds() {
+    // Callers can tell the kinds of failure apart, and still get the message.
+    let start = Fen::parse(START_POSITION_FEN).unwrap();
+    assert!(matches!(Fen::parse("8/8 w"), Err(Error::Parse(_))));
+    assert!(matches!(Move::parse("e2"), Err(Error::Parse(_))));
+    let illegal = Move::from_san(&start.board, start.color, "Ke2").unwrap_err();
+    assert_eq!(illegal, Error::IllegalMove(String::from("Illegal move: Ke2")));
+    assert_eq!(illegal.to_string(), "Illegal move: Ke2");
+    assert_eq!(String::from(illegal), "Illegal move: Ke2");
+    assert!(matches!(OpeningBook::load("/nonexistent/book.bin"), Err(Error::Io(_))));
+    assert!(matches!(OpeningBook::from_bytes(&[0; 3]), Err(Error::InvalidData(_))));
+    assert!(matches!(Network::from_bytes(b"not a network"), Err(Error::InvalidData(_))));
+    let mut tree = GrowTree::new(0u32);
+    assert!(matches!(tree.reroot(5), Err(Error::InvalidArgument(_))));
+    let missing = DtmTablebase::new().probe_wdl(&Fen::parse("4k3/8/8/8/8/8/8/3QK3 w - - 0 1").unwrap().board, start.color);
+    assert!(matches!(missing, Err(Error::NotFound(_))));
+
+    // The error works with the standard library's error handling.
+    let boxed: Box<dyn std::error::Error> = Box::new(Error::Io(String::from("Cannot read book.bin")));
+    assert_eq!(boxed.to_string(), "Cannot read book.bin");
+}
+
+// The parse error from the result, with its kind, line, column and offending text.
+fn parse_error<T: std::fmt::Debug>(result: Result<T, Error>) -> (ParseErrorKind, usize, usize, String) {
+    match result {
+        Err(Error::Parse(ParseError { kind, line, column, text, .. })) => (kind, line, column, text),
+        other => panic!("Expect a parse error, got {:?}", other)
+    }
+}
+
+#[test]
+fn test_parse_errors() {
+    let diagram = Board::new().to_string();
+    assert!(Board::parse_str(&format!("\n\n{}\n\n", diagram)).is_ok());
+    // A piece that is not one, on the rank 6 line, and a missing border.
+    let broken = diagram.replacen("6 |  ", "6 | x", 1);
+    assert_eq!(parse_error(Board::parse_str(&broken)), (ParseErrorKind::Unexpected, 4, 5, String::from("x")));
+    let broken = diagram.replacen("8 | ", "8 # ", 1);
+    assert_eq!(parse_error(Board::parse_str(&broken)), (ParseErrorKind::Unexpected, 2, 2, String::from(" ")));
+    assert_eq!(parse_error(Board::parse_str(&format!("{}{}", diagram, diagram))), (ParseErrorKind::LineCount, 12, 1, diagram.lines().next().unwrap().to_string()));
+    let short: String = diagram.lines().take(5).map(|line| format!("{}\n", line)).collect();
+    assert_eq!(parse_error(Board::parse_str(&short)), (ParseErrorKind::LineCount, 5, 22, String::new()));
+
+    assert_eq!(parse_error(Fen::parse("8/8 w")), (ParseErrorKind::FieldCount, 1, 6, String::new()));
+    assert_eq!(parse_error(Fen::parse("8/8 w - - 0 1")), (ParseErrorKind::FieldCount, 1, 4, String::new()));
+    assert_eq!(parse_error(Fen::parse("rnbqkbnr/ppppxppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1")), (ParseErrorKind::InvalidPiece, 1, 14, String::from("x")));
+    assert_eq!(parse_error(Fen::parse("rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1")), (ParseErrorKind::FieldCount, 1, 19, String::from("9")));
+    assert_eq!(parse_error(Fen::parse("8/8/8/8/8/8/8/8 w KX - 0 1")), (ParseErrorKind::Unexpected, 1, 20, String::from("X")));
+    assert_eq!(parse_error(Fen::parse("8/8/8/8/8/8/8/8 w - e9 0 1")), (ParseErrorKind::InvalidSquare, 1, 21, String::from("e9")));
+    assert_eq!(parse_error(Fen::parse("8/8/8/8/8/8/8/8 w - - x 1")), (ParseErrorKind::InvalidNumber, 1, 23, String::from("x")));
+    assert_eq!(parse_error(Move::parse("e2i4")), (ParseErrorKind::InvalidSquare, 1, 3, String::from("i4")));
+    assert_eq!(parse_error(Move::parse("e7e8k")), (ParseErrorKind::InvalidMove, 1, 5, String::from("k")));
+
+    let pgn = "[Event \"Casual\"]\n[Site Nowhere]\n\n1. e4 e5 *\n";
+    assert_eq!(parse_error(parse_pgn(pgn)), (ParseErrorKind::InvalidTag, 2, 7, String::from("Nowhere")));
+    let positions = "4k3/8/8/8/8/8/8/3QK3 w - - [1-0]\n4k3/8/8/8/8/8/8/3QK3 w - - [2-0]\n";
+    assert_eq!(parse_error(parse_labeled_positions(positions)), (ParseErrorKind::InvalidResult, 2, 29, String::from("2-0")));
+    let positions = "4k3/8/8/8/8/8/8/3QK3 w - - [1-0]\n  4k3/8/8/8/8/8/8/3QKX w - - [1-0]\n";
+    assert_eq!(parse_error(parse_labeled_positions(positions)), (ParseErrorKind::InvalidPiece, 2, 22, String::from("X")));
+    assert_eq!(parse_error(DtmTablebase::new().generate("KQ")), (ParseErrorKind::Missing, 1, 3, String::new()));
+    assert_eq!(parse_error(DtmTablebase::new().generate("QK")), (ParseErrorKind::Missing, 1, 1, String::new()));
+    assert_eq!(parse_error(DtmTablebase::new().generate("KXK")), (ParseErrorKind::InvalidPiece, 1, 2, String::from("X")));
+    assert_eq!(parse_error(DtmTablebase::new().generate("KQRRK")), (ParseErrorKind::Unsupported, 1, 1, String::from("KQRRK")));
+    assert_eq!(parse_error(EvaluationParameters::parse_weights("# Weights\nknight_value 300\n")).0, ParseErrorKind::UnknownName);
+    let name = EvaluationParameters::default().names()[0].clone();
+    assert_eq!(parse_error(EvaluationParameters::parse_weights(&format!("{} 10\n{} ten\n", name, name))), (ParseErrorKind::InvalidNumber, 2, name.len() + 2, String::from("ten")));
+
+    // The snippet shows the line and points at the offending text.
+    let input = "8/8/8/8/8/8/8/8 w KX - 0 1";
+    match Fen::parse(input) {
+        Err(Error::Parse(error)) => {
+            assert_eq!(error.to_string(), "Unexpected castling right: X at line 1, column 20");
+            assert_eq!(error.snippet(input), "Unexpected castling right: X\n --> line 1, column 20\n  |\n1 | 8/8/8/8/8/8/8/8 w KX - 0 1\n  |                    ^");
+        },
+        other => panic!("Expect a parse error, got {:?}", other)
+    }
+}
@@ -0,0 +1,352 @@
+use chess::board::{ Board, Color, Coordinates, File, Kind, Piece, Rank, Square };
+use chess::engine::{ evaluate_pawn_structure, king_safety, mobility_score, pawn_breakdown, score_board, search, Evaluator, KingSafetyWeights, MaterialEvaluator, MobilityWeights, Network, NnueEvaluator, PositionalEvaluator, SearchContext, SearchOptions, ZeroEvaluator };
+
+#[test]
+fn test_score() {
+    let mut board = Board::new();
+    assert_eq!(0, score_board(&board));
+
+    board.setup();
+    assert_eq!(0, score_board(&board));
+
+    board.set_square(Coordinates::new(File::A, Rank::R1), Square::Empty);
+    assert_eq!(true, score_board(&board) < 0);
+
+    board.set_square(Coordinates::new(File::A, Rank::R8), Square::Empty);
+    assert_eq!(0, score_board(&board));
+
+    board.set_square(Coordinates::new(File::A, Rank::R7), Square::Empty);
+    assert_eq!(true, score_board(&board) > 0);
+}
+
+#[test]
+fn test_piece_squares() {
+    // Centralized pieces are worth more than pieces on the rim.
+    let mut board = Board::new();
+    board.set_square(Coordinates::new(File::A, Rank::R1), Square::Occupied(Piece(Color::White, Kind::Knight)));
+    board.set_square(Coordinates::new(File::E, Rank::R5), Square::Occupied(Piece(Color::Black, Kind::Knight)));
+    assert!(score_board(&board) < 0);
+
+    // Flipping the colors negates the score.
+    let board = Board::parse_str("
+      +-v-------------v-+
+    8 | ♜   ♝ ♛ ♚ ♝   ♜ |
+    7 | ♟︎ ♟︎ ♟︎   ♟︎ ♟︎ ♟︎ ♟︎ |
+    6 |     ♞           |
+    5 |       ♟︎     ♘   |
+    4 |       ♙ ♞       |
+    3 |                 |
+    2 | ♙ ♙ ♙   ♙ ♙ ♙ ♙ |
+    1 | ♖   ♗ ♕ ♔ ♗   ♖ |
+      +---------------v-+
+        a b c d e f g h").unwrap();
+    assert_eq!(-score_board(&board), score_board(&board.mirror()));
+    assert_eq!(board, board.mirror().mirror());
+}
+
+#[test]
+fn test_pawn_structure() {
+    let board = Board::parse_str("
+      +-----------------+
+    8 |         ♚       |
+    7 | ♟︎           ♟︎   |
+    6 |             ♟︎   |
+    5 |                 |
+    4 |       ♙         |
+    3 |     ♙           |
+    2 |     ♙         ♙ |
+    1 |         ♔       |
+      +-----------------+
+        a b c d e f g h").unwrap();
+    let structure = evaluate_pawn_structure(&board);
+
+    // White has doubled c-pawns and an isolated h-pawn, black has doubled g-pawns and three isolated pawns.
+    assert_eq!(1, structure.white.doubled);
+    assert_eq!(1, structure.black.doubled);
+    assert_eq!(1, structure.white.isolated);
+    assert_eq!(3, structure.black.isolated);
+    // The front c-pawn, the d-pawn and the a-pawn are passed. Both sides have two pawn islands.
+    assert_eq!((1 << 18) | (1 << 27), structure.white.passed_pawns);
+    assert_eq!(1 << 48, structure.black.passed_pawns);
+    assert_eq!(1, structure.white.islands);
+    assert_eq!(1, structure.black.islands);
+}
+
+// Two connected passers on d5 and e5.
+fn connected_passers() -> Board {
+    Board::parse_str("
+      +-----------------+
+    8 |         ♚       |
+    7 |                 |
+    6 |                 |
+    5 |       ♙ ♙       |
+    4 |                 |
+    3 |                 |
+    2 |                 |
+    1 |         ♔       |
+      +-----------------+
+        a b c d e f g h").unwrap()
+}
+
+// The passers blocked by a knight in front of them, and free with the knight elsewhere.
+fn blocked_and_free() -> (Board, Board) {
+    let board = connected_passers();
+    let mut blocked = board;
+    blocked.set_square(Coordinates::new(File::D, Rank::R6), Square::Occupied(Piece(Color::Black, Kind::Knight)));
+    let mut free = board;
+    free.set_square(Coordinates::new(File::A, Rank::R6), Square::Occupied(Piece(Color::Black, Kind::Knight)));
+    (blocked, free)
+}
+
+#[test]
+fn test_passed_pawns() {
+    // Connected passers are worth more than split ones.
+    let board = connected_passers();
+    assert_eq!(2, evaluate_pawn_structure(&board).white.connected_passers);
+    assert!(pawn_breakdown(&board).white.connected_passers.endgame > 0);
+    assert!(format!("{}", pawn_breakdown(&board)).contains("connected passers"));
+
+    // A blocked passer is worth less.
+    let (blocked, free) = blocked_and_free();
+    assert!(pawn_breakdown(&blocked).white.passed.endgame < pawn_breakdown(&free).white.passed.endgame);
+    assert_eq!(-score_board(&blocked), score_board(&blocked.mirror()));
+}
+
+#[test]
+fn test_mobility() {
+    // A bishop on an open diagonal is more mobile than one hemmed in by its own pawns.
+    let board = Board::parse_str("
+      +-----------------+
+    8 |         ♚       |
+    7 | ♝               |
+    6 |   ♟︎             |
+    5 |                 |
+    4 |                 |
+    3 |                 |
+    2 |                 |
+    1 |     ♗   ♔       |
+      +-----------------+
+        a b c d e f g h").unwrap();
+    let score = mobility_score(&board, &MobilityWeights::default());
+    assert!(score.middlegame > 0);
+
+    // Squares controlled by enemy pawns don't count.
+    let mut covered = board;
+    covered.set_square(Coordinates::new(File::E, Rank::R5), Square::Occupied(Piece(Color::Black, Kind::Pawn)));
+    assert!(mobility_score(&covered, &MobilityWeights::default()).middlegame < score.middlegame);
+    assert_eq!(-mobility_score(&board, &MobilityWeights::default()), mobility_score(&board.mirror(), &MobilityWeights::default()));
+}
+
+#[test]
+fn test_king_safety() {
+    // A castled king behind its pawns is safer than a king in the center with open files.
+    let board = Board::parse_str("
+      +-----------------+
+    8 |       ♛ ♚       |
+    7 | ♟︎ ♟︎ ♟︎       ♟︎ ♟︎ |
+    6 |           ♞     |
+    5 |                 |
+    4 |                 |
+    3 |           ♘     |
+    2 | ♙ ♙ ♙       ♙ ♙ |
+    1 |     ♖ ♕     ♔   |
+      +-----------------+
+        a b c d e f g h").unwrap();
+    let weights = KingSafetyWeights::default();
+    assert!(king_safety(&board, Color::White, &weights).middlegame > king_safety(&board, Color::Black, &weights).middlegame);
+
+    // Attackers near the king make it less safe.
+    let mut attacked = board;
+    attacked.set_square(Coordinates::new(File::H, Rank::R4), Square::Occupied(Piece(Color::Black, Kind::Queen)));
+    attacked.set_square(Coordinates::new(File::D, Rank::R8), Square::Empty);
+    attacked.set_square(Coordinates::new(File::G, Rank::R4), Square::Occupied(Piece(Color::Black, Kind::Knight)));
+    assert!(king_safety(&attacked, Color::White, &weights).middlegame < king_safety(&board, Color::White, &weights).middlegame);
+    assert_eq!(king_safety(&board, Color::White, &weights), king_safety(&board.mirror(), Color::Black, &weights));
+}
+
+#[test]
+fn test_evaluators() {
+    let mut board = Board::new();
+    board.setup();
+    assert_eq!(0, MaterialEvaluator.evaluate(&board));
+    assert_eq!(score_board(&board), PositionalEvaluator::new().evaluate(&board));
+    board.set_square(Coordinates::new(File::D, Rank::R8), Square::Empty);
+    assert_eq!(900, MaterialEvaluator.evaluate(&board));
+    assert_eq!(score_board(&board), PositionalEvaluator::new().evaluate(&board));
+}
+
+// A queen on d5 that nothing defends, with a knight on e3 to take it.
+fn hanging_queen() -> Board {
+    Board::parse_str("
+      +-----------------+
+    8 |         ♚       |
+    7 |                 |
+    6 |                 |
+    5 |       ♛         |
+    4 |                 |
+    3 |         ♘       |
+    2 |                 |
+    1 |         ♔       |
+      +-----------------+
+        a b c d e f g h").unwrap()
+}
+
+#[test]
+fn test_swapped_evaluators() {
+    // Evaluators can be swapped in as boxed trait objects.
+    let board = hanging_queen();
+    let mut evaluator: Box<dyn Evaluator> = Box::new(MaterialEvaluator);
+    let result = search(&board, Color::White, &SearchOptions::default().with_depth(2), &mut SearchContext::new().with_evaluator(&mut evaluator));
+    assert_eq!(300, result.score);
+
+    // Without an evaluation, everything but mate is a draw.
+    let result = search(&board, Color::White, &SearchOptions::default().with_depth(2), &mut SearchContext::new().with_evaluator(&mut ZeroEvaluator));
+    assert_eq!(0, result.score);
+}
+
+#[test]
+fn test_make_unmake() {
+    // The search pairs every make with an unmake.
+    struct CountingEvaluator {
+        depth: i32,
+        max_depth: i32,
+    }
+
+    impl Evaluator for CountingEvaluator {
+        fn evaluate(&mut self, board: &Board) -> i32 {
+            MaterialEvaluator.evaluate(board)
+        }
+
+        fn make(&mut self, _parent: &Board, _child: &Board) {
+            self.depth += 1;
+            self.max_depth = self.max_depth.max(self.depth);
+        }
+
+        fn unmake(&mut self, _parent: &Board, _child: &Board) {
+            self.depth -= 1;
+        }
+    }
+
+    let mut evaluator = CountingEvaluator { depth: 0, max_depth: 0 };
+    search(&hanging_queen(), Color::White, &SearchOptions::default().with_depth(3), &mut SearchContext::new().with_evaluator(&mut evaluator));
+    assert_eq!(0, evaluator.depth);
+    assert!(evaluator.max_depth >= 3);
+}
+
+// A network with pseudo-random weights. The accumulator sizes are not multiples of the SIMD width on purpose.
+fn random_network() -> Network {
+    let mut state: u32 = 12345;
+    let mut random = |range: i32| {
+        state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        ((state >> 16) as i32 % (2 * range + 1)) - range
+    };
+    let mut network = Network::new(40, 6);
+    network.input_weights.iter_mut().for_each(|weight| *weight = random(40) as i16);
+    network.input_biases.iter_mut().for_each(|bias| *bias = random(40) as i16 + 40);
+    network.hidden_weights.iter_mut().for_each(|weight| *weight = random(60) as i8);
+    network.hidden_biases.iter_mut().for_each(|bias| *bias = random(500));
+    network.output_weights.iter_mut().for_each(|weight| *weight = random(120) as i8);
+    network.output_bias = random(1000);
+    network
+}
+
+#[test]
+fn test_nnue_file() {
+    let network = random_network();
+    let bytes = network.to_bytes();
+    assert_eq!(network, Network::from_bytes(&bytes).unwrap());
+    assert!(Network::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    assert!(Network::from_bytes(&[&bytes[..], &[0]].concat()).is_err());
+    assert!(Network::from_bytes(b"NNUF").is_err());
+
+    let path = std::env::temp_dir().join(format!("crate-nnue-{}.bin", std::process::id()));
+    std::fs::write(&path, &bytes).unwrap();
+    let loaded = NnueEvaluator::load(&path);
+    std::fs::remove_file(&path).unwrap();
+    let mut start = Board::new();
+    start.setup();
+    assert_eq!(NnueEvaluator::new(network).evaluate(&start), loaded.unwrap().evaluate(&start));
+}
+
+#[test]
+fn test_nnue() {
+    let network = random_network();
+    let mut evaluator = NnueEvaluator::new(network.clone());
+    let mut scalar = NnueEvaluator::new(network);
+    scalar.set_simd(false);
+    assert!(!scalar.simd());
+
+    // Incremental updates during a search match a full refresh, with and without SIMD.
+    struct CheckedEvaluator {
+        incremental: NnueEvaluator,
+        reference: NnueEvaluator,
+        evaluations: u32,
+    }
+
+    impl Evaluator for CheckedEvaluator {
+        fn evaluate(&mut self, board: &Board) -> i32 {
+            let score = self.incremental.evaluate(board);
+            self.reference.reset(board);
+            assert_eq!(self.reference.evaluate(board), score);
+            self.evaluations += 1;
+            score
+        }
+
+        fn reset(&mut self, root: &Board) {
+            self.incremental.reset(root);
+        }
+
+        fn make(&mut self, parent: &Board, child: &Board) {
+            self.incremental.make(parent, child);
+        }
+
+        fn unmake(&mut self, parent: &Board, child: &Board) {
+            self.incremental.unmake(parent, child);
+        }
+    }
+
+    let board = Board::parse_str("
+      +-v-------------v-+
+    8 | ♜       ♚     ♜ |
+    7 | ♟︎ ♟︎ ♟︎   ♛ ♟︎ ♟︎ ♟︎ |
+    6 |     ♞ ♝   ♞     |
+    5 |       ♟︎ ♟︎       |
+    4 |       ♙ ♙       |
+    3 |     ♘ ♗   ♘     |
+    2 | ♙ ♙ ♙   ♕ ♙ ♙ ♙ |
+    1 | ♖       ♔     ♖ |
+      +-v-------------v-+
+        a b c d e f g h").unwrap();
+    let mut checked = CheckedEvaluator { incremental: evaluator, reference: scalar, evaluations: 0 };
+    search(&board, Color::White, &SearchOptions::default().with_depth(2), &mut SearchContext::new().with_evaluator(&mut checked));
+    assert!(checked.evaluations > 100);
+
+    evaluator = checked.incremental;
+    scalar = checked.reference;
+    evaluator.reset(&board);
+    assert_eq!(scalar.evaluate(&board), evaluator.evaluate(&board));
+    // Evaluating an unrelated board falls back to a full refresh.
+    let mut start = Board::new();
+    start.setup();
+    assert_eq!(scalar.evaluate(&start), evaluator.evaluate(&start));
+}
+
+#[test]
+fn test_nnue_extreme_weights() {
+    // Biases at the ends of their range saturate the neurons instead of overflowing.
+    let mut network = Network::new(1, 1);
+    network.input_biases = vec![127];
+    network.hidden_weights = vec![127, 127];
+    network.output_weights = vec![127];
+    let mut start = Board::new();
+    start.setup();
+    for (bias, expected) in &[(i32::MAX, (i32::MAX as i64 + 127 * 127) / 16), (i32::MIN, i32::MIN as i64 / 16)] {
+        network.hidden_biases = vec![*bias];
+        network.output_bias = *bias;
+        let mut evaluator = NnueEvaluator::new(network.clone());
+        assert_eq!(*expected, evaluator.evaluate(&start) as i64);
+        evaluator.set_simd(false);
+        evaluator.reset(&start);
+        assert_eq!(*expected, evaluator.evaluate(&start) as i64);
+    }
+}
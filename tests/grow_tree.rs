@@ -0,0 +1,141 @@
+use chess::util::{ GrowTree };
+
+// Two small trees, the second grafted onto the first:
+// root (0) -> aap (1) -> aap 1 (3), and schaap (2) -> root 2 (4) -> aap (5) -> aap 1 (6), and schaap (7).
+fn sample_tree() -> GrowTree<&'static str> {
+  let mut tree: GrowTree<&str> = GrowTree::new("root");
+  let aap_index = tree.add_child("aap", 0);
+  let schaap_index = tree.add_child("schaap", 0);
+  tree.add_child("aap 1", aap_index);
+
+  let mut tree2: GrowTree<&str> = GrowTree::new("root 2");
+  let aap_index = tree2.add_child("aap", 0);
+  tree2.add_child("schaap", 0);
+  tree2.add_child("aap 1", aap_index);
+
+  tree.add_tree_into(schaap_index, tree2);
+  tree
+}
+
+fn indices<'a>(nodes: impl Iterator<Item = (usize, &'a &'static str)>) -> Vec<usize> {
+  nodes.map(|(index, _)| index).collect()
+}
+
+fn items<'a>(nodes: impl Iterator<Item = (usize, &'a &'static str)>) -> Vec<&'static str> {
+  nodes.map(|(_, item)| *item).collect()
+}
+
+#[test]
+fn test_grow_tree() {
+  let mut tree = sample_tree();
+  assert_eq!(&"root", tree.root());
+  assert_eq!(Some(&"aap 1"), tree.item(3));
+  assert_eq!(Some((0, &"root")), tree.parent(1));
+  assert_eq!(Some(&3), tree.map(|item| item.len()).item(1));
+  if let Some(item) = tree.item_mut(4) {
+    *item = "wortel";
+  }
+  assert_eq!(vec![(4, &"wortel")], tree.children(2).collect::<Vec<_>>());
+  assert_eq!(&[4], tree.child_indices(2));
+  assert_eq!(8, tree.len());
+  assert!(!tree.is_empty());
+}
+
+#[test]
+fn test_subtrees() {
+  let tree = sample_tree();
+  let subtree = tree.subtree(2).unwrap();
+  assert_eq!(vec![&"schaap", &"root 2", &"aap", &"aap 1", &"schaap"], subtree.dfs(0).map(|(_, item)| *item).collect::<Vec<_>>());
+  assert!(tree.subtree(100).is_none());
+
+  let owned = tree.into_subtree(1).unwrap();
+  assert_eq!(vec!["aap", "aap 1"], items(owned.dfs(0)));
+  assert_eq!("GrowTree {\n  -- aap\n   |-- aap 1\n}\n", owned.to_string());
+}
+
+#[test]
+fn test_traversals() {
+  let tree = sample_tree();
+  let dfs = tree.dfs(0);
+  assert_eq!(vec!["root", "aap", "aap 1", "schaap", "root 2", "aap", "aap 1", "schaap"], items(dfs));
+  let bfs = tree.bfs(0);
+  assert_eq!(vec![0, 1, 2, 3, 4, 5, 7, 6], indices(bfs));
+  assert_eq!(vec![4, 5, 7, 6], indices(tree.bfs(4)));
+  assert_eq!(vec![3, 6, 7], indices(tree.leaves(0)));
+  assert!(tree.is_leaf(3) && !tree.is_leaf(4));
+  assert_eq!(0, tree.dfs(100).count());
+}
+
+#[test]
+fn test_ancestors() {
+  let tree = sample_tree();
+  let ancestors = tree.ancestors(6);
+  assert_eq!(vec![5, 4, 2, 0], indices(ancestors));
+  assert_eq!(vec![6, 5, 4, 2, 0], indices(tree.path_to_root(6)));
+  assert_eq!(0, tree.ancestors(0).count());
+  assert_eq!((Some(0), Some(4), None), (tree.depth(0), tree.depth(6), tree.depth(100)));
+}
+
+#[test]
+fn test_remove_subtree() {
+  // Removing moves the later nodes down.
+  let mut removed = sample_tree();
+  assert_eq!(Ok(vec![Some(0), Some(1), Some(2), Some(3), Some(4), None, None, Some(5)]), removed.remove_subtree(5));
+  assert_eq!(6, removed.len());
+  assert_eq!(vec![(5, &"schaap")], removed.children(4).collect::<Vec<_>>());
+  assert_eq!(Some((4, &"root 2")), removed.parent(5));
+  assert_eq!(vec![0, 1, 3, 2, 4, 5], indices(removed.dfs(0)));
+  assert!(removed.remove_subtree(0).is_err());
+  assert!(removed.remove_subtree(6).is_err());
+}
+
+#[test]
+fn test_retain() {
+  // Retaining removes whole subtrees and keeps the root.
+  let mut retained = sample_tree();
+  let new_indices = retained.retain(|_, item| !item.starts_with("aap"));
+  assert_eq!(vec![Some(0), None, Some(1), None, Some(2), None, None, Some(3)], new_indices);
+  assert_eq!(vec!["root", "schaap", "root 2", "schaap"], items(retained.dfs(0)));
+  assert_eq!(Some(3), retained.depth(3));
+
+  let mut only_root = sample_tree();
+  only_root.retain(|_, _| false);
+  assert_eq!(1, only_root.len());
+  assert_eq!("GrowTree {\n  -- root\n}\n", only_root.to_string());
+}
+
+#[test]
+fn test_release_subtree() {
+  // Released slots are reused, and the other indices stay valid.
+  let mut recycled = sample_tree().with_max_nodes(8);
+  assert_eq!(Some(8), recycled.max_nodes());
+  assert!(recycled.is_full());
+  assert_eq!(Ok(2), recycled.release_subtree(5));
+  assert!(!recycled.is_full() && !recycled.contains(5) && !recycled.contains(6) && recycled.contains(7));
+  assert_eq!((None, None), (recycled.item(6), recycled.parent(6)));
+  assert_eq!(vec![(7, &"schaap")], recycled.children(4).collect::<Vec<_>>());
+  assert_eq!(5, recycled.add_child("koe", 7));
+  assert_eq!(6, recycled.add_child("kalf", 0));
+  assert_eq!(8, recycled.add_child("geit", 0));
+  assert_eq!(vec![1, 2, 6, 8], recycled.child_indices(0));
+  assert_eq!(Some(4), recycled.depth(5));
+  assert!(recycled.release_subtree(0).is_err());
+  assert!(recycled.release_subtree(100).is_err());
+
+  // Rerooting a tree with released slots keeps the subtree, its root first.
+  assert_eq!(Ok(vec![None, None, None, None, Some(0), Some(1), None, Some(2), None]), recycled.reroot(4));
+  assert_eq!(vec!["root 2", "schaap", "koe"], items(recycled.dfs(0)));
+  assert_eq!((None, Some((2, &"schaap"))), (recycled.parent(0), recycled.parent(1)));
+  assert!(recycled.reroot(3).is_err());
+}
+
+#[test]
+fn test_reroot() {
+  let mut rerooted = sample_tree();
+  assert_eq!(Ok(vec![None, None, None, None, Some(0), Some(1), Some(2), Some(3)]), rerooted.reroot(4));
+  assert_eq!(vec!["root 2", "aap", "aap 1", "schaap"], items(rerooted.dfs(0)));
+  assert_eq!((None, Some((1, &"aap"))), (rerooted.parent(0), rerooted.parent(2)));
+  assert!(rerooted.reroot(100).is_err());
+  let lengths: GrowTree<usize> = rerooted.map_into(|item| item.len());
+  assert_eq!(vec![6, 3, 5, 6], lengths.dfs(0).map(|(_, length)| *length).collect::<Vec<_>>());
+}
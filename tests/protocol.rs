@@ -0,0 +1,314 @@
+use chess::board::{ Color, Fen, FlippedBoard, START_POSITION_FEN };
+use chess::book::{ encode_move, load_pgn, parse_pgn, polyglot_key, BookEntry, PgnGame };
+use chess::engine::{ next_boards, Move };
+use chess::protocol::{ run_play, run_session, run_uci, run_xboard, PlayOptions, Session, UciEngine, XboardEngine };
+
+fn uci_session(script: &str) -> Vec<String> {
+    let output = run_uci(std::io::Cursor::new(script.to_string()), Vec::new());
+    String::from_utf8(output).unwrap().lines().map(String::from).collect()
+}
+
+fn best_move(lines: &[String]) -> &str {
+    let line = lines.iter().rev().find(|line| line.starts_with("bestmove")).unwrap();
+    line.split_whitespace().nth(1).unwrap()
+}
+
+fn is_legal(fen: &str, text: &str) -> bool {
+    let fen = Fen::parse(fen).unwrap();
+    Move::parse(text).unwrap().apply(&fen.board, fen.color).is_some()
+}
+
+#[test]
+fn test_uci_handshake() {
+    let lines = uci_session("uci\nisready\n");
+    assert!(lines[0].starts_with("id name "));
+    assert!(lines.iter().any(|line| line.starts_with("option name MultiPV type spin")));
+    assert_eq!(lines[lines.len() - 2], "uciok");
+    assert_eq!(lines[lines.len() - 1], "readyok");
+
+    // Nothing is read or answered after quit.
+    assert_eq!(uci_session("quit\nisready\n"), Vec::<String>::new());
+
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let mut engine = UciEngine::new(sender);
+    assert!(engine.handle("isready"));
+    assert!(!engine.handle("quit"));
+    assert_eq!(receiver.try_recv().unwrap(), "readyok");
+}
+
+#[test]
+fn test_uci_search() {
+    // Moves from the start position, and the info lines before the best move.
+    let lines = uci_session("ucinewgame\nposition startpos moves e2e4 e7e5 g1f3\ngo depth 2\n");
+    assert!(lines[0].starts_with("info depth 1 score cp "));
+    assert!(lines.iter().any(|line| line.starts_with("info depth 2 ") && line.contains(" pv ")));
+    assert!(lines.last().unwrap().starts_with("bestmove "));
+    assert!(is_legal("rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2", best_move(&lines)));
+
+    let lines = uci_session("setoption name multipv value 3\nposition startpos\ngo depth 1\n");
+    assert!(lines.iter().any(|line| line.starts_with("info depth 1 multipv 3 ")));
+
+    // Errors are reported without ending the session.
+    let lines = uci_session("position fen 8/8/8 w - - 0 1\nposition startpos moves e2e5\nsetoption name Colour value red\nfly\nisready\n");
+    assert_eq!(lines.iter().filter(|line| line.starts_with("info string ")).count(), 4);
+    assert_eq!(lines.last().unwrap(), "readyok");
+}
+
+#[test]
+fn test_uci_mate() {
+    let mate = "k7/2K5/8/1Q6/8/8/8/8 w - - 0 1";
+    let lines = uci_session(&format!("position fen {}\ngo depth 3\n", mate));
+    assert!(lines.iter().any(|line| line.contains(" score mate 1 ")));
+    let fen = Fen::parse(mate).unwrap();
+    let mated = Move::parse(best_move(&lines)).unwrap().apply(&fen.board, fen.color).unwrap();
+    assert!(next_boards(&mated, Color::Black).is_empty());
+
+    // Getting mated counts down in negative moves, and with no moves left the null move is sent.
+    let lines = uci_session("position fen k7/8/1K6/8/8/8/8/7Q b - - 0 1\ngo depth 3\n");
+    assert!(lines.iter().any(|line| line.contains(" score mate -1 ")));
+    let lines = uci_session("position fen k7/1Q6/1K6/8/8/8/8/8 b - - 0 1\ngo depth 2\n");
+    assert_eq!(lines.last().unwrap(), "bestmove 0000");
+}
+
+#[test]
+fn test_uci_limits() {
+    // Infinite searches end on stop and at the end of the input, and quickly.
+    let start = std::time::Instant::now();
+    let lines = uci_session("position startpos\ngo infinite\nstop\n");
+    assert!(is_legal(START_POSITION_FEN, best_move(&lines)));
+    let lines = uci_session("position startpos\ngo infinite\n");
+    assert!(is_legal(START_POSITION_FEN, best_move(&lines)));
+    assert!(start.elapsed() < std::time::Duration::from_secs(5));
+
+    // A ponder search waits for ponderhit, then plays on the clock.
+    let lines = uci_session("position startpos moves d2d4\ngo ponder wtime 1000 btime 1000\nponderhit\n");
+    assert!(is_legal("rnbqkbnr/pppppppp/8/8/3P4/8/PPP1PPPP/RNBQKBNR b KQkq - 0 1", best_move(&lines)));
+
+    // Limits on time and nodes.
+    let lines = uci_session("position startpos\ngo movetime 100\n");
+    assert!(is_legal(START_POSITION_FEN, best_move(&lines)));
+    let lines = uci_session("position startpos\ngo nodes 500 wtime -20 btime 1000 winc 0 binc 0 movestogo 5\n");
+    assert!(is_legal(START_POSITION_FEN, best_move(&lines)));
+}
+
+fn xboard_session(script: &str) -> Vec<String> {
+    let output = run_xboard(std::io::Cursor::new(script.to_string()), Vec::new());
+    String::from_utf8(output).unwrap().lines().map(String::from).collect()
+}
+
+fn moves(lines: &[String]) -> Vec<&str> {
+    lines.iter().filter_map(|line| line.strip_prefix("move ")).collect()
+}
+
+#[test]
+fn test_xboard_handshake() {
+    let lines = xboard_session("xboard\nprotover 2\nping 7\n");
+    assert!(lines[0].starts_with("feature myname=\"chess "));
+    assert!(lines[0].contains(" usermove=1 ") && lines[0].contains(" setboard=1 ") && lines[0].ends_with(" done=1"));
+    assert_eq!(lines[1], "pong 7");
+
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let mut engine = XboardEngine::new(sender);
+    assert!(engine.handle("ping 1"));
+    assert!(!engine.handle("quit"));
+    assert_eq!(receiver.try_recv().unwrap(), "pong 1");
+}
+
+#[test]
+fn test_xboard_game() {
+    // The engine plays Black after new, and answers with thinking output when posting.
+    let lines = xboard_session("new\npost\nsd 2\nlevel 40 5 0\ntime 30000\nusermove e2e4\n");
+    assert!(lines[0].starts_with("1 "));
+    assert!(lines[0].split_whitespace().count() >= 5);
+    assert!(lines.iter().any(|line| line.starts_with("2 ")));
+    let reply = moves(&lines);
+    assert_eq!(reply.len(), 1);
+    let fen = Fen::parse("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1").unwrap();
+    assert!(Move::parse(reply[0]).unwrap().apply(&fen.board, fen.color).is_some());
+
+    // In force mode moves are only recorded, and go makes the engine play the side to move.
+    let lines = xboard_session("new\nforce\ne2e4\ne7e5\nsd 1\nnopost\ngo\n");
+    assert_eq!(lines.len(), 1);
+    let fen = Fen::parse("rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2").unwrap();
+    assert!(Move::parse(moves(&lines)[0]).unwrap().apply(&fen.board, fen.color).is_some());
+
+    // After force, result and quit the engine does not move.
+    assert!(moves(&xboard_session("new\nst 30\nusermove d2d4\nforce\n")).is_empty());
+    assert!(moves(&xboard_session("new\nst 30\nusermove d2d4\nresult 1-0 {White resigns}\n")).is_empty());
+    assert!(moves(&xboard_session("new\nst 30\nusermove d2d4\nquit\n")).is_empty());
+}
+
+#[test]
+fn test_xboard_results() {
+    // Mate is found, played and announced, with a mate score in the thinking output.
+    let lines = xboard_session("force\nsetboard k7/2K5/8/1Q6/8/8/8/8 w - - 0 1\npost\nst 1\nsd 3\ngo\n");
+    assert!(lines.iter().any(|line| line.split_whitespace().nth(1) == Some("100001")));
+    assert_eq!(lines.last().unwrap(), "1-0 {White mates}");
+    let fen = Fen::parse("k7/2K5/8/1Q6/8/8/8/8 w - - 0 1").unwrap();
+    let mated = Move::parse(moves(&lines)[0]).unwrap().apply(&fen.board, fen.color).unwrap();
+    assert!(next_boards(&mated, Color::Black).is_empty());
+
+    // A move into stalemate is announced, and undo and remove take moves back.
+    let lines = xboard_session("force\nsetboard k7/8/1K6/8/8/8/8/2Q5 w - - 0 1\nc1c7\nundo\nremove\nc1c8\n");
+    assert_eq!(lines, vec!["1/2-1/2 {Stalemate}", "Error (no moves to take back): remove", "1-0 {White mates}"]);
+    assert_eq!(moves(&xboard_session("force\nsetboard k7/8/1K6/8/8/8/8/2Q5 w - - 0 1\nc1c8\nundo\nsd 1\ngo\n")).len(), 1);
+}
+
+#[test]
+fn test_xboard_errors() {
+    // Errors, and a move now that still plays a legal move.
+    let lines = xboard_session("new\nusermove e2e5\nsetboard 8/8 w\nlevel 40 x 0\nst inf\nfly\n");
+    assert_eq!(lines, vec!["Illegal move: e2e5", "tellusererror Illegal position", "Error (invalid time control): level 40 x 0",
+        "Error (invalid time): st inf", "Error (unknown command): fly"]);
+    let lines = xboard_session("new\nst 30\nusermove d2d4\n?\n");
+    assert_eq!(moves(&lines).len(), 1);
+
+    // The engine keeps its own moves, so the game goes on after them.
+    let output = run_session::<XboardEngine, _, _>(std::io::Cursor::new("new\nsd 1\ne2e4\nforce\nundo\nundo\nundo\n"), Vec::new());
+    let lines: Vec<String> = String::from_utf8(output).unwrap().lines().map(String::from).collect();
+    assert_eq!(lines.last().unwrap(), "Error (no moves to take back): undo");
+    assert_eq!(lines.len(), 2);
+}
+
+fn play_session(script: &str, options: &PlayOptions) -> (PgnGame, String) {
+    let mut output = Vec::new();
+    let game = run_play(std::io::Cursor::new(script.to_string()), &mut output, options).unwrap();
+    (game, String::from_utf8(output).unwrap())
+}
+
+#[test]
+fn test_san() {
+    // Moves in standard algebraic notation, with the details that make them unambiguous.
+    let san = |fen: &str, text: &str| {
+        let fen = Fen::parse(fen).unwrap();
+        Move::parse(text).unwrap().to_san(&fen.board, fen.color).unwrap()
+    };
+    assert_eq!(san(START_POSITION_FEN, "g1f3"), "Nf3");
+    assert_eq!(san("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", "e1c1"), "O-O-O");
+    assert_eq!(san("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1", "e8g8"), "O-O");
+    assert_eq!(san("4k3/8/8/8/8/8/8/R4RK1 w - - 0 1", "a1d1"), "Rad1");
+    assert_eq!(san("4k3/8/8/R7/8/8/8/R3K3 w - - 0 1", "a1a3"), "R1a3");
+    assert_eq!(san("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6"), "exd6");
+    assert_eq!(san("3rk3/2P5/8/8/8/8/8/4K3 w - - 0 1", "c7d8q"), "cxd8=Q+");
+    assert_eq!(san("k7/2K5/8/1Q6/8/8/8/8 w - - 0 1", "b5b7"), "Qb7#");
+    let fen = Fen::parse(START_POSITION_FEN).unwrap();
+    assert!(Move::parse("e2e5").unwrap().to_san(&fen.board, fen.color).is_err());
+}
+
+#[test]
+fn test_pgn() {
+    // PGN is written with move numbers from the starting position and read back the same.
+    let game = PgnGame { tags: vec![(String::from("FEN"), String::from("4k3/8/8/8/8/8/8/R3K3 b - - 0 12"))],
+        moves: vec![String::from("Kd7"), String::from("Ra7+")], result: String::from("*") };
+    assert_eq!(game.to_string(), "[FEN \"4k3/8/8/8/8/8/8/R3K3 b - - 0 12\"]\n\n12... Kd7 13. Ra7+ *\n");
+    assert_eq!(parse_pgn(&game.to_string()).unwrap(), vec![game]);
+}
+
+#[test]
+fn test_flipped_board() {
+    // The board seen from Black's side.
+    let board = Fen::parse("r3k3/8/8/8/8/8/8/4K2R w Kq - 0 1").unwrap().board;
+    let flipped: FlippedBoard = board.flipped();
+    assert_eq!(flipped.to_string(), "  +-v---------------+
+1 | ♖     ♔         |
+2 |                 |
+3 |                 |
+4 |                 |
+5 |                 |
+6 |                 |
+7 |                 |
+8 |       ♚       ♜ |
+  +---------------v-+
+    h g f e d c b a
+");
+}
+
+#[test]
+fn test_play() {
+    // Mate in coordinates, announced, then saved as PGN.
+    let path = std::env::temp_dir().join(format!("chess-play-{}.pgn", std::process::id()));
+    let options = PlayOptions { depth: 1, start: Fen::parse("k7/8/1K6/8/8/8/8/7Q w - - 0 1").unwrap(), ..PlayOptions::default() };
+    let (game, output) = play_session(&format!("fen\nh1a2\nflip\nhint\nh1h8\n{}\n", path.display()), &options);
+    assert!(output.contains("k7/8/1K6/8/8/8/8/7Q w - - 0 1\n"));
+    assert!(output.contains("Illegal move: h1a2"));
+    assert!(output.contains("Hint: Q"));
+    assert!(output.contains("Checkmate. White wins."));
+    assert_eq!(game.moves, vec!["Qh8#"]);
+    assert_eq!(game.result, "1-0");
+    let saved = load_pgn(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(saved[0].tag("Black"), game.tag("Black"));
+    assert_eq!(saved[0].moves, vec!["Qh8#"]);
+
+    // The engine moves first when the human plays Black, and undo takes back both moves.
+    let options = PlayOptions::from_args(&["--color", "black", "--depth", "1", "--time", "5"].map(String::from)).unwrap();
+    let (game, output) = play_session("undo\ne5\nundo\nundo\nd7d5\nresign\n\n", &options);
+    assert_eq!(output.matches("Engine plays ").count(), 3);
+    assert_eq!(output.matches("Nothing to undo.").count(), 2);
+    assert!(output.contains("    h g f e d c b a"));
+    assert!(output.contains("You resign. White wins."));
+    assert_eq!(game.moves.len(), 3);
+    assert_eq!(game.moves[1], "d5");
+    assert_eq!(game.result, "1-0");
+    assert!(PlayOptions::from_args(&["--colour", "black"].map(String::from)).is_err());
+}
+
+#[test]
+fn test_play_endings() {
+    // Checks and draws are announced.
+    let options = PlayOptions { depth: 1, start: Fen::parse("k7/8/1K6/8/8/8/8/2Q5 w - - 0 1").unwrap(), ..PlayOptions::default() };
+    let (game, output) = play_session("Qc7\n", &options);
+    assert!(output.contains("Draw by stalemate."));
+    assert_eq!(game.result, "1/2-1/2");
+    let options = PlayOptions { depth: 1, start: Fen::parse("k7/8/8/8/8/8/2q5/1NK5 w - - 0 1").unwrap(), ..PlayOptions::default() };
+    let (_, output) = play_session("Kxc2\n", &options);
+    assert!(output.contains("Check."));
+    assert!(output.contains("Draw by insufficient material."));
+    let options = PlayOptions { depth: 1, start: Fen::parse("k7/8/1K6/8/8/8/8/6Q1 w - - 99 80").unwrap(), ..PlayOptions::default() };
+    assert!(play_session("Qg2\n", &options).1.contains("Draw by the fifty-move rule."));
+}
+
+#[test]
+fn test_book_options() {
+    // A book with a move no search would pick for each side: 1. a3, and 1... a6 after 1. e4.
+    let start = Fen::parse(START_POSITION_FEN).unwrap().board;
+    let after_e4 = Move::parse("e2e4").unwrap().apply(&start, Color::White).unwrap();
+    let (a3, a6) = (Move::parse("a2a3").unwrap(), Move::parse("a7a6").unwrap());
+    let mut entries = [
+        BookEntry { key: polyglot_key(&start, Color::White), raw_move: encode_move(&a3, &start), weight: 1, learn: 0 },
+        BookEntry { key: polyglot_key(&after_e4, Color::Black), raw_move: encode_move(&a6, &after_e4), weight: 1, learn: 0 },
+    ];
+    entries.sort_by_key(|entry| entry.key);
+    let path = std::env::temp_dir().join(format!("chess-protocol-book-{}.bin", std::process::id()));
+    std::fs::write(&path, entries.iter().flat_map(|entry| entry.to_bytes()).collect::<Vec<u8>>()).unwrap();
+    let book = path.to_str().unwrap();
+
+    // UCI plays from the book only with OwnBook on, and searches once out of it.
+    let lines = uci_session("uci\n");
+    assert!(lines.contains(&String::from("option name OwnBook type check default false")));
+    assert!(lines.contains(&String::from("option name BookFile type string default <empty>")));
+    assert_ne!(best_move(&uci_session(&format!("setoption name BookFile value {}\nposition startpos\ngo depth 1\n", book))), "a2a3");
+    let script = format!("setoption name BookFile value {}\nsetoption name OwnBook value true\nposition startpos\ngo depth 1\n", book);
+    assert_eq!(uci_session(&script), vec!["bestmove a2a3"]);
+    let lines = uci_session(&format!("{}position startpos moves a2a3\ngo depth 1\n", script));
+    assert!(lines[1].starts_with("info depth 1 "));
+    let lines = uci_session("setoption name BookFile value /nonexistent/book.bin\nsetoption name OwnBook value maybe\n");
+    assert!(lines[0].starts_with("info string Cannot read /nonexistent/book.bin: "));
+    assert_eq!(lines[1], "info string Invalid value for OwnBook: maybe");
+
+    // CECP announces the options in its features and takes them with `option`.
+    assert!(xboard_session("protover 2\n")[0].contains(" option=\"OwnBook -check 0\" option=\"BookFile -file \" "));
+    let lines = xboard_session(&format!("new\noption BookFile={}\noption OwnBook=1\nusermove e2e4\n", book));
+    assert_eq!(moves(&lines), vec!["a7a6"]);
+    let lines = xboard_session("option Hash=16\noption BookFile=/nonexistent/book.bin\n");
+    assert_eq!(lines[0], "Error (unknown option): option Hash=16");
+    assert!(lines[1].starts_with("tellusererror Cannot read /nonexistent/book.bin: "));
+
+    // The terminal game takes the book as an argument.
+    let options = PlayOptions::from_args(&["--color", "black", "--depth", "1", "--book", book].map(String::from)).unwrap();
+    let (game, _) = play_session("resign\n\n", &options);
+    assert_eq!(game.moves, vec!["a3"]);
+    std::fs::remove_file(&path).unwrap();
+    let options = PlayOptions { book: Some(book.to_string()), ..PlayOptions::default() };
+    assert!(run_play(std::io::Cursor::new(String::new()), &mut Vec::new(), &options).is_err());
+}
@@ -0,0 +1,436 @@
+use chess::board::{ Board, Color, Coordinates, Fen, File, Kind, Piece, Rank, Square, START_POSITION_FEN };
+use chess::engine::{ mcts_search, mcts_search_from, play_game, is_mate_score, is_threatened_by, next_boards, solve_mate, search, Cutoff, GameOutcome, GameRecord, LeafEvaluation, MateLine, MaterialEvaluator, MctsNode, MctsOptions, MctsResult, NodeType, Move, PositionalEvaluator, ScoreTarget, ScoreTree, SearchContext, SearchLine, SearchOptions, SearchResult, SearchState, SearchTrace, SelectionPolicy, TraceLimits, TraceNode };
+use chess::util::{ GrowTree };
+
+#[test]
+fn test_score_tree() {
+  let mut tree = ScoreTree::new();
+  assert!(tree.is_empty());
+  assert!(tree.add(0, ScoreTarget::Highest, Some(0)).is_err());
+  let root = tree.add(0, ScoreTarget::Highest, None).unwrap();
+  let first = tree.add(5, ScoreTarget::Lowest, Some(root)).unwrap();
+  tree.add(3, ScoreTarget::Highest, Some(first)).unwrap();
+  tree.add(8, ScoreTarget::Highest, Some(first)).unwrap();
+  let second = tree.add(7, ScoreTarget::Lowest, Some(root)).unwrap();
+  let second_leaf = tree.add(6, ScoreTarget::Highest, Some(second)).unwrap();
+  assert_eq!(Some(3), tree.score_at(first));
+  assert_eq!(Some(6), tree.score_at(root));
+  assert_eq!(Some(2), tree.depth_at(root));
+  assert_eq!(vec![root, second, second_leaf], tree.best_path(root));
+
+  // Scores propagate up whenever a node is added.
+  let third = tree.add(20, ScoreTarget::Lowest, Some(root)).unwrap();
+  assert_eq!((Some(20), Some(1)), (tree.score_at(root), tree.depth_at(root)));
+  tree.add(1, ScoreTarget::Highest, Some(third)).unwrap();
+  assert_eq!(Some(6), tree.score_at(root));
+  assert_eq!(Some(second), tree.best_child(root));
+  assert_eq!(&[first, second, third], tree.children(root));
+
+  let subtree = tree.subtree(second);
+  assert_eq!(2, subtree.len());
+  assert_eq!(Some(6), subtree.score_at(0));
+  assert_eq!("ScoreTree {\n- local: 7, deep: (6, 1), target: Lowest\n  |- local: 6, target: Highest\n}\n", subtree.to_string());
+  assert!(tree.subtree(100).is_empty());
+
+  // Among equal wins the fastest is chosen, among equal losses the slowest.
+  let mut tree = ScoreTree::new();
+  let root = tree.add(0, ScoreTarget::Highest, None).unwrap();
+  let slow = tree.add(0, ScoreTarget::Lowest, Some(root)).unwrap();
+  tree.add(100, ScoreTarget::Highest, Some(slow)).unwrap();
+  let fast = tree.add(100, ScoreTarget::Lowest, Some(root)).unwrap();
+  assert_eq!(Some(fast), tree.best_child(root));
+  let mut tree = ScoreTree::new();
+  let root = tree.add(0, ScoreTarget::Highest, None).unwrap();
+  let fast = tree.add(-100, ScoreTarget::Lowest, Some(root)).unwrap();
+  let slow = tree.add(0, ScoreTarget::Lowest, Some(root)).unwrap();
+  tree.add(-100, ScoreTarget::Highest, Some(slow)).unwrap();
+  assert_ne!(Some(fast), tree.best_child(root));
+  assert_eq!(Some(2), tree.depth_at(root));
+}
+
+#[test]
+fn test_score_deep() {
+  // Two rooks against a bare king: no mate in one, but a quiet rook move mates in two.
+  let board = Board::parse_str("
+    +-----------------+
+  8 |             ♖   |
+  7 |                 |
+  6 |                 |
+  5 |                 |
+  4 |                 |
+  3 |                 |
+  2 |               ♚ |
+  1 |             ♖   |
+    +-----------------+
+      a b c d e f g h").unwrap();
+
+  assert!(solve_mate(&board, Color::White, 1).is_empty());
+  let lines = solve_mate(&board, Color::White, 2);
+  let key = Move::parse("g1g7").unwrap().apply(&board, Color::White).unwrap();
+  assert!(lines.iter().any(|line| line.board == key));
+  // Every defence is answered by a mate, at every level of the tree.
+  fn check_mates(line: &MateLine) {
+    assert_eq!(next_boards(&line.board, Color::Black).len(), line.replies.len());
+    if line.replies.is_empty() {
+      assert!(is_threatened_by(&line.board, line.board.squares().iter().position(|square| *square == Square::Occupied(Piece(Color::Black, Kind::King)))
+        .and_then(Coordinates::new_from_index).unwrap(), Color::White));
+      assert!(next_boards(&line.board, Color::Black).is_empty());
+    }
+    for (reply, continuation) in line.replies.iter() {
+      assert!(continuation.moves < line.moves);
+      assert!(next_boards(reply, Color::White).contains(&continuation.board));
+      check_mates(continuation);
+    }
+  }
+  for line in lines.iter() {
+    assert_eq!(2, line.moves);
+    check_mates(line);
+  }
+  let line = lines.iter().find(|line| line.board == key).unwrap();
+  assert_eq!(5, line.size());
+
+  // Mate in one is found first, and stalemating moves never count.
+  let fen = Fen::parse("k7/2K5/8/1Q6/8/8/8/8 w - - 0 1").unwrap();
+  let lines = solve_mate(&fen.board, fen.color, 3);
+  assert_eq!(1, lines[0].moves);
+  assert!(lines[0].replies.is_empty());
+  let stalemate = Move::parse("b5b6").unwrap().apply(&fen.board, fen.color).unwrap();
+  assert!(lines.iter().all(|line| line.board != stalemate));
+  assert!(solve_mate(&Fen::parse("k7/2K5/1Q6/8/8/8/8/8 b - - 0 1").unwrap().board, Color::Black, 2).is_empty());
+}
+
+// A queen on d5 that nothing defends, with a knight on e3 to take it.
+fn hanging_queen() -> Board {
+    Board::parse_str("
+      +-----------------+
+    8 |         ♚       |
+    7 |                 |
+    6 |                 |
+    5 |       ♛         |
+    4 |                 |
+    3 |         ♘       |
+    2 |                 |
+    1 |         ♔       |
+      +-----------------+
+        a b c d e f g h").unwrap()
+}
+
+#[test]
+fn test_search_mate() {
+    // Back rank mate in one.
+    let board = Board::parse_str("
+      +-----------------+
+    8 |             ♚   |
+    7 |           ♟︎ ♟︎ ♟︎ |
+    6 |                 |
+    5 |                 |
+    4 |                 |
+    3 |                 |
+    2 |           ♙ ♙ ♙ |
+    1 | ♖           ♔   |
+      +-----------------+
+        a b c d e f g h").unwrap();
+    for options in &[SearchOptions::default(), SearchOptions::plain()] {
+        let result = search(&board, Color::White, &options.with_depth(3), &mut SearchContext::new());
+        assert!(is_mate_score(result.score));
+        assert!(result.best_board.unwrap().get_square(Coordinates::new(File::A, Rank::R8)).is_occupied_by(Piece(Color::White, Kind::Rook)));
+    }
+}
+
+#[test]
+fn test_search_capture() {
+    // Take the hanging queen: nothing defends her on d5, with or without the selective techniques.
+    let board = hanging_queen();
+    for options in &[SearchOptions::default(), SearchOptions::plain()] {
+        let result = search(&board, Color::White, &options.with_depth(3), &mut SearchContext::new());
+        assert!(result.best_board.unwrap().get_square(Coordinates::new(File::D, Rank::R5)).is_occupied_by(Piece(Color::White, Kind::Knight)));
+        assert!(result.score > 0);
+    }
+}
+
+#[test]
+fn test_selective_search() {
+    // The selective techniques search fewer nodes than the plain search.
+    let mut board = Board::new();
+    board.setup();
+    let plain = search(&board, Color::White, &SearchOptions::plain().with_depth(4), &mut SearchContext::new());
+    let selective = search(&board, Color::White, &SearchOptions::default().with_depth(4), &mut SearchContext::new());
+    assert!(selective.nodes < plain.nodes);
+}
+
+#[test]
+fn test_search_state() {
+    let search_again = |state: &mut SearchState, fen: &str, threads: usize| {
+        let fen = Fen::parse(fen).unwrap();
+        let options = SearchOptions::default().with_depth(4).with_threads(threads);
+        let kept = search(&fen.board, fen.color, &options, &mut SearchContext::new().with_state(state));
+        assert!(!kept.principal_variation.is_empty() && kept.principal_variation[0] == kept.best_board.unwrap());
+        kept
+    };
+    let start = Fen::parse(START_POSITION_FEN).unwrap();
+    let fresh = search(&start.board, start.color, &SearchOptions::default().with_depth(4), &mut SearchContext::new());
+
+    // A new state searches like no state at all.
+    let mut state = SearchState::new();
+    let first = search_again(&mut state, START_POSITION_FEN, 1);
+    assert_eq!(fresh, first);
+
+    // The table kept from the first search makes the same search again cheaper.
+    let second = search_again(&mut state, START_POSITION_FEN, 1);
+    assert!(second.nodes < first.nodes, "{} nodes after {}", second.nodes, first.nodes);
+
+    // The state also serves searches with another number of threads.
+    search_again(&mut state, "6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1", 1);
+    search_again(&mut state, "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", 3);
+
+    // Cleared, it forgets all of that.
+    state.clear();
+    assert_eq!(fresh, search_again(&mut state, START_POSITION_FEN, 1));
+}
+
+#[test]
+fn test_multi_pv() {
+    // Mate on the back rank beats winning the knight, which beats everything else.
+    let board = Board::parse_str("
+      +-----------------+
+    8 |             ♚   |
+    7 |           ♟︎ ♟︎ ♟︎ |
+    6 |                 |
+    5 |   ♞             |
+    4 |                 |
+    3 |                 |
+    2 |           ♙ ♙ ♙ |
+    1 | ♖         ♗ ♔   |
+      +-----------------+
+        a b c d e f g h").unwrap();
+    let lines: Vec<SearchLine> = search(&board, Color::White, &SearchOptions::default().with_depth(3).with_multi_pv(3), &mut SearchContext::new()).lines;
+    assert_eq!(3, lines.len());
+    assert!(is_mate_score(lines[0].score));
+    assert!(lines[0].principal_variation[0].get_square(Coordinates::new(File::A, Rank::R8)).is_occupied_by(Piece(Color::White, Kind::Rook)));
+    assert!(lines[1].principal_variation[0].get_square(Coordinates::new(File::B, Rank::R5)).is_occupied_by(Piece(Color::White, Kind::Bishop)));
+    assert!(lines.windows(2).all(|pair| pair[0].score >= pair[1].score));
+    assert!(lines[1].principal_variation[0] != lines[2].principal_variation[0]);
+
+    // The first line is the single best line, and every iteration is reported with all its lines.
+    let mut reported = vec![];
+    let options = SearchOptions::default().with_depth(3).with_multi_pv(3);
+    let mut report = |result: &SearchResult| reported.push((result.depth, result.lines.len()));
+    let result = search(&board, Color::White, &options, &mut SearchContext::new().with_reporter(&mut report));
+    assert_eq!(vec![(1, 3), (2, 3), (3, 3)], reported);
+    assert_eq!(result.score, result.lines[0].score);
+    assert_eq!(search(&board, Color::White, &SearchOptions::default().with_depth(3), &mut SearchContext::new()).best_board, result.best_board);
+
+    // Asking for more lines than there are moves gives every move.
+    let moves = next_boards(&board, Color::White).len();
+    let options = SearchOptions::default().with_depth(1).with_multi_pv(100);
+    assert_eq!(moves, search(&board, Color::White, &options, &mut SearchContext::new()).lines.len());
+}
+
+#[test]
+fn test_parallel_search() {
+    // One thread is reproducible.
+    let mut board = Board::new();
+    board.setup();
+    let options = SearchOptions::default();
+    let first = search(&board, Color::White, &options.with_depth(3), &mut SearchContext::new());
+    let second = search(&board, Color::White, &options.with_depth(3), &mut SearchContext::new());
+    assert_eq!(first, second);
+
+    // More threads find the same tactics.
+    let board = hanging_queen();
+    let options = SearchOptions::default().with_threads(4);
+    let result = search(&board, Color::White, &options.with_depth(4), &mut SearchContext::new());
+    assert_eq!(4, result.depth);
+    assert!(result.best_board.unwrap().get_square(Coordinates::new(File::D, Rank::R5)).is_occupied_by(Piece(Color::White, Kind::Knight)));
+    assert!(result.score > 0);
+    let result = search(&board, Color::White, &options.with_depth(3), &mut SearchContext::new().with_evaluator(&mut MaterialEvaluator));
+    assert_eq!(300, result.score);
+}
+
+// A trace without limits that matter, for the plain search.
+fn full_trace() -> SearchOptions {
+    SearchOptions::plain().with_trace(TraceLimits { max_nodes: 100_000, max_ply: 64 })
+}
+
+#[test]
+fn test_search_trace() {
+    // Nothing is recorded unless asked for.
+    let fen = Fen::parse("k7/2K5/8/1Q6/8/8/8/8 w - - 0 1").unwrap();
+    assert_eq!(None, search(&fen.board, fen.color, &SearchOptions::plain().with_depth(2), &mut SearchContext::new()).trace);
+
+    // The root holds the full window and the score of the search, and the best move is a principal variation node.
+    let result = search(&fen.board, fen.color, &full_trace().with_depth(2), &mut SearchContext::new());
+    let trace: SearchTrace = result.trace.clone().unwrap();
+    let root: &TraceNode = trace.tree.root();
+    assert_eq!((fen.board, None, 0, 2), (root.board, root.played, root.ply, root.depth));
+    assert_eq!((-1_000_000, 1_000_000, result.score, NodeType::Pv), (root.alpha, root.beta, root.score, root.node_type));
+    let children: Vec<(usize, &TraceNode)> = trace.tree.children(0).collect();
+    assert_eq!(next_boards(&fen.board, fen.color).len(), children.len());
+    let (_, best_node) = children.iter().find(|(_, node)| Some(node.board) == result.best_board).unwrap();
+    assert_eq!(Move::between(&fen.board, &best_node.board, fen.color), best_node.played);
+    assert_eq!((Some(Cutoff::Mate), -result.score, 1), (best_node.cutoff, best_node.score, best_node.ply));
+    assert!(children.iter().filter(|(_, node)| node.cutoff == Some(Cutoff::Mate)).all(|(index, node)| node.score == -999_999 && trace.tree.is_leaf(*index)));
+    assert!(children.iter().any(|(_, node)| node.cutoff == Some(Cutoff::Stalemate)));
+    assert_eq!(trace.len(), trace.to_dot().matches(" -> ").count() + 1);
+    assert!(!trace.is_empty());
+}
+
+#[test]
+fn test_search_trace_limits() {
+    // Limits on nodes and plies.
+    let fen = Fen::parse("k7/2K5/8/1Q6/8/8/8/8 w - - 0 1").unwrap();
+    let options = SearchOptions::plain().with_trace(TraceLimits { max_nodes: 10, max_ply: 64 });
+    assert_eq!(10, search(&fen.board, fen.color, &options.with_depth(3), &mut SearchContext::new()).trace.unwrap().len());
+    let options = SearchOptions::plain().with_trace(TraceLimits { max_nodes: 100_000, max_ply: 1 });
+    let shallow = search(&fen.board, fen.color, &options.with_depth(3), &mut SearchContext::new()).trace.unwrap();
+    assert_eq!(1 + next_boards(&fen.board, fen.color).len(), shallow.len());
+}
+
+#[test]
+fn test_search_trace_nodes() {
+    // Cutoffs, quiescence and the node type of every node follow from its window.
+    let start = Fen::parse("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3").unwrap();
+    let options = SearchOptions::default().with_trace(TraceLimits { max_nodes: 1_000_000, max_ply: 64 });
+    let trace = search(&start.board, start.color, &options.with_depth(3), &mut SearchContext::new()).trace.unwrap();
+    let mut all_nodes = vec![];
+    for (index, node) in trace.tree.dfs(0) {
+        let expected = if node.score >= node.beta { NodeType::Cut } else if node.score <= node.alpha { NodeType::All } else { NodeType::Pv };
+        assert_eq!(expected, node.node_type);
+        if let Some((_, parent)) = trace.tree.parent(index) {
+            assert_eq!(parent.ply + 1, node.ply);
+            assert!(parent.quiescence <= node.quiescence);
+        }
+        all_nodes.push(*node);
+    }
+    assert!(all_nodes.iter().any(|node| node.cutoff == Some(Cutoff::Beta)));
+    assert!(all_nodes.iter().any(|node| node.cutoff == Some(Cutoff::StandPat)));
+    assert!(all_nodes.iter().any(|node| node.quiescence && node.depth == 0));
+    assert_eq!(all_nodes.len(), trace.len());
+
+    // Pruning keeps the wanted nodes and the paths to them.
+    let pruned = trace.pruned(&|node| node.node_type == NodeType::Pv && !node.quiescence);
+    assert!(pruned.len() < trace.len());
+    assert_eq!(trace.tree.root(), pruned.tree.root());
+    assert_eq!(all_nodes.iter().filter(|node| node.node_type == NodeType::Pv && !node.quiescence).count(), pruned.len());
+    assert_eq!(1, trace.pruned(&|_| false).len());
+}
+
+#[test]
+fn test_search_trace_exports() {
+    let fen = Fen::parse("k7/2K5/8/1Q6/8/8/8/8 w - - 0 1").unwrap();
+    let options = SearchOptions::plain().with_trace(TraceLimits { max_nodes: 100_000, max_ply: 1 });
+    let shallow = search(&fen.board, fen.color, &options.with_depth(3), &mut SearchContext::new()).trace.unwrap();
+    let dot = shallow.to_dot();
+    assert!(dot.starts_with("digraph search {\n"));
+    assert!(dot.contains(&format!("n0 -> n1 [label=\"{}\"];", shallow.tree.item(1).unwrap().played.unwrap())));
+    assert!(dot.contains("[label=\"d3 [-1000000, 1000000]\\n999999 Pv\", color=green];"));
+    assert!(dot.ends_with("}\n"));
+    let json = shallow.pruned(&|node| node.played == Some(Move::parse("b5b8").unwrap())).to_json();
+    assert!(json.starts_with("{\"move\":null,\"fen\":\"k7/2K5/8/1Q6/8/8/8/8 w - - 0 1\",\"ply\":0,\"depth\":3,\"quiescence\":false,"));
+    assert!(json.contains("\"children\":[{\"move\":\"b5b8\",\"fen\":\"kQ6/2K5/8/8/8/8/8/8 b - - 0 1\",\"ply\":1,\"depth\":2,"));
+    assert!(json.ends_with("\"cutoff\":\"Mate\",\"children\":[]}]}"));
+}
+
+#[test]
+fn test_mcts() {
+    // Queen mates are found with every selection policy and leaf evaluation, and stalemate is avoided.
+    let fen = Fen::parse("k7/2K5/8/1Q6/8/8/8/8 w - - 0 1").unwrap();
+    let stalemate = Move::parse("b5b6").unwrap().apply(&fen.board, fen.color).unwrap();
+    for (selection, leaf_evaluation) in &[(SelectionPolicy::Puct, LeafEvaluation::ValueFunction), (SelectionPolicy::Uct, LeafEvaluation::RandomPlayout),
+            (SelectionPolicy::Uct, LeafEvaluation::HeuristicPlayout)] {
+        let options = MctsOptions::default().with_iterations(200).with_selection(*selection).with_leaf_evaluation(*leaf_evaluation).with_playout_plies(8);
+        let result: MctsResult = mcts_search(&fen.board, fen.color, &options, &mut MaterialEvaluator);
+        let best = result.best_board.unwrap();
+        assert!(next_boards(&best, Color::Black).is_empty() && is_threatened_by(&best, Coordinates::new(File::A, Rank::R8), Color::White));
+        assert_ne!(stalemate, best);
+        assert!(result.value > 0.9);
+        assert_eq!(vec![best], result.principal_variation);
+
+        // Every iteration visits the root and one of its children.
+        let root: &MctsNode = result.tree.root();
+        assert_eq!(200, root.visits);
+        assert_eq!(200, result.tree.children(0).map(|(_, child)| child.visits).sum::<u32>());
+        assert_eq!(next_boards(&fen.board, fen.color).len(), result.tree.children(0).len());
+        let priors: f64 = result.tree.children(0).map(|(_, child)| child.prior).sum();
+        assert!((priors - 1.0).abs() < 1e-9);
+    }
+
+    // No moves, no best move.
+    let stalemated = mcts_search(&stalemate, Color::Black, &MctsOptions::default().with_iterations(10), &mut MaterialEvaluator);
+    assert_eq!(None, stalemated.best_board);
+    assert_eq!(0.5, stalemated.value);
+}
+
+#[test]
+fn test_mcts_self_play() {
+    // Against alpha-beta in self-play: both sides find the mate with the queen.
+    let fen = Fen::parse("k7/2K5/8/1Q6/8/8/8/8 w - - 0 1").unwrap();
+    let options = MctsOptions::default().with_iterations(200);
+    let record: GameRecord = play_game(&fen.board, fen.color, 10, &mut |board, color| mcts_search(board, color, &options, &mut PositionalEvaluator::new()).best_board,
+        &mut |board, color| search(board, color, &SearchOptions::default().with_depth(2), &mut SearchContext::new()).best_board).unwrap();
+    assert_eq!(GameOutcome::Win(Color::White), record.outcome);
+    assert_eq!(1, record.boards.len());
+    let mirrored = fen.board.mirror();
+    let record = play_game(&mirrored, Color::White, 10, &mut |board, color| search(board, color, &SearchOptions::default().with_depth(2), &mut SearchContext::new()).best_board,
+        &mut |board, color| mcts_search(board, color, &options, &mut PositionalEvaluator::new()).best_board).unwrap();
+    assert_eq!(GameOutcome::Win(Color::Black), record.outcome);
+    assert_eq!(2, record.boards.len());
+}
+
+#[test]
+fn test_play_game() {
+    // Kings alone shuffle until the ply limit, and illegal choices are refused.
+    let kings = Fen::parse("k7/8/8/8/8/8/8/7K w - - 0 1").unwrap().board;
+    let record = play_game(&kings, Color::White, 6, &mut |board, color| search(board, color, &SearchOptions::default().with_depth(1), &mut SearchContext::new()).best_board,
+        &mut |board, color| search(board, color, &SearchOptions::default().with_depth(1), &mut SearchContext::new()).best_board).unwrap();
+    assert_eq!(GameOutcome::Draw, record.outcome);
+    assert!(record.boards.len() <= 6);
+    assert!(play_game(&kings, Color::White, 6, &mut |board, _| Some(*board), &mut |_, _| None).is_err());
+}
+
+#[test]
+fn test_mcts_continue() {
+    // Analysis continues below the move played.
+    let start = Fen::parse(START_POSITION_FEN).unwrap();
+    let options = MctsOptions::default().with_iterations(100);
+    let result = mcts_search(&start.board, start.color, &options, &mut MaterialEvaluator);
+    let (played, visits) = result.tree.children(0).find(|(_, node)| Some(node.board) == result.best_board).map(|(index, node)| (index, node.visits)).unwrap();
+    let mut tree = result.tree;
+    tree.reroot(played).unwrap();
+    assert_eq!((result.best_board.unwrap(), Color::Black), (tree.root().board, tree.root().color));
+    let continued = mcts_search_from(tree, &options, &mut MaterialEvaluator);
+    assert_eq!(visits + 100, continued.tree.root().visits);
+}
+
+#[test]
+fn test_mcts_saved_tree() {
+    // Analysis continues from a saved tree as from the tree it was saved from.
+    let start = Fen::parse(START_POSITION_FEN).unwrap();
+    let options = MctsOptions::default().with_iterations(100);
+    let searched = mcts_search(&start.board, start.color, &options, &mut MaterialEvaluator);
+
+    let bytes = searched.tree.to_bytes();
+    assert!(bytes.len() < 12 + 56 * searched.tree.len());
+    let loaded: GrowTree<MctsNode> = GrowTree::from_bytes(&bytes).unwrap();
+    assert_eq!(searched.tree.dfs(0).map(|(_, node)| *node).collect::<Vec<_>>(), loaded.dfs(0).map(|(_, node)| *node).collect::<Vec<_>>());
+    assert!(GrowTree::<MctsNode>::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    assert!(GrowTree::<MctsNode>::from_bytes(&bytes[1..]).is_err());
+    let path = std::env::temp_dir().join(format!("crate-mcts-{}.bin", std::process::id()));
+    searched.tree.save(&path).unwrap();
+    let loaded = GrowTree::<MctsNode>::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(GrowTree::<MctsNode>::load(&path).is_err());
+    assert_eq!(mcts_search_from(searched.tree.clone(), &options, &mut MaterialEvaluator).principal_variation,
+        mcts_search_from(loaded, &options, &mut MaterialEvaluator).principal_variation);
+}
+
+#[test]
+fn test_mcts_node_limit() {
+    // Under a node limit the least visited subtrees make room.
+    let start = Fen::parse(START_POSITION_FEN).unwrap();
+    let options = MctsOptions::default().with_iterations(300).with_max_nodes(200);
+    let result = mcts_search(&start.board, start.color, &options, &mut MaterialEvaluator);
+    assert!(result.tree.len() <= 200);
+    assert_eq!(300, result.tree.root().visits);
+    assert!(result.best_board.is_some());
+}
@@ -0,0 +1,285 @@
+use std::sync::{ OnceLock };
+
+use chess::board::{ Board, Color, Coordinates, Fen, Kind, Piece, Square };
+use chess::engine::{ is_checked, is_mate_score, search, Move, Player, SearchContext, SearchOptions, TABLEBASE_WIN_SCORE };
+use chess::tablebase::{ DtmTablebase, SyzygyTablebase, Tablebase, Wdl };
+use chess::{ Error };
+
+// A KQvK table pair in the Syzygy format. White to move wins everywhere except with the king on b1,
+// the queen on h8 and the black king on e6, and positions mirroring that, which the table says lose.
+// Black to move always loses. The DTZ table stores white to move only: 19 plies everywhere.
+fn syzygy_fixture() -> (Vec<u8>, Vec<u8>) {
+    let mut wdl = vec![0x71, 0xE8, 0x23, 0x5D, 0x01, 0x00, 0x66, 0x55, 0xEE, 0x00];
+    // White to move: one block of 2^11 bytes, a sparse index entry every 2^15 values.
+    wdl.extend_from_slice(&[0x00, 11, 15, 0, 1, 0, 0, 0, 2, 1]);
+    // Codes of one bit are symbol 2, codes of two bits symbols 0 and 1.
+    wdl.extend_from_slice(&[2, 0, 0, 0]);
+    // Symbol 0 is a win, 1 a loss and 2 a pair of wins.
+    wdl.extend_from_slice(&[3, 0, 0x04, 0xF0, 0xFF, 0x00, 0xF0, 0xFF, 0x00, 0x00, 0x00, 0x00]);
+    // Black to move: a single value.
+    wdl.extend_from_slice(&[0x80, 0x00]);
+    // The sparse index and the block length: 31332 values.
+    wdl.extend_from_slice(&[0, 0, 0, 0, 0x00, 0x40, 0x63, 0x7A]);
+    wdl.resize(64, 0);
+    let loss_index = 62 * 62 + 43;
+    let mut bits = vec![true; loss_index / 2];
+    bits.extend_from_slice(&[false, false, false, true]);
+    bits.resize(2048 * 8, true);
+    wdl.extend(bits.chunks(8).map(|byte| byte.iter().fold(0u8, |value, bit| value << 1 | *bit as u8)));
+
+    let dtz = vec![0xD7, 0x66, 0x0C, 0xA5, 0x01, 0x00, 0x66, 0x55, 0xEE, 0x00, 0x80, 9];
+    (wdl, dtz)
+}
+
+// Opens the fixture from its own directory, so tests running at the same time do not share one.
+fn syzygy_tablebase(name: &str) -> SyzygyTablebase {
+    let directory = std::env::temp_dir().join(format!("crate-syzygy-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+    let (wdl, dtz) = syzygy_fixture();
+    std::fs::write(directory.join("KQvK.rtbw"), &wdl).unwrap();
+    std::fs::write(directory.join("KQvK.rtbz"), &dtz).unwrap();
+    let tablebase = SyzygyTablebase::open(&directory);
+    std::fs::remove_dir_all(&directory).unwrap();
+    tablebase.unwrap()
+}
+
+#[test]
+fn test_tablebase_files() {
+    let directory = std::env::temp_dir().join(format!("crate-syzygy-files-{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+    assert!(SyzygyTablebase::open(&directory).is_err());
+    let (wdl, dtz) = syzygy_fixture();
+    std::fs::write(directory.join("KQvK.rtbw"), &wdl[..10]).unwrap();
+    assert!(SyzygyTablebase::open(&directory).is_err());
+    std::fs::write(directory.join("KQvK.rtbw"), &wdl).unwrap();
+    std::fs::write(directory.join("KQvK.rtbz"), &dtz).unwrap();
+    std::fs::write(directory.join("README"), "Not a table").unwrap();
+    let tablebase = SyzygyTablebase::open(&directory);
+    std::fs::remove_dir_all(&directory).unwrap();
+    assert_eq!(3, tablebase.unwrap().max_pieces());
+}
+
+#[test]
+fn test_tablebase() {
+    let tablebase = syzygy_tablebase("probe");
+    let probe = |fen: &str| {
+        let fen = Fen::parse(fen).unwrap();
+        tablebase.probe_wdl(&fen.board, fen.color)
+    };
+    assert_eq!(Ok(Wdl::Win), probe("7Q/8/3k4/8/8/8/8/1K6 w - - 0 1"));
+    // The lost position, mirrored horizontally, vertically and along the diagonal, and with the colors swapped.
+    for fen in &["7Q/8/4k3/8/8/8/8/1K6 w - - 0 1", "Q7/8/3k4/8/8/8/8/6K1 w - - 0 1", "1K6/8/8/8/8/4k3/8/7Q w - - 0 1",
+            "7Q/8/8/5k2/8/8/K7/8 w - - 0 1", "1k6/8/8/8/8/4K3/8/7q b - - 0 1"] {
+        assert_eq!(Ok(Wdl::Loss), probe(fen));
+    }
+    assert_eq!(Ok(Wdl::Loss), probe("7Q/8/4k3/8/8/8/8/1K6 b - - 0 1"));
+    assert_eq!(Ok(Wdl::Win), probe("1k6/8/8/8/8/3K4/8/7q b - - 0 1"));
+    // Captures are searched: the hanging queen draws.
+    assert_eq!(Ok(Wdl::Draw), probe("8/8/4k3/3Q4/8/8/8/K7 b - - 0 1"));
+    assert_eq!(Ok(Wdl::Draw), probe("8/8/4k3/8/8/8/8/K7 w - - 0 1"));
+    assert!(probe("8/8/4k3/8/8/8/8/KR6 w - - 0 1").is_err());
+    assert!(probe("8/8/4k3/8/8/8/8/KRR5 w - - 0 1").is_err());
+    assert!(probe("4k3/8/8/8/8/8/8/R3K3 w Q - 0 1").is_err());
+
+    let won = Fen::parse("7Q/8/3k4/8/8/8/8/1K6 w - - 0 1").unwrap().board;
+    assert_eq!(Ok(19), tablebase.probe_dtz(&won, Color::White));
+    assert_eq!(Ok(-20), tablebase.probe_dtz(&won, Color::Black));
+}
+
+#[test]
+fn test_tablebase_search() {
+    let tablebase = syzygy_tablebase("search");
+    let won = Fen::parse("7Q/8/3k4/8/8/8/8/1K6 w - - 0 1").unwrap().board;
+    // Moves that hang the queen are filtered out.
+    let children = tablebase.filter_root_moves(&won, Color::White).unwrap();
+    let hanging = Move::parse("h8e5").unwrap().apply(&won, Color::White).unwrap();
+    assert!(!children.is_empty() && !children.contains(&hanging));
+    assert!(children.iter().all(|child| tablebase.probe_wdl(child, Color::Black) == Ok(Wdl::Loss)));
+
+    let result = search(&won, Color::White, &SearchOptions::default().with_depth(3), &mut SearchContext::new().with_tablebase(&tablebase));
+    assert!(result.score > TABLEBASE_WIN_SCORE - 10 && !is_mate_score(result.score));
+    assert!(result.tablebase_hits > 0);
+    assert!(children.contains(&result.best_board.unwrap()));
+    let mut player = Player::new(2, SearchOptions::default()).with_tablebase(tablebase);
+    assert!(children.contains(&player.play(&won, Color::White).unwrap()));
+}
+
+// Real tables: KQvK, KRvK and KPvK, with their .rtbw and .rtbz files in tests/syzygy, as listed in
+// tests/syzygy/README.md. Until they are checked in, this runs with `cargo test -- --ignored` once they are in place.
+#[test]
+#[ignore = "needs the KQvK, KRvK and KPvK Syzygy files in tests/syzygy"]
+fn test_real_tablebase() {
+    let tablebase = SyzygyTablebase::open("tests/syzygy").expect("KQvK, KRvK and KPvK tables in tests/syzygy");
+    assert_eq!(3, tablebase.max_pieces());
+    let probe = |fen: &str| {
+        let fen = Fen::parse(fen).unwrap();
+        (tablebase.probe_wdl(&fen.board, fen.color), tablebase.probe_dtz(&fen.board, fen.color).map(|dtz| dtz.signum()))
+    };
+    assert_eq!((Ok(Wdl::Win), Ok(1)), probe("8/8/8/4k3/8/8/8/KQ6 w - - 0 1"));
+    assert_eq!((Ok(Wdl::Loss), Ok(-1)), probe("8/8/8/4k3/8/8/8/KR6 b - - 0 1"));
+    // The king in front of the pawn holds, and a pawn that promotes at once wins in one zeroing ply.
+    assert_eq!((Ok(Wdl::Draw), Ok(0)), probe("8/8/8/8/8/4k3/4P3/4K3 w - - 0 1"));
+    assert_eq!((Ok(Wdl::Win), Ok(1)), probe("8/4P3/8/8/8/8/k7/4K3 w - - 0 1"));
+    let promotion = Fen::parse("8/4P3/8/8/8/8/k7/4K3 w - - 0 1").unwrap();
+    assert_eq!(Ok(1), tablebase.probe_dtz(&promotion.board, promotion.color));
+
+    // Every legal KQvK position agrees with our own retrograde analysis, which does not read the Syzygy format.
+    // DTZ has the sign of the result, and without zeroing moves it is never more than a ply past the mate.
+    let generated = kqk_tablebase();
+    let mut positions = 0;
+    for (white_king, queen, black_king) in (0..64).flat_map(|a| (0..64).flat_map(move |b| (0..64).map(move |c| (a, b, c)))) {
+        if white_king == queen || queen == black_king || white_king == black_king {
+            continue;
+        }
+        let mut board = Board::new();
+        for (index, piece) in &[(white_king, Piece(Color::White, Kind::King)), (queen, Piece(Color::White, Kind::Queen)),
+                (black_king, Piece(Color::Black, Kind::King))] {
+            board.set_square(Coordinates::new_from_index(*index).unwrap(), Square::Occupied(*piece));
+        }
+        for color in &[Color::White, Color::Black] {
+            if is_checked(&board, color.opposite()) {
+                continue;
+            }
+            let expected = generated.probe_wdl(&board, *color).unwrap();
+            assert_eq!(Ok(expected), tablebase.probe_wdl(&board, *color));
+            let dtz = tablebase.probe_dtz(&board, *color).unwrap();
+            let sign = match expected { Wdl::Win => 1, Wdl::Loss => -1, _ => 0 };
+            assert_eq!(sign, dtz.signum());
+            if let Some(dtm) = generated.probe_dtm(&board, *color).unwrap() {
+                assert!(dtz.unsigned_abs() <= dtm + 1);
+            }
+            positions += 1;
+        }
+    }
+    assert!(positions > 300_000);
+}
+
+// Generating KQK takes a while without optimizations, so the tests share one table.
+fn kqk_tablebase() -> &'static DtmTablebase {
+    static TABLEBASE: OnceLock<DtmTablebase> = OnceLock::new();
+    TABLEBASE.get_or_init(|| {
+        let mut tablebase = DtmTablebase::new();
+        tablebase.generate("KQK").unwrap();
+        tablebase
+    })
+}
+
+fn probe_dtm(tablebase: &DtmTablebase, fen: &str) -> (Result<Wdl, Error>, Result<Option<u32>, Error>) {
+    let fen = Fen::parse(fen).unwrap();
+    (tablebase.probe_wdl(&fen.board, fen.color), tablebase.probe_dtm(&fen.board, fen.color))
+}
+
+#[test]
+fn test_dtm_tablebase() {
+    let mut empty = DtmTablebase::new();
+    assert!(empty.generate("KQ").is_err());
+    assert!(empty.generate("KXK").is_err());
+    assert!(empty.generate("KQRRK").is_err());
+    assert!(empty.materials().is_empty());
+    let tablebase = kqk_tablebase();
+    assert_eq!(vec![String::from("KQK")], tablebase.materials());
+    // The longest KQK mate takes 10 moves.
+    assert_eq!(Some(19), tablebase.longest_mate("KQK"));
+    assert_eq!((Ok(Wdl::Win), Ok(Some(1))), probe_dtm(tablebase, "6k1/Q7/6K1/8/8/8/8/8 w - - 0 1"));
+    assert_eq!((Ok(Wdl::Loss), Ok(Some(0))), probe_dtm(tablebase, "6k1/6Q1/6K1/8/8/8/8/8 b - - 0 1"));
+    // Black is the stronger side, mirrored, and on the other wing.
+    assert_eq!((Ok(Wdl::Win), Ok(Some(1))), probe_dtm(tablebase, "8/8/8/8/8/1k6/7q/1K6 b - - 0 1"));
+    // Stalemate, a hanging queen and bare kings are draws.
+    assert_eq!((Ok(Wdl::Draw), Ok(None)), probe_dtm(tablebase, "k7/2K5/1Q6/8/8/8/8/8 b - - 0 1"));
+    assert_eq!((Ok(Wdl::Draw), Ok(None)), probe_dtm(tablebase, "8/8/4k3/3Q4/8/8/8/K7 b - - 0 1"));
+    assert_eq!((Ok(Wdl::Draw), Ok(None)), probe_dtm(tablebase, "8/8/4k3/8/8/8/8/K7 w - - 0 1"));
+    assert!(probe_dtm(tablebase, "8/8/4k3/8/8/8/8/KR6 w - - 0 1").0.is_err());
+    assert!(probe_dtm(tablebase, "4k3/8/8/8/8/8/8/Q3K3 w Q - 0 1").0.is_err());
+}
+
+#[test]
+fn test_dtm_tablebase_search() {
+    let tablebase = kqk_tablebase();
+    // Every move kept at the root mates fastest.
+    let won = Fen::parse("8/8/8/4k3/8/8/8/KQ6 w - - 0 1").unwrap().board;
+    let dtm = tablebase.probe_dtm(&won, Color::White).unwrap().unwrap();
+    assert!(dtm % 2 == 1 && dtm <= 19);
+    let children = tablebase.filter_root_moves(&won, Color::White).unwrap();
+    assert!(!children.is_empty());
+    assert!(children.iter().all(|child| tablebase.probe_dtm(child, Color::Black) == Ok(Some(dtm - 1))));
+
+    let result = search(&won, Color::White, &SearchOptions::default().with_depth(2), &mut SearchContext::new().with_tablebase(tablebase));
+    assert_eq!(1_000_000 - dtm as i32, result.score);
+    assert!(children.contains(&result.best_board.unwrap()));
+}
+
+#[test]
+fn test_dtm_tablebase_files() {
+    let tablebase = kqk_tablebase();
+    let directory = std::env::temp_dir().join(format!("crate-dtm-{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+    assert!(DtmTablebase::load(&directory).is_err());
+    tablebase.save(&directory).unwrap();
+    let bytes = std::fs::read(directory.join("KQK.dtm")).unwrap();
+    std::fs::write(directory.join("KQK.dtm"), &bytes[..bytes.len() - 1]).unwrap();
+    assert!(DtmTablebase::load(&directory).is_err());
+    std::fs::write(directory.join("KQK.dtm"), &bytes).unwrap();
+    let loaded = DtmTablebase::load(&directory).unwrap();
+    std::fs::remove_dir_all(&directory).unwrap();
+    // Smaller than a byte per position.
+    assert!(bytes.len() < 2 * 10 * 64 * 64);
+    assert_eq!(tablebase.materials(), loaded.materials());
+    assert_eq!(Some(19), loaded.longest_mate("KQK"));
+    assert_eq!(probe_dtm(tablebase, "8/8/8/4k3/8/8/8/KQ6 w - - 0 1"), probe_dtm(&loaded, "8/8/8/4k3/8/8/8/KQ6 w - - 0 1"));
+
+    // The player takes the loaded table and mates fastest.
+    let won = Fen::parse("8/8/8/4k3/8/8/8/KQ6 w - - 0 1").unwrap().board;
+    let children = tablebase.filter_root_moves(&won, Color::White).unwrap();
+    let mut player = Player::new(2, SearchOptions::default()).with_tablebase(loaded);
+    assert!(children.contains(&player.play(&won, Color::White).unwrap()));
+}
+
+// The larger tables take minutes to generate without optimizations, so the tests for them run
+// with `cargo test --release -- --ignored`.
+#[test]
+#[ignore]
+fn test_dtm_tablebase_successors() {
+    let mut tablebase = DtmTablebase::new();
+    let probe = |tablebase: &DtmTablebase, fen: &str| {
+        let fen = Fen::parse(fen).unwrap();
+        tablebase.probe_wdl(&fen.board, fen.color)
+    };
+    // Tables with captures and promotions bring in the smaller ones.
+    tablebase.generate("KRK").unwrap();
+    tablebase.generate("KPK").unwrap();
+    assert_eq!(vec!["KBK", "KNK", "KPK", "KQK", "KRK"], tablebase.materials());
+    // The longest KRK mate takes 16 moves.
+    assert_eq!(Some(31), tablebase.longest_mate("KRK"));
+    assert_eq!(None, tablebase.longest_mate("KNK"));
+    // The king on the sixth rank in front of its pawn wins, the rook pawn does not.
+    assert_eq!(Ok(Wdl::Loss), probe(&tablebase, "4k3/8/4K3/8/4P3/8/8/8 b - - 0 1"));
+    assert_eq!(Ok(Wdl::Draw), probe(&tablebase, "7k/8/8/8/7P/8/8/2K5 w - - 0 1"));
+}
+
+#[test]
+#[ignore]
+fn test_kbnk_tablebase() {
+    let mut tablebase = DtmTablebase::new();
+    tablebase.generate("KBNK").unwrap();
+    assert_eq!(vec!["KBK", "KBNK", "KNK"], tablebase.materials());
+    // The longest bishop and knight mate takes 33 moves.
+    assert_eq!(Some(65), tablebase.longest_mate("KBNK"));
+
+    let probe = |fen: &str| {
+        let fen = Fen::parse(fen).unwrap();
+        (tablebase.probe_wdl(&fen.board, fen.color), tablebase.probe_dtm(&fen.board, fen.color))
+    };
+    // Mate in one in the corner of the bishop's color, for either side.
+    assert_eq!((Ok(Wdl::Win), Ok(Some(1))), probe("k7/8/NK6/8/8/8/8/1B6 w - - 0 1"));
+    assert_eq!((Ok(Wdl::Win), Ok(Some(1))), probe("1b6/8/8/8/8/nk6/8/K7 b - - 0 1"));
+    // Taking the bishop leaves a knight that cannot mate.
+    assert_eq!((Ok(Wdl::Draw), Ok(None)), probe("8/8/8/8/3k4/4B3/8/K6N b - - 0 1"));
+
+    // The moves kept at the root bring the mate one move closer.
+    let won = Fen::parse("8/8/8/4k3/8/8/8/KBN5 w - - 0 1").unwrap().board;
+    let dtm = tablebase.probe_dtm(&won, Color::White).unwrap().unwrap();
+    assert!(dtm % 2 == 1 && dtm <= 65);
+    let children = tablebase.filter_root_moves(&won, Color::White).unwrap();
+    assert!(!children.is_empty());
+    assert!(children.iter().all(|child| tablebase.probe_dtm(child, Color::Black) == Ok(Some(dtm - 1))));
+}
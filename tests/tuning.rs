@@ -0,0 +1,95 @@
+use chess::board::{ Color, Kind };
+use chess::engine::{ EvaluationParameters };
+use chess::tuning::{ evaluation_error, find_scaling_constant, load_labeled_positions, parse_labeled_positions, tune, LabeledPosition, TuneResult, TunerOptions };
+use chess::{ Error, ParseErrorKind };
+
+fn sample_positions() -> Vec<LabeledPosition> {
+    parse_labeled_positions("
+        # White is a queen up, black is a queen up, and a level position.
+        3qk3/8/8/8/8/8/8/4K3 b - - c9 \"0-1\";
+        4k3/8/8/8/8/8/8/3QK3 w - - 0 1 [1.0]
+        4k3/8/8/8/8/8/8/3QK3 w - - 0 1 [1/2-1/2]
+        rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 1/2-1/2
+        4k3/pppp4/8/8/8/8/4PPPP/4K3 w - - 0 1 [0.5]
+    ").unwrap()
+}
+
+#[test]
+fn test_labeled_positions() {
+    let positions = sample_positions();
+    assert_eq!(5, positions.len());
+    assert_eq!(Color::Black, positions[0].color);
+    assert_eq!(vec![0.0, 1.0, 0.5, 0.5, 0.5], positions.iter().map(|position| position.result).collect::<Vec<f64>>());
+    assert!(parse_labeled_positions("4k3/8/8/8/8/8/8/3QK3 w - - 0 1 [2.0]").is_err());
+    // A position without a label is rejected, rather than taking its move counters for a result.
+    for unlabeled in &["rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", "4k3/8/8/8/8/8/8/3QK3 w - - 0 0", "4k3/8/8/8/8/8/8/3QK3 w - - 0 1 0.5"] {
+        match parse_labeled_positions(unlabeled) {
+            Err(Error::Parse(error)) => assert_eq!(ParseErrorKind::InvalidResult, error.kind),
+            other => panic!("Expected a missing label for {}, got {:?}", unlabeled, other),
+        }
+    }
+
+    let path = std::env::temp_dir().join(format!("crate-tuning-{}.epd", std::process::id()));
+    std::fs::write(&path, "4k3/8/8/8/8/8/8/3QK3 w - - c9 \"1-0\";\n").unwrap();
+    let loaded = load_labeled_positions(&path);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(1.0, loaded.unwrap()[0].result);
+}
+
+#[test]
+fn test_parameter_vector() {
+    let parameters = EvaluationParameters::default();
+    let values = parameters.to_vector();
+    assert_eq!(values.len(), parameters.names().len());
+    assert_eq!(parameters, EvaluationParameters::from_vector(&values).unwrap());
+    assert!(EvaluationParameters::from_vector(&values[1..]).is_err());
+    assert!(parameters.to_rust_source("WEIGHTS").starts_with(&format!("#[rustfmt::skip]\npub const WEIGHTS: [i32; {}] = [", values.len())));
+}
+
+#[test]
+fn test_weights_file() {
+    // Parameters survive a weights file, and partial weights files change only what they name.
+    let parameters = EvaluationParameters::default();
+    assert_eq!(parameters, EvaluationParameters::parse_weights(&parameters.to_weights()).unwrap());
+    let changed = EvaluationParameters::parse_weights("# Comment\npiece_values.queen.mg 1000\n").unwrap();
+    assert_eq!(1000, changed.piece_values[Kind::Queen.index()].middlegame);
+    assert!(EvaluationParameters::parse_weights("piece_values.dragon.mg 1000").is_err());
+}
+
+#[test]
+fn test_tuning() {
+    // Tuning never makes the fit worse, and only touches the selected parameters.
+    let positions = sample_positions();
+    let parameters = EvaluationParameters::default();
+    let scaling_constant = find_scaling_constant(&positions, &parameters, 2);
+    assert!(scaling_constant > 0.0);
+    let options = TunerOptions {
+        threads: 2,
+        max_iterations: 3,
+        step: 20,
+        only: vec![String::from("piece_values.queen")],
+        scaling_constant: Some(scaling_constant),
+    };
+    let result: TuneResult = tune(&positions, &parameters, &options);
+    assert!(result.error <= result.initial_error);
+    assert_eq!(result.error, evaluation_error(&positions, &result.parameters, scaling_constant, 1));
+    assert_eq!(parameters.piece_values[Kind::Rook.index()], result.parameters.piece_values[Kind::Rook.index()]);
+    assert_eq!(parameters.pawns, result.parameters.pawns);
+}
+
+#[test]
+fn test_tuning_fixed_parameters() {
+    // The king value is never tuned, even when selected, and more threads than positions find the same fit.
+    let positions = sample_positions();
+    let parameters = EvaluationParameters::default();
+    let options = TunerOptions {
+        threads: 8,
+        max_iterations: 3,
+        step: 20,
+        only: vec![String::from("piece_values")],
+        scaling_constant: Some(1.0),
+    };
+    let result = tune(&positions, &parameters, &options);
+    assert_eq!(parameters.piece_values[Kind::King.index()], result.parameters.piece_values[Kind::King.index()]);
+    assert_eq!(result, tune(&positions, &parameters, &TunerOptions { threads: 1, ..options }));
+}